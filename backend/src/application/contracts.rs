@@ -1,5 +1,13 @@
-use crate::domain::model::{FileID, NoteID, PlaylistSummary, Post, PostID, TagCategory, TagID};
-use serde::{Deserialize, Serialize};
+use crate::domain::model::{
+    ApiToken, Comment, CommentID, FileID, Invite, NoteID, PlaylistID, PlaylistItem, PlaylistItemID,
+    PlaylistProgress, PlaylistRole, PlaylistSummary, PlaylistVisibility, Post, PostID, PostRating,
+    PostRevision, RegistrationMode, Session, SessionID, SmartPlaylistQuery, SmartPlaylistSort,
+    StoredObject, TagCategory, TagID, TokenScope, UserID, UserRole,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -19,6 +27,46 @@ pub struct TagQuery {
     pub must: Vec<String>,
     pub should: Vec<String>,
     pub must_not: Vec<String>,
+    // Posts only: `fav:me`, `order:` and `rating:` terms
+    pub favorited_by: Option<UserID>,
+    pub order: PostOrder,
+    // None shows every rating
+    pub ratings: Option<Vec<PostRating>>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PostOrder {
+    // `should` tags matched, newest within the same count
+    #[default]
+    Relevance,
+    // Vote score
+    Score,
+    // Most recently favorited by `favorited_by` first
+    Favorited,
+}
+
+impl From<&SmartPlaylistQuery> for TagQuery {
+    fn from(query: &SmartPlaylistQuery) -> Self {
+        Self {
+            must: query.must.clone(),
+            // `should` tags only affect the score, which Newest ignores
+            should: match query.sort {
+                SmartPlaylistSort::Relevance => query.should.clone(),
+                SmartPlaylistSort::Newest => vec![],
+            },
+            must_not: query.must_not.clone(),
+            ..Self::default()
+        }
+    }
+}
+
+// What the uploader says about a post, next to its file
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NewPostDetails {
+    pub title: String,
+    pub tags: Vec<NewTag>,
+    pub rating: PostRating,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -26,7 +74,9 @@ pub struct NewPost {
     pub id: PostID,
     pub title: String,
     pub file_id: FileID,
+    pub uploader_id: UserID,
     pub tag_ids: Vec<TagID>,
+    pub rating: PostRating,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -43,6 +93,7 @@ pub struct UpdatePost {
     pub description: Option<String>,
     pub tag_ids: Option<Vec<TagID>>,
     pub notes: Option<Vec<UpdatePostNote>>,
+    pub rating: Option<PostRating>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -51,6 +102,8 @@ pub struct NewPlaylist {
     pub description: Option<String>,
     pub tag_ids: Option<Vec<TagID>>,
     pub cover: Option<FileID>,
+    pub visibility: Option<PlaylistVisibility>,
+    pub smart_query: Option<SmartPlaylistQuery>,
     pub items: Option<Vec<NewPlaylistItem>>,
 }
 
@@ -72,26 +125,401 @@ pub struct UpdatePlaylist {
     pub title: Option<String>,
     pub description: Option<String>,
     pub tag_ids: Option<Vec<TagID>>,
-    pub cover: Option<FileID>,
+    // `null` drops the chosen cover and falls back to the derived one
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub cover: Option<Option<FileID>>,
+    pub visibility: Option<PlaylistVisibility>,
+    // Missing keeps the current query, `null` turns the playlist back into a manual one
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub smart_query: Option<Option<SmartPlaylistQuery>>,
     pub items: Option<Vec<NewPlaylistItem>>,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UpdatePlaylistProgress {
+    pub item_id: PlaylistItemID,
+    pub position_ms: Option<u64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ContinueWatchingEntry {
+    pub playlist: PlaylistSummary,
+    pub progress: PlaylistProgress,
+    pub item: Option<PlaylistItem>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistFormat {
+    M3u8,
+    Xspf,
+    Json,
+}
+
+// Self-contained copy of a playlist, the JSON export and what every import
+// format is read into. Posts carry enough of their file to be found again on
+// another instance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlaylistBundle {
+    pub title: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<NewTag>,
+    pub items: Vec<BundleItem>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BundleItem {
+    Post {
+        title: Option<String>,
+        file_id: Option<FileID>,
+        hash: Option<String>,
+        path: Option<String>,
+        duration_ms: Option<u64>,
+    },
+    Note {
+        text: String,
+    },
+}
+
+// A post found for an imported entry through its file
+#[derive(Clone)]
+pub struct PostFileMatch {
+    pub post_id: PostID,
+    pub file_id: FileID,
+    pub hash: Option<String>,
+    pub path: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImportedPlaylist {
+    pub id: PlaylistID,
+    pub imported: u64,
+    // Titles (or locations) of entries without a matching post
+    pub unmatched: Vec<String>,
+}
+
+// What the derived cover of a playlist is built from
+#[derive(Clone)]
+pub struct PlaylistCoverSources {
+    pub has_custom_cover: bool,
+    // Picture posts in item order, at most four
+    pub pictures: Vec<(FileID, PathBuf)>,
+    pub auto_cover: Option<FileID>,
+    pub built_from: Vec<FileID>,
+}
+
+fn deserialize_present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+// `index` is the zero-based slot in the playlist, past the end appends
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AddPlaylistItem {
+    pub index: Option<u32>,
+    pub content: NewPlaylistItemContent,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MovePlaylistItem {
+    pub index: u32,
+}
+
+// Must list every item of the playlist exactly once
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReorderPlaylistItems {
+    pub item_ids: Vec<PlaylistItemID>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MergePlaylist {
+    pub source_id: PlaylistID,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DerivePlaylistTags {
+    // How many of the most common post tags to take
+    pub limit: Option<u32>,
+}
+
+// How many rows a bulk curation call actually added, duplicates are skipped
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CurationResult {
+    pub added: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NewPlaylistCollaborator {
+    pub username: String,
+    pub role: PlaylistRole,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UpdatePlaylistCollaborator {
+    pub role: PlaylistRole,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct PlaylistQuery {
     pub tags: TagQuery,
     pub text: String,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlaylistShare {
+    pub visibility: PlaylistVisibility,
+    pub share_token: Option<String>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct NewUser {
     pub username: String,
     pub password_hash: String,
+    // Checked in the same transaction that creates the user, which lets the
+    // very first account through regardless
+    pub registration: RegistrationMode,
+    // Consumed in that transaction too when the mode asks for an invite
+    pub invite_code_hash: Option<String>,
+    // Linked in the same transaction, for accounts provisioned on SSO login
+    pub identity: Option<ExternalIdentity>,
+}
+
+// Subject at an OpenID Connect issuer
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExternalIdentity {
+    pub issuer: String,
+    pub subject: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NewInvite {
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CreatedInvite {
+    #[serde(flatten)]
+    pub invite: Invite,
+    pub code: String,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct UpdateUser {
     pub username: Option<String>,
     pub password_hash: Option<String>,
+    pub max_rating: Option<PostRating>,
+}
+
+// Stored session; `state` is the session map of the web layer, kept opaque
+#[derive(Clone)]
+pub struct NewSession {
+    pub id: SessionID,
+    pub key_hash: String,
+    pub user_id: Option<UserID>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub state: HashMap<String, String>,
+    pub expires_at: OffsetDateTime,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ActiveSession {
+    #[serde(flatten)]
+    pub session: Session,
+    pub current: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RevokedSessions {
+    pub revoked: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NewApiToken {
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub token: ApiToken,
+    pub secret: String,
+}
+
+// Shown once when two-factor enrollment starts
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NewComment {
+    pub id: CommentID,
+    pub post_id: PostID,
+    pub parent_id: Option<CommentID>,
+    pub author_id: UserID,
+    pub body: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CommentsResponse {
+    pub comments: Vec<Comment>,
+    pub has_next: bool,
+    pub has_prev: bool,
+    pub next_cursor: Option<KeysetPageCursor>,
+    pub prev_cursor: Option<KeysetPageCursor>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RevisionsResponse {
+    pub revisions: Vec<PostRevision>,
+    pub has_next: bool,
+    pub has_prev: bool,
+    pub next_cursor: Option<KeysetPageCursor>,
+    pub prev_cursor: Option<KeysetPageCursor>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrashedPost {
+    pub id: PostID,
+    pub title: String,
+    pub uploader_id: Option<UserID>,
+    pub deleted_at: OffsetDateTime,
+    pub deleted_by: Option<UserID>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrashResponse {
+    pub posts: Vec<TrashedPost>,
+    pub has_next: bool,
+    pub has_prev: bool,
+    pub next_cursor: Option<KeysetPageCursor>,
+    pub prev_cursor: Option<KeysetPageCursor>,
+}
+
+// A dropped `files` row; its bytes and thumbnails are still on disk until the
+// caller removes them
+#[derive(Clone)]
+pub struct PurgedFile {
+    pub id: FileID,
+    pub path: PathBuf,
+    pub thumbnails: Vec<PathBuf>,
+}
+
+#[derive(Clone, Default)]
+pub struct PurgeReport {
+    pub posts: u64,
+    pub files: Vec<PurgedFile>,
+}
+
+// One `files` row as the storage check sees it
+#[derive(Clone)]
+pub struct FileRecord {
+    pub id: FileID,
+    pub path: PathBuf,
+    pub hash: Option<String>,
+    pub created_at: Option<OffsetDateTime>,
+    // Used by a post (trashed ones included) or as a playlist cover
+    pub referenced: bool,
+    pub thumbnails: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+pub struct StorageCheckOptions {
+    #[serde(default)]
+    pub fix: bool,
+    // Reads every object in full, so it is opt-in
+    #[serde(default)]
+    pub verify_hashes: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct StorageIssue {
+    pub id: FileID,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Serialize)]
+pub struct HashMismatch {
+    pub id: FileID,
+    pub path: PathBuf,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct StorageRepairs {
+    pub rows_deleted: u64,
+    pub objects_deleted: u64,
+    pub bytes_freed: u64,
+    pub hashes_recorded: u64,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct StorageReport {
+    pub rows_checked: u64,
+    pub objects_checked: u64,
+    // Rows whose bytes are gone
+    pub missing: Vec<StorageIssue>,
+    // Bytes no row or thumbnail points at
+    pub untracked: Vec<StoredObject>,
+    pub untracked_bytes: u64,
+    // Rows no post or playlist uses
+    pub unreferenced: Vec<StorageIssue>,
+    pub hash_mismatches: Vec<HashMismatch>,
+    // Rows without a recorded hash, only counted when hashes are verified
+    pub unhashed: u64,
+    pub repairs: Option<StorageRepairs>,
+}
+
+impl StorageReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty()
+            && self.untracked.is_empty()
+            && self.unreferenced.is_empty()
+            && self.hash_mismatches.is_empty()
+    }
+}
+
+// The caller's own favorite and vote on a post, next to its current score
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PostInteraction {
+    pub favorited: bool,
+    // -1, 0 or 1
+    pub vote: i16,
+    pub score: i32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+// Shown once, only their hashes are kept
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+// Owner and scopes of a valid token
+#[derive(Clone)]
+pub struct ApiTokenGrant {
+    pub user_id: UserID,
+    pub scopes: Vec<TokenScope>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UpdateUserRole {
+    pub role: UserRole,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
@@ -156,3 +584,13 @@ pub struct SearchPlaylistsResponse {
     pub next_cursor: Option<KeysetPageCursor>,
     pub prev_cursor: Option<KeysetPageCursor>,
 }
+
+// Items are ordered by position; cursors carry it as `last_score`
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct PlaylistItemsResponse {
+    pub items: Vec<PlaylistItem>,
+    pub has_next: bool,
+    pub has_prev: bool,
+    pub next_cursor: Option<KeysetPageCursor>,
+    pub prev_cursor: Option<KeysetPageCursor>,
+}
@@ -0,0 +1,27 @@
+use image::imageops::{self, FilterType};
+use image::{ImageError, ImageFormat, RgbImage};
+use std::io::Cursor;
+
+pub const MOSAIC_TILE: u32 = 256;
+pub const MOSAIC_SIDE: u32 = MOSAIC_TILE * 2;
+
+// Lays the first four pictures out as a 2x2 grid, each one cropped to a
+// square tile, and encodes the result as jpeg
+pub fn render_mosaic(pictures: &[Vec<u8>]) -> Result<Vec<u8>, ImageError> {
+    let mut canvas = RgbImage::new(MOSAIC_SIDE, MOSAIC_SIDE);
+
+    for (index, bytes) in pictures.iter().take(4).enumerate() {
+        let tile = image::load_from_memory(bytes)?
+            .resize_to_fill(MOSAIC_TILE, MOSAIC_TILE, FilterType::Triangle)
+            .to_rgb8();
+
+        let x = (index as u32 % 2) * MOSAIC_TILE;
+        let y = (index as u32 / 2) * MOSAIC_TILE;
+        imageops::replace(&mut canvas, &tile, i64::from(x), i64::from(y));
+    }
+
+    let mut out = Cursor::new(Vec::new());
+    canvas.write_to(&mut out, ImageFormat::Jpeg)?;
+
+    Ok(out.into_inner())
+}
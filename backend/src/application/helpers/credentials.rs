@@ -0,0 +1,89 @@
+use std::fmt;
+
+// Rules for account names and passwords, shared by registration and the
+// account settings
+
+pub const USERNAME_MIN_LEN: usize = 3;
+pub const USERNAME_MAX_LEN: usize = 32;
+pub const PASSWORD_MIN_LEN: usize = 8;
+// Hashing cost grows with the input, so very long passwords are refused
+pub const PASSWORD_MAX_LEN: usize = 128;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CredentialError {
+    UsernameLength,
+    UsernameCharacters,
+    UsernameEdges,
+    PasswordLength,
+    PasswordIsUsername,
+}
+
+impl fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialError::UsernameLength => write!(
+                f,
+                "Username must be {USERNAME_MIN_LEN} to {USERNAME_MAX_LEN} characters long"
+            ),
+            CredentialError::UsernameCharacters => write!(
+                f,
+                "Username may only contain latin letters, digits, '_', '-' and '.'"
+            ),
+            CredentialError::UsernameEdges => {
+                write!(f, "Username must start and end with a letter or a digit")
+            }
+            CredentialError::PasswordLength => write!(
+                f,
+                "Password must be {PASSWORD_MIN_LEN} to {PASSWORD_MAX_LEN} characters long"
+            ),
+            CredentialError::PasswordIsUsername => {
+                write!(f, "Password must not be the same as the username")
+            }
+        }
+    }
+}
+
+pub fn validate_username(username: &str) -> Result<(), CredentialError> {
+    if !(USERNAME_MIN_LEN..=USERNAME_MAX_LEN).contains(&username.chars().count()) {
+        return Err(CredentialError::UsernameLength);
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(CredentialError::UsernameCharacters);
+    }
+
+    let edges_ok = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+    if !edges_ok(username.chars().next()) || !edges_ok(username.chars().last()) {
+        return Err(CredentialError::UsernameEdges);
+    }
+
+    Ok(())
+}
+
+pub fn validate_password(password: &str, username: &str) -> Result<(), CredentialError> {
+    if !(PASSWORD_MIN_LEN..=PASSWORD_MAX_LEN).contains(&password.chars().count()) {
+        return Err(CredentialError::PasswordLength);
+    }
+    if password.eq_ignore_ascii_case(username) {
+        return Err(CredentialError::PasswordIsUsername);
+    }
+
+    Ok(())
+}
+
+// Closest valid username to a name coming from elsewhere, e.g. an SSO claim
+pub fn username_from(raw: &str) -> String {
+    let cleaned: String = raw
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .take(USERNAME_MAX_LEN)
+        .collect();
+    let trimmed = cleaned.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+
+    if trimmed.len() < USERNAME_MIN_LEN {
+        return format!("user{trimmed}");
+    }
+    trimmed.to_string()
+}
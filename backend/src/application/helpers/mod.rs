@@ -1 +1,7 @@
+pub mod content_hash;
+pub mod cover_mosaic;
+pub mod credentials;
 pub mod file_type_determinator;
+pub mod playlist_formats;
+pub mod tokens;
+pub mod totp;
@@ -0,0 +1,267 @@
+use crate::application::contracts::{BundleItem, PlaylistBundle};
+use crate::domain::model::{FileID, RepoError};
+use std::fmt::Write;
+use uuid::Uuid;
+
+// Playlist files for desktop players. Post entries point at the files
+// endpoint, notes become comments. Our own extension lines and the xspf
+// `urn:gl:*` fields keep hash and path so an import can find the post again.
+
+const DEFAULT_TITLE: &str = "Imported playlist";
+const HASH_URN: &str = "urn:gl:hash:";
+const PATH_REL: &str = "urn:gl:path";
+
+pub fn render_m3u8(bundle: &PlaylistBundle, files_url: &str) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    let _ = writeln!(out, "#PLAYLIST:{}", single_line(&bundle.title));
+
+    for item in &bundle.items {
+        match item {
+            BundleItem::Note { text } => {
+                for line in text.lines() {
+                    let _ = writeln!(out, "# {line}");
+                }
+            }
+            BundleItem::Post {
+                title,
+                file_id: Some(file_id),
+                hash,
+                path,
+                duration_ms,
+            } => {
+                let seconds = duration_ms.map_or(-1, |ms| (ms / 1000) as i64);
+                let title = title.as_deref().map(single_line).unwrap_or_default();
+                let _ = writeln!(out, "#EXTINF:{seconds},{title}");
+                if let Some(hash) = hash {
+                    let _ = writeln!(out, "#EXTGL-HASH:{hash}");
+                }
+                if let Some(path) = path {
+                    let _ = writeln!(out, "#EXTGL-PATH:{path}");
+                }
+                let _ = writeln!(out, "{files_url}{file_id}");
+            }
+            // Nothing a player could open
+            BundleItem::Post { file_id: None, .. } => {}
+        }
+    }
+
+    out
+}
+
+pub fn parse_m3u8(text: &str) -> Result<PlaylistBundle, RepoError> {
+    let mut bundle = PlaylistBundle {
+        title: DEFAULT_TITLE.to_string(),
+        description: None,
+        tags: Vec::new(),
+        items: Vec::new(),
+    };
+    let mut note: Vec<&str> = Vec::new();
+    let mut title = None;
+    let mut duration_ms = None;
+    let mut hash = None;
+    let mut path = None;
+
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(value) = comment.strip_prefix("PLAYLIST:") {
+                bundle.title = value.trim().to_string();
+            } else if let Some(value) = comment.strip_prefix("EXTINF:") {
+                let (seconds, name) = value.split_once(',').unwrap_or((value, ""));
+                duration_ms = seconds
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+                    .map(|seconds| seconds * 1000);
+                title = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+            } else if let Some(value) = comment.strip_prefix("EXTGL-HASH:") {
+                hash = Some(value.trim().to_string());
+            } else if let Some(value) = comment.strip_prefix("EXTGL-PATH:") {
+                path = Some(value.trim().to_string());
+            } else if !comment.starts_with("EXT") {
+                // Directives of other players are skipped, plain comments are notes
+                note.push(comment.strip_prefix(' ').unwrap_or(comment));
+            }
+            continue;
+        }
+
+        if !note.is_empty() {
+            bundle.items.push(BundleItem::Note {
+                text: note.join("\n"),
+            });
+            note.clear();
+        }
+
+        // A bare location that isn't a URL is taken as a storage path
+        let path = path.take().or_else(|| {
+            (!line.contains("://") && file_id_from_url(line).is_none()).then(|| line.to_string())
+        });
+        bundle.items.push(BundleItem::Post {
+            title: title.take().or_else(|| Some(line.to_string())),
+            file_id: file_id_from_url(line),
+            hash: hash.take(),
+            path,
+            duration_ms: duration_ms.take(),
+        });
+    }
+
+    if !note.is_empty() {
+        bundle.items.push(BundleItem::Note {
+            text: note.join("\n"),
+        });
+    }
+
+    Ok(bundle)
+}
+
+pub fn render_xspf(bundle: &PlaylistBundle, files_url: &str) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+    );
+    let _ = writeln!(out, "  <title>{}</title>", escape_xml(&bundle.title));
+    if let Some(description) = &bundle.description {
+        let _ = writeln!(
+            out,
+            "  <annotation>{}</annotation>",
+            escape_xml(description)
+        );
+    }
+    out.push_str("  <trackList>\n");
+
+    for item in &bundle.items {
+        match item {
+            BundleItem::Note { text } => {
+                // `--` can't appear inside an xml comment
+                let _ = writeln!(out, "    <!-- {} -->", text.replace("--", "- -"));
+            }
+            BundleItem::Post {
+                title,
+                file_id: Some(file_id),
+                hash,
+                path,
+                duration_ms,
+            } => {
+                out.push_str("    <track>\n");
+                let _ = writeln!(out, "      <location>{files_url}{file_id}</location>");
+                if let Some(hash) = hash {
+                    let _ = writeln!(
+                        out,
+                        "      <identifier>{HASH_URN}{}</identifier>",
+                        escape_xml(hash)
+                    );
+                }
+                if let Some(title) = title {
+                    let _ = writeln!(out, "      <title>{}</title>", escape_xml(title));
+                }
+                if let Some(duration_ms) = duration_ms {
+                    let _ = writeln!(out, "      <duration>{duration_ms}</duration>");
+                }
+                if let Some(path) = path {
+                    let _ = writeln!(
+                        out,
+                        "      <meta rel=\"{PATH_REL}\">{}</meta>",
+                        escape_xml(path)
+                    );
+                }
+                out.push_str("    </track>\n");
+            }
+            BundleItem::Post { file_id: None, .. } => {}
+        }
+    }
+
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+pub fn parse_xspf(text: &str) -> Result<PlaylistBundle, RepoError> {
+    let document = roxmltree::Document::parse(text).map_err(|err| {
+        log::warn!("xspf import failed to parse document: {err}");
+        RepoError::InvalidInput
+    })?;
+
+    let root = document.root_element();
+    if root.tag_name().name() != "playlist" {
+        log::warn!("xspf import got <{}> as root", root.tag_name().name());
+        return Err(RepoError::InvalidInput);
+    }
+
+    let child_text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|child| child.tag_name().name() == name)
+            .and_then(|child| child.text())
+            .map(|text| text.trim().to_string())
+    };
+
+    let mut bundle = PlaylistBundle {
+        title: child_text(root, "title").unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+        description: child_text(root, "annotation"),
+        tags: Vec::new(),
+        items: Vec::new(),
+    };
+
+    let Some(track_list) = root
+        .children()
+        .find(|child| child.tag_name().name() == "trackList")
+    else {
+        return Ok(bundle);
+    };
+
+    for node in track_list.children() {
+        if node.is_comment() {
+            if let Some(text) = node.text() {
+                bundle.items.push(BundleItem::Note {
+                    text: text.trim().to_string(),
+                });
+            }
+            continue;
+        }
+        if node.tag_name().name() != "track" {
+            continue;
+        }
+
+        let location = child_text(node, "location");
+        let hash = node
+            .children()
+            .filter(|child| child.tag_name().name() == "identifier")
+            .filter_map(|child| child.text())
+            .find_map(|text| text.trim().strip_prefix(HASH_URN).map(str::to_string));
+        let path = node
+            .children()
+            .find(|child| {
+                child.tag_name().name() == "meta" && child.attribute("rel") == Some(PATH_REL)
+            })
+            .and_then(|child| child.text())
+            .map(|text| text.trim().to_string());
+
+        bundle.items.push(BundleItem::Post {
+            title: child_text(node, "title").or_else(|| location.clone()),
+            file_id: location.as_deref().and_then(file_id_from_url),
+            hash,
+            path,
+            duration_ms: child_text(node, "duration").and_then(|ms| ms.parse().ok()),
+        });
+    }
+
+    Ok(bundle)
+}
+
+// `.../files/{id}` locations point at a file of this instance
+fn file_id_from_url(location: &str) -> Option<FileID> {
+    let (_, id) = location.rsplit_once("/files/")?;
+    Uuid::parse_str(id.trim_end_matches('/')).ok()
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,175 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+// RFC 6238 defaults, the only parameters authenticator apps agree on
+const DIGITS: u32 = 6;
+const STEP_SECS: i64 = 30;
+// Codes of the neighbouring steps pass too, to forgive clock drift
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+
+const SECRET_BYTES: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = percent_encode(issuer);
+    format!(
+        "otpauth://totp/{issuer}:{}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECS}",
+        percent_encode(account)
+    )
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn code_at(key: &[u8], step: i64) -> Option<u32> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).ok()?;
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation
+    let offset = usize::from(digest[digest.len() - 1] & 0x0f);
+    let value = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    Some(value % 10u32.pow(DIGITS))
+}
+
+// The time step the code belongs to, if it is valid around `unix_time`
+pub fn verify_code(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    let current = unix_time.div_euclid(STEP_SECS);
+    (current - ALLOWED_DRIFT_STEPS..=current + ALLOWED_DRIFT_STEPS)
+        .find(|&step| code_at(&key, step) == Some(code))
+}
+
+// What an authenticator app would show at `unix_time`
+#[cfg(test)]
+pub fn code_for(secret: &str, unix_time: i64) -> String {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+    let code = code_at(&key, unix_time.div_euclid(STEP_SECS)).unwrap();
+    format!("{code:0width$}", width = DIGITS as usize)
+}
+
+// One-time codes for when the authenticator is lost, as `xxxxx-xxxxx`
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            OsRng.fill_bytes(&mut bytes);
+            let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            format!("{}-{}", &hex[..5], &hex[5..])
+        })
+        .collect()
+}
+
+// Recovery codes are compared without case, spaces or dashes
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The RFC 6238 SHA-1 seed, "12345678901234567890"
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    // RFC 6238 appendix B, SHA-1 rows cut to six digits
+    const RFC_VECTORS: [(i64, &str); 6] = [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        let key = BASE32_NOPAD.decode(RFC_SECRET.as_bytes()).unwrap();
+        for (time, code) in RFC_VECTORS {
+            let step = time / STEP_SECS;
+            assert_eq!(
+                code_at(&key, step).map(|value| format!("{value:06}")),
+                Some(code.to_string()),
+                "T = {time}"
+            );
+            assert_eq!(
+                verify_code(RFC_SECRET, code, time),
+                Some(step),
+                "T = {time}"
+            );
+        }
+    }
+
+    #[test]
+    fn forgives_one_step_of_drift() {
+        let (time, code) = RFC_VECTORS[1];
+        let step = time / STEP_SECS;
+
+        for offset in [-STEP_SECS, STEP_SECS] {
+            assert_eq!(verify_code(RFC_SECRET, code, time + offset), Some(step));
+        }
+        for offset in [-2 * STEP_SECS, 2 * STEP_SECS] {
+            assert_eq!(verify_code(RFC_SECRET, code, time + offset), None);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        let (time, _) = RFC_VECTORS[1];
+
+        assert_eq!(
+            verify_code(RFC_SECRET, " 081 804 ", time),
+            Some(time / STEP_SECS)
+        );
+        assert_eq!(verify_code(RFC_SECRET, "81804", time), None);
+        assert_eq!(verify_code(RFC_SECRET, "0818040", time), None);
+        assert_eq!(verify_code(RFC_SECRET, "08180a", time), None);
+        assert_eq!(verify_code("not base32!", "081804", time), None);
+    }
+
+    #[test]
+    fn recovery_codes_normalize_to_what_was_issued() {
+        let codes = generate_recovery_codes();
+
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            assert_eq!(code.len(), 11);
+            assert_eq!(
+                normalize_recovery_code(&code.to_uppercase().replace('-', " - ")),
+                normalize_recovery_code(code)
+            );
+        }
+    }
+}
@@ -1,4 +1,4 @@
 pub mod contracts;
-mod helpers;
+pub(crate) mod helpers;
 pub mod ports;
 pub mod use_cases;
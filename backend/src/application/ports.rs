@@ -1,20 +1,37 @@
 use crate::application::contracts::{
-    Cursor, KeysetCursor, NewPlaylist, NewPost, NewTag, NewUser, PlaylistQuery,
-    SearchPlaylistsResponse, SearchPostsKeysetResponse, SearchPostsOffsetResponse, TagQuery,
-    UpdatePlaylist, UpdatePost,
+    AddPlaylistItem, ApiTokenGrant, CommentsResponse, ContinueWatchingEntry, CurationResult,
+    Cursor, ExternalIdentity, FileRecord, KeysetCursor, NewComment, NewPlaylist,
+    NewPlaylistCollaborator, NewPost, NewSession, NewTag, NewUser, PlaylistBundle,
+    PlaylistCoverSources, PlaylistItemsResponse, PlaylistQuery, PlaylistShare, PostFileMatch,
+    PostInteraction, PurgeReport, PurgedFile, RevisionsResponse, SearchPlaylistsResponse,
+    SearchPostsKeysetResponse, SearchPostsOffsetResponse, TagQuery, TrashResponse, UpdatePlaylist,
+    UpdatePlaylistProgress, UpdatePost, UpdateUser,
 };
 use crate::domain::model::{
-    File, FileID, Playlist, PlaylistID, Post, PostID, RepoError, Tag, User, UserID,
+    ApiToken, ApiTokenID, Comment, CommentID, File, FileID, Invite, InviteID, Playlist,
+    PlaylistCollaborator, PlaylistID, PlaylistItemID, PlaylistProgress, PlaylistRole, Post, PostID,
+    PostRating, RepoError, RevisionID, Session, SessionID, SmartPlaylistQuery, Tag, TokenScope,
+    User, UserID, UserRole, UserTotp,
 };
 use async_trait::async_trait;
+use std::collections::HashMap;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[async_trait]
 pub trait PostRepository: Send + Sync {
     async fn create(&self, post: NewPost) -> Result<PostID, RepoError>;
     async fn get(&self, id: PostID) -> Result<Post, RepoError>;
-    async fn update(&self, id: PostID, update_post: UpdatePost) -> Result<(), RepoError>;
-    async fn delete(&self, id: PostID) -> Result<(), RepoError>;
+    async fn uploader_of(&self, id: PostID) -> Result<Option<UserID>, RepoError>;
+    // Records what changed as a revision by `editor_id`
+    async fn update(
+        &self,
+        id: PostID,
+        editor_id: UserID,
+        update_post: UpdatePost,
+    ) -> Result<(), RepoError>;
+    // Moves the post to the trash
+    async fn delete(&self, id: PostID, deleted_by: UserID) -> Result<(), RepoError>;
     async fn search(
         &self,
         query: TagQuery,
@@ -30,6 +47,65 @@ pub trait PostRepository: Send + Sync {
         &self,
         cursor: KeysetCursor,
     ) -> Result<SearchPostsKeysetResponse, RepoError>;
+    async fn find_by_files(
+        &self,
+        file_ids: Vec<FileID>,
+        hashes: Vec<String>,
+        paths: Vec<String>,
+    ) -> Result<Vec<PostFileMatch>, RepoError>;
+    // Adds the favorite, or removes it when the user already has it
+    async fn toggle_favorite(&self, user_id: UserID, post_id: PostID) -> Result<(), RepoError>;
+    // 1 or -1 replaces the user's vote, 0 withdraws it
+    async fn vote(&self, user_id: UserID, post_id: PostID, value: i16) -> Result<(), RepoError>;
+    async fn interaction(
+        &self,
+        user_id: UserID,
+        post_id: PostID,
+    ) -> Result<PostInteraction, RepoError>;
+    // InvalidInput when the parent belongs to another post or is deleted
+    async fn create_comment(&self, comment: NewComment) -> Result<(), RepoError>;
+    async fn get_comment(&self, id: CommentID) -> Result<Comment, RepoError>;
+    // Top-level comments, or the replies to `parent_id`, oldest first
+    async fn list_comments(
+        &self,
+        post_id: PostID,
+        parent_id: Option<CommentID>,
+        cursor: KeysetCursor,
+    ) -> Result<CommentsResponse, RepoError>;
+    // NotFound for deleted comments
+    async fn update_comment(&self, id: CommentID, body: String) -> Result<(), RepoError>;
+    async fn delete_comment(&self, id: CommentID, deleted_by: UserID) -> Result<(), RepoError>;
+    // Newest first
+    async fn list_revisions(
+        &self,
+        post_id: PostID,
+        cursor: KeysetCursor,
+    ) -> Result<RevisionsResponse, RepoError>;
+    // Everyone's trashed posts, or only those uploaded by `uploader_id`;
+    // recently deleted first
+    async fn trashed(
+        &self,
+        uploader_id: Option<UserID>,
+        cursor: KeysetCursor,
+    ) -> Result<TrashResponse, RepoError>;
+    // NotFound unless the post is in the trash
+    async fn trashed_uploader_of(&self, id: PostID) -> Result<Option<UserID>, RepoError>;
+    async fn restore(&self, id: PostID) -> Result<(), RepoError>;
+    // Deletes up to `limit` posts trashed before `deleted_before`, along with
+    // the `files` rows nothing references anymore
+    async fn purge_trashed(
+        &self,
+        deleted_before: OffsetDateTime,
+        limit: i64,
+    ) -> Result<PurgeReport, RepoError>;
+    // Restores the post as it was right after `revision_id`, itself recorded
+    // as a new revision; NotFound when the revision belongs to another post
+    async fn rollback(
+        &self,
+        post_id: PostID,
+        revision_id: RevisionID,
+        editor_id: UserID,
+    ) -> Result<(), RepoError>;
 }
 
 #[async_trait]
@@ -39,7 +115,38 @@ pub trait PlaylistRepository: Send + Sync {
         user_id: UserID,
         new_playlist: NewPlaylist,
     ) -> Result<PlaylistID, RepoError>;
-    async fn get(&self, user_id: UserID, playlist_id: PlaylistID) -> Result<Playlist, RepoError>;
+    // Items holding posts rated above `max_rating` are left out
+    async fn get(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+    ) -> Result<Playlist, RepoError>;
+    async fn get_shared(
+        &self,
+        share_token: &str,
+        max_rating: PostRating,
+    ) -> Result<Playlist, RepoError>;
+    async fn get_items(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+        cursor: KeysetCursor,
+    ) -> Result<PlaylistItemsResponse, RepoError>;
+    async fn get_shared_items(
+        &self,
+        share_token: &str,
+        max_rating: PostRating,
+        cursor: KeysetCursor,
+    ) -> Result<PlaylistItemsResponse, RepoError>;
+    async fn get_smart_query(
+        &self,
+        viewer_id: Option<UserID>,
+        playlist_id: PlaylistID,
+    ) -> Result<SmartPlaylistQuery, RepoError>;
+    // Posts of the manual items in item order; the caller checks access
+    async fn pinned_post_ids(&self, playlist_id: PlaylistID) -> Result<Vec<PostID>, RepoError>;
     async fn update(
         &self,
         user_id: UserID,
@@ -47,6 +154,38 @@ pub trait PlaylistRepository: Send + Sync {
         update_playlist: UpdatePlaylist,
     ) -> Result<(), RepoError>;
     async fn delete(&self, user_id: UserID, playlist_id: PlaylistID) -> Result<(), RepoError>;
+    async fn export(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistBundle, RepoError>;
+    async fn save_progress(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        progress: UpdatePlaylistProgress,
+    ) -> Result<PlaylistProgress, RepoError>;
+    async fn clear_progress(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<(), RepoError>;
+    async fn continue_watching(
+        &self,
+        user_id: UserID,
+        limit: u32,
+    ) -> Result<Vec<ContinueWatchingEntry>, RepoError>;
+    async fn cover_sources(
+        &self,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistCoverSources, RepoError>;
+    async fn set_auto_cover(
+        &self,
+        playlist_id: PlaylistID,
+        cover: Option<FileID>,
+        built_from: Vec<FileID>,
+    ) -> Result<(), RepoError>;
     async fn search(
         &self,
         user_id: UserID,
@@ -58,6 +197,89 @@ pub trait PlaylistRepository: Send + Sync {
         user_id: UserID,
         cursor: KeysetCursor,
     ) -> Result<SearchPlaylistsResponse, RepoError>;
+    async fn search_public(
+        &self,
+        query: PlaylistQuery,
+        cursor: KeysetCursor,
+    ) -> Result<SearchPlaylistsResponse, RepoError>;
+    async fn set_share_token(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        share_token: Option<String>,
+    ) -> Result<PlaylistShare, RepoError>;
+    async fn add_item(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item: AddPlaylistItem,
+    ) -> Result<PlaylistItemID, RepoError>;
+    async fn remove_item(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item_id: PlaylistItemID,
+    ) -> Result<(), RepoError>;
+    async fn move_item(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item_id: PlaylistItemID,
+        index: u32,
+    ) -> Result<(), RepoError>;
+    async fn reorder_items(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item_ids: Vec<PlaylistItemID>,
+    ) -> Result<(), RepoError>;
+    async fn duplicate(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistID, RepoError>;
+    async fn merge(
+        &self,
+        user_id: UserID,
+        target_id: PlaylistID,
+        source_id: PlaylistID,
+    ) -> Result<CurationResult, RepoError>;
+    async fn apply_tags_to_posts(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        only_uploaded_by: Option<UserID>,
+    ) -> Result<CurationResult, RepoError>;
+    async fn derive_tags_from_posts(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        limit: u32,
+    ) -> Result<CurationResult, RepoError>;
+    async fn list_collaborators(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<Vec<PlaylistCollaborator>, RepoError>;
+    async fn add_collaborator(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        collaborator: NewPlaylistCollaborator,
+    ) -> Result<PlaylistCollaborator, RepoError>;
+    async fn update_collaborator(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        collaborator_id: UserID,
+        role: PlaylistRole,
+    ) -> Result<PlaylistCollaborator, RepoError>;
+    async fn remove_collaborator(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        collaborator_id: UserID,
+    ) -> Result<(), RepoError>;
 }
 
 #[async_trait]
@@ -70,11 +292,108 @@ pub trait TagRepository: Send + Sync {
 pub trait UserRepository: Send + Sync {
     async fn find_by_id(&self, id: Uuid) -> Result<User, RepoError>;
     async fn find_by_username(&self, username: &str) -> Result<User, RepoError>;
+    async fn find_by_identity(&self, identity: &ExternalIdentity) -> Result<User, RepoError>;
+    // Conflict when the identity already signs in as some user
+    async fn link_identity(
+        &self,
+        user_id: UserID,
+        identity: &ExternalIdentity,
+    ) -> Result<(), RepoError>;
+    // Conflict for a taken username, Forbidden when the registration mode
+    // turns the user away, InvalidInput for an unusable invite code
     async fn create(&self, user: NewUser) -> Result<Uuid, RepoError>;
+    async fn set_role(&self, id: UserID, role: UserRole) -> Result<(), RepoError>;
+    async fn update(&self, id: UserID, update_user: UpdateUser) -> Result<(), RepoError>;
+    // `None` deletes the owned playlists or posts, a user id takes them over
+    async fn delete(
+        &self,
+        id: UserID,
+        playlists_to: Option<UserID>,
+        posts_to: Option<UserID>,
+    ) -> Result<(), RepoError>;
+    async fn create_invite(
+        &self,
+        created_by: UserID,
+        code_hash: String,
+        expires_at: OffsetDateTime,
+    ) -> Result<Invite, RepoError>;
+    async fn list_invites(&self) -> Result<Vec<Invite>, RepoError>;
+    // Only unused invites can be revoked
+    async fn revoke_invite(&self, id: InviteID) -> Result<(), RepoError>;
+}
+
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    // Unexpired sessions only
+    async fn load(&self, key_hash: &str) -> Result<Option<HashMap<String, String>>, RepoError>;
+    async fn create(&self, session: NewSession) -> Result<(), RepoError>;
+    async fn update(
+        &self,
+        key_hash: &str,
+        user_id: Option<UserID>,
+        state: HashMap<String, String>,
+        expires_at: OffsetDateTime,
+    ) -> Result<(), RepoError>;
+    async fn touch(&self, key_hash: &str, expires_at: OffsetDateTime) -> Result<(), RepoError>;
+    async fn delete(&self, key_hash: &str) -> Result<(), RepoError>;
+    async fn list_for_user(&self, user_id: UserID) -> Result<Vec<Session>, RepoError>;
+    async fn revoke(&self, user_id: UserID, session_id: SessionID) -> Result<(), RepoError>;
+    async fn revoke_all(
+        &self,
+        user_id: UserID,
+        except: Option<SessionID>,
+    ) -> Result<u64, RepoError>;
+}
+
+#[async_trait]
+pub trait ApiTokenRepository: Send + Sync {
+    async fn create(
+        &self,
+        user_id: UserID,
+        name: String,
+        scopes: Vec<TokenScope>,
+        token_hash: String,
+        expires_at: OffsetDateTime,
+    ) -> Result<ApiToken, RepoError>;
+    async fn list_for_user(&self, user_id: UserID) -> Result<Vec<ApiToken>, RepoError>;
+    async fn revoke(&self, user_id: UserID, token_id: ApiTokenID) -> Result<(), RepoError>;
+    // Resolves an unexpired token and marks it used
+    async fn authenticate(&self, token_hash: &str) -> Result<ApiTokenGrant, RepoError>;
+}
+
+#[async_trait]
+pub trait TotpRepository: Send + Sync {
+    async fn find(&self, user_id: UserID) -> Result<UserTotp, RepoError>;
+    // Starts over an unconfirmed enrollment; `Conflict` once two-factor is on
+    async fn begin_enrollment(&self, user_id: UserID, secret: String) -> Result<(), RepoError>;
+    async fn confirm(
+        &self,
+        user_id: UserID,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<(), RepoError>;
+    // `Conflict` when the step is not newer than the last accepted one
+    async fn use_step(&self, user_id: UserID, step: i64) -> Result<(), RepoError>;
+    async fn use_recovery_code(&self, user_id: UserID, code_hash: &str) -> Result<(), RepoError>;
+    async fn replace_recovery_codes(
+        &self,
+        user_id: UserID,
+        code_hashes: Vec<String>,
+    ) -> Result<(), RepoError>;
+    async fn recovery_codes_left(&self, user_id: UserID) -> Result<i64, RepoError>;
+    async fn disable(&self, user_id: UserID) -> Result<(), RepoError>;
 }
 
 #[async_trait]
 pub trait FileRepository: Send + Sync {
     async fn create(&self, file_info: File) -> Result<FileID, RepoError>;
     async fn get(&self, id: FileID) -> Result<File, RepoError>;
+
+    // Rows in id order, starting after `after`
+    async fn scan(&self, after: Option<FileID>, limit: i64) -> Result<Vec<FileRecord>, RepoError>;
+
+    async fn set_hash(&self, id: FileID, hash: String) -> Result<(), RepoError>;
+
+    // Skips rows that gained a reference since they were scanned
+    async fn delete_unreferenced(&self, ids: Vec<FileID>) -> Result<Vec<PurgedFile>, RepoError>;
 }
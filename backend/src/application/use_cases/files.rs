@@ -1,5 +1,12 @@
+use crate::application::contracts::{
+    FileRecord, HashMismatch, StorageCheckOptions, StorageIssue, StorageRepairs, StorageReport,
+};
 use crate::application::ports::FileRepository;
-use crate::domain::model::{File, FileID, RepoError};
+use crate::domain::files::FileStorage;
+use crate::domain::model::{File, FileID, RepoError, StorageError};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
 
 // File Use-Case
 pub struct GetFileUseCase<FR> {
@@ -11,3 +18,339 @@ impl<FR: FileRepository> GetFileUseCase<FR> {
         self.repo.get(id).await
     }
 }
+
+const SCAN_BATCH: i64 = 500;
+// Uploads write their bytes before the row and the row before the post,
+// so anything younger than this may still be in flight
+const UPLOAD_GRACE: time::Duration = time::Duration::hours(1);
+
+pub struct CheckStorageUseCase<FR, FS> {
+    pub files: FR,
+    pub storage: FS,
+}
+
+impl<FR: FileRepository, FS: FileStorage> CheckStorageUseCase<FR, FS> {
+    // Rows without bytes and hash mismatches are only reported, there is
+    // nothing to restore them from
+    pub async fn execute(&self, options: StorageCheckOptions) -> Result<StorageReport, RepoError> {
+        let settled_before = OffsetDateTime::now_utc() - UPLOAD_GRACE;
+        let mut report = StorageReport::default();
+        let mut repairs = StorageRepairs::default();
+        let mut known: HashSet<PathBuf> = HashSet::new();
+        let mut unreferenced: Vec<FileID> = Vec::new();
+
+        // Listed before the scan, so bytes written meanwhile are not judged at all
+        let objects = self.storage.list().await.map_err(|err| {
+            log::error!("storage check failed to list objects: {:?}", err);
+            RepoError::StorageError
+        })?;
+        report.objects_checked = objects.len() as u64;
+
+        let mut after = None;
+        loop {
+            let batch = self.files.scan(after, SCAN_BATCH).await?;
+            let done = batch.len() < SCAN_BATCH as usize;
+            after = batch.last().map(|record| record.id);
+
+            for record in batch {
+                report.rows_checked += 1;
+                self.check_record(&record, options, &mut report, &mut repairs, &mut known)
+                    .await?;
+
+                if !record.referenced && record.created_at.is_none_or(|at| at < settled_before) {
+                    unreferenced.push(record.id);
+                    report.unreferenced.push(StorageIssue {
+                        id: record.id,
+                        path: record.path,
+                    });
+                }
+            }
+
+            if done {
+                break;
+            }
+        }
+
+        for object in objects {
+            if known.contains(&object.path) || object.modified.is_some_and(|at| at > settled_before)
+            {
+                continue;
+            }
+            report.untracked_bytes += object.size;
+            report.untracked.push(object);
+        }
+
+        if options.fix {
+            for ids in unreferenced.chunks(SCAN_BATCH as usize) {
+                let dropped = self.files.delete_unreferenced(ids.to_vec()).await?;
+                repairs.rows_deleted += dropped.len() as u64;
+
+                for file in &dropped {
+                    for path in file.thumbnails.iter().chain([&file.path]) {
+                        self.remove(path, &mut repairs).await;
+                    }
+                }
+            }
+
+            for object in &report.untracked {
+                self.remove(&object.path, &mut repairs).await;
+            }
+
+            report.repairs = Some(repairs);
+        }
+
+        Ok(report)
+    }
+
+    async fn check_record(
+        &self,
+        record: &FileRecord,
+        options: StorageCheckOptions,
+        report: &mut StorageReport,
+        repairs: &mut StorageRepairs,
+        known: &mut HashSet<PathBuf>,
+    ) -> Result<(), RepoError> {
+        for path in &record.thumbnails {
+            match self.storage.stat(path).await {
+                Ok(object) => known.insert(object.path),
+                Err(_) => known.insert(path.clone()),
+            };
+        }
+
+        match self.storage.stat(&record.path).await {
+            Ok(object) => {
+                known.insert(object.path);
+            }
+            Err(StorageError::NotFound) => {
+                report.missing.push(StorageIssue {
+                    id: record.id,
+                    path: record.path.clone(),
+                });
+                return Ok(());
+            }
+            // Kept as known so a flaky disk never gets its files removed
+            Err(err) => {
+                log::warn!(
+                    "storage check failed to stat {} of file {}: {:?}",
+                    record.path.display(),
+                    record.id,
+                    err
+                );
+                known.insert(record.path.clone());
+                return Ok(());
+            }
+        }
+
+        if !options.verify_hashes {
+            return Ok(());
+        }
+
+        let actual = match self.storage.digest(&record.path).await {
+            Ok(actual) => actual,
+            Err(err) => {
+                log::warn!(
+                    "storage check failed to hash {} of file {}: {:?}",
+                    record.path.display(),
+                    record.id,
+                    err
+                );
+                return Ok(());
+            }
+        };
+
+        match &record.hash {
+            Some(expected) if !expected.eq_ignore_ascii_case(&actual) => {
+                report.hash_mismatches.push(HashMismatch {
+                    id: record.id,
+                    path: record.path.clone(),
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+            Some(_) => {}
+            None => {
+                report.unhashed += 1;
+                // Unreferenced rows are about to go anyway
+                if options.fix && record.referenced {
+                    self.files.set_hash(record.id, actual).await?;
+                    repairs.hashes_recorded += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn remove(&self, path: &Path, repairs: &mut StorageRepairs) {
+        let size = self
+            .storage
+            .stat(path)
+            .await
+            .map_or(0, |object| object.size);
+        match self.storage.delete(path).await {
+            Ok(()) => {
+                repairs.objects_deleted += 1;
+                repairs.bytes_freed += size;
+            }
+            Err(StorageError::NotFound) => {}
+            Err(err) => log::warn!(
+                "storage check failed to remove {}: {:?}",
+                path.display(),
+                err
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::helpers::content_hash::ContentHasher;
+    use crate::storage::file_storage::files::LocalFileStorage;
+    use crate::storage::postgres::files::PostgresFileRepository;
+    use crate::storage::postgres::testing::load_schema;
+    use sqlx::PgPool;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    const SETTLED: Duration = Duration::from_secs(2 * 60 * 60);
+
+    // Writes bytes under `root`, backdated by `age`
+    fn object(root: &Path, path: &str, age: Duration) {
+        let full = root.join(path);
+        std::fs::write(&full, path).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&full)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    // Files row with the correct hash of what `object` writes, `age` old
+    async fn row(pool: &PgPool, path: &str, age: Duration) -> FileID {
+        let mut hasher = ContentHasher::default();
+        hasher.update(path.as_bytes());
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO files (path, hash, media_type, created_at)
+            VALUES ($1, $2, 1, NOW() - make_interval(secs => $3))
+            RETURNING id
+            "#,
+        )
+        .bind(path)
+        .bind(hasher.finish())
+        .bind(age.as_secs_f64())
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    async fn post(pool: &PgPool, file_id: FileID) {
+        sqlx::query("INSERT INTO posts (title, file_id) VALUES ('post', $1)")
+            .bind(file_id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    fn ids(issues: &[StorageIssue]) -> Vec<FileID> {
+        issues.iter().map(|issue| issue.id).collect()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn storage_check_reports_and_fixes_what_has_settled(pool: PgPool) {
+        load_schema(&pool).await;
+        let root = std::env::temp_dir().join(format!("gl-storage-{}", Uuid::now_v7()));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let used = row(&pool, "used.jpg", SETTLED).await;
+        object(&root, "used.jpg", SETTLED);
+        post(&pool, used).await;
+        sqlx::query("UPDATE files SET hash = NULL WHERE id = $1")
+            .bind(used)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let gone = row(&pool, "gone.jpg", SETTLED).await;
+        post(&pool, gone).await;
+
+        let corrupt = row(&pool, "corrupt.jpg", SETTLED).await;
+        object(&root, "corrupt.jpg", SETTLED);
+        post(&pool, corrupt).await;
+        sqlx::query("UPDATE files SET hash = 'deadbeef' WHERE id = $1")
+            .bind(corrupt)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let orphan = row(&pool, "orphan.jpg", SETTLED).await;
+        object(&root, "orphan.jpg", SETTLED);
+        object(&root, "stray.bin", SETTLED);
+
+        // An upload in flight: bytes and a row without a post yet
+        row(&pool, "young.jpg", Duration::ZERO).await;
+        object(&root, "young.jpg", Duration::ZERO);
+        object(&root, "fresh.bin", Duration::ZERO);
+
+        let check = CheckStorageUseCase {
+            files: PostgresFileRepository::new(pool.clone()),
+            storage: LocalFileStorage::new(&root),
+        };
+
+        let report = check.execute(StorageCheckOptions::default()).await.unwrap();
+        assert_eq!(report.rows_checked, 5);
+        assert_eq!(report.objects_checked, 6);
+        assert_eq!(ids(&report.missing), [gone]);
+        assert_eq!(ids(&report.unreferenced), [orphan]);
+        let untracked: Vec<&Path> = report
+            .untracked
+            .iter()
+            .map(|object| object.path.as_path())
+            .collect();
+        assert_eq!(untracked, [Path::new("stray.bin")]);
+        assert!(report.hash_mismatches.is_empty());
+        assert!(report.repairs.is_none());
+        assert!(root.join("orphan.jpg").exists());
+
+        let report = check
+            .execute(StorageCheckOptions {
+                fix: true,
+                verify_hashes: true,
+            })
+            .await
+            .unwrap();
+        let mismatches: Vec<FileID> = report.hash_mismatches.iter().map(|m| m.id).collect();
+        assert_eq!(mismatches, [corrupt]);
+        assert_eq!(report.unhashed, 1);
+
+        let repairs = report.repairs.unwrap();
+        assert_eq!(repairs.rows_deleted, 1);
+        assert_eq!(repairs.objects_deleted, 2);
+        assert_eq!(
+            repairs.bytes_freed,
+            ("orphan.jpg".len() + "stray.bin".len()) as u64
+        );
+        assert_eq!(repairs.hashes_recorded, 1);
+        assert!(!root.join("orphan.jpg").exists());
+        assert!(!root.join("stray.bin").exists());
+        assert!(root.join("young.jpg").exists());
+        assert!(root.join("fresh.bin").exists());
+
+        // Missing and corrupt rows are only reported, never removed
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM files")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 4);
+        let hash: Option<String> = sqlx::query_scalar("SELECT hash FROM files WHERE id = $1")
+            .bind(used)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(hash.is_some());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
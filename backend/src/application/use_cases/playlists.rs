@@ -1,8 +1,29 @@
 use crate::application::contracts::{
-    KeysetCursor, NewPlaylist, PlaylistQuery, SearchPlaylistsResponse, UpdatePlaylist,
+    AddPlaylistItem, BundleItem, ContinueWatchingEntry, CurationResult, ImportedPlaylist,
+    KeysetCursor, NewPlaylist, NewPlaylistCollaborator, NewPlaylistItem, NewPlaylistItemContent,
+    PlaylistBundle, PlaylistFormat, PlaylistItemsResponse, PlaylistQuery, PlaylistShare,
+    SearchPlaylistsResponse, SearchPostsKeysetResponse, TagQuery, UpdatePlaylist,
+    UpdatePlaylistProgress,
 };
-use crate::application::ports::PlaylistRepository;
-use crate::domain::model::{Playlist, PlaylistID, RepoError, UserID};
+use crate::application::helpers::content_hash::content_hash;
+use crate::application::helpers::cover_mosaic::{MOSAIC_SIDE, render_mosaic};
+use crate::application::helpers::playlist_formats::{
+    parse_m3u8, parse_xspf, render_m3u8, render_xspf,
+};
+use crate::application::helpers::tokens::generate_token;
+use crate::application::ports::{
+    FileRepository, PlaylistRepository, PostRepository, TagRepository,
+};
+use crate::domain::files::FileStorage;
+use crate::domain::model::{
+    Actor, File, FileID, FileMeta, FileType, Playlist, PlaylistCollaborator, PlaylistID,
+    PlaylistItemID, PlaylistProgress, PlaylistRole, PostRating, RepoError, SmartPlaylistQuery,
+    StorageError, UserID,
+};
+use actix_web::web::Bytes;
+use futures_util::stream;
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 // Playlist Use-Case
 
@@ -13,29 +34,127 @@ pub struct GetPlaylistUseCase<PLR> {
 impl<PLR: PlaylistRepository> GetPlaylistUseCase<PLR> {
     pub async fn execute(
         &self,
-        user_id: UserID,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
         playlist_id: PlaylistID,
     ) -> Result<Playlist, RepoError> {
-        self.repo.get(user_id, playlist_id).await
+        self.repo.get(viewer_id, max_rating, playlist_id).await
     }
 }
 
-pub struct SearchPlaylistsUseCase<PLR> {
+pub struct GetSharedPlaylistUseCase<PLR> {
     pub repo: PLR,
 }
 
-pub struct CreatePlaylistUseCase<PLR> {
+impl<PLR: PlaylistRepository> GetSharedPlaylistUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        share_token: &str,
+        max_rating: PostRating,
+    ) -> Result<Playlist, RepoError> {
+        self.repo.get_shared(share_token, max_rating).await
+    }
+}
+
+pub struct GetPlaylistItemsUseCase<PLR> {
     pub repo: PLR,
 }
 
-pub struct DeletePlaylistUseCase<PLR> {
+impl<PLR: PlaylistRepository> GetPlaylistItemsUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+        cursor: KeysetCursor,
+    ) -> Result<PlaylistItemsResponse, RepoError> {
+        self.repo
+            .get_items(viewer_id, max_rating, playlist_id, cursor)
+            .await
+    }
+}
+
+pub struct GetSharedPlaylistItemsUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> GetSharedPlaylistItemsUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        share_token: &str,
+        max_rating: PostRating,
+        cursor: KeysetCursor,
+    ) -> Result<PlaylistItemsResponse, RepoError> {
+        self.repo
+            .get_shared_items(share_token, max_rating, cursor)
+            .await
+    }
+}
+
+pub struct SearchPublicPlaylistsUseCase<PLR> {
     pub repo: PLR,
 }
 
-pub struct UpdatePlaylistUseCase<PLR> {
+impl<PLR: PlaylistRepository> SearchPublicPlaylistsUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        query: PlaylistQuery,
+        cursor: KeysetCursor,
+    ) -> Result<SearchPlaylistsResponse, RepoError> {
+        self.repo.search_public(query, cursor).await
+    }
+}
+
+// Issues a fresh share link, invalidating the previous one.
+// A private playlist becomes unlisted so the link resolves.
+pub struct SharePlaylistUseCase<PLR> {
     pub repo: PLR,
 }
 
+impl<PLR: PlaylistRepository> SharePlaylistUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistShare, RepoError> {
+        self.repo
+            .set_share_token(user_id, playlist_id, Some(generate_token()))
+            .await
+    }
+}
+
+pub struct RevokePlaylistShareUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> RevokePlaylistShareUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistShare, RepoError> {
+        self.repo.set_share_token(user_id, playlist_id, None).await
+    }
+}
+
+pub struct SearchPlaylistsUseCase<PLR> {
+    pub repo: PLR,
+}
+
+pub struct CreatePlaylistUseCase<PLR, FR> {
+    pub playlists: PLR,
+    pub files: FR,
+}
+
+pub struct DeletePlaylistUseCase<PLR> {
+    pub repo: PLR,
+}
+
+pub struct UpdatePlaylistUseCase<PLR, FR> {
+    pub playlists: PLR,
+    pub files: FR,
+}
+
 impl<PLR: PlaylistRepository> SearchPlaylistsUseCase<PLR> {
     pub async fn execute(
         &self,
@@ -67,25 +186,683 @@ impl<PLR: PlaylistRepository> DeletePlaylistUseCase<PLR> {
     }
 }
 
-impl<PLR: PlaylistRepository> CreatePlaylistUseCase<PLR> {
+fn check_smart_query(query: &SmartPlaylistQuery) -> Result<(), RepoError> {
+    if query.has_valid_limit() {
+        Ok(())
+    } else {
+        Err(RepoError::InvalidInput)
+    }
+}
+
+// A cover has to be an uploaded picture
+async fn check_cover<FR: FileRepository>(files: &FR, cover: FileID) -> Result<(), RepoError> {
+    let file = files.get(cover).await.map_err(|err| match err {
+        RepoError::NotFound => RepoError::InvalidInput,
+        err => err,
+    })?;
+
+    match file.media_type {
+        FileType::Picture => Ok(()),
+        _ => Err(RepoError::InvalidInput),
+    }
+}
+
+impl<PLR: PlaylistRepository, FR: FileRepository> CreatePlaylistUseCase<PLR, FR> {
     pub async fn execute(
         &self,
         user_id: UserID,
         new_playlist: NewPlaylist,
     ) -> Result<PlaylistID, RepoError> {
-        self.repo.create(user_id, new_playlist).await
+        if let Some(query) = &new_playlist.smart_query {
+            check_smart_query(query)?;
+        }
+        if let Some(cover) = new_playlist.cover {
+            check_cover(&self.files, cover).await?;
+        }
+
+        self.playlists.create(user_id, new_playlist).await
     }
 }
 
-impl<PLR: PlaylistRepository> UpdatePlaylistUseCase<PLR> {
+impl<PLR: PlaylistRepository, FR: FileRepository> UpdatePlaylistUseCase<PLR, FR> {
     pub async fn execute(
         &self,
         user_id: UserID,
         playlist_id: PlaylistID,
         update_playlist: UpdatePlaylist,
     ) -> Result<(), RepoError> {
-        self.repo
+        if let Some(Some(query)) = &update_playlist.smart_query {
+            check_smart_query(query)?;
+        }
+        if let Some(Some(cover)) = update_playlist.cover {
+            check_cover(&self.files, cover).await?;
+        }
+
+        self.playlists
             .update(user_id, playlist_id, update_playlist)
             .await
     }
 }
+
+// Keeps the derived cover in step with the items: the first picture, or a
+// mosaic once there are four of them. Playlists with a chosen cover are left
+// alone until the cover is dropped.
+pub struct RefreshPlaylistCoverUseCase<PLR, FR, FS> {
+    pub playlists: PLR,
+    pub files: FR,
+    pub storage: FS,
+}
+
+impl<PLR: PlaylistRepository, FR: FileRepository, FS: FileStorage>
+    RefreshPlaylistCoverUseCase<PLR, FR, FS>
+{
+    pub async fn execute(&self, playlist_id: PlaylistID) -> Result<(), RepoError> {
+        let sources = self.playlists.cover_sources(playlist_id).await?;
+        if sources.has_custom_cover {
+            return Ok(());
+        }
+
+        let picture_ids: Vec<FileID> = sources.pictures.iter().map(|(id, _)| *id).collect();
+        if picture_ids == sources.built_from
+            && sources.auto_cover.is_some() != picture_ids.is_empty()
+        {
+            return Ok(());
+        }
+
+        let mut mosaic = None;
+        let cover = match picture_ids.len() {
+            0 => None,
+            1..=3 => Some(picture_ids[0]),
+            // A picture that can't be decoded shouldn't leave the playlist bare
+            _ => match self.build_mosaic(&sources.pictures).await {
+                Ok(mosaic_id) => {
+                    mosaic = Some(mosaic_id);
+                    mosaic
+                }
+                Err(_) => Some(picture_ids[0]),
+            },
+        };
+
+        let result = self
+            .playlists
+            .set_auto_cover(playlist_id, cover, picture_ids)
+            .await;
+
+        // The cover that lost out goes unless something still points at it;
+        // a picture borrowed from a post always is
+        let replaced = match &result {
+            Ok(()) => sources
+                .auto_cover
+                .filter(|previous| Some(*previous) != cover),
+            Err(_) => mosaic,
+        };
+        if let Some(file_id) = replaced {
+            self.drop_unreferenced(file_id).await;
+        }
+
+        result
+    }
+
+    // Best effort, the storage check sweeps up whatever is left
+    async fn drop_unreferenced(&self, file_id: FileID) {
+        let dropped = match self.files.delete_unreferenced(vec![file_id]).await {
+            Ok(dropped) => dropped,
+            Err(err) => {
+                log::warn!("cover refresh failed to drop file {file_id}: {err:?}");
+                return;
+            }
+        };
+
+        for file in &dropped {
+            for path in file.thumbnails.iter().chain([&file.path]) {
+                match self.storage.delete(path).await {
+                    Ok(()) | Err(StorageError::NotFound) => {}
+                    Err(err) => log::warn!(
+                        "cover refresh failed to remove {} of file {}: {:?}",
+                        path.display(),
+                        file.id,
+                        err
+                    ),
+                }
+            }
+        }
+    }
+
+    async fn build_mosaic(&self, pictures: &[(FileID, PathBuf)]) -> Result<FileID, RepoError> {
+        let mut images = Vec::with_capacity(pictures.len());
+        for (file_id, path) in pictures {
+            let bytes = self.storage.read(path).await.map_err(|err| {
+                log::warn!("cover mosaic failed to read file {file_id}: {err:?}");
+                RepoError::StorageError
+            })?;
+            images.push(bytes);
+        }
+
+        let jpeg = tokio::task::spawn_blocking(move || render_mosaic(&images))
+            .await
+            .map_err(|err| {
+                log::error!("cover mosaic render task failed: {err}");
+                RepoError::StorageError
+            })?
+            .map_err(|err| {
+                log::warn!("cover mosaic failed to render: {err}");
+                RepoError::StorageError
+            })?;
+
+        let hash = content_hash(&jpeg);
+        let stream = stream::iter([Ok::<_, StorageError>(Bytes::from(jpeg))]);
+        let (file_id, rel_path) = self
+            .storage
+            .save_stream(stream, Some("jpg"))
+            .await
+            .map_err(|_| RepoError::StorageError)?;
+
+        let path = PathBuf::from(rel_path);
+        let created = self
+            .files
+            .create(File {
+                id: file_id,
+                path: path.clone(),
+                hash: Some(hash),
+                media_type: FileType::Picture,
+                meta: Some(FileMeta {
+                    width: Some(MOSAIC_SIDE),
+                    height: Some(MOSAIC_SIDE),
+                    extension: Some("jpg".to_string()),
+                    duration_ms: None,
+                }),
+                created_at: None,
+                thumbnail: None,
+            })
+            .await;
+
+        // Without a row nothing would ever point at the bytes again
+        if created.is_err()
+            && let Err(err) = self.storage.delete(&path).await
+        {
+            log::warn!(
+                "cover mosaic failed to remove unsaved file {}: {:?}",
+                path.display(),
+                err
+            );
+        }
+        created
+    }
+}
+
+// Playlist items
+
+pub struct AddPlaylistItemUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> AddPlaylistItemUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item: AddPlaylistItem,
+    ) -> Result<PlaylistItemID, RepoError> {
+        self.repo.add_item(user_id, playlist_id, item).await
+    }
+}
+
+pub struct RemovePlaylistItemUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> RemovePlaylistItemUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item_id: PlaylistItemID,
+    ) -> Result<(), RepoError> {
+        self.repo.remove_item(user_id, playlist_id, item_id).await
+    }
+}
+
+pub struct MovePlaylistItemUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> MovePlaylistItemUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item_id: PlaylistItemID,
+        index: u32,
+    ) -> Result<(), RepoError> {
+        self.repo
+            .move_item(user_id, playlist_id, item_id, index)
+            .await
+    }
+}
+
+pub struct ReorderPlaylistItemsUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> ReorderPlaylistItemsUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item_ids: Vec<PlaylistItemID>,
+    ) -> Result<(), RepoError> {
+        self.repo
+            .reorder_items(user_id, playlist_id, item_ids)
+            .await
+    }
+}
+
+// Playback progress
+
+pub struct SavePlaylistProgressUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> SavePlaylistProgressUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        progress: UpdatePlaylistProgress,
+    ) -> Result<PlaylistProgress, RepoError> {
+        self.repo
+            .save_progress(user_id, playlist_id, progress)
+            .await
+    }
+}
+
+pub struct ClearPlaylistProgressUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> ClearPlaylistProgressUseCase<PLR> {
+    pub async fn execute(&self, user_id: UserID, playlist_id: PlaylistID) -> Result<(), RepoError> {
+        self.repo.clear_progress(user_id, playlist_id).await
+    }
+}
+
+pub struct ContinueWatchingUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> ContinueWatchingUseCase<PLR> {
+    const LIMIT: u32 = 20;
+
+    pub async fn execute(&self, user_id: UserID) -> Result<Vec<ContinueWatchingEntry>, RepoError> {
+        self.repo.continue_watching(user_id, Self::LIMIT).await
+    }
+}
+
+// Export and import
+
+pub struct ExportPlaylistUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> ExportPlaylistUseCase<PLR> {
+    // `files_url` is prepended to file ids to build the entry locations
+    pub async fn execute(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+        format: PlaylistFormat,
+        files_url: &str,
+    ) -> Result<String, RepoError> {
+        let bundle = self.repo.export(viewer_id, max_rating, playlist_id).await?;
+
+        match format {
+            PlaylistFormat::M3u8 => Ok(render_m3u8(&bundle, files_url)),
+            PlaylistFormat::Xspf => Ok(render_xspf(&bundle, files_url)),
+            PlaylistFormat::Json => serde_json::to_string_pretty(&bundle).map_err(|err| {
+                log::error!("playlists.export failed to serialize bundle: {err}");
+                RepoError::StorageError
+            }),
+        }
+    }
+}
+
+pub struct ImportPlaylistUseCase<PLR, PR, TR> {
+    pub playlists: PLR,
+    pub posts: PR,
+    pub tags: TR,
+}
+
+impl<PLR: PlaylistRepository, PR: PostRepository, TR: TagRepository>
+    ImportPlaylistUseCase<PLR, PR, TR>
+{
+    // Same spacing the repository leaves between appended items
+    const POSITION_GAP: u32 = 1024;
+
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        format: PlaylistFormat,
+        body: &str,
+    ) -> Result<ImportedPlaylist, RepoError> {
+        let bundle = match format {
+            PlaylistFormat::M3u8 => parse_m3u8(body)?,
+            PlaylistFormat::Xspf => parse_xspf(body)?,
+            PlaylistFormat::Json => {
+                serde_json::from_str::<PlaylistBundle>(body).map_err(|err| {
+                    log::warn!("playlists.import got an unreadable bundle: {err}");
+                    RepoError::InvalidInput
+                })?
+            }
+        };
+
+        let mut file_ids = Vec::new();
+        let mut hashes = Vec::new();
+        let mut paths = Vec::new();
+        for item in &bundle.items {
+            if let BundleItem::Post {
+                file_id,
+                hash,
+                path,
+                ..
+            } = item
+            {
+                file_ids.extend(*file_id);
+                hashes.extend(hash.clone());
+                paths.extend(path.clone());
+            }
+        }
+
+        let matches = self.posts.find_by_files(file_ids, hashes, paths).await?;
+        let mut by_file = HashMap::new();
+        let mut by_hash = HashMap::new();
+        let mut by_path = HashMap::new();
+        for found in matches {
+            by_file.insert(found.file_id, found.post_id);
+            if let Some(hash) = found.hash {
+                by_hash.insert(hash, found.post_id);
+            }
+            by_path.insert(found.path, found.post_id);
+        }
+
+        // An entry is matched by file id (same instance), then hash, then path
+        let mut items = Vec::new();
+        let mut unmatched = Vec::new();
+        for item in bundle.items {
+            let content = match item {
+                BundleItem::Note { text } => NewPlaylistItemContent::Note { text },
+                BundleItem::Post {
+                    title,
+                    file_id,
+                    hash,
+                    path,
+                    ..
+                } => {
+                    let post_id = file_id
+                        .and_then(|id| by_file.get(&id))
+                        .or_else(|| hash.as_ref().and_then(|hash| by_hash.get(hash)))
+                        .or_else(|| path.as_ref().and_then(|path| by_path.get(path)));
+
+                    match post_id {
+                        Some(post_id) => NewPlaylistItemContent::Post { post_id: *post_id },
+                        None => {
+                            unmatched.push(title.or(path).unwrap_or_default());
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            items.push(NewPlaylistItem {
+                position: (items.len() as u32 + 1) * Self::POSITION_GAP,
+                content,
+            });
+        }
+
+        let tag_ids = if bundle.tags.is_empty() {
+            None
+        } else {
+            let tags = self.tags.get_or_create(bundle.tags).await?;
+            Some(tags.into_iter().map(|tag| tag.id).collect())
+        };
+
+        let imported = items.len() as u64;
+        let id = self
+            .playlists
+            .create(
+                user_id,
+                NewPlaylist {
+                    title: bundle.title,
+                    description: bundle.description,
+                    tag_ids,
+                    cover: None,
+                    visibility: None,
+                    smart_query: None,
+                    items: Some(items),
+                },
+            )
+            .await?;
+
+        Ok(ImportedPlaylist {
+            id,
+            imported,
+            unmatched,
+        })
+    }
+}
+
+// Curation
+
+pub struct DuplicatePlaylistUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> DuplicatePlaylistUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistID, RepoError> {
+        self.repo.duplicate(user_id, playlist_id).await
+    }
+}
+
+pub struct MergePlaylistsUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> MergePlaylistsUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        target_id: PlaylistID,
+        source_id: PlaylistID,
+    ) -> Result<CurationResult, RepoError> {
+        if target_id == source_id {
+            return Err(RepoError::Conflict);
+        }
+
+        self.repo.merge(user_id, target_id, source_id).await
+    }
+}
+
+pub struct ApplyPlaylistTagsUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> ApplyPlaylistTagsUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        actor: Actor,
+        playlist_id: PlaylistID,
+    ) -> Result<CurationResult, RepoError> {
+        if !actor.can_upload() {
+            return Err(RepoError::Forbidden);
+        }
+
+        // Tagging someone else's post needs the same rights as editing it
+        let only_uploaded_by = (!actor.can_moderate()).then_some(actor.id);
+
+        self.repo
+            .apply_tags_to_posts(actor.id, playlist_id, only_uploaded_by)
+            .await
+    }
+}
+
+pub struct DerivePlaylistTagsUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> DerivePlaylistTagsUseCase<PLR> {
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 50;
+
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        limit: Option<u32>,
+    ) -> Result<CurationResult, RepoError> {
+        let limit = limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .clamp(1, Self::MAX_LIMIT);
+
+        self.repo
+            .derive_tags_from_posts(user_id, playlist_id, limit)
+            .await
+    }
+}
+
+// Playlist collaborators
+
+pub struct ListPlaylistCollaboratorsUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> ListPlaylistCollaboratorsUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<Vec<PlaylistCollaborator>, RepoError> {
+        self.repo.list_collaborators(user_id, playlist_id).await
+    }
+}
+
+pub struct AddPlaylistCollaboratorUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> AddPlaylistCollaboratorUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        collaborator: NewPlaylistCollaborator,
+    ) -> Result<PlaylistCollaborator, RepoError> {
+        self.repo
+            .add_collaborator(user_id, playlist_id, collaborator)
+            .await
+    }
+}
+
+pub struct UpdatePlaylistCollaboratorUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> UpdatePlaylistCollaboratorUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        collaborator_id: UserID,
+        role: PlaylistRole,
+    ) -> Result<PlaylistCollaborator, RepoError> {
+        self.repo
+            .update_collaborator(user_id, playlist_id, collaborator_id, role)
+            .await
+    }
+}
+
+pub struct RemovePlaylistCollaboratorUseCase<PLR> {
+    pub repo: PLR,
+}
+
+impl<PLR: PlaylistRepository> RemovePlaylistCollaboratorUseCase<PLR> {
+    pub async fn execute(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        collaborator_id: UserID,
+    ) -> Result<(), RepoError> {
+        self.repo
+            .remove_collaborator(user_id, playlist_id, collaborator_id)
+            .await
+    }
+}
+
+// Smart playlists
+
+pub struct GetSmartPlaylistPostsUseCase<PLR, PR> {
+    pub playlists: PLR,
+    pub posts: PR,
+}
+
+impl<PLR: PlaylistRepository, PR: PostRepository> GetSmartPlaylistPostsUseCase<PLR, PR> {
+    pub async fn execute(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+        cursor: KeysetCursor,
+    ) -> Result<SearchPostsKeysetResponse, RepoError> {
+        let smart_query = self
+            .playlists
+            .get_smart_query(viewer_id, playlist_id)
+            .await?;
+        let tag_query = TagQuery {
+            ratings: Some(PostRating::up_to(max_rating)),
+            ..TagQuery::from(&smart_query)
+        };
+
+        let mut page = match smart_query.limit {
+            Some(limit) => {
+                // A limited playlist is a fixed top-N list served as a single page
+                let top = KeysetCursor {
+                    limit: Some(i64::from(limit)),
+                    ..KeysetCursor::default()
+                };
+                let mut page = self.posts.search_keyset(tag_query, top).await?;
+                page.has_next = false;
+                page.has_prev = false;
+                page.next_cursor = None;
+                page.prev_cursor = None;
+                page
+            }
+            None => self.posts.search_keyset(tag_query, cursor).await?,
+        };
+
+        // Pinned posts lead the first page and are not repeated further down
+        let pinned_ids = self.playlists.pinned_post_ids(playlist_id).await?;
+        if pinned_ids.is_empty() {
+            return Ok(page);
+        }
+        page.posts.retain(|post| !pinned_ids.contains(&post.id));
+        if page.has_prev {
+            return Ok(page);
+        }
+
+        let mut pinned = Vec::with_capacity(pinned_ids.len());
+        for id in pinned_ids {
+            match self.posts.get(id).await {
+                Ok(post) if post.rating <= max_rating => pinned.push(post),
+                Ok(_) | Err(RepoError::NotFound) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        pinned.append(&mut page.posts);
+        page.posts = pinned;
+
+        Ok(page)
+    }
+}
@@ -1,13 +1,20 @@
 use crate::application::contracts::{
-    Cursor, KeysetCursor, NewPost, NewTag, SearchPostsKeysetResponse, SearchPostsOffsetResponse,
-    TagQuery, UpdatePost,
+    CommentsResponse, Cursor, KeysetCursor, NewComment, NewPost, NewPostDetails, PostInteraction,
+    PostOrder, PurgeReport, RevisionsResponse, SearchPostsKeysetResponse,
+    SearchPostsOffsetResponse, TagQuery, TrashResponse, UpdatePost,
 };
+use crate::application::helpers::content_hash::ContentHasher;
 use crate::application::helpers::file_type_determinator::file_type_from_mime_and_ext;
 use crate::application::ports::{FileRepository, PostRepository, TagRepository};
 use crate::domain::files::FileStorage;
-use crate::domain::model::{ByteStream, File, Post, PostID, RepoError};
+use crate::domain::model::{
+    Actor, ByteStream, Comment, CommentID, File, Post, PostID, PostRating, RepoError, RevisionID,
+    StorageError,
+};
 use actix_web::mime::Mime;
+use futures_util::StreamExt;
 use std::path::PathBuf;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 // Post Use-Case
@@ -23,14 +30,24 @@ impl<PR: PostRepository, TR: TagRepository, FR: FileRepository, FS: FileStorage>
 {
     pub async fn execute(
         &self,
-        title: String,
+        actor: Actor,
+        details: NewPostDetails,
         stream: ByteStream,
         file_ext: Option<&str>,
         mime_type: Option<Mime>,
-        tags: Vec<NewTag>,
     ) -> Result<PostID, RepoError> {
+        if !actor.can_upload() {
+            return Err(RepoError::Forbidden);
+        }
+
         let media_type = file_type_from_mime_and_ext(mime_type, file_ext)?;
 
+        let mut hasher = ContentHasher::default();
+        let stream = stream.inspect(|chunk| {
+            if let Ok(bytes) = chunk {
+                hasher.update(bytes);
+            }
+        });
         let (file_id, rel_path) = self
             .storage
             .save_stream(stream, file_ext)
@@ -43,27 +60,39 @@ impl<PR: PostRepository, TR: TagRepository, FR: FileRepository, FS: FileStorage>
             .map_err(|_| RepoError::StorageError)?;
          */
 
-        // TODO Decide what to do with hash meta
         let file_model = File {
             id: file_id,
             path: PathBuf::from(rel_path),
             media_type,
-            hash: None,
+            hash: Some(hasher.finish()),
             meta: None,
             created_at: None,
             thumbnail: None,
         };
 
-        self.files.create(file_model).await?;
+        // Without a row nothing would ever point at the bytes again
+        let path = file_model.path.clone();
+        if let Err(err) = self.files.create(file_model).await {
+            if let Err(remove_err) = self.storage.delete(&path).await {
+                log::warn!(
+                    "failed to remove bytes of unsaved file {}: {:?}",
+                    path.display(),
+                    remove_err
+                );
+            }
+            return Err(err);
+        }
 
-        let created_tags = self.tags.get_or_create(tags).await?;
+        let created_tags = self.tags.get_or_create(details.tags).await?;
         let tag_ids: Vec<Uuid> = created_tags.into_iter().map(|t| t.id).collect();
 
         let new_post = NewPost {
             id: Uuid::now_v7(),
-            title,
+            title: details.title,
             file_id,
+            uploader_id: actor.id,
             tag_ids,
+            rating: details.rating,
         };
 
         self.posts.create(new_post).await
@@ -133,19 +162,468 @@ impl<PR: PostRepository> GetAllPostsKeysetUseCase<PR> {
 }
 
 impl<PR: PostRepository> GetPostUseCase<PR> {
-    pub async fn execute(&self, id: PostID) -> Result<Post, RepoError> {
-        self.repo.get(id).await
+    // Posts rated above `max_rating` are Forbidden
+    pub async fn execute(&self, id: PostID, max_rating: PostRating) -> Result<Post, RepoError> {
+        let post = self.repo.get(id).await?;
+        if post.rating > max_rating {
+            return Err(RepoError::Forbidden);
+        }
+        Ok(post)
     }
 }
 
 impl<PR: PostRepository> DeletePostUseCase<PR> {
-    pub async fn execute(&self, id: PostID) -> Result<(), RepoError> {
-        self.repo.delete(id).await
+    pub async fn execute(&self, actor: Actor, id: PostID) -> Result<(), RepoError> {
+        ensure_can_manage(&self.repo, actor, id).await?;
+        self.repo.delete(id, actor.id).await
     }
 }
 
 impl<PR: PostRepository> UpdatePostUseCase<PR> {
-    pub async fn execute(&self, id: PostID, update_post: UpdatePost) -> Result<(), RepoError> {
-        self.repo.update(id, update_post).await
+    pub async fn execute(
+        &self,
+        actor: Actor,
+        id: PostID,
+        update_post: UpdatePost,
+    ) -> Result<(), RepoError> {
+        ensure_can_manage(&self.repo, actor, id).await?;
+        self.repo.update(id, actor.id, update_post).await
+    }
+}
+
+pub struct TogglePostFavoriteUseCase<PR> {
+    pub repo: PR,
+}
+
+pub struct VotePostUseCase<PR> {
+    pub repo: PR,
+}
+
+pub struct GetPostInteractionUseCase<PR> {
+    pub repo: PR,
+}
+
+pub struct GetFavoritePostsUseCase<PR> {
+    pub repo: PR,
+}
+
+impl<PR: PostRepository> TogglePostFavoriteUseCase<PR> {
+    pub async fn execute(&self, actor: Actor, id: PostID) -> Result<PostInteraction, RepoError> {
+        ensure_within_rating(&self.repo, actor, id).await?;
+        self.repo.toggle_favorite(actor.id, id).await?;
+        self.repo.interaction(actor.id, id).await
+    }
+}
+
+impl<PR: PostRepository> VotePostUseCase<PR> {
+    pub async fn execute(
+        &self,
+        actor: Actor,
+        id: PostID,
+        value: i16,
+    ) -> Result<PostInteraction, RepoError> {
+        if !(-1..=1).contains(&value) {
+            return Err(RepoError::InvalidInput);
+        }
+
+        ensure_within_rating(&self.repo, actor, id).await?;
+        self.repo.vote(actor.id, id, value).await?;
+        self.repo.interaction(actor.id, id).await
+    }
+}
+
+impl<PR: PostRepository> GetPostInteractionUseCase<PR> {
+    pub async fn execute(&self, actor: Actor, id: PostID) -> Result<PostInteraction, RepoError> {
+        ensure_within_rating(&self.repo, actor, id).await?;
+        self.repo.interaction(actor.id, id).await
+    }
+}
+
+impl<PR: PostRepository> GetFavoritePostsUseCase<PR> {
+    pub async fn execute(
+        &self,
+        actor: Actor,
+        cursor: KeysetCursor,
+    ) -> Result<SearchPostsKeysetResponse, RepoError> {
+        let query = TagQuery {
+            favorited_by: Some(actor.id),
+            ratings: Some(PostRating::up_to(actor.max_rating)),
+            order: PostOrder::Favorited,
+            ..TagQuery::default()
+        };
+        self.repo.search_keyset(query, cursor).await
+    }
+}
+
+// Comment Use-Case
+pub struct CreateCommentUseCase<PR> {
+    pub repo: PR,
+}
+
+pub struct ListCommentsUseCase<PR> {
+    pub repo: PR,
+}
+
+pub struct UpdateCommentUseCase<PR> {
+    pub repo: PR,
+}
+
+pub struct DeleteCommentUseCase<PR> {
+    pub repo: PR,
+}
+
+const MAX_COMMENT_LENGTH: usize = 10_000;
+
+impl<PR: PostRepository> CreateCommentUseCase<PR> {
+    pub async fn execute(
+        &self,
+        actor: Actor,
+        post_id: PostID,
+        parent_id: Option<CommentID>,
+        body: String,
+    ) -> Result<Comment, RepoError> {
+        let body = normalize_comment_body(body)?;
+        // Nobody comments on a post they aren't allowed to see
+        let post = self.repo.get(post_id).await?;
+        if post.rating > actor.max_rating {
+            return Err(RepoError::Forbidden);
+        }
+        let id = Uuid::now_v7();
+
+        self.repo
+            .create_comment(NewComment {
+                id,
+                post_id,
+                parent_id,
+                author_id: actor.id,
+                body,
+            })
+            .await?;
+        self.repo.get_comment(id).await
+    }
+}
+
+impl<PR: PostRepository> ListCommentsUseCase<PR> {
+    // Comments follow the visibility of their post
+    pub async fn execute(
+        &self,
+        post_id: PostID,
+        parent_id: Option<CommentID>,
+        max_rating: PostRating,
+        cursor: KeysetCursor,
+    ) -> Result<CommentsResponse, RepoError> {
+        let post = self.repo.get(post_id).await?;
+        if post.rating > max_rating {
+            return Err(RepoError::Forbidden);
+        }
+        self.repo.list_comments(post_id, parent_id, cursor).await
+    }
+}
+
+impl<PR: PostRepository> UpdateCommentUseCase<PR> {
+    // Only the author may edit, moderators can only remove
+    pub async fn execute(
+        &self,
+        actor: Actor,
+        id: CommentID,
+        body: String,
+    ) -> Result<Comment, RepoError> {
+        let body = normalize_comment_body(body)?;
+        let comment = self.repo.get_comment(id).await?;
+        if comment.deleted {
+            return Err(RepoError::NotFound);
+        }
+        if comment.author_id != Some(actor.id) {
+            return Err(RepoError::Forbidden);
+        }
+
+        self.repo.update_comment(id, body).await?;
+        self.repo.get_comment(id).await
+    }
+}
+
+impl<PR: PostRepository> DeleteCommentUseCase<PR> {
+    pub async fn execute(&self, actor: Actor, id: CommentID) -> Result<(), RepoError> {
+        let comment = self.repo.get_comment(id).await?;
+        if comment.deleted {
+            return Err(RepoError::NotFound);
+        }
+        if !actor.can_delete_comment(comment.author_id) {
+            return Err(RepoError::Forbidden);
+        }
+
+        self.repo.delete_comment(id, actor.id).await?;
+        if comment.author_id != Some(actor.id) {
+            log::info!(
+                target: "audit",
+                "comment {} on post {} removed by moderator {}",
+                id,
+                comment.post_id,
+                actor.id
+            );
+        }
+        Ok(())
+    }
+}
+
+fn normalize_comment_body(body: String) -> Result<String, RepoError> {
+    let body = body.trim();
+    if body.is_empty() || body.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(RepoError::InvalidInput);
+    }
+    Ok(body.to_string())
+}
+
+// Revision Use-Case
+pub struct GetPostRevisionsUseCase<PR> {
+    pub repo: PR,
+}
+
+pub struct RollbackPostUseCase<PR> {
+    pub repo: PR,
+}
+
+impl<PR: PostRepository> GetPostRevisionsUseCase<PR> {
+    // History follows the visibility of its post
+    pub async fn execute(
+        &self,
+        post_id: PostID,
+        max_rating: PostRating,
+        cursor: KeysetCursor,
+    ) -> Result<RevisionsResponse, RepoError> {
+        let post = self.repo.get(post_id).await?;
+        if post.rating > max_rating {
+            return Err(RepoError::Forbidden);
+        }
+        self.repo.list_revisions(post_id, cursor).await
+    }
+}
+
+impl<PR: PostRepository> RollbackPostUseCase<PR> {
+    pub async fn execute(
+        &self,
+        actor: Actor,
+        post_id: PostID,
+        revision_id: RevisionID,
+    ) -> Result<(), RepoError> {
+        ensure_can_manage(&self.repo, actor, post_id).await?;
+        self.repo.rollback(post_id, revision_id, actor.id).await
+    }
+}
+
+// Trash Use-Case
+pub struct GetTrashUseCase<PR> {
+    pub repo: PR,
+}
+
+pub struct RestorePostUseCase<PR> {
+    pub repo: PR,
+}
+
+pub struct PurgeTrashUseCase<PR, FS> {
+    pub posts: PR,
+    pub storage: FS,
+}
+
+impl<PR: PostRepository> GetTrashUseCase<PR> {
+    // Moderators see everyone's trash, others only their own uploads
+    pub async fn execute(
+        &self,
+        actor: Actor,
+        cursor: KeysetCursor,
+    ) -> Result<TrashResponse, RepoError> {
+        let uploader_id = (!actor.can_moderate()).then_some(actor.id);
+        self.repo.trashed(uploader_id, cursor).await
+    }
+}
+
+impl<PR: PostRepository> RestorePostUseCase<PR> {
+    pub async fn execute(&self, actor: Actor, id: PostID) -> Result<(), RepoError> {
+        let uploader_id = self.repo.trashed_uploader_of(id).await?;
+        if !actor.can_manage_post(uploader_id) {
+            return Err(RepoError::Forbidden);
+        }
+        self.repo.restore(id).await
+    }
+}
+
+const PURGE_BATCH: i64 = 100;
+
+impl<PR: PostRepository, FS: FileStorage> PurgeTrashUseCase<PR, FS> {
+    // Rows go first, so a failed removal only leaves bytes behind on disk
+    pub async fn execute(&self, deleted_before: OffsetDateTime) -> Result<PurgeReport, RepoError> {
+        let mut total = PurgeReport::default();
+        loop {
+            let report = self
+                .posts
+                .purge_trashed(deleted_before, PURGE_BATCH)
+                .await?;
+
+            for file in &report.files {
+                for path in file.thumbnails.iter().chain([&file.path]) {
+                    match self.storage.delete(path).await {
+                        Ok(()) | Err(StorageError::NotFound) => {}
+                        Err(err) => log::warn!(
+                            "trash purge failed to remove {} of file {}: {:?}",
+                            path.display(),
+                            file.id,
+                            err
+                        ),
+                    }
+                }
+            }
+
+            total.posts += report.posts;
+            total.files.extend(report.files);
+            if report.posts < PURGE_BATCH as u64 {
+                return Ok(total);
+            }
+        }
+    }
+}
+
+// Posts above the actor's filter don't exist as far as interactions go
+async fn ensure_within_rating<PR: PostRepository>(
+    repo: &PR,
+    actor: Actor,
+    id: PostID,
+) -> Result<(), RepoError> {
+    let post = repo.get(id).await?;
+
+    if post.rating <= actor.max_rating {
+        Ok(())
+    } else {
+        Err(RepoError::NotFound)
+    }
+}
+
+async fn ensure_can_manage<PR: PostRepository>(
+    repo: &PR,
+    actor: Actor,
+    id: PostID,
+) -> Result<(), RepoError> {
+    let uploader_id = repo.uploader_of(id).await?;
+
+    if actor.can_manage_post(uploader_id) {
+        Ok(())
+    } else {
+        Err(RepoError::Forbidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::file_storage::files::LocalFileStorage;
+    use crate::storage::postgres::posts::PostgresPostRepository;
+    use crate::storage::postgres::testing::load_schema;
+    use sqlx::PgPool;
+    use time::Duration;
+
+    // Files row with the given path, its bytes written under `root`
+    async fn file(pool: &PgPool, root: &std::path::Path, path: &str) -> Uuid {
+        std::fs::write(root.join(path), path).unwrap();
+        sqlx::query_scalar("INSERT INTO files (path, media_type) VALUES ($1, 1) RETURNING id")
+            .bind(path)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn post(pool: &PgPool, file_id: Uuid, deleted_days_ago: Option<i64>) -> PostID {
+        let deleted_at =
+            deleted_days_ago.map(|days| OffsetDateTime::now_utc() - Duration::days(days));
+        sqlx::query_scalar(
+            "INSERT INTO posts (title, file_id, deleted_at) VALUES ('post', $1, $2) RETURNING id",
+        )
+        .bind(file_id)
+        .bind(deleted_at)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    async fn post_ids(pool: &PgPool) -> Vec<PostID> {
+        sqlx::query_scalar("SELECT id FROM posts ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn purge_removes_old_trash_and_the_files_only_it_used(pool: PgPool) {
+        load_schema(&pool).await;
+        let root = std::env::temp_dir().join(format!("gl-purge-{}", Uuid::now_v7()));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let alone = file(&pool, &root, "alone.jpg").await;
+        std::fs::write(root.join("alone_thumb.jpg"), "thumb").unwrap();
+        sqlx::query(
+            "INSERT INTO thumbnails (file_id, path, width, height) VALUES ($1, 'alone_thumb.jpg', 1, 1)",
+        )
+        .bind(alone)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let shared = file(&pool, &root, "shared.jpg").await;
+        let recent = file(&pool, &root, "recent.jpg").await;
+
+        let old = post(&pool, alone, Some(10)).await;
+        post(&pool, shared, Some(10)).await;
+        let live = post(&pool, shared, None).await;
+        let fresh = post(&pool, recent, Some(1)).await;
+
+        let playlist: Uuid =
+            sqlx::query_scalar("INSERT INTO playlists (title) VALUES ('list') RETURNING id")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        sqlx::query(
+            "INSERT INTO playlist_items (playlist_id, position, post_id) VALUES ($1, 0, $2)",
+        )
+        .bind(playlist)
+        .bind(old)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let purge = PurgeTrashUseCase {
+            posts: PostgresPostRepository::new(pool.clone()),
+            storage: LocalFileStorage::new(&root),
+        };
+        let report = purge
+            .execute(OffsetDateTime::now_utc() - Duration::days(7))
+            .await
+            .unwrap();
+
+        assert_eq!(report.posts, 2);
+        assert_eq!(
+            report.files.iter().map(|file| file.id).collect::<Vec<_>>(),
+            [alone]
+        );
+        let mut left = vec![live, fresh];
+        left.sort();
+        assert_eq!(post_ids(&pool).await, left);
+        assert!(!root.join("alone.jpg").exists());
+        assert!(!root.join("alone_thumb.jpg").exists());
+        assert!(root.join("shared.jpg").exists());
+        assert!(root.join("recent.jpg").exists());
+        let items: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM playlist_items")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(items, 0);
+
+        // Bytes that are already gone don't stop the rows from going
+        std::fs::remove_file(root.join("recent.jpg")).unwrap();
+        let report = purge.execute(OffsetDateTime::now_utc()).await.unwrap();
+        assert_eq!(report.posts, 1);
+        assert_eq!(
+            report.files.iter().map(|file| file.id).collect::<Vec<_>>(),
+            [recent]
+        );
+        assert_eq!(post_ids(&pool).await, [live]);
+
+        std::fs::remove_dir_all(&root).ok();
     }
 }
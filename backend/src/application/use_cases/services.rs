@@ -1,14 +1,26 @@
 use crate::application::ports::{
     FileRepository, PlaylistRepository, PostRepository, TagRepository,
 };
-use crate::application::use_cases::files::GetFileUseCase;
+use crate::application::use_cases::files::{CheckStorageUseCase, GetFileUseCase};
 use crate::application::use_cases::playlists::{
-    CreatePlaylistUseCase, DeletePlaylistUseCase, GetAllPlaylistsUseCase, GetPlaylistUseCase,
-    SearchPlaylistsUseCase, UpdatePlaylistUseCase,
+    AddPlaylistCollaboratorUseCase, AddPlaylistItemUseCase, ApplyPlaylistTagsUseCase,
+    ClearPlaylistProgressUseCase, ContinueWatchingUseCase, CreatePlaylistUseCase,
+    DeletePlaylistUseCase, DerivePlaylistTagsUseCase, DuplicatePlaylistUseCase,
+    ExportPlaylistUseCase, GetAllPlaylistsUseCase, GetPlaylistItemsUseCase, GetPlaylistUseCase,
+    GetSharedPlaylistItemsUseCase, GetSharedPlaylistUseCase, GetSmartPlaylistPostsUseCase,
+    ImportPlaylistUseCase, ListPlaylistCollaboratorsUseCase, MergePlaylistsUseCase,
+    MovePlaylistItemUseCase, RefreshPlaylistCoverUseCase, RemovePlaylistCollaboratorUseCase,
+    RemovePlaylistItemUseCase, ReorderPlaylistItemsUseCase, RevokePlaylistShareUseCase,
+    SavePlaylistProgressUseCase, SearchPlaylistsUseCase, SearchPublicPlaylistsUseCase,
+    SharePlaylistUseCase, UpdatePlaylistCollaboratorUseCase, UpdatePlaylistUseCase,
 };
 use crate::application::use_cases::posts::{
-    CreatePostUseCase, DeletePostUseCase, GetAllPostsKeysetUseCase, GetAllPostsUseCase,
-    GetPostUseCase, SearchPostsKeysetUseCase, SearchPostsUseCase, UpdatePostUseCase,
+    CreateCommentUseCase, CreatePostUseCase, DeleteCommentUseCase, DeletePostUseCase,
+    GetAllPostsKeysetUseCase, GetAllPostsUseCase, GetFavoritePostsUseCase,
+    GetPostInteractionUseCase, GetPostRevisionsUseCase, GetPostUseCase, GetTrashUseCase,
+    ListCommentsUseCase, RestorePostUseCase, RollbackPostUseCase, SearchPostsKeysetUseCase,
+    SearchPostsUseCase, TogglePostFavoriteUseCase, UpdateCommentUseCase, UpdatePostUseCase,
+    VotePostUseCase,
 };
 use crate::application::use_cases::tags::SearchTagsUseCase;
 use crate::domain::files::FileStorage;
@@ -22,17 +34,58 @@ pub struct Services<PR, PLR, TR, FR, FS> {
     pub update_post: UpdatePostUseCase<PR>,
     pub get_all_posts: GetAllPostsUseCase<PR>,
     pub get_all_posts_keyset: GetAllPostsKeysetUseCase<PR>,
+    pub toggle_post_favorite: TogglePostFavoriteUseCase<PR>,
+    pub vote_post: VotePostUseCase<PR>,
+    pub get_post_interaction: GetPostInteractionUseCase<PR>,
+    pub get_favorite_posts: GetFavoritePostsUseCase<PR>,
+    //  Comments
+    pub create_comment: CreateCommentUseCase<PR>,
+    pub list_comments: ListCommentsUseCase<PR>,
+    pub update_comment: UpdateCommentUseCase<PR>,
+    pub delete_comment: DeleteCommentUseCase<PR>,
+    //  Revisions
+    pub get_post_revisions: GetPostRevisionsUseCase<PR>,
+    pub rollback_post: RollbackPostUseCase<PR>,
+    //  Trash
+    pub get_trash: GetTrashUseCase<PR>,
+    pub restore_post: RestorePostUseCase<PR>,
     //  Playlists
     pub get_playlist: GetPlaylistUseCase<PLR>,
-    pub create_playlist: CreatePlaylistUseCase<PLR>,
+    pub create_playlist: CreatePlaylistUseCase<PLR, FR>,
     pub delete_playlist: DeletePlaylistUseCase<PLR>,
-    pub update_playlist: UpdatePlaylistUseCase<PLR>,
+    pub update_playlist: UpdatePlaylistUseCase<PLR, FR>,
+    pub refresh_playlist_cover: RefreshPlaylistCoverUseCase<PLR, FR, FS>,
     pub search_playlists: SearchPlaylistsUseCase<PLR>,
     pub get_all_playlists: GetAllPlaylistsUseCase<PLR>,
+    pub get_shared_playlist: GetSharedPlaylistUseCase<PLR>,
+    pub get_playlist_items: GetPlaylistItemsUseCase<PLR>,
+    pub get_shared_playlist_items: GetSharedPlaylistItemsUseCase<PLR>,
+    pub search_public_playlists: SearchPublicPlaylistsUseCase<PLR>,
+    pub share_playlist: SharePlaylistUseCase<PLR>,
+    pub revoke_playlist_share: RevokePlaylistShareUseCase<PLR>,
+    pub add_playlist_item: AddPlaylistItemUseCase<PLR>,
+    pub remove_playlist_item: RemovePlaylistItemUseCase<PLR>,
+    pub move_playlist_item: MovePlaylistItemUseCase<PLR>,
+    pub reorder_playlist_items: ReorderPlaylistItemsUseCase<PLR>,
+    pub export_playlist: ExportPlaylistUseCase<PLR>,
+    pub import_playlist: ImportPlaylistUseCase<PLR, PR, TR>,
+    pub save_playlist_progress: SavePlaylistProgressUseCase<PLR>,
+    pub clear_playlist_progress: ClearPlaylistProgressUseCase<PLR>,
+    pub continue_watching: ContinueWatchingUseCase<PLR>,
+    pub duplicate_playlist: DuplicatePlaylistUseCase<PLR>,
+    pub merge_playlists: MergePlaylistsUseCase<PLR>,
+    pub apply_playlist_tags: ApplyPlaylistTagsUseCase<PLR>,
+    pub derive_playlist_tags: DerivePlaylistTagsUseCase<PLR>,
+    pub list_playlist_collaborators: ListPlaylistCollaboratorsUseCase<PLR>,
+    pub add_playlist_collaborator: AddPlaylistCollaboratorUseCase<PLR>,
+    pub update_playlist_collaborator: UpdatePlaylistCollaboratorUseCase<PLR>,
+    pub remove_playlist_collaborator: RemovePlaylistCollaboratorUseCase<PLR>,
+    pub get_smart_playlist_posts: GetSmartPlaylistPostsUseCase<PLR, PR>,
     //  Tags
     pub search_tags: SearchTagsUseCase<TR>,
     //  Files
     pub get_file: GetFileUseCase<FR>,
+    pub check_storage: CheckStorageUseCase<FR, FS>,
 }
 
 impl<PR, PLR, TR, FR, FS> Services<PR, PLR, TR, FR, FS>
@@ -73,18 +126,64 @@ where
             get_all_posts_keyset: GetAllPostsKeysetUseCase {
                 repo: posts.clone(),
             },
+            toggle_post_favorite: TogglePostFavoriteUseCase {
+                repo: posts.clone(),
+            },
+            vote_post: VotePostUseCase {
+                repo: posts.clone(),
+            },
+            get_post_interaction: GetPostInteractionUseCase {
+                repo: posts.clone(),
+            },
+            get_favorite_posts: GetFavoritePostsUseCase {
+                repo: posts.clone(),
+            },
+            //  Comments
+            create_comment: CreateCommentUseCase {
+                repo: posts.clone(),
+            },
+            list_comments: ListCommentsUseCase {
+                repo: posts.clone(),
+            },
+            update_comment: UpdateCommentUseCase {
+                repo: posts.clone(),
+            },
+            delete_comment: DeleteCommentUseCase {
+                repo: posts.clone(),
+            },
+            //  Revisions
+            get_post_revisions: GetPostRevisionsUseCase {
+                repo: posts.clone(),
+            },
+            rollback_post: RollbackPostUseCase {
+                repo: posts.clone(),
+            },
+            //  Trash
+            get_trash: GetTrashUseCase {
+                repo: posts.clone(),
+            },
+            restore_post: RestorePostUseCase {
+                repo: posts.clone(),
+            },
             //  Playlist
             get_playlist: GetPlaylistUseCase {
                 repo: playlist.clone(),
             },
             create_playlist: CreatePlaylistUseCase {
-                repo: playlist.clone(),
+                playlists: playlist.clone(),
+                files: files.clone(),
             },
             delete_playlist: DeletePlaylistUseCase {
                 repo: playlist.clone(),
             },
             update_playlist: UpdatePlaylistUseCase {
-                repo: playlist.clone(),
+                playlists: playlist.clone(),
+                files: files.clone(),
+            },
+            refresh_playlist_cover: RefreshPlaylistCoverUseCase {
+                playlists: playlist.clone(),
+                files: files.clone(),
+                storage: storage.clone(),
             },
             search_playlists: SearchPlaylistsUseCase {
                 repo: playlist.clone(),
@@ -92,10 +191,88 @@ where
             get_all_playlists: GetAllPlaylistsUseCase {
                 repo: playlist.clone(),
             },
+            get_shared_playlist: GetSharedPlaylistUseCase {
+                repo: playlist.clone(),
+            },
+            get_playlist_items: GetPlaylistItemsUseCase {
+                repo: playlist.clone(),
+            },
+            get_shared_playlist_items: GetSharedPlaylistItemsUseCase {
+                repo: playlist.clone(),
+            },
+            search_public_playlists: SearchPublicPlaylistsUseCase {
+                repo: playlist.clone(),
+            },
+            share_playlist: SharePlaylistUseCase {
+                repo: playlist.clone(),
+            },
+            revoke_playlist_share: RevokePlaylistShareUseCase {
+                repo: playlist.clone(),
+            },
+            add_playlist_item: AddPlaylistItemUseCase {
+                repo: playlist.clone(),
+            },
+            remove_playlist_item: RemovePlaylistItemUseCase {
+                repo: playlist.clone(),
+            },
+            move_playlist_item: MovePlaylistItemUseCase {
+                repo: playlist.clone(),
+            },
+            reorder_playlist_items: ReorderPlaylistItemsUseCase {
+                repo: playlist.clone(),
+            },
+            export_playlist: ExportPlaylistUseCase {
+                repo: playlist.clone(),
+            },
+            import_playlist: ImportPlaylistUseCase {
+                playlists: playlist.clone(),
+                posts: posts.clone(),
+                tags: tags.clone(),
+            },
+            save_playlist_progress: SavePlaylistProgressUseCase {
+                repo: playlist.clone(),
+            },
+            clear_playlist_progress: ClearPlaylistProgressUseCase {
+                repo: playlist.clone(),
+            },
+            continue_watching: ContinueWatchingUseCase {
+                repo: playlist.clone(),
+            },
+            duplicate_playlist: DuplicatePlaylistUseCase {
+                repo: playlist.clone(),
+            },
+            merge_playlists: MergePlaylistsUseCase {
+                repo: playlist.clone(),
+            },
+            apply_playlist_tags: ApplyPlaylistTagsUseCase {
+                repo: playlist.clone(),
+            },
+            derive_playlist_tags: DerivePlaylistTagsUseCase {
+                repo: playlist.clone(),
+            },
+            list_playlist_collaborators: ListPlaylistCollaboratorsUseCase {
+                repo: playlist.clone(),
+            },
+            add_playlist_collaborator: AddPlaylistCollaboratorUseCase {
+                repo: playlist.clone(),
+            },
+            update_playlist_collaborator: UpdatePlaylistCollaboratorUseCase {
+                repo: playlist.clone(),
+            },
+            remove_playlist_collaborator: RemovePlaylistCollaboratorUseCase {
+                repo: playlist.clone(),
+            },
+            get_smart_playlist_posts: GetSmartPlaylistPostsUseCase {
+                playlists: playlist.clone(),
+                posts: posts.clone(),
+            },
             //  Tags
             search_tags: SearchTagsUseCase { repo: tags },
             //  Files
-            get_file: GetFileUseCase { repo: files },
+            get_file: GetFileUseCase {
+                repo: files.clone(),
+            },
+            check_storage: CheckStorageUseCase { files, storage },
         }
     }
 }
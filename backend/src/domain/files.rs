@@ -1,8 +1,8 @@
-use crate::domain::model::{FileID, RelativePath, StorageError};
+use crate::domain::model::{FileID, RelativePath, StorageError, StoredObject};
 use actix_web::web::Bytes;
 use async_trait::async_trait;
 use futures_util::Stream;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[async_trait]
 pub trait FileStorage {
@@ -19,4 +19,18 @@ pub trait FileStorage {
         temp_path: PathBuf,
         ext: Option<&str>,
     ) -> Result<(FileID, RelativePath), StorageError>;
+
+    async fn read(&self, path: &Path) -> Result<Vec<u8>, StorageError>;
+
+    async fn delete(&self, path: &Path) -> Result<(), StorageError>;
+
+    // Every object under the root, in no particular order
+    async fn list(&self) -> Result<Vec<StoredObject>, StorageError>;
+
+    // The returned path is relative to the root whenever the object lives under it,
+    // so it can be matched against `list`
+    async fn stat(&self, path: &Path) -> Result<StoredObject, StorageError>;
+
+    // Same fingerprint uploads record in `files.hash`, computed without loading the object whole
+    async fn digest(&self, path: &Path) -> Result<String, StorageError>;
 }
@@ -3,6 +3,7 @@ use futures_util::Stream;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::str::FromStr;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
@@ -15,6 +16,11 @@ pub type TagID = Uuid;
 pub type PlaylistID = Uuid;
 pub type PlaylistItemID = Uuid;
 pub type UserID = Uuid;
+pub type SessionID = Uuid;
+pub type ApiTokenID = Uuid;
+pub type InviteID = Uuid;
+pub type CommentID = Uuid;
+pub type RevisionID = Uuid;
 pub type RelativePath = String;
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -71,12 +77,132 @@ pub struct Post {
     pub id: PostID,
     pub title: String,
     pub description: Option<String>,
+    pub uploader_id: Option<UserID>,
     pub file: File,
     pub tags: Vec<Tag>,
     pub notes: Vec<PostNote>,
+    pub score: i32,
+    pub rating: PostRating,
+    // Comments that are not deleted, replies included
+    pub comment_count: i64,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: CommentID,
+    pub post_id: PostID,
+    // None for top-level comments
+    pub parent_id: Option<CommentID>,
+    // None once the author's account is gone
+    pub author_id: Option<UserID>,
+    pub author_name: Option<String>,
+    // None once deleted; the comment stays so its replies keep their place
+    pub body: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub edited_at: Option<OffsetDateTime>,
+    pub deleted: bool,
+    pub reply_count: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct FieldChange<T> {
+    pub old: T,
+    pub new: T,
+}
+
+// Only the fields an edit actually changed are present
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct PostChanges {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<FieldChange<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<FieldChange<Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rating: Option<FieldChange<PostRating>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<FieldChange<Vec<PostNote>>>,
+}
+
+impl PostChanges {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.rating.is_none()
+            && self.notes.is_none()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PostRevision {
+    pub id: RevisionID,
+    pub post_id: PostID,
+    // None once the editor's account is gone
+    pub editor_id: Option<UserID>,
+    pub editor_name: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub changes: PostChanges,
+    // Tags deleted since are left out
+    pub tags_added: Vec<Tag>,
+    pub tags_removed: Vec<Tag>,
+}
+
+// Ordered by how explicit the content is; viewers see everything up to their
+// `max_rating`
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PostRating {
+    Safe = 0,
+    Questionable = 1,
+    Explicit = 2,
+}
+
+impl PostRating {
+    pub const ALL: [PostRating; 3] = [
+        PostRating::Safe,
+        PostRating::Questionable,
+        PostRating::Explicit,
+    ];
+
+    pub fn up_to(max: PostRating) -> Vec<PostRating> {
+        Self::ALL
+            .into_iter()
+            .filter(|rating| *rating <= max)
+            .collect()
+    }
+}
+
+impl From<i16> for PostRating {
+    fn from(v: i16) -> Self {
+        match v {
+            0 => PostRating::Safe,
+            2 => PostRating::Explicit,
+            _ => PostRating::Questionable,
+        }
+    }
+}
+
+impl From<PostRating> for i16 {
+    fn from(v: PostRating) -> Self {
+        v as i16
+    }
+}
+
+// As written in `rating:` search terms
+impl FromStr for PostRating {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "s" | "safe" => Ok(PostRating::Safe),
+            "q" | "questionable" => Ok(PostRating::Questionable),
+            "e" | "explicit" => Ok(PostRating::Explicit),
+            other => Err(format!(
+                "unknown rating `{other}`, expected safe, questionable or explicit"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct PostNote {
     pub id: NoteID,
     pub text: String,
@@ -142,50 +268,357 @@ pub struct StoredFile {
     pub media_type: FileType,
 }
 
+// Bytes as the storage backend sees them, with paths relative to its root
+#[derive(Clone, Serialize, Debug)]
+pub struct StoredObject {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<OffsetDateTime>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub enum PlaylistVisibility {
+    #[default]
+    Private = 0,
+    Unlisted = 1,
+    Public = 2,
+}
+
+impl From<i16> for PlaylistVisibility {
+    fn from(v: i16) -> Self {
+        match v {
+            1 => PlaylistVisibility::Unlisted,
+            2 => PlaylistVisibility::Public,
+            _ => PlaylistVisibility::Private,
+        }
+    }
+}
+
+impl From<PlaylistVisibility> for i16 {
+    fn from(v: PlaylistVisibility) -> Self {
+        v as i16
+    }
+}
+
+// Ordered by power, each role can do everything the previous one can.
+// Owner is implied by `playlists.owner_id` and never stored as a collaborator.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PlaylistRole {
+    Viewer = 0,
+    Editor = 1,
+    Admin = 2,
+    Owner = 3,
+}
+
+impl From<i16> for PlaylistRole {
+    fn from(v: i16) -> Self {
+        match v {
+            1 => PlaylistRole::Editor,
+            2 => PlaylistRole::Admin,
+            3 => PlaylistRole::Owner,
+            _ => PlaylistRole::Viewer,
+        }
+    }
+}
+
+impl From<PlaylistRole> for i16 {
+    fn from(v: PlaylistRole) -> Self {
+        v as i16
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub enum SmartPlaylistSort {
+    // `should` tags first, newest within the same score
+    #[default]
+    Relevance,
+    Newest,
+}
+
+// Stored definition of a smart playlist; its posts are searched on every read
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct SmartPlaylistQuery {
+    #[serde(default)]
+    pub must: Vec<String>,
+    #[serde(default)]
+    pub should: Vec<String>,
+    #[serde(default)]
+    pub must_not: Vec<String>,
+    #[serde(default)]
+    pub sort: SmartPlaylistSort,
+    // Keeps only the first N posts (one page, so 1..=100) instead of paging
+    // through every match
+    pub limit: Option<u32>,
+}
+
+impl SmartPlaylistQuery {
+    // A top-N list is served as one keyset page
+    pub const MAX_LIMIT: u32 = 100;
+
+    pub fn has_valid_limit(&self) -> bool {
+        self.limit
+            .is_none_or(|limit| (1..=Self::MAX_LIMIT).contains(&limit))
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Playlist {
     pub id: PlaylistID,
+    pub owner_id: Option<UserID>,
     pub title: String,
     pub description: Option<String>,
     pub tags: Vec<Tag>,
+    // The chosen cover, or the derived one when nothing was chosen
     pub cover: Option<FileID>,
+    pub cover_is_auto: bool,
+    pub visibility: PlaylistVisibility,
+    // Only filled in for admins and the owner
+    pub share_token: Option<String>,
+    // Role of the viewer, None for strangers
+    pub role: Option<PlaylistRole>,
+    // Set for smart playlists, `items` are then pinned on top of the results
+    pub smart_query: Option<SmartPlaylistQuery>,
+    pub item_count: i64,
+    // First page only, the rest is loaded through the items listing
     pub items: Vec<PlaylistItem>,
+    pub has_more_items: bool,
+    // Where the viewer stopped, None for anonymous viewers or a fresh start
+    pub progress: Option<PlaylistProgress>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlaylistProgress {
+    // None once the item was removed from the playlist
+    pub item_id: Option<PlaylistItemID>,
+    // Offset inside a video or audio item, 0 for pictures and notes
+    pub position_ms: u64,
+    pub updated_at: OffsetDateTime,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PlaylistItem {
     pub id: PlaylistItemID,
     pub position: u32,
+    pub added_by: Option<UserID>,
     pub content: PlaylistContent,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlaylistCollaborator {
+    pub user_id: UserID,
+    pub username: String,
+    pub role: PlaylistRole,
+    pub invited_by: Option<UserID>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub enum PlaylistContent {
-    Post(Post),
+    Post(PostPreview),
     Note(String),
 }
 
+// Just enough of a post to render a playlist row, full post is loaded on open
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PostPreview {
+    pub id: PostID,
+    pub title: String,
+    pub file_id: FileID,
+    pub media_type: FileType,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PlaylistSummary {
     pub id: PlaylistID,
     pub title: String,
     pub description: String,
     pub cover: Option<FileID>,
+    pub cover_is_auto: bool,
+    pub visibility: PlaylistVisibility,
+    pub is_smart: bool,
     pub item_count: i64,
     pub tags: Vec<Tag>,
 }
 
+// Site-wide role, ordered by power like `PlaylistRole`
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    Viewer = 0,
+    #[default]
+    Uploader = 1,
+    Moderator = 2,
+    Admin = 3,
+}
+
+impl From<i16> for UserRole {
+    fn from(v: i16) -> Self {
+        match v {
+            1 => UserRole::Uploader,
+            2 => UserRole::Moderator,
+            3 => UserRole::Admin,
+            _ => UserRole::Viewer,
+        }
+    }
+}
+
+impl From<UserRole> for i16 {
+    fn from(v: UserRole) -> Self {
+        v as i16
+    }
+}
+
+// Who may create an account; the very first account is always allowed
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationMode {
+    Open,
+    #[default]
+    Invite,
+    Closed,
+}
+
+impl FromStr for RegistrationMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "open" => Ok(RegistrationMode::Open),
+            "invite" => Ok(RegistrationMode::Invite),
+            "closed" => Ok(RegistrationMode::Closed),
+            other => Err(format!(
+                "unknown registration mode `{other}`, expected open, invite or closed"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Invite {
+    pub id: InviteID,
+    pub created_by: Option<UserID>,
+    pub created_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+    pub used_by: Option<UserID>,
+    pub used_at: Option<OffsetDateTime>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: UserID,
     pub username: String,
     pub password_hash: String,
+    pub role: UserRole,
+    // Content filter applied to feeds unless a search asks for a rating
+    pub max_rating: PostRating,
+}
+
+// Login session as listed to its owner
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: SessionID,
+    pub created_at: OffsetDateTime,
+    pub last_seen_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+// TOTP second factor of an account
+#[derive(Clone)]
+pub struct UserTotp {
+    pub user_id: UserID,
+    // Base32, as handed to authenticator apps
+    pub secret: String,
+    pub confirmed_at: Option<OffsetDateTime>,
+}
+
+impl UserTotp {
+    pub fn is_enabled(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+}
+
+// What an API token may be used for
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    // Reading requests
+    Read = 0,
+    // Requests that create, change or delete something
+    Upload = 1,
+    // The owner's admin rights; without it a token acts as a moderator at most
+    Admin = 2,
+}
+
+impl From<i16> for TokenScope {
+    fn from(v: i16) -> Self {
+        match v {
+            1 => TokenScope::Upload,
+            2 => TokenScope::Admin,
+            _ => TokenScope::Read,
+        }
+    }
+}
+
+impl From<TokenScope> for i16 {
+    fn from(v: TokenScope) -> Self {
+        v as i16
+    }
+}
+
+// Listed to its owner; the secret itself is only shown once on creation
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: ApiTokenID,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+// Signed-in caller as seen by use cases
+#[derive(Clone, Copy, Debug)]
+pub struct Actor {
+    pub id: UserID,
+    pub role: UserRole,
+    // Authenticated with an API token rather than a session
+    pub via_token: bool,
+    pub max_rating: PostRating,
+}
+
+impl Actor {
+    pub fn can_upload(&self) -> bool {
+        self.role >= UserRole::Uploader
+    }
+
+    pub fn can_moderate(&self) -> bool {
+        self.role >= UserRole::Moderator
+    }
+
+    // Anonymous viewers only get safe posts
+    pub fn viewer_max_rating(actor: Option<&Actor>) -> PostRating {
+        actor.map_or(PostRating::Safe, |actor| actor.max_rating)
+    }
+
+    // Authors delete their own comments, moderators remove anyone's
+    pub fn can_delete_comment(&self, author_id: Option<UserID>) -> bool {
+        self.can_moderate() || author_id == Some(self.id)
+    }
+
+    // Uploaders manage their own posts, moderators manage everyone's
+    pub fn can_manage_post(&self, uploader_id: Option<UserID>) -> bool {
+        self.can_moderate() || (self.can_upload() && uploader_id == Some(self.id))
+    }
 }
 
 // Errors
 #[derive(Debug)]
 pub enum RepoError {
     NotFound,
+    Forbidden,
+    Conflict,
+    InvalidInput,
     StorageError,
 }
 #[derive(Debug)]
@@ -194,3 +627,33 @@ pub enum StorageError {
     StorageError,
     Io,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actor(role: UserRole) -> Actor {
+        Actor {
+            id: Uuid::now_v7(),
+            role,
+            via_token: false,
+            max_rating: PostRating::Safe,
+        }
+    }
+
+    #[test]
+    fn uploaders_manage_only_their_own_posts() {
+        let viewer = actor(UserRole::Viewer);
+        let uploader = actor(UserRole::Uploader);
+        let moderator = actor(UserRole::Moderator);
+
+        assert!(!viewer.can_upload());
+        assert!(!viewer.can_manage_post(Some(viewer.id)));
+        assert!(uploader.can_manage_post(Some(uploader.id)));
+        assert!(!uploader.can_manage_post(Some(moderator.id)));
+        assert!(!uploader.can_manage_post(None));
+        assert!(moderator.can_manage_post(Some(uploader.id)));
+        assert!(moderator.can_manage_post(None));
+        assert!(actor(UserRole::Admin).can_moderate());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::application::contracts::StorageCheckOptions;
+use crate::application::ports::{FileRepository, PostRepository};
+use crate::application::use_cases::files::CheckStorageUseCase;
+use crate::application::use_cases::posts::PurgeTrashUseCase;
+use crate::domain::files::FileStorage;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+const TRASH_PURGE_EVERY: Duration = Duration::from_secs(60 * 60);
+
+// Runs for the life of the process; a failed pass is retried on the next tick
+pub async fn purge_trash<PR, FS>(purge: PurgeTrashUseCase<PR, FS>, retention: time::Duration)
+where
+    PR: PostRepository,
+    FS: FileStorage + Send + Sync,
+{
+    let mut ticker = tokio::time::interval(TRASH_PURGE_EVERY);
+    loop {
+        ticker.tick().await;
+
+        match purge.execute(OffsetDateTime::now_utc() - retention).await {
+            Ok(report) if report.posts > 0 => log::info!(
+                "trash purge removed {} posts and {} files",
+                report.posts,
+                report.files.len()
+            ),
+            Ok(_) => {}
+            Err(err) => log::error!("trash purge failed: {:?}", err),
+        }
+    }
+}
+
+pub async fn check_storage<FR, FS>(
+    check: CheckStorageUseCase<FR, FS>,
+    every: Duration,
+    options: StorageCheckOptions,
+) where
+    FR: FileRepository,
+    FS: FileStorage + Send + Sync,
+{
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+
+        let report = match check.execute(options).await {
+            Ok(report) => report,
+            Err(err) => {
+                log::error!("storage check failed: {:?}", err);
+                continue;
+            }
+        };
+
+        if !report.is_clean() {
+            log::warn!(
+                "storage check found {} missing, {} untracked ({} bytes), {} unreferenced and {} mismatched files",
+                report.missing.len(),
+                report.untracked.len(),
+                report.untracked_bytes,
+                report.unreferenced.len(),
+                report.hash_mismatches.len()
+            );
+        }
+        if let Some(repairs) = report.repairs {
+            log::info!(
+                "storage check removed {} rows and {} objects ({} bytes), recorded {} hashes",
+                repairs.rows_deleted,
+                repairs.objects_deleted,
+                repairs.bytes_freed,
+                repairs.hashes_recorded
+            );
+        }
+    }
+}
@@ -1,19 +1,72 @@
+use crate::application::contracts::StorageCheckOptions;
+use crate::application::use_cases::files::CheckStorageUseCase;
+use crate::application::use_cases::posts::PurgeTrashUseCase;
+use crate::domain::model::RegistrationMode;
+use crate::storage::postgres::api_tokens::PostgresApiTokenRepository;
 use crate::storage::postgres::files::PostgresFileRepository;
 use crate::storage::postgres::playlists::PostgresPlaylistRepository;
 use crate::storage::postgres::posts::PostgresPostRepository;
+use crate::storage::postgres::sessions::PostgresSessionRepository;
 use crate::storage::postgres::tags::PostgresTagRepository;
+use crate::storage::postgres::totp::PostgresTotpRepository;
 use crate::storage::postgres::users::PostgresUserRepository;
-use crate::web::web_server;
+use crate::web::oidc::{OidcClient, OidcConfig};
+use crate::web::rate_limit::{RateLimitConfig, RateLimits, TrustedProxies};
+use crate::web::web_server::{self, PublicUrl};
 use anyhow::Context;
 use sqlx::postgres::PgPoolOptions;
 use storage::file_storage::files::LocalFileStorage;
 
 mod application;
 mod domain;
+mod jobs;
 mod logging;
 mod storage;
 mod web;
 
+fn rate_limit_from_env(name: &str, default: RateLimitConfig) -> anyhow::Result<RateLimitConfig> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse::<RateLimitConfig>()
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("{name} is invalid")),
+        Err(_) => Ok(default),
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false)
+}
+
+// SSO stays off unless an issuer is configured
+fn oidc_from_env() -> anyhow::Result<Option<OidcClient>> {
+    let Ok(issuer) = std::env::var("BACKEND_OIDC_ISSUER") else {
+        return Ok(None);
+    };
+
+    let config = OidcConfig {
+        issuer,
+        client_id: std::env::var("BACKEND_OIDC_CLIENT_ID")
+            .context("BACKEND_OIDC_CLIENT_ID is not set")?,
+        client_secret: std::env::var("BACKEND_OIDC_CLIENT_SECRET").ok(),
+        redirect_url: std::env::var("BACKEND_OIDC_REDIRECT_URL")
+            .context("BACKEND_OIDC_REDIRECT_URL is not set")?,
+        scopes: std::env::var("BACKEND_OIDC_SCOPES")
+            .unwrap_or_else(|_| "openid profile email".to_string()),
+        provision_users: std::env::var("BACKEND_OIDC_PROVISION")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(true),
+        after_login_url: std::env::var("BACKEND_OIDC_AFTER_LOGIN")
+            .unwrap_or_else(|_| "/".to_string()),
+    };
+
+    OidcClient::new(config).map(Some)
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
@@ -27,6 +80,48 @@ async fn main() -> anyhow::Result<()> {
         .unwrap_or(8080);
     let secret_key =
         std::env::var("BACKEND_COOKIE_SECRET").context("BACKEND_COOKIE_SECRET is not set")?;
+    let registration_mode = match std::env::var("BACKEND_REGISTRATION") {
+        Ok(value) => value
+            .parse::<RegistrationMode>()
+            .map_err(anyhow::Error::msg)
+            .context("BACKEND_REGISTRATION is invalid")?,
+        Err(_) => RegistrationMode::default(),
+    };
+    let defaults = RateLimits::default();
+    let rate_limits = RateLimits {
+        api: rate_limit_from_env("BACKEND_RATE_LIMIT_API", defaults.api)?,
+        auth: rate_limit_from_env("BACKEND_RATE_LIMIT_AUTH", defaults.auth)?,
+        upload: rate_limit_from_env("BACKEND_RATE_LIMIT_UPLOAD", defaults.upload)?,
+    };
+    // Forwarded headers only count when they come from one of these
+    let trusted_proxies = match std::env::var("BACKEND_TRUSTED_PROXIES") {
+        Ok(value) => value
+            .parse::<TrustedProxies>()
+            .map_err(anyhow::Error::msg)
+            .context("BACKEND_TRUSTED_PROXIES is invalid")?,
+        Err(_) => TrustedProxies::default(),
+    };
+    let public_url = match std::env::var("BACKEND_PUBLIC_URL") {
+        Ok(value) => value
+            .parse::<PublicUrl>()
+            .map_err(anyhow::Error::msg)
+            .context("BACKEND_PUBLIC_URL is invalid")?,
+        Err(_) => PublicUrl::default(),
+    };
+    let oidc = oidc_from_env()?;
+    let trash_retention_days = std::env::var("BACKEND_TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(30);
+    // 0 turns the periodic storage check off, the admin endpoint still works
+    let storage_check_hours = std::env::var("BACKEND_STORAGE_CHECK_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(24);
+    let storage_check = StorageCheckOptions {
+        fix: env_flag("BACKEND_STORAGE_CHECK_FIX"),
+        verify_hashes: env_flag("BACKEND_STORAGE_CHECK_HASHES"),
+    };
 
     log::info!("connecting to postgres");
     let pool = PgPoolOptions::new()
@@ -40,8 +135,29 @@ async fn main() -> anyhow::Result<()> {
     let file_repo = PostgresFileRepository::new(pool.clone());
     let playlist_repo = PostgresPlaylistRepository::new(pool.clone());
     let user_repo = PostgresUserRepository::new(pool.clone());
+    let session_repo = PostgresSessionRepository::new(pool.clone());
+    let token_repo = PostgresApiTokenRepository::new(pool.clone());
+    let totp_repo = PostgresTotpRepository::new(pool.clone());
     let file_storage = LocalFileStorage::new("./gl_posts");
 
+    tokio::spawn(jobs::purge_trash(
+        PurgeTrashUseCase {
+            posts: post_repo.clone(),
+            storage: file_storage.clone(),
+        },
+        time::Duration::days(trash_retention_days.into()),
+    ));
+    if storage_check_hours > 0 {
+        tokio::spawn(jobs::check_storage(
+            CheckStorageUseCase {
+                files: file_repo.clone(),
+                storage: file_storage.clone(),
+            },
+            std::time::Duration::from_secs(storage_check_hours * 60 * 60),
+            storage_check,
+        ));
+    }
+
     log::info!(
         "server startup complete, listening on http://{}:{}",
         server_ip_address,
@@ -53,7 +169,15 @@ async fn main() -> anyhow::Result<()> {
         tag_repo,
         file_repo,
         user_repo,
+        session_repo,
+        token_repo,
+        totp_repo,
         file_storage,
+        registration_mode,
+        rate_limits,
+        trusted_proxies,
+        public_url,
+        oidc,
         server_ip_address,
         server_port,
         secret_key,
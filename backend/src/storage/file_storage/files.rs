@@ -1,13 +1,16 @@
+use crate::application::helpers::content_hash::ContentHasher;
 use crate::domain::files::FileStorage;
-use crate::domain::model::{FileID, RelativePath, StorageError};
+use crate::domain::model::{FileID, RelativePath, StorageError, StoredObject};
 use actix_web::web::Bytes;
 use async_trait::async_trait;
 use futures_util::{Stream, StreamExt};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use uuid::Uuid;
 
+const DIGEST_CHUNK: usize = 64 * 1024;
+
 #[derive(Clone)]
 pub struct LocalFileStorage {
     root: PathBuf,
@@ -42,6 +45,35 @@ impl LocalFileStorage {
         }
         path
     }
+
+    // Older imports keep absolute paths, which may still point under the root
+    async fn relative_to_root(&self, path: &Path) -> PathBuf {
+        match (
+            fs::canonicalize(&self.root).await,
+            fs::canonicalize(path).await,
+        ) {
+            (Ok(root), Ok(full)) => full
+                .strip_prefix(&root)
+                .map(Path::to_path_buf)
+                .unwrap_or(full),
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+fn storage_error(err: std::io::Error) -> StorageError {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => StorageError::NotFound,
+        _ => StorageError::Io,
+    }
+}
+
+fn stored_object(path: PathBuf, meta: &std::fs::Metadata) -> StoredObject {
+    StoredObject {
+        path,
+        size: meta.len(),
+        modified: meta.modified().ok().map(Into::into),
+    }
 }
 
 #[async_trait]
@@ -68,9 +100,25 @@ impl FileStorage for LocalFileStorage {
             .await
             .map_err(|_| StorageError::Io)?;
 
-        while let Some(chunk) = stream.next().await {
-            let bytes = chunk.map_err(|_| StorageError::Io)?;
-            file.write_all(&bytes).await.map_err(|_| StorageError::Io)?;
+        let written: Result<(), StorageError> = async {
+            while let Some(chunk) = stream.next().await {
+                let bytes = chunk.map_err(|_| StorageError::Io)?;
+                file.write_all(&bytes).await.map_err(|_| StorageError::Io)?;
+            }
+            file.flush().await.map_err(|_| StorageError::Io)
+        }
+        .await;
+
+        // A broken upload must not leave a half-written file behind
+        if let Err(err) = written {
+            drop(file);
+            if let Err(remove_err) = fs::remove_file(&full_destination_path).await {
+                log::warn!(
+                    "failed to remove partial upload {}: {remove_err}",
+                    full_destination_path.display()
+                );
+            }
+            return Err(err);
         }
 
         let relative_path_string = relative_path_buf.to_string_lossy().to_string();
@@ -101,4 +149,75 @@ impl FileStorage for LocalFileStorage {
 
         Ok((id, relative_path_string))
     }
+
+    // Stored paths are relative to the root, older imports keep absolute ones
+    async fn read(&self, path: &Path) -> Result<Vec<u8>, StorageError> {
+        fs::read(self.root.join(path)).await.map_err(storage_error)
+    }
+
+    async fn delete(&self, path: &Path) -> Result<(), StorageError> {
+        fs::remove_file(self.root.join(path))
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn list(&self) -> Result<Vec<StoredObject>, StorageError> {
+        let mut objects = Vec::new();
+        let mut dirs = vec![self.root.clone()];
+
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                // Nothing has been uploaded yet
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound && dir == self.root => {
+                    return Ok(objects);
+                }
+                Err(err) => return Err(storage_error(err)),
+            };
+
+            while let Some(entry) = entries.next_entry().await.map_err(storage_error)? {
+                let meta = entry.metadata().await.map_err(storage_error)?;
+                let path = entry.path();
+                if meta.is_dir() {
+                    dirs.push(path);
+                } else if meta.is_file() {
+                    let relative = path
+                        .strip_prefix(&self.root)
+                        .map(Path::to_path_buf)
+                        .unwrap_or(path);
+                    objects.push(stored_object(relative, &meta));
+                }
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn stat(&self, path: &Path) -> Result<StoredObject, StorageError> {
+        let full_path = self.root.join(path);
+        let meta = fs::metadata(&full_path).await.map_err(storage_error)?;
+
+        let path = if path.is_absolute() {
+            self.relative_to_root(path).await
+        } else {
+            path.to_path_buf()
+        };
+        Ok(stored_object(path, &meta))
+    }
+
+    async fn digest(&self, path: &Path) -> Result<String, StorageError> {
+        let mut file = fs::File::open(self.root.join(path))
+            .await
+            .map_err(storage_error)?;
+        let mut hasher = ContentHasher::default();
+        let mut buf = vec![0u8; DIGEST_CHUNK];
+
+        loop {
+            let read = file.read(&mut buf).await.map_err(storage_error)?;
+            if read == 0 {
+                return Ok(hasher.finish());
+            }
+            hasher.update(&buf[..read]);
+        }
+    }
 }
@@ -0,0 +1,140 @@
+use crate::application::contracts::ApiTokenGrant;
+use crate::application::ports::ApiTokenRepository;
+use crate::domain::model::{ApiToken, ApiTokenID, RepoError, TokenScope, UserID};
+use async_trait::async_trait;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct PostgresApiTokenRepository {
+    pool: PgPool,
+}
+
+impl PostgresApiTokenRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn scopes_from_db(scopes: Vec<i16>) -> Vec<TokenScope> {
+    scopes.into_iter().map(TokenScope::from).collect()
+}
+
+#[async_trait]
+impl ApiTokenRepository for PostgresApiTokenRepository {
+    async fn create(
+        &self,
+        user_id: UserID,
+        name: String,
+        scopes: Vec<TokenScope>,
+        token_hash: String,
+        expires_at: OffsetDateTime,
+    ) -> Result<ApiToken, RepoError> {
+        let id = Uuid::now_v7();
+        let stored_scopes: Vec<i16> = scopes.iter().copied().map(i16::from).collect();
+
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO api_tokens (id, user_id, name, token_hash, scopes, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING created_at
+            "#,
+            id,
+            user_id,
+            name,
+            token_hash,
+            &stored_scopes,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("api_tokens.create failed for user {}: {err}", user_id);
+            RepoError::StorageError
+        })?;
+
+        Ok(ApiToken {
+            id,
+            name,
+            scopes,
+            created_at: row.created_at,
+            expires_at,
+            last_used_at: None,
+        })
+    }
+
+    async fn list_for_user(&self, user_id: UserID) -> Result<Vec<ApiToken>, RepoError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, name, scopes, created_at, expires_at, last_used_at
+            FROM api_tokens
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("api_tokens.list_for_user failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ApiToken {
+                id: row.id,
+                name: row.name,
+                scopes: scopes_from_db(row.scopes),
+                created_at: row.created_at,
+                expires_at: row.expires_at,
+                last_used_at: row.last_used_at,
+            })
+            .collect())
+    }
+
+    async fn revoke(&self, user_id: UserID, token_id: ApiTokenID) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            "DELETE FROM api_tokens WHERE id = $1 AND user_id = $2",
+            token_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("api_tokens.revoke failed for {}: {err}", token_id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn authenticate(&self, token_hash: &str) -> Result<ApiTokenGrant, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            UPDATE api_tokens
+            SET last_used_at = NOW()
+            WHERE token_hash = $1 AND expires_at > NOW()
+            RETURNING user_id, scopes
+            "#,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("api_tokens.authenticate db query failed: {err}");
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        Ok(ApiTokenGrant {
+            user_id: row.user_id,
+            scopes: scopes_from_db(row.scopes),
+        })
+    }
+}
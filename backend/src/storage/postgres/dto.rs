@@ -1,8 +1,11 @@
+use crate::application::contracts::TrashedPost;
 use crate::domain::model::{
-    File, FileID, FileMeta, PlaylistContent, PlaylistID, PlaylistItem, PlaylistItemID, Tag,
-    TagCategory, TagID,
+    Comment, CommentID, File, FileID, FileMeta, PlaylistContent, PlaylistID, PlaylistItem,
+    PlaylistItemID, PlaylistSummary, Post, PostChanges, PostID, PostRevision, RevisionID, Tag,
+    TagCategory, TagID, UserID,
 };
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use sqlx::types::Json;
 use std::path::PathBuf;
 use time::OffsetDateTime;
@@ -116,6 +119,7 @@ pub struct PlaylistItemResponse {
     pub id: PlaylistItemID,
     pub playlist_id: PlaylistID,
     pub position: u32,
+    pub added_by: Option<UserID>,
     pub content: PlaylistContent,
 }
 impl From<PlaylistItemResponse> for PlaylistItem {
@@ -123,7 +127,159 @@ impl From<PlaylistItemResponse> for PlaylistItem {
         Self {
             id: p.id,
             position: p.position,
+            added_by: p.added_by,
             content: p.content,
         }
     }
 }
+
+#[derive(Debug, FromRow)]
+pub struct PostSearchRow {
+    pub id: PostID,
+    pub title: String,
+    pub description: Option<String>,
+    pub uploader_id: Option<UserID>,
+    pub tags: Json<Vec<TagResponse>>,
+    pub file: Json<FileResponse>,
+    pub vote_score: i32,
+    pub rating: i16,
+    pub comment_count: i64,
+    pub score: i64,
+    pub full_count: i64,
+}
+
+impl From<PostSearchRow> for Post {
+    fn from(row: PostSearchRow) -> Self {
+        Self {
+            id: row.id,
+            title: row.title,
+            description: row.description,
+            uploader_id: row.uploader_id,
+            file: row.file.0.into(),
+            tags: row.tags.0.into_iter().map(Tag::from).collect(),
+            //TODO load notes
+            notes: vec![],
+            score: row.vote_score,
+            rating: row.rating.into(),
+            comment_count: row.comment_count,
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct PlaylistSearchRow {
+    pub id: PlaylistID,
+    pub title: String,
+    pub description: String,
+    pub cover: Option<FileID>,
+    pub cover_is_auto: bool,
+    pub visibility: i16,
+    pub is_smart: bool,
+    pub item_count: i64,
+    pub tags: Json<Vec<TagResponse>>,
+    pub score: i64,
+}
+
+impl From<PlaylistSearchRow> for PlaylistSummary {
+    fn from(row: PlaylistSearchRow) -> Self {
+        Self {
+            id: row.id,
+            title: row.title,
+            description: row.description,
+            cover: row.cover,
+            cover_is_auto: row.cover_is_auto,
+            visibility: row.visibility.into(),
+            is_smart: row.is_smart,
+            item_count: row.item_count,
+            tags: row.tags.0.into_iter().map(Tag::from).collect(),
+        }
+    }
+}
+
+// A playlist summary plus where the user stopped in it
+#[derive(Debug, FromRow)]
+pub struct ContinueWatchingRow {
+    #[sqlx(flatten)]
+    pub playlist: PlaylistSearchRow,
+    pub item_id: Option<PlaylistItemID>,
+    pub position_ms: i64,
+    pub updated_at: OffsetDateTime,
+}
+
+pub struct CommentRow {
+    pub id: CommentID,
+    pub post_id: PostID,
+    pub parent_id: Option<CommentID>,
+    pub author_id: Option<UserID>,
+    pub author_name: Option<String>,
+    pub body: String,
+    pub created_at: OffsetDateTime,
+    pub edited_at: Option<OffsetDateTime>,
+    pub deleted_at: Option<OffsetDateTime>,
+    pub reply_count: i64,
+}
+
+impl From<CommentRow> for Comment {
+    fn from(row: CommentRow) -> Self {
+        let deleted = row.deleted_at.is_some();
+        Self {
+            id: row.id,
+            post_id: row.post_id,
+            parent_id: row.parent_id,
+            author_id: row.author_id,
+            author_name: row.author_name,
+            body: (!deleted).then_some(row.body),
+            created_at: row.created_at,
+            edited_at: row.edited_at,
+            deleted,
+            reply_count: row.reply_count,
+        }
+    }
+}
+
+pub struct RevisionRow {
+    pub id: RevisionID,
+    pub post_id: PostID,
+    pub editor_id: Option<UserID>,
+    pub editor_name: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub changes: Json<PostChanges>,
+    pub tags_added: Json<Vec<TagResponse>>,
+    pub tags_removed: Json<Vec<TagResponse>>,
+}
+
+impl From<RevisionRow> for PostRevision {
+    fn from(row: RevisionRow) -> Self {
+        Self {
+            id: row.id,
+            post_id: row.post_id,
+            editor_id: row.editor_id,
+            editor_name: row.editor_name,
+            created_at: row.created_at,
+            changes: row.changes.0,
+            tags_added: row.tags_added.0.into_iter().map(Tag::from).collect(),
+            tags_removed: row.tags_removed.0.into_iter().map(Tag::from).collect(),
+        }
+    }
+}
+
+pub struct TrashedPostRow {
+    pub id: PostID,
+    pub title: String,
+    pub uploader_id: Option<UserID>,
+    pub deleted_at: OffsetDateTime,
+    pub deleted_by: Option<UserID>,
+    pub score: i64,
+}
+
+impl From<TrashedPostRow> for TrashedPost {
+    fn from(row: TrashedPostRow) -> Self {
+        Self {
+            id: row.id,
+            title: row.title,
+            uploader_id: row.uploader_id,
+            deleted_at: row.deleted_at,
+            deleted_by: row.deleted_by,
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::application::contracts::{FileRecord, PurgedFile};
 use crate::application::ports::FileRepository;
 use crate::domain::model::File;
 use crate::domain::model::FileID;
@@ -7,6 +8,7 @@ use crate::storage::postgres::dto::FileResponse;
 use async_trait::async_trait;
 use sqlx::PgPool;
 use sqlx::types::Json;
+use std::path::PathBuf;
 
 #[derive(Clone)]
 pub struct PostgresFileRepository {
@@ -63,13 +65,113 @@ impl FileRepository for PostgresFileRepository {
             "#,
             id
         )
-        .fetch_one(&self.pool)
+        .fetch_optional(&self.pool)
         .await
         .map_err(|e| {
             log::error!("files.get db query failed: {e}");
             RepoError::StorageError
-        })?;
+        })?
+        .ok_or(RepoError::NotFound)?;
 
         File::try_from(response).map_err(|_| RepoError::StorageError)
     }
+
+    async fn scan(&self, after: Option<FileID>, limit: i64) -> Result<Vec<FileRecord>, RepoError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                f.id,
+                f.path,
+                f.hash,
+                f.created_at,
+                (
+                    EXISTS (SELECT 1 FROM posts p WHERE p.file_id = f.id)
+                    OR EXISTS (
+                        SELECT 1 FROM playlists pl
+                        WHERE pl.cover_file_id = f.id OR pl.auto_cover_file_id = f.id
+                    )
+                ) AS "referenced!",
+                ARRAY(SELECT t.path FROM thumbnails t WHERE t.file_id = f.id) AS "thumbnails!"
+            FROM files f
+            WHERE $1::uuid IS NULL OR f.id > $1
+            ORDER BY f.id
+            LIMIT $2
+            "#,
+            after,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("files.scan db query failed: {err}");
+            RepoError::StorageError
+        })?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| FileRecord {
+                id: row.id,
+                path: PathBuf::from(row.path),
+                hash: row.hash,
+                created_at: row.created_at,
+                referenced: row.referenced,
+                thumbnails: row.thumbnails.into_iter().map(PathBuf::from).collect(),
+            })
+            .collect())
+    }
+
+    async fn set_hash(&self, id: FileID, hash: String) -> Result<(), RepoError> {
+        let result = sqlx::query!("UPDATE files SET hash = $2 WHERE id = $1", id, hash)
+            .execute(&self.pool)
+            .await
+            .map_err(|err| {
+                log::error!("files.set_hash db query failed: {err}");
+                RepoError::StorageError
+            })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn delete_unreferenced(&self, ids: Vec<FileID>) -> Result<Vec<PurgedFile>, RepoError> {
+        // Thumbnails go with their file (ON DELETE CASCADE), so their paths
+        // are read from the snapshot the statement started with
+        let rows = sqlx::query!(
+            r#"
+            WITH gone AS (
+                DELETE FROM files f
+                WHERE f.id = ANY($1)
+                    AND NOT EXISTS (SELECT 1 FROM posts p WHERE p.file_id = f.id)
+                    AND NOT EXISTS (
+                        SELECT 1 FROM playlists pl
+                        WHERE pl.cover_file_id = f.id OR pl.auto_cover_file_id = f.id
+                    )
+                RETURNING f.id, f.path
+            )
+            SELECT
+                gone.id,
+                gone.path,
+                ARRAY(SELECT t.path FROM thumbnails t WHERE t.file_id = gone.id) AS "thumbnails!"
+            FROM gone
+            "#,
+            &ids
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("files.delete_unreferenced db query failed: {err}");
+            RepoError::StorageError
+        })?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| PurgedFile {
+                id: row.id,
+                path: PathBuf::from(row.path),
+                thumbnails: row.thumbnails.into_iter().map(PathBuf::from).collect(),
+            })
+            .collect())
+    }
 }
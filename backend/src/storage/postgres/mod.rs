@@ -1,6 +1,12 @@
+pub mod api_tokens;
 mod dto;
 pub mod files;
 pub mod playlists;
 pub mod posts;
+mod query;
+pub mod sessions;
 pub mod tags;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod totp;
 pub mod users;
@@ -1,17 +1,24 @@
 use crate::application::contracts::{
-    KeysetCursor, KeysetDirection, KeysetPageCursor, NewPlaylist, NewPlaylistItemContent,
-    PaginationMode, PlaylistQuery, SearchPlaylistsResponse, UpdatePlaylist,
+    AddPlaylistItem, BundleItem, ContinueWatchingEntry, CurationResult, KeysetCursor,
+    KeysetDirection, NewPlaylist, NewPlaylistCollaborator, NewPlaylistItemContent, NewTag,
+    PlaylistBundle, PlaylistCoverSources, PlaylistItemsResponse, PlaylistQuery, PlaylistShare,
+    SearchPlaylistsResponse, UpdatePlaylist, UpdatePlaylistProgress,
 };
 use crate::application::ports::PlaylistRepository;
 use crate::domain::model::{
-    Playlist, PlaylistContent, PlaylistID, PlaylistItem, PlaylistSummary, Post, RepoError, Tag,
-    UserID,
+    FileID, Playlist, PlaylistCollaborator, PlaylistContent, PlaylistID, PlaylistItem,
+    PlaylistItemID, PlaylistProgress, PlaylistRole, PlaylistSummary, PlaylistVisibility, PostID,
+    PostPreview, PostRating, RepoError, SmartPlaylistQuery, Tag, TagCategory, UserID,
+};
+use crate::storage::postgres::dto::{ContinueWatchingRow, PlaylistSearchRow, TagResponse};
+use crate::storage::postgres::query::{
+    KeysetArgs, SearchQuery, SearchTarget, TagFilter, build_keyset_page,
 };
-use crate::storage::postgres::dto::{FileResponse, TagResponse};
 use async_trait::async_trait;
-use serde::Deserialize;
-use sqlx::PgPool;
 use sqlx::types::Json;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use uuid::Uuid;
 
 #[derive(Clone)]
@@ -19,26 +26,48 @@ pub struct PostgresPlaylistRepository {
     pool: PgPool,
 }
 
-#[derive(Debug, Deserialize)]
-struct PlaylistItemPayload {
+#[derive(Debug)]
+struct PlaylistItemRow {
     id: Uuid,
     position: i32,
-    post: Option<PlaylistPostPayload>,
-    note: Option<String>,
+    added_by: Option<Uuid>,
+    note_text: Option<String>,
+    post_id: Option<Uuid>,
+    post_title: Option<String>,
+    file_id: Option<Uuid>,
+    media_type: Option<i16>,
 }
 
-#[derive(Debug, Deserialize)]
-struct PlaylistPostPayload {
-    id: Uuid,
-    title: String,
-    description: Option<String>,
-    file: FileResponse,
-    tags: Vec<TagResponse>,
+impl From<PlaylistItemRow> for PlaylistItem {
+    fn from(row: PlaylistItemRow) -> Self {
+        let post = match (row.post_id, row.post_title, row.file_id, row.media_type) {
+            (Some(id), Some(title), Some(file_id), Some(media_type)) => Some(PostPreview {
+                id,
+                title,
+                file_id,
+                media_type: media_type.into(),
+            }),
+            _ => None,
+        };
+
+        Self {
+            id: row.id,
+            position: row.position.max(0) as u32,
+            added_by: row.added_by,
+            content: match post {
+                Some(post) => PlaylistContent::Post(post),
+                None => PlaylistContent::Note(row.note_text.unwrap_or_default()),
+            },
+        }
+    }
 }
 
 impl PostgresPlaylistRepository {
     const DEFAULT_KEYSET_LIMIT: i64 = 30;
     const MAX_KEYSET_LIMIT: i64 = 100;
+    const DEFAULT_ITEMS_LIMIT: i64 = 50;
+    // Spacing between neighbouring item positions, leaves room for inserts
+    const POSITION_GAP: i32 = 1024;
 
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
@@ -51,89 +80,544 @@ impl PostgresPlaylistRepository {
             .clamp(1, Self::MAX_KEYSET_LIMIT)
     }
 
-    fn build_keyset_response(
-        mut entries: Vec<(PlaylistSummary, f64)>,
-        limit: i64,
-        direction: KeysetDirection,
-        use_cursor: bool,
-    ) -> SearchPlaylistsResponse {
-        let has_more_in_direction = entries.len() as i64 > limit;
-        if has_more_in_direction {
-            entries.truncate(limit as usize);
-        }
+    fn resolve_items_limit(cursor: &KeysetCursor) -> i64 {
+        cursor
+            .limit
+            .unwrap_or(Self::DEFAULT_ITEMS_LIMIT)
+            .clamp(1, Self::MAX_KEYSET_LIMIT)
+    }
 
-        if matches!(direction, KeysetDirection::Prev) {
-            entries.reverse();
-        }
+    async fn keyset_page(
+        &self,
+        query: SearchQuery,
+        keyset: KeysetArgs,
+        context: &str,
+    ) -> Result<SearchPlaylistsResponse, RepoError> {
+        let rows = query
+            .keyset(&keyset)
+            .build()
+            .build_query_as::<PlaylistSearchRow>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| {
+                log::error!("{context} db query failed: {err}");
+                RepoError::StorageError
+            })?;
 
-        let has_next = if matches!(direction, KeysetDirection::Next) {
-            has_more_in_direction
-        } else {
-            use_cursor
-        };
-        let has_prev = if matches!(direction, KeysetDirection::Prev) {
-            has_more_in_direction
+        let entries = rows
+            .into_iter()
+            .map(|row| {
+                let score = row.score as f64;
+                (PlaylistSummary::from(row), score)
+            })
+            .collect();
+
+        let page = build_keyset_page(entries, |playlist: &PlaylistSummary| playlist.id, &keyset);
+
+        Ok(SearchPlaylistsResponse {
+            playlists: page.entries,
+            has_next: page.has_next,
+            has_prev: page.has_prev,
+            next_cursor: page.next_cursor,
+            prev_cursor: page.prev_cursor,
+        })
+    }
+
+    // Loads a playlist without any access check; callers decide who may see it
+    async fn fetch(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+    ) -> Result<Playlist, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                pl.id,
+                pl.owner_id,
+                pl.title,
+                pl.description,
+                COALESCE(pl.cover_file_id, pl.auto_cover_file_id) AS cover,
+                (pl.cover_file_id IS NULL AND pl.auto_cover_file_id IS NOT NULL) AS "cover_is_auto!",
+                pl.visibility,
+                pl.share_token,
+                pl.smart_query AS "smart_query: Json<SmartPlaylistQuery>",
+                CASE
+                    WHEN pl.owner_id = $2 THEN 3::smallint
+                    ELSE (
+                        SELECT c.role
+                        FROM playlist_collaborators c
+                        WHERE c.playlist_id = pl.id AND c.user_id = $2
+                    )
+                END AS role,
+                COALESCE(
+                    (
+                        SELECT jsonb_agg(
+                            jsonb_build_object(
+                                'id', t.id,
+                                'name', t.name,
+                                'category', t.category,
+                                'count', t.post_count
+                            )
+                            ORDER BY t.name
+                        )
+                        FROM playlist_tags pt
+                        JOIN tags t ON t.id = pt.tag_id
+                        WHERE pt.playlist_id = pl.id
+                    ),
+                    '[]'::jsonb
+                ) AS "tags!: Json<Vec<TagResponse>>",
+                (
+                    SELECT COUNT(*)
+                    FROM playlist_items pi
+                    WHERE pi.playlist_id = pl.id
+                ) AS "item_count!",
+                pp.item_id AS "progress_item_id?",
+                pp.position_ms AS "progress_position_ms?",
+                pp.updated_at AS "progress_updated_at?"
+            FROM playlists pl
+            LEFT JOIN playlist_progress pp ON pp.playlist_id = pl.id AND pp.user_id = $2
+            WHERE pl.id = $1
+            "#,
+            playlist_id,
+            viewer_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.fetch failed to load playlist {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        let row = row.ok_or(RepoError::NotFound)?;
+
+        let role = row.role.map(PlaylistRole::from);
+        let can_share = role.is_some_and(|role| role >= PlaylistRole::Admin);
+
+        let first_page = self
+            .items_page(
+                playlist_id,
+                max_rating,
+                KeysetArgs::from_cursor(&KeysetCursor::default(), Self::DEFAULT_ITEMS_LIMIT, true),
+            )
+            .await?;
+
+        Ok(Playlist {
+            id: playlist_id,
+            owner_id: row.owner_id,
+            title: row.title,
+            description: row.description,
+            tags: row.tags.0.into_iter().map(Tag::from).collect(),
+            cover: row.cover,
+            cover_is_auto: row.cover_is_auto,
+            visibility: row.visibility.into(),
+            share_token: row.share_token.filter(|_| can_share),
+            role,
+            smart_query: row.smart_query.map(|query| query.0),
+            item_count: row.item_count,
+            items: first_page.items,
+            has_more_items: first_page.has_next,
+            progress: row.progress_updated_at.map(|updated_at| PlaylistProgress {
+                item_id: row.progress_item_id,
+                position_ms: row.progress_position_ms.unwrap_or_default().max(0) as u64,
+                updated_at,
+            }),
+        })
+    }
+
+    // Locks the playlist row for the rest of the transaction so concurrent
+    // edits can't hand out the same position, and checks the caller's role.
+    // Users without any access get NotFound so private playlists stay hidden.
+    async fn lock_for(
+        conn: &mut PgConnection,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        required: PlaylistRole,
+        context: &str,
+    ) -> Result<PlaylistRole, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT pl.owner_id, c.role AS "role?"
+            FROM playlists pl
+            LEFT JOIN playlist_collaborators c
+                ON c.playlist_id = pl.id AND c.user_id = $2
+            WHERE pl.id = $1
+            FOR UPDATE OF pl
+            "#,
+            playlist_id,
+            user_id
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|err| {
+            log::error!("{context} failed to lock playlist {}: {err}", playlist_id);
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        let role = if row.owner_id == Some(user_id) {
+            PlaylistRole::Owner
         } else {
-            use_cursor
+            row.role
+                .map(PlaylistRole::from)
+                .ok_or(RepoError::NotFound)?
         };
 
-        let next_cursor = if has_next {
-            entries.last().map(|(playlist, score)| KeysetPageCursor {
-                mode: PaginationMode::Keyset,
-                direction: KeysetDirection::Next,
-                last_id: playlist.id,
-                last_score: *score,
-                limit,
-            })
+        if role < required {
+            return Err(RepoError::Forbidden);
+        }
+
+        sqlx::query!(
+            "UPDATE playlists SET updated_at = NOW() WHERE id = $1",
+            playlist_id
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|err| {
+            log::error!("{context} failed to touch playlist {}: {err}", playlist_id);
+            RepoError::StorageError
+        })?;
+
+        Ok(role)
+    }
+
+    // Public playlists are visible to everyone, the rest only to members
+    async fn ensure_visible<'e, E: PgExecutor<'e>>(
+        executor: E,
+        viewer_id: Option<UserID>,
+        playlist_id: PlaylistID,
+        context: &str,
+    ) -> Result<(), RepoError> {
+        sqlx::query!(
+            r#"
+            SELECT pl.id
+            FROM playlists pl
+            WHERE pl.id = $1
+                AND (
+                    pl.visibility = 2
+                    OR pl.owner_id = $2
+                    OR EXISTS (
+                        SELECT 1
+                        FROM playlist_collaborators c
+                        WHERE c.playlist_id = pl.id AND c.user_id = $2
+                    )
+                )
+            "#,
+            playlist_id,
+            viewer_id
+        )
+        .fetch_optional(executor)
+        .await
+        .map_err(|err| {
+            log::error!("{context} failed to check access to {}: {err}", playlist_id);
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        Ok(())
+    }
+
+    // Same as `lock_for` for read-only calls: no row lock, no timestamp bump
+    async fn role_of(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        context: &str,
+    ) -> Result<PlaylistRole, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT pl.owner_id, c.role AS "role?"
+            FROM playlists pl
+            LEFT JOIN playlist_collaborators c
+                ON c.playlist_id = pl.id AND c.user_id = $2
+            WHERE pl.id = $1
+            "#,
+            playlist_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("{context} failed to load role in {}: {err}", playlist_id);
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        if row.owner_id == Some(user_id) {
+            return Ok(PlaylistRole::Owner);
+        }
+
+        row.role.map(PlaylistRole::from).ok_or(RepoError::NotFound)
+    }
+
+    // A member can only hand out or take away roles below their own,
+    // so admins manage viewers and editors and only the owner manages admins
+    fn check_can_manage(
+        caller: PlaylistRole,
+        current: Option<PlaylistRole>,
+        new: Option<PlaylistRole>,
+    ) -> Result<(), RepoError> {
+        let target = current.max(new).unwrap_or(PlaylistRole::Viewer);
+        if caller < PlaylistRole::Admin || caller <= target {
+            return Err(RepoError::Forbidden);
+        }
+
+        Ok(())
+    }
+
+    async fn collaborator_role(
+        conn: &mut PgConnection,
+        playlist_id: PlaylistID,
+        collaborator_id: UserID,
+        context: &str,
+    ) -> Result<Option<(PlaylistRole, String)>, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT c.role, u.username
+            FROM playlist_collaborators c
+            JOIN users u ON u.id = c.user_id
+            WHERE c.playlist_id = $1 AND c.user_id = $2
+            "#,
+            playlist_id,
+            collaborator_id
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "{context} failed to load collaborator {} of {}: {err}",
+                collaborator_id,
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(row.map(|row| (PlaylistRole::from(row.role), row.username)))
+    }
+
+    // Position for a new item at `index`, halfway between its neighbours.
+    // `None` when the neighbours are adjacent and the playlist needs renumbering.
+    async fn free_position(
+        conn: &mut PgConnection,
+        playlist_id: PlaylistID,
+        index: u32,
+        exclude: Option<PlaylistItemID>,
+    ) -> Result<Option<i32>, RepoError> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM playlist_items
+            WHERE playlist_id = $1 AND ($2::uuid IS NULL OR id <> $2)
+            "#,
+            playlist_id,
+            exclude
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.free_position failed to count items of {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        let index = i64::from(index).min(count);
+
+        let neighbours = sqlx::query_scalar!(
+            r#"
+            SELECT position
+            FROM playlist_items
+            WHERE playlist_id = $1 AND ($2::uuid IS NULL OR id <> $2)
+            ORDER BY position, id
+            OFFSET $3
+            LIMIT 2
+            "#,
+            playlist_id,
+            exclude,
+            (index - 1).max(0)
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.free_position failed to load neighbours in {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        let (prev, next) = if index == 0 {
+            (None, neighbours.first().copied())
         } else {
-            None
+            (neighbours.first().copied(), neighbours.get(1).copied())
         };
 
-        let prev_cursor = if has_prev {
-            entries.first().map(|(playlist, score)| KeysetPageCursor {
-                mode: PaginationMode::Keyset,
-                direction: KeysetDirection::Prev,
-                last_id: playlist.id,
-                last_score: *score,
-                limit,
+        let lower = prev.unwrap_or(0);
+        Ok(match next {
+            None => lower.checked_add(Self::POSITION_GAP),
+            Some(upper) if upper - lower > 1 => Some(lower + (upper - lower) / 2),
+            Some(_) => None,
+        })
+    }
+
+    async fn renumber_items(
+        conn: &mut PgConnection,
+        playlist_id: PlaylistID,
+    ) -> Result<(), RepoError> {
+        sqlx::query!(
+            r#"
+            UPDATE playlist_items pi
+            SET position = (r.rn * $2)::int
+            FROM (
+                SELECT id, row_number() OVER (ORDER BY position, id) AS rn
+                FROM playlist_items
+                WHERE playlist_id = $1
+            ) r
+            WHERE pi.id = r.id
+            "#,
+            playlist_id,
+            i64::from(Self::POSITION_GAP)
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|err| {
+            log::error!("playlists.renumber_items failed for {}: {err}", playlist_id);
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn position_at(
+        conn: &mut PgConnection,
+        playlist_id: PlaylistID,
+        index: u32,
+        exclude: Option<PlaylistItemID>,
+    ) -> Result<i32, RepoError> {
+        if let Some(position) = Self::free_position(conn, playlist_id, index, exclude).await? {
+            return Ok(position);
+        }
+
+        Self::renumber_items(conn, playlist_id).await?;
+
+        Self::free_position(conn, playlist_id, index, exclude)
+            .await?
+            .ok_or_else(|| {
+                log::error!(
+                    "playlists.position_at no free position in {} after renumbering",
+                    playlist_id
+                );
+                RepoError::StorageError
             })
-        } else {
-            None
+    }
+
+    // Keyset page over (position, id); the position doubles as the cursor score
+    // Posts rated above `max_rating` are left out; notes always show
+    async fn items_page(
+        &self,
+        playlist_id: PlaylistID,
+        max_rating: PostRating,
+        keyset: KeysetArgs,
+    ) -> Result<PlaylistItemsResponse, RepoError> {
+        let (last_position, last_id) = match keyset.after {
+            Some((position, id)) => (Some(position as i32), Some(id)),
+            None => (None, None),
         };
+        let limit = keyset.limit + 1;
 
-        SearchPlaylistsResponse {
-            playlists: entries.into_iter().map(|(playlist, _)| playlist).collect(),
-            has_next,
-            has_prev,
-            next_cursor,
-            prev_cursor,
+        let rows = match keyset.direction {
+            KeysetDirection::Next => {
+                sqlx::query_as!(
+                    PlaylistItemRow,
+                    r#"
+                    SELECT
+                        pi.id,
+                        pi.position,
+                        pi.added_by,
+                        pi.note_text,
+                        p.id AS "post_id?",
+                        p.title AS "post_title?",
+                        f.id AS "file_id?",
+                        f.media_type AS "media_type?"
+                    FROM playlist_items pi
+                    LEFT JOIN posts p ON p.id = pi.post_id
+                    LEFT JOIN files f ON f.id = p.file_id
+                    WHERE pi.playlist_id = $1
+                        AND ($2::int IS NULL OR (pi.position, pi.id) > ($2, $3))
+                        AND (p.id IS NULL OR (p.rating <= $5 AND p.deleted_at IS NULL))
+                    ORDER BY pi.position, pi.id
+                    LIMIT $4
+                    "#,
+                    playlist_id,
+                    last_position,
+                    last_id,
+                    limit,
+                    i16::from(max_rating)
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+            KeysetDirection::Prev => {
+                sqlx::query_as!(
+                    PlaylistItemRow,
+                    r#"
+                    SELECT
+                        pi.id,
+                        pi.position,
+                        pi.added_by,
+                        pi.note_text,
+                        p.id AS "post_id?",
+                        p.title AS "post_title?",
+                        f.id AS "file_id?",
+                        f.media_type AS "media_type?"
+                    FROM playlist_items pi
+                    LEFT JOIN posts p ON p.id = pi.post_id
+                    LEFT JOIN files f ON f.id = p.file_id
+                    WHERE pi.playlist_id = $1
+                        AND ($2::int IS NULL OR (pi.position, pi.id) < ($2, $3))
+                        AND (p.id IS NULL OR (p.rating <= $5 AND p.deleted_at IS NULL))
+                    ORDER BY pi.position DESC, pi.id DESC
+                    LIMIT $4
+                    "#,
+                    playlist_id,
+                    last_position,
+                    last_id,
+                    limit,
+                    i16::from(max_rating)
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
         }
-    }
+        .map_err(|err| {
+            log::error!(
+                "playlists.items_page failed to load items of {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
 
-    fn map_playlist_items(items: Vec<PlaylistItemPayload>) -> Vec<PlaylistItem> {
-        items
+        let entries = rows
             .into_iter()
-            .map(|item| {
-                let content = match item.post {
-                    Some(post) => PlaylistContent::Post(Post {
-                        id: post.id,
-                        title: post.title,
-                        description: post.description,
-                        tags: post.tags.into_iter().map(Tag::from).collect(),
-                        file: post.file.into(),
-                        //TODO load notes
-                        notes: vec![],
-                    }),
-                    None => PlaylistContent::Note(item.note.unwrap_or_default()),
-                };
-
-                PlaylistItem {
-                    id: item.id,
-                    position: item.position.max(0) as u32,
-                    content,
-                }
+            .map(|row| {
+                let position = f64::from(row.position);
+                (PlaylistItem::from(row), position)
             })
-            .collect()
+            .collect();
+
+        let page = build_keyset_page(entries, |item: &PlaylistItem| item.id, &keyset);
+
+        Ok(PlaylistItemsResponse {
+            items: page.entries,
+            has_next: page.has_next,
+            has_prev: page.has_prev,
+            next_cursor: page.next_cursor,
+            prev_cursor: page.prev_cursor,
+        })
     }
 }
 
@@ -153,14 +637,17 @@ impl PlaylistRepository for PostgresPlaylistRepository {
 
         sqlx::query!(
             r#"
-            INSERT INTO playlists (id, title, description, cover_file_id, owner_id)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO playlists
+                (id, title, description, cover_file_id, owner_id, visibility, smart_query)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
             "#,
             playlist_id,
             new_playlist.title,
             new_playlist.description,
             new_playlist.cover,
-            user_id
+            user_id,
+            i16::from(new_playlist.visibility.unwrap_or_default()),
+            new_playlist.smart_query.map(Json) as Option<Json<SmartPlaylistQuery>>
         )
         .execute(&mut *tx)
         .await
@@ -209,13 +696,14 @@ impl PlaylistRepository for PostgresPlaylistRepository {
                     NewPlaylistItemContent::Post { post_id } => {
                         sqlx::query!(
                             r#"
-                            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text)
-                            VALUES ($1, $2, $3, $4, NULL)
+                            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text, added_by)
+                            VALUES ($1, $2, $3, $4, NULL, $5)
                             "#,
                             item_id,
                             playlist_id,
                             position,
-                            post_id
+                            post_id,
+                            user_id
                         )
                         .execute(&mut *tx)
                         .await
@@ -231,13 +719,14 @@ impl PlaylistRepository for PostgresPlaylistRepository {
                     NewPlaylistItemContent::Note { text } => {
                         sqlx::query!(
                             r#"
-                            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text)
-                            VALUES ($1, $2, $3, NULL, $4)
+                            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text, added_by)
+                            VALUES ($1, $2, $3, NULL, $4, $5)
                             "#,
                             item_id,
                             playlist_id,
                             position,
-                            text
+                            text,
+                            user_id
                         )
                         .execute(&mut *tx)
                         .await
@@ -265,107 +754,135 @@ impl PlaylistRepository for PostgresPlaylistRepository {
         Ok(playlist_id)
     }
 
-    async fn get(&self, user_id: UserID, playlist_id: PlaylistID) -> Result<Playlist, RepoError> {
-        let row = sqlx::query!(
-            r#"
-            SELECT
-                pl.id,
-                pl.title,
-                pl.description,
-                pl.cover_file_id AS cover,
-                COALESCE(
-                    (
-                        SELECT jsonb_agg(
-                            jsonb_build_object(
-                                'id', t.id,
-                                'name', t.name,
-                                'category', t.category,
-                                'count', t.post_count
-                            )
-                            ORDER BY t.name
-                        )
-                        FROM playlist_tags pt
-                        JOIN tags t ON t.id = pt.tag_id
-                        WHERE pt.playlist_id = pl.id
-                    ),
-                    '[]'::jsonb
-                ) AS "tags!: Json<Vec<TagResponse>>",
-                COALESCE(
-                    (
-                        SELECT jsonb_agg(item ORDER BY (item->>'position')::int)
-                        FROM (
-                            SELECT jsonb_build_object(
-                                'id', pi.id,
-                                'position', pi.position,
-                                'post',
-                                CASE
-                                    WHEN pi.post_id IS NULL THEN NULL
-                                    ELSE jsonb_build_object(
-                                        'id', p.id,
-                                        'title', p.title,
-                                        'description', p.description,
-                                        'file', jsonb_build_object(
-                                            'id', f.id,
-                                            'path', f.path,
-                                            'hash', f.hash,
-                                            'media_type', f.media_type,
-                                            'meta', f.meta,
-                                            'created_at', f.created_at
-                                        ),
-                                        'tags', COALESCE(
-                                            (
-                                                SELECT jsonb_agg(
-                                                    jsonb_build_object(
-                                                        'id', t2.id,
-                                                        'name', t2.name,
-                                                        'category', t2.category,
-                                                        'count', t2.post_count
-                                                    )
-                                                    ORDER BY t2.name
-                                                )
-                                                FROM post_tags ptt
-                                                JOIN tags t2 ON t2.id = ptt.tag_id
-                                                WHERE ptt.post_id = p.id
-                                            ),
-                                            '[]'::jsonb
-                                        )
-                                    )
-                                END,
-                                'note', pi.note_text
-                            ) AS item
-                            FROM playlist_items pi
-                            LEFT JOIN posts p ON p.id = pi.post_id
-                            LEFT JOIN files f ON f.id = p.file_id
-                            WHERE pi.playlist_id = pl.id
-                        ) raw_items
-                    ),
-                    '[]'::jsonb
-                ) AS "items!: Json<Vec<PlaylistItemPayload>>"
+    async fn get(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+    ) -> Result<Playlist, RepoError> {
+        let playlist = self.fetch(viewer_id, max_rating, playlist_id).await?;
+
+        if playlist.role.is_none() && playlist.visibility != PlaylistVisibility::Public {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(playlist)
+    }
+
+    async fn get_shared(
+        &self,
+        share_token: &str,
+        max_rating: PostRating,
+    ) -> Result<Playlist, RepoError> {
+        let row = sqlx::query!(
+            "SELECT id FROM playlists WHERE share_token = $1 AND visibility <> 0",
+            share_token
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("playlists.get_shared failed to resolve share token: {err}");
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        self.fetch(None, max_rating, row.id).await
+    }
+
+    async fn get_items(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+        cursor: KeysetCursor,
+    ) -> Result<PlaylistItemsResponse, RepoError> {
+        Self::ensure_visible(&self.pool, viewer_id, playlist_id, "playlists.get_items").await?;
+
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_items_limit(&cursor), true);
+        self.items_page(playlist_id, max_rating, keyset).await
+    }
+
+    async fn get_shared_items(
+        &self,
+        share_token: &str,
+        max_rating: PostRating,
+        cursor: KeysetCursor,
+    ) -> Result<PlaylistItemsResponse, RepoError> {
+        let row = sqlx::query!(
+            "SELECT id FROM playlists WHERE share_token = $1 AND visibility <> 0",
+            share_token
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("playlists.get_shared_items failed to resolve share token: {err}");
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_items_limit(&cursor), true);
+        self.items_page(row.id, max_rating, keyset).await
+    }
+
+    async fn get_smart_query(
+        &self,
+        viewer_id: Option<UserID>,
+        playlist_id: PlaylistID,
+    ) -> Result<SmartPlaylistQuery, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT pl.smart_query AS "smart_query: Json<SmartPlaylistQuery>"
             FROM playlists pl
-            WHERE pl.id = $1 AND pl.owner_id = $2
+            WHERE pl.id = $1
+                AND (
+                    pl.visibility = 2
+                    OR pl.owner_id = $2
+                    OR EXISTS (
+                        SELECT 1
+                        FROM playlist_collaborators c
+                        WHERE c.playlist_id = pl.id AND c.user_id = $2
+                    )
+                )
             "#,
             playlist_id,
-            user_id
+            viewer_id
         )
         .fetch_optional(&self.pool)
         .await
         .map_err(|err| {
             log::error!(
-                "playlists.get failed to fetch playlist {}: {err}",
+                "playlists.get_smart_query failed for {}: {err}",
                 playlist_id
             );
             RepoError::StorageError
-        })?;
+        })?
+        .ok_or(RepoError::NotFound)?;
 
-        let row = row.ok_or(RepoError::NotFound)?;
+        // Manual playlists have no query to resolve
+        row.smart_query
+            .map(|query| query.0)
+            .ok_or(RepoError::NotFound)
+    }
 
-        Ok(Playlist {
-            id: playlist_id,
-            title: row.title,
-            description: row.description,
-            tags: row.tags.0.into_iter().map(Tag::from).collect(),
-            cover: row.cover,
-            items: Self::map_playlist_items(row.items.0),
+    async fn pinned_post_ids(&self, playlist_id: PlaylistID) -> Result<Vec<PostID>, RepoError> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT post_id AS "post_id!"
+            FROM playlist_items
+            WHERE playlist_id = $1 AND post_id IS NOT NULL
+            GROUP BY post_id
+            ORDER BY MIN(position)
+            "#,
+            playlist_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.pinned_post_ids failed for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
         })
     }
 
@@ -383,45 +900,42 @@ impl PlaylistRepository for PostgresPlaylistRepository {
             RepoError::StorageError
         })?;
 
-        let exists = sqlx::query!(
-            "SELECT id FROM playlists WHERE id = $1 AND owner_id = $2",
-            playlist_id,
-            user_id
-        )
-        .fetch_optional(&mut *tx)
-        .await
-        .map_err(|err| {
-            log::error!(
-                "playlists.update failed to check playlist {} ownership for {}: {err}",
-                playlist_id,
-                user_id
-            );
-            RepoError::StorageError
-        })?;
-
-        if exists.is_none() {
-            return Err(RepoError::NotFound);
-        }
+        // Visibility decides who can see the playlist, so it is an admin call
+        let required = if update_playlist.visibility.is_some() {
+            PlaylistRole::Admin
+        } else {
+            PlaylistRole::Editor
+        };
+        Self::lock_for(&mut tx, user_id, playlist_id, required, "playlists.update").await?;
 
         if update_playlist.title.is_some()
             || update_playlist.description.is_some()
             || update_playlist.cover.is_some()
+            || update_playlist.visibility.is_some()
+            || update_playlist.smart_query.is_some()
         {
+            // Making a playlist private also drops its share link
             sqlx::query!(
                 r#"
                 UPDATE playlists
                 SET
-                    title = COALESCE($3, title),
-                    description = COALESCE($4, description),
-                    cover_file_id = COALESCE($5, cover_file_id),
+                    title = COALESCE($2, title),
+                    description = COALESCE($3, description),
+                    cover_file_id = CASE WHEN $8 THEN $4 ELSE cover_file_id END,
+                    visibility = COALESCE($5, visibility),
+                    share_token = CASE WHEN $5 = 0 THEN NULL ELSE share_token END,
+                    smart_query = CASE WHEN $6 THEN $7 ELSE smart_query END,
                     updated_at = NOW()
-                WHERE id = $1 AND owner_id = $2
+                WHERE id = $1
                 "#,
                 playlist_id,
-                user_id,
                 update_playlist.title,
                 update_playlist.description,
-                update_playlist.cover
+                update_playlist.cover.flatten(),
+                update_playlist.visibility.map(i16::from),
+                update_playlist.smart_query.is_some(),
+                update_playlist.smart_query.flatten().map(Json) as Option<Json<SmartPlaylistQuery>>,
+                update_playlist.cover.is_some()
             )
             .execute(&mut *tx)
             .await
@@ -498,13 +1012,14 @@ impl PlaylistRepository for PostgresPlaylistRepository {
                     NewPlaylistItemContent::Post { post_id } => {
                         sqlx::query!(
                             r#"
-                            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text)
-                            VALUES ($1, $2, $3, $4, NULL)
+                            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text, added_by)
+                            VALUES ($1, $2, $3, $4, NULL, $5)
                             "#,
                             item_id,
                             playlist_id,
                             position,
-                            post_id
+                            post_id,
+                            user_id
                         )
                         .execute(&mut *tx)
                         .await
@@ -520,13 +1035,14 @@ impl PlaylistRepository for PostgresPlaylistRepository {
                     NewPlaylistItemContent::Note { text } => {
                         sqlx::query!(
                             r#"
-                            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text)
-                            VALUES ($1, $2, $3, NULL, $4)
+                            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text, added_by)
+                            VALUES ($1, $2, $3, NULL, $4, $5)
                             "#,
                             item_id,
                             playlist_id,
                             position,
-                            text
+                            text,
+                            user_id
                         )
                         .execute(&mut *tx)
                         .await
@@ -555,22 +1071,36 @@ impl PlaylistRepository for PostgresPlaylistRepository {
     }
 
     async fn delete(&self, user_id: UserID, playlist_id: PlaylistID) -> Result<(), RepoError> {
-        let result = sqlx::query!(
-            "DELETE FROM playlists WHERE id = $1 AND owner_id = $2",
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.delete failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        Self::lock_for(
+            &mut tx,
+            user_id,
             playlist_id,
-            user_id
+            PlaylistRole::Owner,
+            "playlists.delete",
         )
-        .execute(&self.pool)
-        .await
-        .map_err(|err| {
-            log::error!("playlists.delete failed for {}: {err}", playlist_id);
+        .await?;
+
+        sqlx::query!("DELETE FROM playlists WHERE id = $1", playlist_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!("playlists.delete failed for {}: {err}", playlist_id);
+                RepoError::StorageError
+            })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.delete failed to commit transaction for {}: {err}",
+                playlist_id
+            );
             RepoError::StorageError
         })?;
 
-        if result.rows_affected() == 0 {
-            return Err(RepoError::NotFound);
-        }
-
         Ok(())
     }
 
@@ -582,365 +1112,1595 @@ impl PlaylistRepository for PostgresPlaylistRepository {
     ) -> Result<SearchPlaylistsResponse, RepoError> {
         log::debug!("playlists.search user={user_id} query={query:?} cursor={cursor:?}");
 
-        let limit = Self::resolve_keyset_limit(&cursor);
-        let query_limit = limit + 1;
-        let use_cursor = cursor.last_id.is_some() && cursor.last_score.is_some();
-        let requested_direction = cursor.direction.unwrap_or_default();
-        let direction = if use_cursor {
-            requested_direction
-        } else {
-            KeysetDirection::Next
-        };
-        let last_id = cursor.last_id.unwrap_or_else(Uuid::nil);
-        let last_score = cursor.last_score.unwrap_or(f64::MAX);
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_keyset_limit(&cursor), true);
+        let tags = TagFilter::resolve(&self.pool, &query.tags).await?;
+        let search = SearchQuery::new(SearchTarget::Playlists)
+            .accessible_to(user_id)
+            .text(&query.text)
+            .tags(tags);
 
-        let text = query.text.trim();
-        let use_text_filter = !text.is_empty();
-        let text_pattern = format!("%{text}%");
+        self.keyset_page(search, keyset, "playlists.search").await
+    }
 
-        let parsed_rows: Vec<(PlaylistSummary, f64)> = match direction {
-            KeysetDirection::Next => sqlx::query!(
-                r#"
-                    WITH ranked_playlists AS (
-                        SELECT
-                            pl.id,
-                            pl.title,
-                            COALESCE(pl.description, '') AS description,
-                            pl.cover_file_id AS cover,
-                            COUNT(DISTINCT pi.id)::bigint AS item_count,
-                            COALESCE(
-                                jsonb_agg(
-                                    DISTINCT jsonb_build_object(
-                                        'id', t.id,
-                                        'name', t.name,
-                                        'category', t.category,
-                                        'count', t.post_count
-                                    )
-                                ) FILTER (WHERE t.id IS NOT NULL),
-                                '[]'::jsonb
-                            ) AS tags,
-                            COUNT(DISTINCT CASE
-                                WHEN t.name = ANY($1) THEN t.name
-                            END)::bigint AS should_score
-                        FROM playlists pl
-                        LEFT JOIN playlist_items pi ON pi.playlist_id = pl.id
-                        LEFT JOIN playlist_tags plt ON plt.playlist_id = pl.id
-                        LEFT JOIN tags t ON t.id = plt.tag_id
-                        WHERE
-                            pl.owner_id = $2
-                            AND (
-                                $3 = false
-                                OR pl.title ILIKE $4
-                                OR COALESCE(pl.description, '') ILIKE $4
-                            )
-                        GROUP BY pl.id
-                        HAVING
-                            COUNT(DISTINCT CASE
-                                WHEN t.name = ANY($5) THEN t.name
-                            END) = cardinality($5)
-                            AND
-                            NOT EXISTS (
-                                SELECT 1
-                                FROM playlist_tags x
-                                JOIN tags tx ON tx.id = x.tag_id
-                                WHERE x.playlist_id = pl.id
-                                  AND tx.name = ANY($6)
-                            )
-                    )
-                    SELECT
-                        id,
-                        title,
-                        description AS "description!",
-                        cover,
-                        item_count AS "item_count!: i64",
-                        tags AS "tags!: Json<Vec<TagResponse>>",
-                        should_score AS "should_score!: i64"
-                    FROM ranked_playlists
-                    WHERE
-                        $7 = false
-                        OR should_score::double precision < $8
-                        OR (should_score::double precision = $8 AND id < $9)
-                    ORDER BY should_score DESC, id DESC
-                    LIMIT $10
-                    "#,
-                &query.tags.should[..],
-                user_id,
-                use_text_filter,
-                text_pattern,
-                &query.tags.must[..],
-                &query.tags.must_not[..],
-                use_cursor,
-                last_score,
-                last_id,
-                query_limit
-            )
-            .fetch_all(&self.pool)
-            .await
-            .map(|rows| {
-                rows.into_iter()
-                    .map(|row| {
-                        (
-                            PlaylistSummary {
-                                id: row.id,
-                                title: row.title,
-                                description: row.description,
-                                cover: row.cover,
-                                item_count: row.item_count,
-                                tags: row.tags.0.into_iter().map(Tag::from).collect(),
-                            },
-                            row.should_score as f64,
-                        )
-                    })
-                    .collect()
-            }),
-            KeysetDirection::Prev => sqlx::query!(
-                r#"
-                    WITH ranked_playlists AS (
-                        SELECT
-                            pl.id,
-                            pl.title,
-                            COALESCE(pl.description, '') AS description,
-                            pl.cover_file_id AS cover,
-                            COUNT(DISTINCT pi.id)::bigint AS item_count,
-                            COALESCE(
-                                jsonb_agg(
-                                    DISTINCT jsonb_build_object(
-                                        'id', t.id,
-                                        'name', t.name,
-                                        'category', t.category,
-                                        'count', t.post_count
-                                    )
-                                ) FILTER (WHERE t.id IS NOT NULL),
-                                '[]'::jsonb
-                            ) AS tags,
-                            COUNT(DISTINCT CASE
-                                WHEN t.name = ANY($1) THEN t.name
-                            END)::bigint AS should_score
-                        FROM playlists pl
-                        LEFT JOIN playlist_items pi ON pi.playlist_id = pl.id
-                        LEFT JOIN playlist_tags plt ON plt.playlist_id = pl.id
-                        LEFT JOIN tags t ON t.id = plt.tag_id
-                        WHERE
-                            pl.owner_id = $2
-                            AND (
-                                $3 = false
-                                OR pl.title ILIKE $4
-                                OR COALESCE(pl.description, '') ILIKE $4
-                            )
-                        GROUP BY pl.id
-                        HAVING
-                            COUNT(DISTINCT CASE
-                                WHEN t.name = ANY($5) THEN t.name
-                            END) = cardinality($5)
-                            AND
-                            NOT EXISTS (
-                                SELECT 1
-                                FROM playlist_tags x
-                                JOIN tags tx ON tx.id = x.tag_id
-                                WHERE x.playlist_id = pl.id
-                                  AND tx.name = ANY($6)
-                            )
-                    )
-                    SELECT
-                        id,
-                        title,
-                        description AS "description!",
-                        cover,
-                        item_count AS "item_count!: i64",
-                        tags AS "tags!: Json<Vec<TagResponse>>",
-                        should_score AS "should_score!: i64"
-                    FROM ranked_playlists
-                    WHERE
-                        $7 = false
-                        OR should_score::double precision > $8
-                        OR (should_score::double precision = $8 AND id > $9)
-                    ORDER BY should_score ASC, id ASC
-                    LIMIT $10
-                    "#,
-                &query.tags.should[..],
-                user_id,
-                use_text_filter,
-                text_pattern,
-                &query.tags.must[..],
-                &query.tags.must_not[..],
-                use_cursor,
-                last_score,
-                last_id,
-                query_limit
-            )
-            .fetch_all(&self.pool)
+    async fn get_all(
+        &self,
+        user_id: UserID,
+        cursor: KeysetCursor,
+    ) -> Result<SearchPlaylistsResponse, RepoError> {
+        log::debug!("playlists.get_all user={user_id} cursor={cursor:?}");
+
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_keyset_limit(&cursor), false);
+        let search = SearchQuery::new(SearchTarget::Playlists).accessible_to(user_id);
+
+        self.keyset_page(search, keyset, "playlists.get_all").await
+    }
+
+    async fn search_public(
+        &self,
+        query: PlaylistQuery,
+        cursor: KeysetCursor,
+    ) -> Result<SearchPlaylistsResponse, RepoError> {
+        log::debug!("playlists.search_public query={query:?} cursor={cursor:?}");
+
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_keyset_limit(&cursor), true);
+        let tags = TagFilter::resolve(&self.pool, &query.tags).await?;
+        let search = SearchQuery::new(SearchTarget::Playlists)
+            .visibility(PlaylistVisibility::Public)
+            .text(&query.text)
+            .tags(tags);
+
+        self.keyset_page(search, keyset, "playlists.search_public")
             .await
-            .map(|rows| {
-                rows.into_iter()
-                    .map(|row| {
-                        (
-                            PlaylistSummary {
-                                id: row.id,
-                                title: row.title,
-                                description: row.description,
-                                cover: row.cover,
-                                item_count: row.item_count,
-                                tags: row.tags.0.into_iter().map(Tag::from).collect(),
-                            },
-                            row.should_score as f64,
-                        )
-                    })
-                    .collect()
-            }),
-        }
+    }
+
+    async fn set_share_token(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        share_token: Option<String>,
+    ) -> Result<PlaylistShare, RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.set_share_token failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Admin,
+            "playlists.set_share_token",
+        )
+        .await?;
+
+        // Issuing a link opens a private playlist as unlisted, revoking it
+        // closes an unlisted one again; public playlists stay public.
+        let row = sqlx::query!(
+            r#"
+            UPDATE playlists
+            SET
+                share_token = $2,
+                visibility = CASE
+                    WHEN $2::text IS NOT NULL AND visibility = 0 THEN 1
+                    WHEN $2::text IS NULL AND visibility = 1 THEN 0
+                    ELSE visibility
+                END
+            WHERE id = $1
+            RETURNING visibility, share_token
+            "#,
+            playlist_id,
+            share_token
+        )
+        .fetch_one(&mut *tx)
+        .await
         .map_err(|err| {
-            log::error!("playlists.search db query failed: {err}");
+            log::error!(
+                "playlists.set_share_token failed for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.set_share_token failed to commit transaction for {}: {err}",
+                playlist_id
+            );
             RepoError::StorageError
         })?;
 
-        Ok(Self::build_keyset_response(
-            parsed_rows,
-            limit,
-            direction,
-            use_cursor,
-        ))
+        Ok(PlaylistShare {
+            visibility: row.visibility.into(),
+            share_token: row.share_token,
+        })
     }
 
-    async fn get_all(
+    async fn add_item(
         &self,
         user_id: UserID,
-        cursor: KeysetCursor,
-    ) -> Result<SearchPlaylistsResponse, RepoError> {
-        log::debug!("playlists.get_all user={user_id} cursor={cursor:?}");
+        playlist_id: PlaylistID,
+        item: AddPlaylistItem,
+    ) -> Result<PlaylistItemID, RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.add_item failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
 
-        let limit = Self::resolve_keyset_limit(&cursor);
-        let query_limit = limit + 1;
-        let use_cursor = cursor.last_id.is_some();
-        let requested_direction = cursor.direction.unwrap_or_default();
-        let direction = if use_cursor {
-            requested_direction
-        } else {
-            KeysetDirection::Next
+        Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Editor,
+            "playlists.add_item",
+        )
+        .await?;
+
+        let index = item.index.unwrap_or(u32::MAX);
+        let position = Self::position_at(&mut tx, playlist_id, index, None).await?;
+        let item_id = Uuid::now_v7();
+
+        let (post_id, note_text) = match item.content {
+            NewPlaylistItemContent::Post { post_id } => (Some(post_id), None),
+            NewPlaylistItemContent::Note { text } => (None, Some(text)),
         };
-        let last_id = cursor.last_id.unwrap_or_else(Uuid::nil);
 
-        let parsed_rows: Vec<(PlaylistSummary, f64)> = match direction {
-            KeysetDirection::Next => sqlx::query!(
-                r#"
-                    SELECT
-                        pl.id,
-                        pl.title,
-                        COALESCE(pl.description, '') AS "description!",
-                        pl.cover_file_id AS cover,
-                        COUNT(DISTINCT pi.id)::bigint AS "item_count!: i64",
-                        COALESCE(
-                            jsonb_agg(
-                                DISTINCT jsonb_build_object(
-                                    'id', t.id,
-                                    'name', t.name,
-                                    'category', t.category,
-                                    'count', t.post_count
-                                )
-                            ) FILTER (WHERE t.id IS NOT NULL),
-                            '[]'::jsonb
-                        ) AS "tags!: Json<Vec<TagResponse>>",
-                        0::bigint AS "should_score!: i64"
-                    FROM playlists pl
-                    LEFT JOIN playlist_items pi ON pi.playlist_id = pl.id
-                    LEFT JOIN playlist_tags plt ON plt.playlist_id = pl.id
-                    LEFT JOIN tags t ON t.id = plt.tag_id
-                    WHERE
-                        pl.owner_id = $1
-                        AND ($2 = false OR pl.id < $3)
-                    GROUP BY pl.id
-                    ORDER BY pl.id DESC
-                    LIMIT $4
-                    "#,
-                user_id,
-                use_cursor,
-                last_id,
-                query_limit,
-            )
-            .fetch_all(&self.pool)
-            .await
-            .map(|rows| {
-                rows.into_iter()
-                    .map(|row| {
-                        (
-                            PlaylistSummary {
-                                id: row.id,
-                                title: row.title,
-                                description: row.description,
-                                cover: row.cover,
-                                item_count: row.item_count,
-                                tags: row.tags.0.into_iter().map(Tag::from).collect(),
-                            },
-                            row.should_score as f64,
-                        )
-                    })
-                    .collect()
-            }),
-            KeysetDirection::Prev => sqlx::query!(
-                r#"
-                    SELECT
-                        pl.id,
-                        pl.title,
-                        COALESCE(pl.description, '') AS "description!",
-                        pl.cover_file_id AS cover,
-                        COUNT(DISTINCT pi.id)::bigint AS "item_count!: i64",
-                        COALESCE(
-                            jsonb_agg(
-                                DISTINCT jsonb_build_object(
-                                    'id', t.id,
-                                    'name', t.name,
-                                    'category', t.category,
-                                    'count', t.post_count
-                                )
-                            ) FILTER (WHERE t.id IS NOT NULL),
-                            '[]'::jsonb
-                        ) AS "tags!: Json<Vec<TagResponse>>",
-                        0::bigint AS "should_score!: i64"
-                    FROM playlists pl
-                    LEFT JOIN playlist_items pi ON pi.playlist_id = pl.id
-                    LEFT JOIN playlist_tags plt ON plt.playlist_id = pl.id
-                    LEFT JOIN tags t ON t.id = plt.tag_id
-                    WHERE
-                        pl.owner_id = $1
-                        AND ($2 = false OR pl.id > $3)
-                    GROUP BY pl.id
-                    ORDER BY pl.id ASC
-                    LIMIT $4
-                    "#,
-                user_id,
-                use_cursor,
-                last_id,
-                query_limit,
-            )
-            .fetch_all(&self.pool)
-            .await
-            .map(|rows| {
-                rows.into_iter()
-                    .map(|row| {
-                        (
-                            PlaylistSummary {
-                                id: row.id,
-                                title: row.title,
-                                description: row.description,
-                                cover: row.cover,
-                                item_count: row.item_count,
-                                tags: row.tags.0.into_iter().map(Tag::from).collect(),
-                            },
-                            row.should_score as f64,
-                        )
-                    })
-                    .collect()
-            }),
-        }
+        sqlx::query!(
+            r#"
+            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text, added_by)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            item_id,
+            playlist_id,
+            position,
+            post_id,
+            note_text,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
         .map_err(|err| {
-            log::error!("playlists.get_all db query failed: {err}");
+            log::error!(
+                "playlists.add_item failed to insert item {} for {}: {err}",
+                item_id,
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.add_item failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(item_id)
+    }
+
+    async fn remove_item(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item_id: PlaylistItemID,
+    ) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.remove_item failed to begin transaction: {err}");
             RepoError::StorageError
         })?;
 
-        Ok(Self::build_keyset_response(
-            parsed_rows,
-            limit,
-            direction,
-            use_cursor,
-        ))
+        Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Editor,
+            "playlists.remove_item",
+        )
+        .await?;
+
+        let result = sqlx::query!(
+            "DELETE FROM playlist_items WHERE id = $1 AND playlist_id = $2",
+            item_id,
+            playlist_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.remove_item failed to delete item {} from {}: {err}",
+                item_id,
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.remove_item failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn move_item(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item_id: PlaylistItemID,
+        index: u32,
+    ) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.move_item failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Editor,
+            "playlists.move_item",
+        )
+        .await?;
+
+        let exists = sqlx::query!(
+            "SELECT id FROM playlist_items WHERE id = $1 AND playlist_id = $2",
+            item_id,
+            playlist_id
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.move_item failed to load item {} of {}: {err}",
+                item_id,
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        if exists.is_none() {
+            return Err(RepoError::NotFound);
+        }
+
+        let position = Self::position_at(&mut tx, playlist_id, index, Some(item_id)).await?;
+
+        sqlx::query!(
+            "UPDATE playlist_items SET position = $2 WHERE id = $1",
+            item_id,
+            position
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.move_item failed to move item {} in {}: {err}",
+                item_id,
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.move_item failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn reorder_items(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        item_ids: Vec<PlaylistItemID>,
+    ) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.reorder_items failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Editor,
+            "playlists.reorder_items",
+        )
+        .await?;
+
+        let mut current = sqlx::query_scalar!(
+            "SELECT id FROM playlist_items WHERE playlist_id = $1",
+            playlist_id
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.reorder_items failed to load items of {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        // The client must have seen the same set of items, otherwise its
+        // order would silently drop or misplace something
+        let mut requested = item_ids.clone();
+        current.sort();
+        requested.sort();
+        if current != requested {
+            return Err(RepoError::Conflict);
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE playlist_items pi
+            SET position = (o.ord * $3)::int
+            FROM unnest($2::uuid[]) WITH ORDINALITY AS o(id, ord)
+            WHERE pi.id = o.id AND pi.playlist_id = $1
+            "#,
+            playlist_id,
+            &item_ids,
+            i64::from(Self::POSITION_GAP)
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.reorder_items failed to update positions in {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.reorder_items failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn export(
+        &self,
+        viewer_id: Option<UserID>,
+        max_rating: PostRating,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistBundle, RepoError> {
+        Self::ensure_visible(&self.pool, viewer_id, playlist_id, "playlists.export").await?;
+
+        let playlist = sqlx::query!(
+            "SELECT title, description FROM playlists WHERE id = $1",
+            playlist_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.export failed to load playlist {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        let tags = sqlx::query!(
+            r#"
+            SELECT t.name, t.category
+            FROM playlist_tags pt
+            JOIN tags t ON t.id = pt.tag_id
+            WHERE pt.playlist_id = $1
+            ORDER BY t.name
+            "#,
+            playlist_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.export failed to load tags of {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        let items = sqlx::query!(
+            r#"
+            SELECT
+                pi.note_text,
+                p.title AS "post_title?",
+                f.id AS "file_id?",
+                f.hash AS "hash?",
+                f.path AS "path?",
+                (f.meta ->> 'duration_ms')::bigint AS duration_ms
+            FROM playlist_items pi
+            LEFT JOIN posts p ON p.id = pi.post_id
+            LEFT JOIN files f ON f.id = p.file_id
+            WHERE pi.playlist_id = $1
+                AND (p.id IS NULL OR (p.rating <= $2 AND p.deleted_at IS NULL))
+            ORDER BY pi.position, pi.id
+            "#,
+            playlist_id,
+            i16::from(max_rating)
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.export failed to load items of {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(PlaylistBundle {
+            title: playlist.title,
+            description: playlist.description,
+            tags: tags
+                .into_iter()
+                .map(|tag| NewTag {
+                    category: TagCategory::from(tag.category),
+                    value: tag.name,
+                })
+                .collect(),
+            items: items
+                .into_iter()
+                .map(|item| match item.file_id {
+                    Some(file_id) => BundleItem::Post {
+                        title: item.post_title,
+                        file_id: Some(file_id),
+                        hash: item.hash,
+                        path: item.path,
+                        duration_ms: item.duration_ms.map(|ms| ms.max(0) as u64),
+                    },
+                    None => BundleItem::Note {
+                        text: item.note_text.unwrap_or_default(),
+                    },
+                })
+                .collect(),
+        })
+    }
+
+    async fn save_progress(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        progress: UpdatePlaylistProgress,
+    ) -> Result<PlaylistProgress, RepoError> {
+        let position_ms = i64::try_from(progress.position_ms.unwrap_or_default())
+            .map_err(|_| RepoError::InvalidInput)?;
+
+        Self::ensure_visible(
+            &self.pool,
+            Some(user_id),
+            playlist_id,
+            "playlists.save_progress",
+        )
+        .await?;
+
+        // The item has to belong to the playlist, otherwise nothing is stored
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO playlist_progress (user_id, playlist_id, item_id, position_ms, updated_at)
+            SELECT $1, pi.playlist_id, pi.id, $4, NOW()
+            FROM playlist_items pi
+            WHERE pi.id = $3 AND pi.playlist_id = $2
+            ON CONFLICT (user_id, playlist_id) DO UPDATE
+            SET
+                item_id = EXCLUDED.item_id,
+                position_ms = EXCLUDED.position_ms,
+                updated_at = EXCLUDED.updated_at
+            RETURNING item_id, position_ms, updated_at
+            "#,
+            user_id,
+            playlist_id,
+            progress.item_id,
+            position_ms
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("playlists.save_progress failed for {}: {err}", playlist_id);
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        Ok(PlaylistProgress {
+            item_id: row.item_id,
+            position_ms: row.position_ms.max(0) as u64,
+            updated_at: row.updated_at,
+        })
+    }
+
+    async fn clear_progress(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<(), RepoError> {
+        sqlx::query!(
+            "DELETE FROM playlist_progress WHERE user_id = $1 AND playlist_id = $2",
+            user_id,
+            playlist_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("playlists.clear_progress failed for {}: {err}", playlist_id);
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn continue_watching(
+        &self,
+        user_id: UserID,
+        limit: u32,
+    ) -> Result<Vec<ContinueWatchingEntry>, RepoError> {
+        // Playlists the user can no longer see drop out of the list but keep
+        // their progress in case access comes back
+        let sql = format!(
+            r#"
+            SELECT
+                {projection},
+                0::bigint AS score,
+                pp.item_id,
+                pp.position_ms,
+                pp.updated_at
+            FROM playlist_progress pp
+            JOIN playlists e ON e.id = pp.playlist_id
+            WHERE pp.user_id = $1
+                AND (
+                    e.visibility = 2
+                    OR e.owner_id = $1
+                    OR EXISTS (
+                        SELECT 1
+                        FROM playlist_collaborators c
+                        WHERE c.playlist_id = e.id AND c.user_id = $1
+                    )
+                )
+            ORDER BY pp.updated_at DESC, pp.playlist_id DESC
+            LIMIT $2
+            "#,
+            projection = SearchTarget::Playlists.projection()
+        );
+
+        let rows = sqlx::query_as::<_, ContinueWatchingRow>(&sql)
+            .bind(user_id)
+            .bind(i64::from(limit))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| {
+                log::error!("playlists.continue_watching failed for {user_id}: {err}");
+                RepoError::StorageError
+            })?;
+
+        let item_ids: Vec<PlaylistItemID> = rows.iter().filter_map(|row| row.item_id).collect();
+        let items = sqlx::query_as!(
+            PlaylistItemRow,
+            r#"
+            SELECT
+                pi.id AS "id!",
+                pi.position AS "position!",
+                pi.added_by,
+                pi.note_text,
+                p.id AS "post_id?",
+                p.title AS "post_title?",
+                f.id AS "file_id?",
+                f.media_type AS "media_type?"
+            FROM playlist_items pi
+            LEFT JOIN posts p ON p.id = pi.post_id
+            LEFT JOIN files f ON f.id = p.file_id
+            WHERE pi.id = ANY($1) AND (p.id IS NULL OR p.deleted_at IS NULL)
+            "#,
+            &item_ids
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("playlists.continue_watching failed to load items for {user_id}: {err}");
+            RepoError::StorageError
+        })?;
+
+        let mut items: HashMap<PlaylistItemID, PlaylistItem> = items
+            .into_iter()
+            .map(|row| (row.id, PlaylistItem::from(row)))
+            .collect();
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ContinueWatchingEntry {
+                item: row.item_id.and_then(|item_id| items.remove(&item_id)),
+                progress: PlaylistProgress {
+                    item_id: row.item_id,
+                    position_ms: row.position_ms.max(0) as u64,
+                    updated_at: row.updated_at,
+                },
+                playlist: PlaylistSummary::from(row.playlist),
+            })
+            .collect())
+    }
+
+    async fn cover_sources(
+        &self,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistCoverSources, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                pl.cover_file_id IS NOT NULL AS "has_custom_cover!",
+                pl.auto_cover_file_id AS auto_cover,
+                pl.auto_cover_sources AS built_from
+            FROM playlists pl
+            WHERE pl.id = $1
+            "#,
+            playlist_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.cover_sources failed to load playlist {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        // The same picture posted twice only counts once
+        let pictures = sqlx::query!(
+            r#"
+            SELECT f.id, f.path
+            FROM (
+                SELECT DISTINCT ON (f.id) f.id, f.path, pi.position, pi.id AS item_id
+                FROM playlist_items pi
+                JOIN posts p ON p.id = pi.post_id AND p.deleted_at IS NULL
+                JOIN files f ON f.id = p.file_id
+                WHERE pi.playlist_id = $1 AND f.media_type = 0
+                ORDER BY f.id, pi.position, pi.id
+            ) f
+            ORDER BY f.position, f.item_id
+            LIMIT 4
+            "#,
+            playlist_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.cover_sources failed to load pictures of {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(PlaylistCoverSources {
+            has_custom_cover: row.has_custom_cover,
+            pictures: pictures
+                .into_iter()
+                .map(|picture| (picture.id, PathBuf::from(picture.path)))
+                .collect(),
+            auto_cover: row.auto_cover,
+            built_from: row.built_from,
+        })
+    }
+
+    async fn set_auto_cover(
+        &self,
+        playlist_id: PlaylistID,
+        cover: Option<FileID>,
+        built_from: Vec<FileID>,
+    ) -> Result<(), RepoError> {
+        sqlx::query!(
+            r#"
+            UPDATE playlists
+            SET auto_cover_file_id = $2, auto_cover_sources = $3
+            WHERE id = $1
+            "#,
+            playlist_id,
+            cover,
+            &built_from
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("playlists.set_auto_cover failed for {}: {err}", playlist_id);
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn duplicate(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistID, RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.duplicate failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        Self::ensure_visible(&mut *tx, Some(user_id), playlist_id, "playlists.duplicate").await?;
+
+        // The copy always starts private and owned by whoever made it
+        let copy_id = Uuid::now_v7();
+        sqlx::query!(
+            r#"
+            INSERT INTO playlists
+                (id, title, description, cover_file_id, owner_id, visibility, smart_query)
+            SELECT $2, title || ' (copy)', description, cover_file_id, $3, 0, smart_query
+            FROM playlists
+            WHERE id = $1
+            "#,
+            playlist_id,
+            copy_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.duplicate failed to copy playlist {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO playlist_tags (playlist_id, tag_id)
+            SELECT $2, tag_id
+            FROM playlist_tags
+            WHERE playlist_id = $1
+            "#,
+            playlist_id,
+            copy_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.duplicate failed to copy tags of {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text, added_by)
+            SELECT uuidv7(), $2, position, post_id, note_text, $3
+            FROM playlist_items
+            WHERE playlist_id = $1
+            "#,
+            playlist_id,
+            copy_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.duplicate failed to copy items of {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.duplicate failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(copy_id)
+    }
+
+    async fn merge(
+        &self,
+        user_id: UserID,
+        target_id: PlaylistID,
+        source_id: PlaylistID,
+    ) -> Result<CurationResult, RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.merge failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        Self::lock_for(
+            &mut tx,
+            user_id,
+            target_id,
+            PlaylistRole::Editor,
+            "playlists.merge",
+        )
+        .await?;
+        Self::ensure_visible(&mut *tx, Some(user_id), source_id, "playlists.merge").await?;
+
+        let bounds = sqlx::query!(
+            r#"
+            SELECT
+                (SELECT MAX(position) FROM playlist_items WHERE playlist_id = $1) AS last_position,
+                (SELECT COUNT(*) FROM playlist_items WHERE playlist_id = $2) AS "incoming!"
+            "#,
+            target_id,
+            source_id
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.merge failed to measure {} and {}: {err}",
+                target_id,
+                source_id
+            );
+            RepoError::StorageError
+        })?;
+
+        // Appended items continue the gap sequence, renumber first if it
+        // would run past the end of the position range
+        let gap = i64::from(Self::POSITION_GAP);
+        let mut last_position = i64::from(bounds.last_position.unwrap_or(0));
+        if last_position + (bounds.incoming + 1) * gap > i64::from(i32::MAX) {
+            Self::renumber_items(&mut tx, target_id).await?;
+            last_position = sqlx::query_scalar!(
+                r#"SELECT COUNT(*) AS "count!" FROM playlist_items WHERE playlist_id = $1"#,
+                target_id
+            )
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!(
+                    "playlists.merge failed to count items of {}: {err}",
+                    target_id
+                );
+                RepoError::StorageError
+            })? * gap;
+        }
+
+        // A post (or a note with the same text) already in the target, or
+        // repeated in the source, is only kept once
+        let added = sqlx::query!(
+            r#"
+            INSERT INTO playlist_items (id, playlist_id, position, post_id, note_text, added_by)
+            SELECT
+                uuidv7(),
+                $1,
+                ($3 + row_number() OVER (ORDER BY s.position, s.id) * $4)::int,
+                s.post_id,
+                s.note_text,
+                $5
+            FROM (
+                SELECT DISTINCT ON (post_id, note_text) id, position, post_id, note_text
+                FROM playlist_items
+                WHERE playlist_id = $2
+                ORDER BY post_id, note_text, position, id
+            ) s
+            WHERE NOT EXISTS (
+                SELECT 1
+                FROM playlist_items t
+                WHERE t.playlist_id = $1
+                    AND t.post_id IS NOT DISTINCT FROM s.post_id
+                    AND (s.post_id IS NOT NULL OR t.note_text IS NOT DISTINCT FROM s.note_text)
+            )
+            "#,
+            target_id,
+            source_id,
+            last_position,
+            gap,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.merge failed to copy items from {} into {}: {err}",
+                source_id,
+                target_id
+            );
+            RepoError::StorageError
+        })?
+        .rows_affected();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO playlist_tags (playlist_id, tag_id)
+            SELECT $1, tag_id
+            FROM playlist_tags
+            WHERE playlist_id = $2
+            ON CONFLICT DO NOTHING
+            "#,
+            target_id,
+            source_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.merge failed to copy tags from {} into {}: {err}",
+                source_id,
+                target_id
+            );
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.merge failed to commit transaction for {}: {err}",
+                target_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(CurationResult { added })
+    }
+
+    async fn apply_tags_to_posts(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        only_uploaded_by: Option<UserID>,
+    ) -> Result<CurationResult, RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.apply_tags_to_posts failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Editor,
+            "playlists.apply_tags_to_posts",
+        )
+        .await?;
+
+        // Every touched post gets a revision, like a regular edit
+        let added = sqlx::query_scalar!(
+            r#"
+            WITH added AS (
+                INSERT INTO post_tags (post_id, tag_id)
+                SELECT DISTINCT pi.post_id, pt.tag_id
+                FROM playlist_items pi
+                JOIN playlist_tags pt ON pt.playlist_id = pi.playlist_id
+                JOIN posts p ON p.id = pi.post_id AND p.deleted_at IS NULL
+                WHERE pi.playlist_id = $1
+                  AND ($2::uuid IS NULL OR p.uploader_id = $2)
+                ON CONFLICT DO NOTHING
+                RETURNING post_id, tag_id
+            ),
+            revisions AS (
+                INSERT INTO post_revisions (post_id, editor_id, tags_added)
+                SELECT post_id, $3, array_agg(tag_id)
+                FROM added
+                GROUP BY post_id
+            )
+            SELECT COUNT(*) AS "added!" FROM added
+            "#,
+            playlist_id,
+            only_uploaded_by,
+            user_id
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.apply_tags_to_posts failed for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })? as u64;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.apply_tags_to_posts failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(CurationResult { added })
+    }
+
+    async fn derive_tags_from_posts(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        limit: u32,
+    ) -> Result<CurationResult, RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.derive_tags_from_posts failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Editor,
+            "playlists.derive_tags_from_posts",
+        )
+        .await?;
+
+        let added = sqlx::query!(
+            r#"
+            INSERT INTO playlist_tags (playlist_id, tag_id)
+            SELECT $1, top.tag_id
+            FROM (
+                SELECT pt.tag_id, COUNT(DISTINCT pi.post_id) AS uses
+                FROM playlist_items pi
+                JOIN posts p ON p.id = pi.post_id AND p.deleted_at IS NULL
+                JOIN post_tags pt ON pt.post_id = pi.post_id
+                WHERE pi.playlist_id = $1
+                GROUP BY pt.tag_id
+                ORDER BY uses DESC, pt.tag_id
+                LIMIT $2
+            ) top
+            ON CONFLICT DO NOTHING
+            "#,
+            playlist_id,
+            i64::from(limit)
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.derive_tags_from_posts failed for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?
+        .rows_affected();
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.derive_tags_from_posts failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(CurationResult { added })
+    }
+
+    async fn list_collaborators(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<Vec<PlaylistCollaborator>, RepoError> {
+        self.role_of(user_id, playlist_id, "playlists.list_collaborators")
+            .await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT c.user_id, u.username, c.role, c.invited_by
+            FROM playlist_collaborators c
+            JOIN users u ON u.id = c.user_id
+            WHERE c.playlist_id = $1
+            ORDER BY c.role DESC, u.username
+            "#,
+            playlist_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.list_collaborators failed for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| PlaylistCollaborator {
+                user_id: row.user_id,
+                username: row.username,
+                role: row.role.into(),
+                invited_by: row.invited_by,
+            })
+            .collect())
+    }
+
+    async fn add_collaborator(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        collaborator: NewPlaylistCollaborator,
+    ) -> Result<PlaylistCollaborator, RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.add_collaborator failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        let caller = Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Admin,
+            "playlists.add_collaborator",
+        )
+        .await?;
+
+        let invitee = sqlx::query!(
+            r#"
+            SELECT u.id, (u.id = pl.owner_id) AS "is_owner!"
+            FROM users u, playlists pl
+            WHERE u.username = $1 AND pl.id = $2
+            "#,
+            collaborator.username,
+            playlist_id
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.add_collaborator failed to resolve user {}: {err}",
+                collaborator.username
+            );
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        if invitee.is_owner {
+            return Err(RepoError::Conflict);
+        }
+
+        let current = Self::collaborator_role(
+            &mut tx,
+            playlist_id,
+            invitee.id,
+            "playlists.add_collaborator",
+        )
+        .await?
+        .map(|(role, _)| role);
+        Self::check_can_manage(caller, current, Some(collaborator.role))?;
+
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO playlist_collaborators (playlist_id, user_id, role, invited_by)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (playlist_id, user_id) DO UPDATE SET role = EXCLUDED.role
+            RETURNING invited_by
+            "#,
+            playlist_id,
+            invitee.id,
+            i16::from(collaborator.role),
+            user_id
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.add_collaborator failed to add {} to {}: {err}",
+                invitee.id,
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.add_collaborator failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(PlaylistCollaborator {
+            user_id: invitee.id,
+            username: collaborator.username,
+            role: collaborator.role,
+            invited_by: row.invited_by,
+        })
+    }
+
+    async fn update_collaborator(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        collaborator_id: UserID,
+        role: PlaylistRole,
+    ) -> Result<PlaylistCollaborator, RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.update_collaborator failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        let caller = Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Admin,
+            "playlists.update_collaborator",
+        )
+        .await?;
+
+        let (current, username) = Self::collaborator_role(
+            &mut tx,
+            playlist_id,
+            collaborator_id,
+            "playlists.update_collaborator",
+        )
+        .await?
+        .ok_or(RepoError::NotFound)?;
+        Self::check_can_manage(caller, Some(current), Some(role))?;
+
+        let row = sqlx::query!(
+            r#"
+            UPDATE playlist_collaborators
+            SET role = $3
+            WHERE playlist_id = $1 AND user_id = $2
+            RETURNING invited_by
+            "#,
+            playlist_id,
+            collaborator_id,
+            i16::from(role)
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.update_collaborator failed to update {} in {}: {err}",
+                collaborator_id,
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.update_collaborator failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(PlaylistCollaborator {
+            user_id: collaborator_id,
+            username,
+            role,
+            invited_by: row.invited_by,
+        })
+    }
+
+    async fn remove_collaborator(
+        &self,
+        user_id: UserID,
+        playlist_id: PlaylistID,
+        collaborator_id: UserID,
+    ) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("playlists.remove_collaborator failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        let caller = Self::lock_for(
+            &mut tx,
+            user_id,
+            playlist_id,
+            PlaylistRole::Viewer,
+            "playlists.remove_collaborator",
+        )
+        .await?;
+
+        let (current, _) = Self::collaborator_role(
+            &mut tx,
+            playlist_id,
+            collaborator_id,
+            "playlists.remove_collaborator",
+        )
+        .await?
+        .ok_or(RepoError::NotFound)?;
+
+        // Anyone may leave a playlist on their own
+        if collaborator_id != user_id {
+            Self::check_can_manage(caller, Some(current), None)?;
+        }
+
+        sqlx::query!(
+            "DELETE FROM playlist_collaborators WHERE playlist_id = $1 AND user_id = $2",
+            playlist_id,
+            collaborator_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "playlists.remove_collaborator failed to remove {} from {}: {err}",
+                collaborator_id,
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "playlists.remove_collaborator failed to commit transaction for {}: {err}",
+                playlist_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::postgres::testing::load_schema;
+
+    async fn user(pool: &PgPool, username: &str) -> UserID {
+        sqlx::query_scalar(
+            "INSERT INTO users (username, password_hash) VALUES ($1, 'x') RETURNING id",
+        )
+        .bind(username)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    async fn playlist(
+        repo: &PostgresPlaylistRepository,
+        owner_id: UserID,
+        visibility: PlaylistVisibility,
+    ) -> PlaylistID {
+        repo.create(
+            owner_id,
+            NewPlaylist {
+                title: format!("{visibility:?}"),
+                description: None,
+                tag_ids: None,
+                cover: None,
+                visibility: Some(visibility),
+                smart_query: None,
+                items: None,
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn visible_to(
+        repo: &PostgresPlaylistRepository,
+        viewer_id: Option<UserID>,
+        playlist_id: PlaylistID,
+    ) -> bool {
+        match repo.get(viewer_id, PostRating::Safe, playlist_id).await {
+            Ok(_) => true,
+            Err(RepoError::NotFound) => false,
+            Err(err) => panic!("unexpected {err:?}"),
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn only_public_playlists_are_open_to_everyone(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresPlaylistRepository::new(pool.clone());
+        let alice = user(&pool, "alice").await;
+        let bob = user(&pool, "bob").await;
+
+        let private = playlist(&repo, alice, PlaylistVisibility::Private).await;
+        let unlisted = playlist(&repo, alice, PlaylistVisibility::Unlisted).await;
+        let public = playlist(&repo, alice, PlaylistVisibility::Public).await;
+
+        for id in [private, unlisted, public] {
+            assert!(visible_to(&repo, Some(alice), id).await);
+        }
+        for viewer in [Some(bob), None] {
+            assert!(!visible_to(&repo, viewer, private).await);
+            assert!(!visible_to(&repo, viewer, unlisted).await);
+            assert!(visible_to(&repo, viewer, public).await);
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn share_links_open_private_playlists_until_revoked(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresPlaylistRepository::new(pool.clone());
+        let alice = user(&pool, "alice").await;
+        let bob = user(&pool, "bob").await;
+        let private = playlist(&repo, alice, PlaylistVisibility::Private).await;
+        let public = playlist(&repo, alice, PlaylistVisibility::Public).await;
+
+        assert!(matches!(
+            repo.set_share_token(bob, private, Some("bobs".to_string()))
+                .await,
+            Err(RepoError::NotFound | RepoError::Forbidden)
+        ));
+
+        let share = repo
+            .set_share_token(alice, private, Some("secret".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(share.visibility, PlaylistVisibility::Unlisted);
+        let shared = repo.get_shared("secret", PostRating::Safe).await.unwrap();
+        assert_eq!(shared.id, private);
+        // A link doesn't list the playlist for anyone else
+        assert!(!visible_to(&repo, Some(bob), private).await);
+
+        let share = repo.set_share_token(alice, private, None).await.unwrap();
+        assert_eq!(share.visibility, PlaylistVisibility::Private);
+        assert!(matches!(
+            repo.get_shared("secret", PostRating::Safe).await,
+            Err(RepoError::NotFound)
+        ));
+
+        repo.set_share_token(alice, public, Some("open".to_string()))
+            .await
+            .unwrap();
+        let share = repo.set_share_token(alice, public, None).await.unwrap();
+        assert_eq!(share.visibility, PlaylistVisibility::Public);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn migration_turns_is_public_into_visibility(pool: PgPool) {
+        load_schema(&pool).await;
+        // Back to the shape older schema.sql files created
+        sqlx::raw_sql(
+            r#"
+            DROP INDEX idx_playlists_share_token, idx_playlists_public;
+            ALTER TABLE playlists
+                DROP COLUMN visibility,
+                DROP COLUMN share_token,
+                ADD COLUMN is_public boolean DEFAULT false NOT NULL;
+            INSERT INTO playlists (title, is_public) VALUES ('open', true), ('closed', false);
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let migration = include_str!("../../../../postgres/migrations/002_playlist_visibility.sql");
+        sqlx::raw_sql(migration).execute(&pool).await.unwrap();
+        sqlx::raw_sql(migration).execute(&pool).await.unwrap();
+
+        let rows: Vec<(String, i16)> =
+            sqlx::query_as("SELECT title, visibility FROM playlists ORDER BY title")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("closed".to_string(), PlaylistVisibility::Private as i16),
+                ("open".to_string(), PlaylistVisibility::Public as i16),
+            ]
+        );
+        let is_public_left: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM information_schema.columns
+                WHERE table_name = 'playlists' AND column_name = 'is_public'
+            )
+            "#,
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(!is_public_left);
+    }
+
+    async fn invite(
+        repo: &PostgresPlaylistRepository,
+        by: UserID,
+        playlist_id: PlaylistID,
+        username: &str,
+        role: PlaylistRole,
+    ) -> Result<PlaylistCollaborator, RepoError> {
+        repo.add_collaborator(
+            by,
+            playlist_id,
+            NewPlaylistCollaborator {
+                username: username.to_string(),
+                role,
+            },
+        )
+        .await
+    }
+
+    async fn add_note(
+        repo: &PostgresPlaylistRepository,
+        by: UserID,
+        playlist_id: PlaylistID,
+    ) -> Result<PlaylistItemID, RepoError> {
+        repo.add_item(
+            by,
+            playlist_id,
+            AddPlaylistItem {
+                index: None,
+                content: NewPlaylistItemContent::Note {
+                    text: "note".to_string(),
+                },
+            },
+        )
+        .await
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn collaborator_roles_gate_what_members_can_do(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresPlaylistRepository::new(pool.clone());
+        let alice = user(&pool, "alice").await;
+        let vera = user(&pool, "vera").await;
+        let eddie = user(&pool, "eddie").await;
+        let adam = user(&pool, "adam").await;
+        let sam = user(&pool, "sam").await;
+        let stranger = user(&pool, "stranger").await;
+        let id = playlist(&repo, alice, PlaylistVisibility::Private).await;
+
+        invite(&repo, alice, id, "vera", PlaylistRole::Viewer)
+            .await
+            .unwrap();
+        invite(&repo, alice, id, "eddie", PlaylistRole::Editor)
+            .await
+            .unwrap();
+        invite(&repo, alice, id, "adam", PlaylistRole::Admin)
+            .await
+            .unwrap();
+
+        // Members see the private playlist with their role
+        let seen = repo.get(Some(vera), PostRating::Safe, id).await.unwrap();
+        assert_eq!(seen.role, Some(PlaylistRole::Viewer));
+        assert!(!visible_to(&repo, Some(stranger), id).await);
+
+        // Viewers only look, editors change the items
+        assert!(matches!(
+            add_note(&repo, vera, id).await,
+            Err(RepoError::Forbidden)
+        ));
+        assert!(matches!(
+            add_note(&repo, stranger, id).await,
+            Err(RepoError::NotFound)
+        ));
+        add_note(&repo, eddie, id).await.unwrap();
+        assert!(matches!(
+            invite(&repo, eddie, id, "sam", PlaylistRole::Viewer).await,
+            Err(RepoError::Forbidden)
+        ));
+
+        // Admins manage members below themselves, never other admins
+        invite(&repo, adam, id, "sam", PlaylistRole::Editor)
+            .await
+            .unwrap();
+        assert!(matches!(
+            repo.update_collaborator(adam, id, sam, PlaylistRole::Admin)
+                .await,
+            Err(RepoError::Forbidden)
+        ));
+        assert!(matches!(
+            repo.remove_collaborator(eddie, id, sam).await,
+            Err(RepoError::Forbidden)
+        ));
+        repo.remove_collaborator(adam, id, sam).await.unwrap();
+        assert!(matches!(
+            invite(&repo, adam, id, "alice", PlaylistRole::Viewer).await,
+            Err(RepoError::Conflict)
+        ));
+
+        // Only the owner deletes, anyone may leave
+        assert!(matches!(
+            repo.delete(adam, id).await,
+            Err(RepoError::Forbidden)
+        ));
+        repo.remove_collaborator(vera, id, vera).await.unwrap();
+        assert!(!visible_to(&repo, Some(vera), id).await);
+        repo.delete(alice, id).await.unwrap();
     }
 }
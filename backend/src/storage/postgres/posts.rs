@@ -1,13 +1,24 @@
 use crate::application::contracts::{
-    Cursor, KeysetCursor, KeysetDirection, KeysetPageCursor, NewPost, PaginationMode,
-    SearchPostsKeysetResponse, SearchPostsOffsetResponse, TagQuery, UpdatePost,
+    CommentsResponse, Cursor, KeysetCursor, KeysetDirection, NewComment, NewPost, PostFileMatch,
+    PostInteraction, PurgeReport, PurgedFile, RevisionsResponse, SearchPostsKeysetResponse,
+    SearchPostsOffsetResponse, TagQuery, TrashResponse, TrashedPost, UpdatePost, UpdatePostNote,
 };
 use crate::application::ports::PostRepository;
-use crate::domain::model::{Post, PostID, RepoError, Tag};
-use crate::storage::postgres::dto::{FileResponse, TagResponse};
+use crate::domain::model::{
+    Comment, CommentID, FieldChange, FileID, Post, PostChanges, PostID, PostNote, PostRating,
+    PostRevision, RepoError, RevisionID, Tag, TagID, UserID,
+};
+use crate::storage::postgres::dto::{
+    CommentRow, FileResponse, PostSearchRow, RevisionRow, TagResponse, TrashedPostRow,
+};
+use crate::storage::postgres::query::{
+    KeysetArgs, SearchQuery, SearchTarget, TagFilter, build_keyset_page, count_total_pages,
+};
 use async_trait::async_trait;
-use sqlx::PgPool;
 use sqlx::types::Json;
+use sqlx::{PgConnection, PgPool};
+use std::path::PathBuf;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[derive(Clone)]
@@ -15,6 +26,24 @@ pub struct PostgresPostRepository {
     pool: PgPool,
 }
 
+// The editable part of a post, as revisions record it
+struct PostSnapshot {
+    title: String,
+    description: Option<String>,
+    rating: PostRating,
+    tag_ids: Vec<TagID>,
+    notes: Vec<PostNote>,
+}
+
+// Re-sent notes get fresh ids, so only their content counts as a change
+fn same_notes(left: &[PostNote], right: &[PostNote]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .all(|(l, r)| l.text == r.text && l.x == r.x && l.y == r.y)
+}
+
 impl PostgresPostRepository {
     const OFFSET_LIMIT: i64 = 20;
     const DEFAULT_KEYSET_LIMIT: i64 = 30;
@@ -24,81 +53,317 @@ impl PostgresPostRepository {
         Self { pool }
     }
 
-    fn build_keyset_response(
-        mut entries: Vec<(Post, f64)>,
-        limit: i64,
-        direction: KeysetDirection,
-        use_cursor: bool,
-    ) -> SearchPostsKeysetResponse {
-        let has_more_in_direction = entries.len() as i64 > limit;
-        if has_more_in_direction {
-            entries.truncate(limit as usize);
+    fn resolve_keyset_limit(cursor: &KeysetCursor) -> i64 {
+        cursor
+            .limit
+            .unwrap_or(Self::DEFAULT_KEYSET_LIMIT)
+            .clamp(1, Self::MAX_KEYSET_LIMIT)
+    }
+
+    // Locks the post for the rest of the transaction
+    async fn snapshot(
+        conn: &mut PgConnection,
+        id: PostID,
+        context: &str,
+    ) -> Result<PostSnapshot, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                p.title,
+                p.description,
+                p.rating,
+                ARRAY(
+                    SELECT pt.tag_id FROM post_tags pt WHERE pt.post_id = p.id ORDER BY pt.tag_id
+                ) AS "tag_ids!",
+                COALESCE(
+                    (
+                        SELECT jsonb_agg(
+                            jsonb_build_object(
+                                'id', n.id,
+                                'text', n.text,
+                                'x', n.pos_x,
+                                'y', n.pos_y
+                            )
+                            ORDER BY n.id
+                        )
+                        FROM post_notes n
+                        WHERE n.post_id = p.id
+                    ),
+                    '[]'::jsonb
+                ) AS "notes!: Json<Vec<PostNote>>"
+            FROM posts p
+            WHERE p.id = $1 AND p.deleted_at IS NULL
+            FOR UPDATE OF p
+            "#,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|err| {
+            log::error!("{context} failed to load post {}: {err}", id);
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        Ok(PostSnapshot {
+            title: row.title,
+            description: row.description,
+            rating: row.rating.into(),
+            tag_ids: row.tag_ids,
+            notes: row.notes.0,
+        })
+    }
+
+    // Writes the update over `before` and records what actually changed
+    async fn apply_update(
+        conn: &mut PgConnection,
+        id: PostID,
+        editor_id: UserID,
+        before: PostSnapshot,
+        update_post: UpdatePost,
+        context: &str,
+    ) -> Result<(), RepoError> {
+        let mut changes = PostChanges::default();
+        if let Some(title) = update_post
+            .title
+            .as_ref()
+            .filter(|title| **title != before.title)
+        {
+            changes.title = Some(FieldChange {
+                old: before.title.clone(),
+                new: title.clone(),
+            });
+        }
+        if let Some(description) = update_post
+            .description
+            .as_ref()
+            .filter(|description| before.description.as_ref() != Some(*description))
+        {
+            changes.description = Some(FieldChange {
+                old: before.description.clone(),
+                new: Some(description.clone()),
+            });
+        }
+        if let Some(rating) = update_post.rating.filter(|rating| *rating != before.rating) {
+            changes.rating = Some(FieldChange {
+                old: before.rating,
+                new: rating,
+            });
         }
 
-        if matches!(direction, KeysetDirection::Prev) {
-            entries.reverse();
+        if update_post.title.is_some()
+            || update_post.description.is_some()
+            || update_post.rating.is_some()
+        {
+            sqlx::query!(
+                r#"
+                UPDATE posts
+                SET
+                    title = COALESCE($2, title),
+                    description = COALESCE($3, description),
+                    rating = COALESCE($4, rating),
+                    updated_at = NOW()
+                WHERE id = $1
+                "#,
+                id,
+                update_post.title,
+                update_post.description,
+                update_post.rating.map(i16::from)
+            )
+            .execute(&mut *conn)
+            .await
+            .map_err(|err| {
+                log::error!("{context} failed to update base fields for {}: {err}", id);
+                RepoError::StorageError
+            })?;
         }
 
-        let has_next = if matches!(direction, KeysetDirection::Next) {
-            has_more_in_direction
-        } else {
-            use_cursor
-        };
+        let mut tags_added = Vec::new();
+        let mut tags_removed = Vec::new();
+        if let Some(tag_ids) = update_post.tag_ids {
+            sqlx::query!("DELETE FROM post_tags WHERE post_id = $1", id)
+                .execute(&mut *conn)
+                .await
+                .map_err(|err| {
+                    log::error!("{context} failed to clear tags for {}: {err}", id);
+                    RepoError::StorageError
+                })?;
 
-        let has_prev = if matches!(direction, KeysetDirection::Prev) {
-            has_more_in_direction
-        } else {
-            use_cursor
-        };
+            for tag_id in &tag_ids {
+                sqlx::query!(
+                    "INSERT INTO post_tags (post_id, tag_id) VALUES ($1, $2)",
+                    id,
+                    tag_id
+                )
+                .execute(&mut *conn)
+                .await
+                .map_err(|err| {
+                    log::error!("{context} failed to attach tag {} to {}: {err}", tag_id, id);
+                    RepoError::StorageError
+                })?;
+            }
 
-        let next_cursor = if has_next {
-            entries.last().map(|(post, score)| KeysetPageCursor {
-                mode: PaginationMode::Keyset,
-                direction: KeysetDirection::Next,
-                last_id: post.id,
-                last_score: *score,
-                limit,
-            })
-        } else {
-            None
-        };
+            tags_added = tag_ids
+                .iter()
+                .filter(|tag_id| !before.tag_ids.contains(tag_id))
+                .copied()
+                .collect();
+            tags_removed = before
+                .tag_ids
+                .iter()
+                .filter(|tag_id| !tag_ids.contains(tag_id))
+                .copied()
+                .collect();
+        }
 
-        let prev_cursor = if has_prev {
-            entries.first().map(|(post, score)| KeysetPageCursor {
-                mode: PaginationMode::Keyset,
-                direction: KeysetDirection::Prev,
-                last_id: post.id,
-                last_score: *score,
-                limit,
-            })
-        } else {
-            None
-        };
+        if let Some(notes) = update_post.notes {
+            sqlx::query!("DELETE FROM post_notes WHERE post_id = $1", id)
+                .execute(&mut *conn)
+                .await
+                .map_err(|err| {
+                    log::error!("{context} failed to clear notes for {}: {err}", id);
+                    RepoError::StorageError
+                })?;
+
+            let mut written = Vec::with_capacity(notes.len());
+            for note in notes {
+                let note_id = note.id.unwrap_or_else(Uuid::now_v7);
+                sqlx::query!(
+                    r#"
+                    INSERT INTO post_notes (id, post_id, text, pos_x, pos_y)
+                    VALUES ($1, $2, $3, $4, $5)
+                    "#,
+                    note_id,
+                    id,
+                    note.text,
+                    note.x,
+                    note.y
+                )
+                .execute(&mut *conn)
+                .await
+                .map_err(|err| {
+                    log::error!(
+                        "{context} failed to insert note {} for {}: {err}",
+                        note_id,
+                        id
+                    );
+                    RepoError::StorageError
+                })?;
+
+                written.push(PostNote {
+                    id: note_id,
+                    text: note.text,
+                    x: note.x,
+                    y: note.y,
+                });
+            }
 
-        let posts = entries.into_iter().map(|(post, _)| post).collect();
+            if !same_notes(&before.notes, &written) {
+                changes.notes = Some(FieldChange {
+                    old: before.notes,
+                    new: written,
+                });
+            }
+        }
 
-        SearchPostsKeysetResponse {
-            posts,
-            has_next,
-            has_prev,
-            next_cursor,
-            prev_cursor,
+        if changes.is_empty() && tags_added.is_empty() && tags_removed.is_empty() {
+            return Ok(());
         }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO post_revisions (post_id, editor_id, changes, tags_added, tags_removed)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            id,
+            editor_id,
+            Json(&changes) as _,
+            &tags_added,
+            &tags_removed
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|err| {
+            log::error!("{context} failed to record revision for {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        Ok(())
     }
 
-    fn resolve_keyset_limit(cursor: &KeysetCursor) -> i64 {
-        cursor
-            .limit
-            .unwrap_or(Self::DEFAULT_KEYSET_LIMIT)
-            .clamp(1, Self::MAX_KEYSET_LIMIT)
+    async fn fetch_page(
+        &self,
+        query: &SearchQuery,
+        context: &str,
+    ) -> Result<Vec<PostSearchRow>, RepoError> {
+        query
+            .build()
+            .build_query_as::<PostSearchRow>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| {
+                log::error!("{context} db query failed: {err}");
+                RepoError::StorageError
+            })
+    }
+
+    async fn offset_page(
+        &self,
+        query: SearchQuery,
+        cursor: Cursor,
+        context: &str,
+    ) -> Result<SearchPostsOffsetResponse, RepoError> {
+        let limit = Self::OFFSET_LIMIT;
+        let rows = self
+            .fetch_page(&query.offset(limit, cursor.page), context)
+            .await?;
+
+        let full_count = rows.first().map(|row| row.full_count).unwrap_or(0);
+
+        Ok(SearchPostsOffsetResponse {
+            posts: rows.into_iter().map(Post::from).collect(),
+            total_pages: count_total_pages(full_count, limit),
+        })
     }
 
-    fn count_total_pages(full_count: i64, limit: i64) -> i64 {
-        if full_count == 0 {
-            0
-        } else {
-            (full_count + limit - 1) / limit
+    async fn filtered_search(&self, query: &TagQuery) -> Result<SearchQuery, RepoError> {
+        let tags = TagFilter::resolve(&self.pool, query).await?;
+        let mut search = SearchQuery::new(SearchTarget::Posts)
+            .tags(tags)
+            .order(query.order);
+        if let Some(user_id) = query.favorited_by {
+            search = search.favorited_by(user_id);
         }
+        if let Some(ratings) = &query.ratings {
+            search = search.ratings(ratings.clone());
+        }
+        Ok(search)
+    }
+
+    async fn keyset_page(
+        &self,
+        query: SearchQuery,
+        keyset: KeysetArgs,
+        context: &str,
+    ) -> Result<SearchPostsKeysetResponse, RepoError> {
+        let rows = self.fetch_page(&query.keyset(&keyset), context).await?;
+        let entries = rows
+            .into_iter()
+            .map(|row| {
+                let score = row.score as f64;
+                (Post::from(row), score)
+            })
+            .collect();
+
+        let page = build_keyset_page(entries, |post: &Post| post.id, &keyset);
+
+        Ok(SearchPostsKeysetResponse {
+            posts: page.entries,
+            has_next: page.has_next,
+            has_prev: page.has_prev,
+            next_cursor: page.next_cursor,
+            prev_cursor: page.prev_cursor,
+        })
     }
 }
 
@@ -111,10 +376,15 @@ impl PostRepository for PostgresPostRepository {
         })?;
 
         sqlx::query!(
-            "INSERT INTO posts (id, title, file_id) VALUES ($1, $2, $3)",
+            r#"
+            INSERT INTO posts (id, title, file_id, uploader_id, rating)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
             post.id,
             post.title,
-            post.file_id
+            post.file_id,
+            post.uploader_id,
+            i16::from(post.rating)
         )
         .execute(&mut *tx)
         .await
@@ -141,6 +411,26 @@ impl PostRepository for PostgresPostRepository {
             })?;
         }
 
+        // The first revision lets a rollback undo every later edit
+        sqlx::query!(
+            r#"
+            INSERT INTO post_revisions (post_id, editor_id, tags_added)
+            VALUES ($1, $2, $3)
+            "#,
+            post.id,
+            post.uploader_id,
+            &post.tag_ids
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "posts.create failed to record revision for post {}: {err}",
+                post.id
+            );
+            RepoError::StorageError
+        })?;
+
         tx.commit().await.map_err(|err| {
             log::error!(
                 "posts.create failed to commit transaction for post {}: {err}",
@@ -159,6 +449,14 @@ impl PostRepository for PostgresPostRepository {
                 p.id,
                 p.title,
                 p.description,
+                p.uploader_id,
+                p.score,
+                p.rating,
+                (
+                    SELECT COUNT(*)
+                    FROM post_comments c
+                    WHERE c.post_id = p.id AND c.deleted_at IS NULL
+                ) AS "comment_count!",
                 COALESCE(
                     jsonb_agg(
                         jsonb_build_object(
@@ -186,7 +484,7 @@ impl PostRepository for PostgresPostRepository {
             LEFT JOIN post_tags pt ON pt.post_id = p.id
             LEFT JOIN tags t ON t.id = pt.tag_id
             LEFT JOIN files f ON f.id = p.file_id
-            WHERE p.id = $1
+            WHERE p.id = $1 AND p.deleted_at IS NULL
             GROUP BY p.id
             "#,
             id
@@ -204,695 +502,1027 @@ impl PostRepository for PostgresPostRepository {
             id: row.id,
             title: row.title,
             description: row.description,
+            uploader_id: row.uploader_id,
             file: row.file.0.into(),
             tags: row.tags.0.into_iter().map(Tag::from).collect(),
             //TODO load notes
             notes: vec![],
+            score: row.score,
+            rating: row.rating.into(),
+            comment_count: row.comment_count,
         })
     }
 
-    async fn update(&self, id: PostID, update_post: UpdatePost) -> Result<(), RepoError> {
+    async fn uploader_of(&self, id: PostID) -> Result<Option<UserID>, RepoError> {
+        let row = sqlx::query!(
+            "SELECT uploader_id FROM posts WHERE id = $1 AND deleted_at IS NULL",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.uploader_of failed for {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        row.map(|row| row.uploader_id).ok_or(RepoError::NotFound)
+    }
+
+    async fn update(
+        &self,
+        id: PostID,
+        editor_id: UserID,
+        update_post: UpdatePost,
+    ) -> Result<(), RepoError> {
         let mut tx = self.pool.begin().await.map_err(|err| {
             log::error!("posts.update failed to begin transaction for {}: {err}", id);
             RepoError::StorageError
         })?;
 
-        let exists = sqlx::query!("SELECT id FROM posts WHERE id = $1", id)
-            .fetch_optional(&mut *tx)
-            .await
-            .map_err(|err| {
-                log::error!("posts.update failed to check post {} existence: {err}", id);
-                RepoError::StorageError
-            })?;
+        let before = Self::snapshot(&mut tx, id, "posts.update").await?;
+        Self::apply_update(&mut tx, id, editor_id, before, update_post, "posts.update").await?;
 
-        if exists.is_none() {
-            return Err(RepoError::NotFound);
-        }
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "posts.update failed to commit transaction for {}: {err}",
+                id
+            );
+            RepoError::StorageError
+        })?;
 
-        if update_post.title.is_some() || update_post.description.is_some() {
-            sqlx::query!(
-                r#"
-                UPDATE posts
-                SET
-                    title = COALESCE($2, title),
-                    description = COALESCE($3, description),
-                    updated_at = NOW()
-                WHERE id = $1
-                "#,
-                id,
-                update_post.title,
-                update_post.description
-            )
-            .execute(&mut *tx)
-            .await
-            .map_err(|err| {
-                log::error!(
-                    "posts.update failed to update base fields for {}: {err}",
-                    id
-                );
-                RepoError::StorageError
-            })?;
-        }
+        Ok(())
+    }
 
-        if let Some(tag_ids) = update_post.tag_ids {
-            sqlx::query!("DELETE FROM post_tags WHERE post_id = $1", id)
-                .execute(&mut *tx)
-                .await
-                .map_err(|err| {
-                    log::error!("posts.update failed to clear tags for {}: {err}", id);
-                    RepoError::StorageError
-                })?;
+    async fn delete(&self, id: PostID, deleted_by: UserID) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE posts
+            SET deleted_at = NOW(), deleted_by = $2
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+            id,
+            deleted_by
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.delete failed for {}: {err}", id);
+            RepoError::StorageError
+        })?;
 
-            for tag_id in tag_ids {
-                sqlx::query!(
-                    "INSERT INTO post_tags (post_id, tag_id) VALUES ($1, $2)",
-                    id,
-                    tag_id
-                )
-                .execute(&mut *tx)
-                .await
-                .map_err(|err| {
-                    log::error!(
-                        "posts.update failed to attach tag {} to {}: {err}",
-                        tag_id,
-                        id
-                    );
-                    RepoError::StorageError
-                })?;
-            }
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
         }
 
-        if let Some(notes) = update_post.notes {
-            sqlx::query!("DELETE FROM post_notes WHERE post_id = $1", id)
-                .execute(&mut *tx)
-                .await
-                .map_err(|err| {
-                    log::error!("posts.update failed to clear notes for {}: {err}", id);
-                    RepoError::StorageError
-                })?;
+        Ok(())
+    }
 
-            for note in notes {
-                let note_id = note.id.unwrap_or_else(Uuid::now_v7);
-                sqlx::query!(
-                    r#"
-                    INSERT INTO post_notes (id, post_id, text, pos_x, pos_y)
-                    VALUES ($1, $2, $3, $4, $5)
-                    "#,
-                    note_id,
-                    id,
-                    note.text,
-                    note.x,
-                    note.y
-                )
-                .execute(&mut *tx)
-                .await
-                .map_err(|err| {
-                    log::error!(
-                        "posts.update failed to insert note {} for {}: {err}",
-                        note_id,
-                        id
-                    );
-                    RepoError::StorageError
-                })?;
-            }
+    async fn search(
+        &self,
+        query: TagQuery,
+        cursor: Cursor,
+    ) -> Result<SearchPostsOffsetResponse, RepoError> {
+        let search = self.filtered_search(&query).await?;
+        self.offset_page(search, cursor, "posts.search").await
+    }
+
+    async fn get_all(&self, cursor: Cursor) -> Result<SearchPostsOffsetResponse, RepoError> {
+        let search = SearchQuery::new(SearchTarget::Posts);
+        self.offset_page(search, cursor, "posts.get_all").await
+    }
+
+    async fn search_keyset(
+        &self,
+        query: TagQuery,
+        cursor: KeysetCursor,
+    ) -> Result<SearchPostsKeysetResponse, RepoError> {
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_keyset_limit(&cursor), true);
+        let search = self.filtered_search(&query).await?;
+        self.keyset_page(search, keyset, "posts.search_keyset")
+            .await
+    }
+
+    async fn get_all_keyset(
+        &self,
+        cursor: KeysetCursor,
+    ) -> Result<SearchPostsKeysetResponse, RepoError> {
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_keyset_limit(&cursor), false);
+        let search = SearchQuery::new(SearchTarget::Posts);
+        self.keyset_page(search, keyset, "posts.get_all_keyset")
+            .await
+    }
+
+    async fn find_by_files(
+        &self,
+        file_ids: Vec<FileID>,
+        hashes: Vec<String>,
+        paths: Vec<String>,
+    ) -> Result<Vec<PostFileMatch>, RepoError> {
+        // A file shared by several posts resolves to the oldest one
+        let rows = sqlx::query!(
+            r#"
+            SELECT DISTINCT ON (f.id) p.id AS post_id, f.id AS file_id, f.hash, f.path
+            FROM files f
+            JOIN posts p ON p.file_id = f.id AND p.deleted_at IS NULL
+            WHERE f.id = ANY($1) OR f.hash = ANY($2) OR f.path = ANY($3)
+            ORDER BY f.id, p.id
+            "#,
+            &file_ids,
+            &hashes,
+            &paths
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.find_by_files db query failed: {err}");
+            RepoError::StorageError
+        })?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| PostFileMatch {
+                post_id: row.post_id,
+                file_id: row.file_id,
+                hash: row.hash,
+                path: row.path,
+            })
+            .collect())
+    }
+
+    async fn toggle_favorite(&self, user_id: UserID, post_id: PostID) -> Result<(), RepoError> {
+        let removed = sqlx::query!(
+            "DELETE FROM post_favorites WHERE user_id = $1 AND post_id = $2",
+            user_id,
+            post_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.toggle_favorite failed to remove {}: {err}", post_id);
+            RepoError::StorageError
+        })?;
+
+        if removed.rows_affected() > 0 {
+            return Ok(());
         }
 
-        tx.commit().await.map_err(|err| {
-            log::error!(
-                "posts.update failed to commit transaction for {}: {err}",
-                id
-            );
+        let added = sqlx::query!(
+            r#"
+            INSERT INTO post_favorites (user_id, post_id)
+            SELECT $1, id FROM posts WHERE id = $2 AND deleted_at IS NULL
+            ON CONFLICT DO NOTHING
+            "#,
+            user_id,
+            post_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.toggle_favorite failed to add {}: {err}", post_id);
             RepoError::StorageError
         })?;
 
+        // A parallel request may have added it first, only a missing post is
+        // an error
+        if added.rows_affected() == 0 {
+            self.uploader_of(post_id).await?;
+        }
+
         Ok(())
     }
 
-    async fn delete(&self, id: PostID) -> Result<(), RepoError> {
-        let result = sqlx::query!("DELETE FROM posts WHERE id = $1", id)
+    async fn vote(&self, user_id: UserID, post_id: PostID, value: i16) -> Result<(), RepoError> {
+        if value == 0 {
+            sqlx::query!(
+                "DELETE FROM post_votes WHERE post_id = $1 AND user_id = $2",
+                post_id,
+                user_id
+            )
             .execute(&self.pool)
             .await
             .map_err(|err| {
-                log::error!("posts.delete failed for {}: {err}", id);
+                log::error!("posts.vote failed to withdraw vote on {}: {err}", post_id);
                 RepoError::StorageError
             })?;
 
+            return Ok(());
+        }
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO post_votes (post_id, user_id, value)
+            SELECT id, $2, $3 FROM posts WHERE id = $1 AND deleted_at IS NULL
+            ON CONFLICT (post_id, user_id) DO UPDATE
+            SET value = EXCLUDED.value, created_at = NOW()
+            WHERE post_votes.value <> EXCLUDED.value
+            "#,
+            post_id,
+            user_id,
+            value
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.vote failed for {}: {err}", post_id);
+            RepoError::StorageError
+        })?;
+
+        // Nothing changes when the same vote is cast again, so only a missing
+        // post is an error
         if result.rows_affected() == 0 {
-            return Err(RepoError::NotFound);
+            self.uploader_of(post_id).await?;
         }
 
         Ok(())
     }
 
-    async fn search(
+    async fn interaction(
         &self,
-        query: TagQuery,
-        cursor: Cursor,
-    ) -> Result<SearchPostsOffsetResponse, RepoError> {
-        let limit = Self::OFFSET_LIMIT;
-        let page = cursor.page.max(0);
-
-        let rows = sqlx::query!(
+        user_id: UserID,
+        post_id: PostID,
+    ) -> Result<PostInteraction, RepoError> {
+        let row = sqlx::query!(
             r#"
             SELECT
-                p.id,
-                p.title,
-                p.description,
-                COUNT(*) OVER() as "full_count!",
+                p.score,
+                EXISTS (
+                    SELECT 1 FROM post_favorites f
+                    WHERE f.post_id = p.id AND f.user_id = $2
+                ) AS "favorited!",
                 COALESCE(
-                    jsonb_agg(
-                        jsonb_build_object(
-                            'id', pt.tag_id,
-                            'name', t.name,
-                            'category', t.category,
-                            'count', t.post_count
-                        )
-                    ) FILTER (WHERE pt.tag_id IS NOT NULL),
-                '[]'::jsonb
-                ) AS "tags!: Json<Vec<TagResponse>>",
-                (
-                    SELECT jsonb_build_object(
-                        'id', f.id,
-                        'path', f.path,
-                        'hash', f.hash,
-                        'media_type', f.media_type,
-                        'meta', f.meta,
-                        'created_at', f.created_at
-                    )
-                    FROM files f
-                    WHERE f.id = p.file_id
-                ) AS "file!: Json<FileResponse>",
-
-                COUNT(DISTINCT CASE
-                    WHEN t.name = ANY($1) THEN t.name
-                END) AS should_score
-
+                    (
+                        SELECT v.value FROM post_votes v
+                        WHERE v.post_id = p.id AND v.user_id = $2
+                    ),
+                    0::smallint
+                ) AS "vote!"
             FROM posts p
-            LEFT JOIN post_tags pt ON pt.post_id = p.id
-            LEFT JOIN tags t ON t.id = pt.tag_id
-            LEFT JOIN files f ON f.id = p.file_id
-
-            GROUP BY p.id
+            WHERE p.id = $1 AND p.deleted_at IS NULL
+            "#,
+            post_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.interaction failed for {}: {err}", post_id);
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
 
+        Ok(PostInteraction {
+            favorited: row.favorited,
+            vote: row.vote,
+            score: row.score,
+        })
+    }
 
-            HAVING
-                COUNT(DISTINCT CASE
-                    WHEN t.name = ANY($2) THEN t.name
-                END) = cardinality($2)
-                AND
-                NOT EXISTS (
-                    SELECT 1
-                    FROM post_tags x
-                    JOIN tags tx ON tx.id = x.tag_id
-                    WHERE x.post_id = p.id
-                      AND tx.name = ANY($3)
-                )
+    async fn create_comment(&self, comment: NewComment) -> Result<(), RepoError> {
+        if let Some(parent_id) = comment.parent_id {
+            let parent = sqlx::query!(
+                "SELECT post_id, deleted_at FROM post_comments WHERE id = $1",
+                parent_id
+            )
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|err| {
+                log::error!(
+                    "posts.create_comment failed to load parent {}: {err}",
+                    parent_id
+                );
+                RepoError::StorageError
+            })?;
 
-            ORDER BY should_score DESC, p.id DESC
+            match parent {
+                Some(parent)
+                    if parent.post_id == comment.post_id && parent.deleted_at.is_none() => {}
+                _ => return Err(RepoError::InvalidInput),
+            }
+        }
 
-            LIMIT $4
-            OFFSET $5
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO post_comments (id, post_id, parent_id, author_id, body)
+            SELECT $1, p.id, $3, $4, $5 FROM posts p WHERE p.id = $2 AND p.deleted_at IS NULL
             "#,
-            &query.should[..],
-            &query.must[..],
-            &query.must_not[..],
-            limit,
-            page * limit,
+            comment.id,
+            comment.post_id,
+            comment.parent_id,
+            comment.author_id,
+            comment.body
         )
-        .fetch_all(&self.pool)
+        .execute(&self.pool)
         .await
-        .map_err(|e| {
-            log::error!("posts.search db query failed: {e}");
+        .map_err(|err| {
+            log::error!(
+                "posts.create_comment failed for post {}: {err}",
+                comment.post_id
+            );
             RepoError::StorageError
         })?;
 
-        let full_count = rows.first().map(|row| row.full_count).unwrap_or(0);
-        let page_count = Self::count_total_pages(full_count, limit);
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
 
-        Ok(SearchPostsOffsetResponse {
-            posts: rows
-                .into_iter()
-                .map(|r| Post {
-                    id: r.id,
-                    title: r.title,
-                    description: r.description,
-                    file: r.file.0.into(),
-                    tags: r.tags.0.into_iter().map(Tag::from).collect(),
-                    //TODO return notes
-                    notes: vec![],
-                })
-                .collect(),
-            total_pages: page_count,
-        })
+        Ok(())
     }
 
-    async fn get_all(&self, cursor: Cursor) -> Result<SearchPostsOffsetResponse, RepoError> {
-        let limit = Self::OFFSET_LIMIT;
-        let page = cursor.page.max(0);
-
-        let rows = sqlx::query!(
+    async fn get_comment(&self, id: CommentID) -> Result<Comment, RepoError> {
+        let row = sqlx::query_as!(
+            CommentRow,
             r#"
             SELECT
-                p.id,
-                p.title,
-                p.description,
-                COUNT(*) OVER() as "full_count!",
-                COALESCE(
-                    jsonb_agg(
-                        jsonb_build_object(
-                            'id', pt.tag_id,
-                            'name', t.name,
-                            'category', t.category,
-                            'count', t.post_count
-                        )
-                    ) FILTER (WHERE pt.tag_id IS NOT NULL),
-                '[]'::jsonb
-                ) AS "tags!: Json<Vec<TagResponse>>",
-                (
-                    SELECT jsonb_build_object(
-                        'id', f.id,
-                        'path', f.path,
-                        'hash', f.hash,
-                        'media_type', f.media_type,
-                        'meta', f.meta,
-                        'created_at', f.created_at
-                    )
-                    FROM files f
-                    WHERE f.id = p.file_id
-                ) AS "file!: Json<FileResponse>"
-            FROM posts p
-            LEFT JOIN post_tags pt ON pt.post_id = p.id
-            LEFT JOIN tags t ON t.id = pt.tag_id
-            LEFT JOIN files f ON f.id = p.file_id
-            GROUP BY p.id
-            ORDER BY p.id DESC
-
-            LIMIT $1
-            OFFSET $2
+                c.id,
+                c.post_id,
+                c.parent_id,
+                c.author_id,
+                u.username AS "author_name?",
+                c.body,
+                c.created_at,
+                c.edited_at,
+                c.deleted_at,
+                (SELECT COUNT(*) FROM post_comments r WHERE r.parent_id = c.id) AS "reply_count!"
+            FROM post_comments c
+            LEFT JOIN users u ON u.id = c.author_id
+            WHERE c.id = $1
             "#,
-            limit,
-            page * limit,
+            id
         )
-        .fetch_all(&self.pool)
+        .fetch_optional(&self.pool)
         .await
-        .map_err(|e| {
-            log::error!("posts.get_all db query failed: {e}");
+        .map_err(|err| {
+            log::error!("posts.get_comment failed for {}: {err}", id);
             RepoError::StorageError
-        })?;
+        })?
+        .ok_or(RepoError::NotFound)?;
 
-        let full_count = rows.first().map(|row| row.full_count).unwrap_or(0);
-        let page_count = Self::count_total_pages(full_count, limit);
-
-        Ok(SearchPostsOffsetResponse {
-            posts: rows
-                .into_iter()
-                .map(|r| Post {
-                    id: r.id,
-                    title: r.title,
-                    file: r.file.0.into(),
-                    description: r.description,
-                    tags: r.tags.0.into_iter().map(Tag::from).collect(),
-                    //TODO load notes
-                    notes: vec![],
-                })
-                .collect(),
-            total_pages: page_count,
-        })
+        Ok(Comment::from(row))
     }
 
-    async fn search_keyset(
+    async fn list_comments(
         &self,
-        query: TagQuery,
+        post_id: PostID,
+        parent_id: Option<CommentID>,
         cursor: KeysetCursor,
-    ) -> Result<SearchPostsKeysetResponse, RepoError> {
-        let limit = Self::resolve_keyset_limit(&cursor);
-        let query_limit = limit + 1;
-        let use_cursor = cursor.last_id.is_some() && cursor.last_score.is_some();
-        let requested_direction = cursor.direction.unwrap_or_default();
-        let direction = if use_cursor {
-            requested_direction
-        } else {
-            KeysetDirection::Next
-        };
-        let last_id = cursor.last_id.unwrap_or(Uuid::nil());
-        let last_score = cursor.last_score.unwrap_or(f64::MAX);
+    ) -> Result<CommentsResponse, RepoError> {
+        self.uploader_of(post_id).await?;
 
-        let parsed_rows: Vec<(Post, f64)> = match direction {
-            KeysetDirection::Next => sqlx::query!(
-                r#"
-                    WITH ranked_posts AS (
-                        SELECT
-                            p.id,
-                            p.title,
-                            p.description,
-                            COALESCE(
-                                jsonb_agg(
-                                    jsonb_build_object(
-                                        'id', pt.tag_id,
-                                        'name', t.name,
-                                        'category', t.category,
-                                        'count', t.post_count
-                                    )
-                                ) FILTER (WHERE pt.tag_id IS NOT NULL),
-                                '[]'::jsonb
-                            ) AS tags,
-                            (
-                                SELECT jsonb_build_object(
-                                    'id', f.id,
-                                    'path', f.path,
-                                    'hash', f.hash,
-                                    'media_type', f.media_type,
-                                    'meta', f.meta,
-                                    'created_at', f.created_at
-                                )
-                                FROM files f
-                                WHERE f.id = p.file_id
-                            ) AS file,
-                            COUNT(DISTINCT CASE
-                                WHEN t.name = ANY($1) THEN t.name
-                            END)::bigint AS should_score
-                        FROM posts p
-                        LEFT JOIN post_tags pt ON pt.post_id = p.id
-                        LEFT JOIN tags t ON t.id = pt.tag_id
-                        LEFT JOIN files f ON f.id = p.file_id
-                        GROUP BY p.id
-                        HAVING
-                            COUNT(DISTINCT CASE
-                                WHEN t.name = ANY($2) THEN t.name
-                            END) = cardinality($2)
-                            AND
-                            NOT EXISTS (
-                                SELECT 1
-                                FROM post_tags x
-                                JOIN tags tx ON tx.id = x.tag_id
-                                WHERE x.post_id = p.id
-                                  AND tx.name = ANY($3)
-                            )
-                    )
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_keyset_limit(&cursor), false);
+        let last_id = keyset.after.map(|(_, id)| id);
+        let limit = keyset.limit + 1;
+
+        // Comment ids are v7, so id order is posting order
+        let rows = match keyset.direction {
+            KeysetDirection::Next => {
+                sqlx::query_as!(
+                    CommentRow,
+                    r#"
                     SELECT
-                        id,
-                        title,
-                        description,
-                        tags AS "tags!: Json<Vec<TagResponse>>",
-                        file AS "file!: Json<FileResponse>",
-                        should_score AS "should_score!: i64"
-                    FROM ranked_posts
-                    WHERE
-                        $4 = false
-                        OR should_score::double precision < $5
-                        OR (should_score::double precision = $5 AND id < $6)
-                    ORDER BY should_score DESC, id DESC
-                    LIMIT $7
+                        c.id,
+                        c.post_id,
+                        c.parent_id,
+                        c.author_id,
+                        u.username AS "author_name?",
+                        c.body,
+                        c.created_at,
+                        c.edited_at,
+                        c.deleted_at,
+                        (SELECT COUNT(*) FROM post_comments r WHERE r.parent_id = c.id) AS "reply_count!"
+                    FROM post_comments c
+                    LEFT JOIN users u ON u.id = c.author_id
+                    WHERE c.post_id = $1
+                        AND (($2::uuid IS NULL AND c.parent_id IS NULL) OR c.parent_id = $2)
+                        AND ($3::uuid IS NULL OR c.id > $3)
+                    ORDER BY c.id
+                    LIMIT $4
                     "#,
-                &query.should[..],
-                &query.must[..],
-                &query.must_not[..],
-                use_cursor,
-                last_score,
-                last_id,
-                query_limit
-            )
-            .fetch_all(&self.pool)
-            .await
-            .map(|rows| {
-                rows.into_iter()
-                    .map(|row| {
-                        (
-                            Post {
-                                id: row.id,
-                                title: row.title,
-                                description: row.description,
-                                file: row.file.0.into(),
-                                tags: row.tags.0.into_iter().map(Tag::from).collect(),
-                                //TODO load notes
-                                notes: vec![],
-                            },
-                            row.should_score as f64,
-                        )
-                    })
-                    .collect()
-            }),
-            KeysetDirection::Prev => sqlx::query!(
-                r#"
-                    WITH ranked_posts AS (
-                        SELECT
-                            p.id,
-                            p.title,
-                            p.description,
-                            COALESCE(
-                                jsonb_agg(
-                                    jsonb_build_object(
-                                        'id', pt.tag_id,
-                                        'name', t.name,
-                                        'category', t.category,
-                                        'count', t.post_count
-                                    )
-                                ) FILTER (WHERE pt.tag_id IS NOT NULL),
-                                '[]'::jsonb
-                            ) AS tags,
-                            (
-                                SELECT jsonb_build_object(
-                                    'id', f.id,
-                                    'path', f.path,
-                                    'hash', f.hash,
-                                    'media_type', f.media_type,
-                                    'meta', f.meta,
-                                    'created_at', f.created_at
-                                )
-                                FROM files f
-                                WHERE f.id = p.file_id
-                            ) AS file,
-                            COUNT(DISTINCT CASE
-                                WHEN t.name = ANY($1) THEN t.name
-                            END)::bigint AS should_score
-                        FROM posts p
-                        LEFT JOIN post_tags pt ON pt.post_id = p.id
-                        LEFT JOIN tags t ON t.id = pt.tag_id
-                        LEFT JOIN files f ON f.id = p.file_id
-                        GROUP BY p.id
-                        HAVING
-                            COUNT(DISTINCT CASE
-                                WHEN t.name = ANY($2) THEN t.name
-                            END) = cardinality($2)
-                            AND
-                            NOT EXISTS (
-                                SELECT 1
-                                FROM post_tags x
-                                JOIN tags tx ON tx.id = x.tag_id
-                                WHERE x.post_id = p.id
-                                  AND tx.name = ANY($3)
-                            )
-                    )
+                    post_id,
+                    parent_id,
+                    last_id,
+                    limit
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+            KeysetDirection::Prev => {
+                sqlx::query_as!(
+                    CommentRow,
+                    r#"
                     SELECT
-                        id,
-                        title,
-                        description,
-                        tags AS "tags!: Json<Vec<TagResponse>>",
-                        file AS "file!: Json<FileResponse>",
-                        should_score AS "should_score!: i64"
-                    FROM ranked_posts
-                    WHERE
-                        $4 = false
-                        OR should_score::double precision > $5
-                        OR (should_score::double precision = $5 AND id > $6)
-                    ORDER BY should_score ASC, id ASC
-                    LIMIT $7
+                        c.id,
+                        c.post_id,
+                        c.parent_id,
+                        c.author_id,
+                        u.username AS "author_name?",
+                        c.body,
+                        c.created_at,
+                        c.edited_at,
+                        c.deleted_at,
+                        (SELECT COUNT(*) FROM post_comments r WHERE r.parent_id = c.id) AS "reply_count!"
+                    FROM post_comments c
+                    LEFT JOIN users u ON u.id = c.author_id
+                    WHERE c.post_id = $1
+                        AND (($2::uuid IS NULL AND c.parent_id IS NULL) OR c.parent_id = $2)
+                        AND ($3::uuid IS NULL OR c.id < $3)
+                    ORDER BY c.id DESC
+                    LIMIT $4
                     "#,
-                &query.should[..],
-                &query.must[..],
-                &query.must_not[..],
-                use_cursor,
-                last_score,
-                last_id,
-                query_limit
-            )
-            .fetch_all(&self.pool)
-            .await
-            .map(|rows| {
-                rows.into_iter()
-                    .map(|row| {
-                        (
-                            Post {
-                                id: row.id,
-                                title: row.title,
-                                description: row.description,
-                                file: row.file.0.into(),
-                                tags: row.tags.0.into_iter().map(Tag::from).collect(),
-                                //TODO load notes
-                                notes: vec![],
-                            },
-                            row.should_score as f64,
-                        )
-                    })
-                    .collect()
-            }),
+                    post_id,
+                    parent_id,
+                    last_id,
+                    limit
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
         }
         .map_err(|err| {
-            log::error!("posts.search_keyset db query failed: {err}");
+            log::error!(
+                "posts.list_comments failed to load comments of {}: {err}",
+                post_id
+            );
             RepoError::StorageError
         })?;
 
-        Ok(Self::build_keyset_response(
-            parsed_rows,
-            limit,
-            direction,
-            use_cursor,
-        ))
+        let entries = rows
+            .into_iter()
+            .map(|row| (Comment::from(row), 0.0))
+            .collect();
+
+        let page = build_keyset_page(entries, |comment: &Comment| comment.id, &keyset);
+
+        Ok(CommentsResponse {
+            comments: page.entries,
+            has_next: page.has_next,
+            has_prev: page.has_prev,
+            next_cursor: page.next_cursor,
+            prev_cursor: page.prev_cursor,
+        })
     }
 
-    async fn get_all_keyset(
+    async fn update_comment(&self, id: CommentID, body: String) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE post_comments
+            SET body = $2, edited_at = NOW()
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+            id,
+            body
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.update_comment failed for {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_comment(&self, id: CommentID, deleted_by: UserID) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE post_comments
+            SET deleted_at = NOW(), deleted_by = $2
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+            id,
+            deleted_by
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.delete_comment failed for {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn list_revisions(
         &self,
+        post_id: PostID,
         cursor: KeysetCursor,
-    ) -> Result<SearchPostsKeysetResponse, RepoError> {
-        let limit = Self::resolve_keyset_limit(&cursor);
-        let query_limit = limit + 1;
-        let use_cursor = cursor.last_id.is_some();
-        let requested_direction = cursor.direction.unwrap_or_default();
-        let direction = if use_cursor {
-            requested_direction
-        } else {
-            KeysetDirection::Next
-        };
-        let last_id = cursor.last_id.unwrap_or(Uuid::nil());
+    ) -> Result<RevisionsResponse, RepoError> {
+        self.uploader_of(post_id).await?;
 
-        let parsed_rows: Vec<(Post, f64)> = match direction {
-            KeysetDirection::Next => sqlx::query!(
-                r#"
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_keyset_limit(&cursor), false);
+        let last_id = keyset.after.map(|(_, id)| id);
+        let limit = keyset.limit + 1;
+
+        let rows = match keyset.direction {
+            KeysetDirection::Next => {
+                sqlx::query_as!(
+                    RevisionRow,
+                    r#"
                     SELECT
-                        p.id,
-                        p.title,
-                        p.description,
+                        r.id,
+                        r.post_id,
+                        r.editor_id,
+                        u.username AS "editor_name?",
+                        r.created_at,
+                        r.changes AS "changes!: Json<PostChanges>",
                         COALESCE(
-                            jsonb_agg(
-                                jsonb_build_object(
-                                    'id', pt.tag_id,
+                            (
+                                SELECT jsonb_agg(jsonb_build_object(
+                                    'id', t.id,
                                     'name', t.name,
                                     'category', t.category,
                                     'count', t.post_count
-                                )
-                            ) FILTER (WHERE pt.tag_id IS NOT NULL),
+                                ))
+                                FROM tags t
+                                WHERE t.id = ANY(r.tags_added)
+                            ),
                             '[]'::jsonb
-                        ) AS "tags!: Json<Vec<TagResponse>>",
-                        (
-                            SELECT jsonb_build_object(
-                                'id', f.id,
-                                'path', f.path,
-                                'hash', f.hash,
-                                'media_type', f.media_type,
-                                'meta', f.meta,
-                                'created_at', f.created_at
-                            )
-                            FROM files f
-                            WHERE f.id = p.file_id
-                        ) AS "file!: Json<FileResponse>",
-                        0::bigint AS "should_score!: i64"
-                    FROM posts p
-                    LEFT JOIN post_tags pt ON pt.post_id = p.id
-                    LEFT JOIN tags t ON t.id = pt.tag_id
-                    LEFT JOIN files f ON f.id = p.file_id
-                    WHERE $1 = false OR p.id < $2
-                    GROUP BY p.id
-                    ORDER BY p.id DESC
+                        ) AS "tags_added!: Json<Vec<TagResponse>>",
+                        COALESCE(
+                            (
+                                SELECT jsonb_agg(jsonb_build_object(
+                                    'id', t.id,
+                                    'name', t.name,
+                                    'category', t.category,
+                                    'count', t.post_count
+                                ))
+                                FROM tags t
+                                WHERE t.id = ANY(r.tags_removed)
+                            ),
+                            '[]'::jsonb
+                        ) AS "tags_removed!: Json<Vec<TagResponse>>"
+                    FROM post_revisions r
+                    LEFT JOIN users u ON u.id = r.editor_id
+                    WHERE r.post_id = $1 AND ($2::uuid IS NULL OR r.id < $2)
+                    ORDER BY r.id DESC
                     LIMIT $3
                     "#,
-                use_cursor,
-                last_id,
-                query_limit,
-            )
-            .fetch_all(&self.pool)
-            .await
-            .map(|rows| {
-                rows.into_iter()
-                    .map(|row| {
-                        (
-                            Post {
-                                id: row.id,
-                                title: row.title,
-                                description: row.description,
-                                file: row.file.0.into(),
-                                tags: row.tags.0.into_iter().map(Tag::from).collect(),
-                                //TODO load notes
-                                notes: vec![],
-                            },
-                            row.should_score as f64,
-                        )
-                    })
-                    .collect()
-            }),
-            KeysetDirection::Prev => sqlx::query!(
-                r#"
+                    post_id,
+                    last_id,
+                    limit
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+            KeysetDirection::Prev => {
+                sqlx::query_as!(
+                    RevisionRow,
+                    r#"
                     SELECT
-                        p.id,
-                        p.title,
-                        p.description,
+                        r.id,
+                        r.post_id,
+                        r.editor_id,
+                        u.username AS "editor_name?",
+                        r.created_at,
+                        r.changes AS "changes!: Json<PostChanges>",
+                        COALESCE(
+                            (
+                                SELECT jsonb_agg(jsonb_build_object(
+                                    'id', t.id,
+                                    'name', t.name,
+                                    'category', t.category,
+                                    'count', t.post_count
+                                ))
+                                FROM tags t
+                                WHERE t.id = ANY(r.tags_added)
+                            ),
+                            '[]'::jsonb
+                        ) AS "tags_added!: Json<Vec<TagResponse>>",
                         COALESCE(
-                            jsonb_agg(
-                                jsonb_build_object(
-                                    'id', pt.tag_id,
+                            (
+                                SELECT jsonb_agg(jsonb_build_object(
+                                    'id', t.id,
                                     'name', t.name,
                                     'category', t.category,
                                     'count', t.post_count
-                                )
-                            ) FILTER (WHERE pt.tag_id IS NOT NULL),
+                                ))
+                                FROM tags t
+                                WHERE t.id = ANY(r.tags_removed)
+                            ),
                             '[]'::jsonb
-                        ) AS "tags!: Json<Vec<TagResponse>>",
-                        (
-                            SELECT jsonb_build_object(
-                                'id', f.id,
-                                'path', f.path,
-                                'hash', f.hash,
-                                'media_type', f.media_type,
-                                'meta', f.meta,
-                                'created_at', f.created_at
-                            )
-                            FROM files f
-                            WHERE f.id = p.file_id
-                        ) AS "file!: Json<FileResponse>",
-                        0::bigint AS "should_score!: i64"
-                    FROM posts p
-                    LEFT JOIN post_tags pt ON pt.post_id = p.id
-                    LEFT JOIN tags t ON t.id = pt.tag_id
-                    LEFT JOIN files f ON f.id = p.file_id
-                    WHERE $1 = false OR p.id > $2
-                    GROUP BY p.id
-                    ORDER BY p.id ASC
+                        ) AS "tags_removed!: Json<Vec<TagResponse>>"
+                    FROM post_revisions r
+                    LEFT JOIN users u ON u.id = r.editor_id
+                    WHERE r.post_id = $1 AND ($2::uuid IS NULL OR r.id > $2)
+                    ORDER BY r.id
                     LIMIT $3
                     "#,
-                use_cursor,
-                last_id,
-                query_limit,
-            )
-            .fetch_all(&self.pool)
-            .await
-            .map(|rows| {
-                rows.into_iter()
-                    .map(|row| {
-                        (
-                            Post {
-                                id: row.id,
-                                title: row.title,
-                                description: row.description,
-                                file: row.file.0.into(),
-                                tags: row.tags.0.into_iter().map(Tag::from).collect(),
-                                //TODO load notes
-                                notes: vec![],
-                            },
-                            row.should_score as f64,
-                        )
+                    post_id,
+                    last_id,
+                    limit
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+        }
+        .map_err(|err| {
+            log::error!(
+                "posts.list_revisions failed to load revisions of {}: {err}",
+                post_id
+            );
+            RepoError::StorageError
+        })?;
+
+        let entries = rows
+            .into_iter()
+            .map(|row| (PostRevision::from(row), 0.0))
+            .collect();
+
+        let page = build_keyset_page(entries, |revision: &PostRevision| revision.id, &keyset);
+
+        Ok(RevisionsResponse {
+            revisions: page.entries,
+            has_next: page.has_next,
+            has_prev: page.has_prev,
+            next_cursor: page.next_cursor,
+            prev_cursor: page.prev_cursor,
+        })
+    }
+
+    async fn rollback(
+        &self,
+        post_id: PostID,
+        revision_id: RevisionID,
+        editor_id: UserID,
+    ) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!(
+                "posts.rollback failed to begin transaction for {}: {err}",
+                post_id
+            );
+            RepoError::StorageError
+        })?;
+
+        let before = Self::snapshot(&mut tx, post_id, "posts.rollback").await?;
+
+        let target = sqlx::query!(
+            "SELECT id FROM post_revisions WHERE id = $1 AND post_id = $2",
+            revision_id,
+            post_id
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "posts.rollback failed to load revision {}: {err}",
+                revision_id
+            );
+            RepoError::StorageError
+        })?;
+
+        if target.is_none() {
+            return Err(RepoError::NotFound);
+        }
+
+        let newer = sqlx::query!(
+            r#"
+            SELECT
+                changes AS "changes!: Json<PostChanges>",
+                tags_added,
+                tags_removed
+            FROM post_revisions
+            WHERE post_id = $1 AND id > $2
+            ORDER BY id DESC
+            "#,
+            post_id,
+            revision_id
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "posts.rollback failed to load revisions of {}: {err}",
+                post_id
+            );
+            RepoError::StorageError
+        })?;
+
+        // Undo the newer revisions one by one, newest first
+        let mut title = before.title.clone();
+        let mut description = before.description.clone();
+        let mut rating = before.rating;
+        let mut tag_ids = before.tag_ids.clone();
+        let mut notes = before.notes.clone();
+        for revision in newer {
+            let changes = revision.changes.0;
+            if let Some(change) = changes.title {
+                title = change.old;
+            }
+            if let Some(change) = changes.description {
+                description = change.old;
+            }
+            if let Some(change) = changes.rating {
+                rating = change.old;
+            }
+            if let Some(change) = changes.notes {
+                notes = change.old;
+            }
+            tag_ids.retain(|tag_id| !revision.tags_added.contains(tag_id));
+            for tag_id in revision.tags_removed {
+                if !tag_ids.contains(&tag_id) {
+                    tag_ids.push(tag_id);
+                }
+            }
+        }
+
+        // Tags deleted since can't come back
+        let tag_ids = sqlx::query_scalar!(
+            "SELECT id FROM tags WHERE id = ANY($1) ORDER BY id",
+            &tag_ids
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "posts.rollback failed to resolve tags for {}: {err}",
+                post_id
+            );
+            RepoError::StorageError
+        })?;
+
+        let same_tags = tag_ids.len() == before.tag_ids.len()
+            && tag_ids.iter().all(|tag_id| before.tag_ids.contains(tag_id));
+        let update = UpdatePost {
+            title: (title != before.title).then_some(title),
+            // A description can't be cleared, only emptied
+            description: (description != before.description)
+                .then(|| description.unwrap_or_default()),
+            rating: (rating != before.rating).then_some(rating),
+            tag_ids: (!same_tags).then_some(tag_ids),
+            notes: (!same_notes(&before.notes, &notes)).then(|| {
+                notes
+                    .into_iter()
+                    .map(|note| UpdatePostNote {
+                        id: Some(note.id),
+                        text: note.text,
+                        x: note.x,
+                        y: note.y,
                     })
                     .collect()
             }),
+        };
+
+        Self::apply_update(
+            &mut tx,
+            post_id,
+            editor_id,
+            before,
+            update,
+            "posts.rollback",
+        )
+        .await?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "posts.rollback failed to commit transaction for {}: {err}",
+                post_id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn trashed(
+        &self,
+        uploader_id: Option<UserID>,
+        cursor: KeysetCursor,
+    ) -> Result<TrashResponse, RepoError> {
+        let keyset = KeysetArgs::from_cursor(&cursor, Self::resolve_keyset_limit(&cursor), true);
+        let after = keyset.after.map(|(score, id)| (score as i64, id));
+        let limit = keyset.limit + 1;
+
+        // Keyed by deletion time in microseconds, which stays exact in the
+        // f64 cursor
+        let rows = match keyset.direction {
+            KeysetDirection::Next => {
+                sqlx::query_as!(
+                    TrashedPostRow,
+                    r#"
+                    SELECT
+                        id,
+                        title,
+                        uploader_id,
+                        deleted_at AS "deleted_at!",
+                        deleted_by,
+                        (EXTRACT(EPOCH FROM deleted_at) * 1000000)::bigint AS "score!"
+                    FROM posts
+                    WHERE deleted_at IS NOT NULL
+                        AND ($1::uuid IS NULL OR uploader_id = $1)
+                        AND (
+                            $2::bigint IS NULL
+                            OR ((EXTRACT(EPOCH FROM deleted_at) * 1000000)::bigint, id) < ($2, $3)
+                        )
+                    ORDER BY deleted_at DESC, id DESC
+                    LIMIT $4
+                    "#,
+                    uploader_id,
+                    after.map(|(score, _)| score),
+                    after.map(|(_, id)| id),
+                    limit
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+            KeysetDirection::Prev => {
+                sqlx::query_as!(
+                    TrashedPostRow,
+                    r#"
+                    SELECT
+                        id,
+                        title,
+                        uploader_id,
+                        deleted_at AS "deleted_at!",
+                        deleted_by,
+                        (EXTRACT(EPOCH FROM deleted_at) * 1000000)::bigint AS "score!"
+                    FROM posts
+                    WHERE deleted_at IS NOT NULL
+                        AND ($1::uuid IS NULL OR uploader_id = $1)
+                        AND (
+                            $2::bigint IS NULL
+                            OR ((EXTRACT(EPOCH FROM deleted_at) * 1000000)::bigint, id) > ($2, $3)
+                        )
+                    ORDER BY deleted_at, id
+                    LIMIT $4
+                    "#,
+                    uploader_id,
+                    after.map(|(score, _)| score),
+                    after.map(|(_, id)| id),
+                    limit
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+        }
+        .map_err(|err| {
+            log::error!("posts.trashed db query failed: {err}");
+            RepoError::StorageError
+        })?;
+
+        let entries = rows
+            .into_iter()
+            .map(|row| {
+                let score = row.score as f64;
+                (TrashedPost::from(row), score)
+            })
+            .collect();
+
+        let page = build_keyset_page(entries, |post: &TrashedPost| post.id, &keyset);
+
+        Ok(TrashResponse {
+            posts: page.entries,
+            has_next: page.has_next,
+            has_prev: page.has_prev,
+            next_cursor: page.next_cursor,
+            prev_cursor: page.prev_cursor,
+        })
+    }
+
+    async fn trashed_uploader_of(&self, id: PostID) -> Result<Option<UserID>, RepoError> {
+        let row = sqlx::query!(
+            "SELECT uploader_id FROM posts WHERE id = $1 AND deleted_at IS NOT NULL",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.trashed_uploader_of failed for {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        row.map(|row| row.uploader_id).ok_or(RepoError::NotFound)
+    }
+
+    async fn restore(&self, id: PostID) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE posts
+            SET deleted_at = NULL, deleted_by = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("posts.restore failed for {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn purge_trashed(
+        &self,
+        deleted_before: OffsetDateTime,
+        limit: i64,
+    ) -> Result<PurgeReport, RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("posts.purge_trashed failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        let posts = sqlx::query!(
+            r#"
+            SELECT id, file_id
+            FROM posts
+            WHERE deleted_at < $1
+            ORDER BY deleted_at
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+            "#,
+            deleted_before,
+            limit
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!("posts.purge_trashed failed to pick posts: {err}");
+            RepoError::StorageError
+        })?;
+
+        if posts.is_empty() {
+            return Ok(PurgeReport::default());
         }
+
+        let post_ids: Vec<PostID> = posts.iter().map(|row| row.id).collect();
+        let mut file_ids: Vec<FileID> = posts.iter().map(|row| row.file_id).collect();
+        file_ids.sort();
+        file_ids.dedup();
+
+        // Items of a purged post would be left pointing at nothing
+        sqlx::query!(
+            "DELETE FROM playlist_items WHERE post_id = ANY($1)",
+            &post_ids
+        )
+        .execute(&mut *tx)
+        .await
         .map_err(|err| {
-            log::error!("posts.get_all_keyset db query failed: {err}");
+            log::error!("posts.purge_trashed failed to drop playlist items: {err}");
             RepoError::StorageError
         })?;
 
-        Ok(Self::build_keyset_response(
-            parsed_rows,
-            limit,
-            direction,
-            use_cursor,
-        ))
+        let purged = sqlx::query!("DELETE FROM posts WHERE id = ANY($1)", &post_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!("posts.purge_trashed failed to delete posts: {err}");
+                RepoError::StorageError
+            })?
+            .rows_affected();
+
+        // Thumbnails go with their file (ON DELETE CASCADE), so their paths
+        // are read from the snapshot the statement started with
+        let files = sqlx::query!(
+            r#"
+            WITH gone AS (
+                DELETE FROM files f
+                WHERE f.id = ANY($1)
+                    AND NOT EXISTS (SELECT 1 FROM posts p WHERE p.file_id = f.id)
+                    AND NOT EXISTS (SELECT 1 FROM playlists pl WHERE pl.cover_file_id = f.id)
+                RETURNING f.id, f.path
+            )
+            SELECT
+                gone.id,
+                gone.path,
+                ARRAY(SELECT t.path FROM thumbnails t WHERE t.file_id = gone.id) AS "thumbnails!"
+            FROM gone
+            "#,
+            &file_ids
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!("posts.purge_trashed failed to delete files: {err}");
+            RepoError::StorageError
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!("posts.purge_trashed failed to commit transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        Ok(PurgeReport {
+            posts: purged,
+            files: files
+                .into_iter()
+                .map(|row| PurgedFile {
+                    id: row.id,
+                    path: PathBuf::from(row.path),
+                    thumbnails: row.thumbnails.into_iter().map(PathBuf::from).collect(),
+                })
+                .collect(),
+        })
     }
 }
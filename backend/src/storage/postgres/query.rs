@@ -0,0 +1,1242 @@
+use crate::application::contracts::{
+    KeysetCursor, KeysetDirection, KeysetPageCursor, PaginationMode, PostOrder, TagQuery,
+};
+use crate::domain::model::{PlaylistVisibility, PostRating, RepoError, TagID, UserID};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+// Composable search over taggable entities (posts, playlists).
+//
+// Every listing is built from the same three stages:
+//   matched - ids passing ownership/text/tag filters plus their `should` score,
+//             driven from the rarest `must` tag when there is one
+//   page    - keyset predicate or offset, sort by (score, id), limit
+//   final   - entity projection joined back on the page ids
+// so filters are resolved on ids only and the heavy JSON projection is built
+// just for rows that end up in the response.
+
+#[derive(Clone, Copy, Debug)]
+pub enum SearchTarget {
+    Posts,
+    Playlists,
+}
+
+impl SearchTarget {
+    fn table(self) -> &'static str {
+        match self {
+            SearchTarget::Posts => "posts",
+            SearchTarget::Playlists => "playlists",
+        }
+    }
+
+    fn tag_table(self) -> &'static str {
+        match self {
+            SearchTarget::Posts => "post_tags",
+            SearchTarget::Playlists => "playlist_tags",
+        }
+    }
+
+    fn tag_owner_column(self) -> &'static str {
+        match self {
+            SearchTarget::Posts => "post_id",
+            SearchTarget::Playlists => "playlist_id",
+        }
+    }
+
+    // Columns are selected from the entity aliased as `e`; `page` provides
+    // `score` and `full_count`.
+    pub(crate) fn projection(self) -> &'static str {
+        match self {
+            SearchTarget::Posts => {
+                r#"
+                e.id,
+                e.title,
+                e.description,
+                e.uploader_id,
+                COALESCE(
+                    (
+                        SELECT jsonb_agg(
+                            jsonb_build_object(
+                                'id', t.id,
+                                'name', t.name,
+                                'category', t.category,
+                                'count', t.post_count
+                            )
+                        )
+                        FROM post_tags pt
+                        JOIN tags t ON t.id = pt.tag_id
+                        WHERE pt.post_id = e.id
+                    ),
+                    '[]'::jsonb
+                ) AS tags,
+                (
+                    SELECT jsonb_build_object(
+                        'id', f.id,
+                        'path', f.path,
+                        'hash', f.hash,
+                        'media_type', f.media_type,
+                        'meta', f.meta,
+                        'created_at', f.created_at
+                    )
+                    FROM files f
+                    WHERE f.id = e.file_id
+                ) AS file,
+                e.score AS vote_score,
+                e.rating,
+                (
+                    SELECT COUNT(*)
+                    FROM post_comments c
+                    WHERE c.post_id = e.id AND c.deleted_at IS NULL
+                )::bigint AS comment_count
+                "#
+            }
+            SearchTarget::Playlists => {
+                r#"
+                e.id,
+                e.title,
+                COALESCE(e.description, '') AS description,
+                COALESCE(e.cover_file_id, e.auto_cover_file_id) AS cover,
+                (e.cover_file_id IS NULL AND e.auto_cover_file_id IS NOT NULL) AS cover_is_auto,
+                e.visibility,
+                (e.smart_query IS NOT NULL) AS is_smart,
+                (
+                    SELECT COUNT(*)
+                    FROM playlist_items pi
+                    WHERE pi.playlist_id = e.id
+                )::bigint AS item_count,
+                COALESCE(
+                    (
+                        SELECT jsonb_agg(
+                            jsonb_build_object(
+                                'id', t.id,
+                                'name', t.name,
+                                'category', t.category,
+                                'count', t.post_count
+                            )
+                            ORDER BY t.name
+                        )
+                        FROM playlist_tags plt
+                        JOIN tags t ON t.id = plt.tag_id
+                        WHERE plt.playlist_id = e.id
+                    ),
+                    '[]'::jsonb
+                ) AS tags
+                "#
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Page {
+    Offset {
+        limit: i64,
+        offset: i64,
+    },
+    Keyset {
+        direction: KeysetDirection,
+        after: Option<(f64, Uuid)>,
+        limit: i64,
+    },
+}
+
+// Tag names resolved to ids before the search runs. A name can map to several
+// ids (one per category), so every name is kept as its own group.
+#[derive(Clone, Debug, Default)]
+pub struct TagFilter {
+    // Ordered rarest first; the first group drives the scan
+    must: Vec<Vec<TagID>>,
+    should: Vec<Vec<TagID>>,
+    must_not: Vec<TagID>,
+    matches_nothing: bool,
+}
+
+impl TagFilter {
+    pub async fn resolve(pool: &PgPool, query: &TagQuery) -> Result<Self, RepoError> {
+        let must = dedup(&query.must);
+        let should = dedup(&query.should);
+        let must_not = dedup(&query.must_not);
+
+        let names: Vec<String> = must
+            .iter()
+            .chain(should.iter())
+            .chain(must_not.iter())
+            .cloned()
+            .collect();
+
+        if names.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let rows = sqlx::query!(
+            "SELECT id, name, post_count FROM tags WHERE name = ANY($1)",
+            &names[..]
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|err| {
+            log::error!("search failed to resolve tag names {names:?}: {err}");
+            RepoError::StorageError
+        })?;
+
+        let group = |name: &String| -> (Vec<TagID>, i64) {
+            rows.iter().filter(|row| &row.name == name).fold(
+                (Vec::new(), 0),
+                |(mut ids, count), row| {
+                    ids.push(row.id);
+                    (ids, count + i64::from(row.post_count))
+                },
+            )
+        };
+
+        let mut must_groups: Vec<(Vec<TagID>, i64)> = must.iter().map(group).collect();
+        // A required tag nobody has: skip the scan entirely
+        let matches_nothing = must_groups.iter().any(|(ids, _)| ids.is_empty());
+        must_groups.sort_by_key(|(_, count)| *count);
+
+        Ok(Self {
+            must: must_groups.into_iter().map(|(ids, _)| ids).collect(),
+            should: should
+                .iter()
+                .map(|name| group(name).0)
+                .filter(|ids| !ids.is_empty())
+                .collect(),
+            must_not: must_not.iter().flat_map(|name| group(name).0).collect(),
+            matches_nothing,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchQuery {
+    target: SearchTarget,
+    tags: TagFilter,
+    text_pattern: Option<String>,
+    member_id: Option<UserID>,
+    visibility: Option<PlaylistVisibility>,
+    favorited_by: Option<UserID>,
+    order: PostOrder,
+    ratings: Option<Vec<PostRating>>,
+    page: Page,
+}
+
+impl SearchQuery {
+    pub fn new(target: SearchTarget) -> Self {
+        Self {
+            target,
+            tags: TagFilter::default(),
+            text_pattern: None,
+            member_id: None,
+            visibility: None,
+            favorited_by: None,
+            order: PostOrder::Relevance,
+            ratings: None,
+            page: Page::Keyset {
+                direction: KeysetDirection::Next,
+                after: None,
+                limit: 1,
+            },
+        }
+    }
+
+    pub fn tags(mut self, tags: TagFilter) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        let text = text.trim();
+        self.text_pattern = (!text.is_empty()).then(|| format!("%{text}%"));
+        self
+    }
+
+    // Playlists owned by the user or shared with them as a collaborator
+    pub fn accessible_to(mut self, user_id: UserID) -> Self {
+        self.member_id = Some(user_id);
+        self
+    }
+
+    pub fn visibility(mut self, visibility: PlaylistVisibility) -> Self {
+        self.visibility = Some(visibility);
+        self
+    }
+
+    // Posts in the user's favorites
+    pub fn favorited_by(mut self, user_id: UserID) -> Self {
+        self.favorited_by = Some(user_id);
+        self
+    }
+
+    // Posts with one of these ratings
+    pub fn ratings(mut self, ratings: Vec<PostRating>) -> Self {
+        self.ratings = Some(ratings);
+        self
+    }
+
+    // Posts only; Favorited needs `favorited_by`
+    pub fn order(mut self, order: PostOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn offset(mut self, limit: i64, page: i64) -> Self {
+        self.page = Page::Offset {
+            limit,
+            offset: page.max(0) * limit,
+        };
+        self
+    }
+
+    pub fn keyset(mut self, keyset: &KeysetArgs) -> Self {
+        self.page = Page::Keyset {
+            direction: keyset.direction.clone(),
+            after: keyset.after,
+            // One extra row tells whether another page exists in this direction
+            limit: keyset.limit + 1,
+        };
+        self
+    }
+
+    pub fn build(&self) -> QueryBuilder<'static, Postgres> {
+        let mut qb = QueryBuilder::new("WITH matched AS (SELECT e.id, ");
+        self.push_score(&mut qb);
+        qb.push(" AS score FROM ");
+        self.push_source(&mut qb);
+        qb.push(" WHERE TRUE");
+        self.push_filters(&mut qb);
+        qb.push("), page AS (SELECT id, score, ");
+        // The total is only reported for offset pages; counting keyset pages
+        // would force the whole match set to be materialized
+        match self.page {
+            Page::Offset { .. } => qb.push("COUNT(*) OVER()"),
+            Page::Keyset { .. } => qb.push("0::bigint"),
+        };
+        qb.push(" AS full_count FROM matched");
+        self.push_page(&mut qb);
+        qb.push(") SELECT page.score, page.full_count, ");
+        qb.push(self.target.projection());
+        qb.push(" FROM page JOIN ");
+        qb.push(self.target.table());
+        qb.push(" e ON e.id = page.id ORDER BY ");
+        qb.push(self.order_by("page."));
+        qb
+    }
+
+    // With a required tag the scan starts from the rarest tag's link rows
+    // (tag_id index) instead of the whole entity table, and otherwise from
+    // the user's favorites when only those are wanted.
+    fn push_source(&self, qb: &mut QueryBuilder<'static, Postgres>) {
+        let Some(driving) = self.tags.must.first() else {
+            if let Some(user_id) = self.favorited_by {
+                qb.push("post_favorites d JOIN posts e ON e.id = d.post_id AND d.user_id = ");
+                qb.push_bind(user_id);
+                return;
+            }
+            qb.push(self.target.table());
+            qb.push(" e");
+            return;
+        };
+
+        qb.push("(SELECT ");
+        if driving.len() > 1 {
+            qb.push("DISTINCT ");
+        }
+        qb.push("d.");
+        qb.push(self.target.tag_owner_column());
+        qb.push(" AS id FROM ");
+        qb.push(self.target.tag_table());
+        qb.push(" d WHERE d.tag_id = ANY(");
+        qb.push_bind(driving.clone());
+        qb.push(")) d JOIN ");
+        qb.push(self.target.table());
+        qb.push(" e ON e.id = d.id");
+    }
+
+    fn push_has_tag(&self, qb: &mut QueryBuilder<'static, Postgres>, tag_ids: &[TagID]) {
+        qb.push("EXISTS (SELECT 1 FROM ");
+        qb.push(self.target.tag_table());
+        qb.push(" x WHERE x.");
+        qb.push(self.target.tag_owner_column());
+        qb.push(" = e.id AND x.tag_id = ANY(");
+        qb.push_bind(tag_ids.to_vec());
+        qb.push("))");
+    }
+
+    fn push_score(&self, qb: &mut QueryBuilder<'static, Postgres>) {
+        match (self.order, self.favorited_by) {
+            (PostOrder::Score, _) => {
+                qb.push("e.score::bigint");
+                return;
+            }
+            // Microseconds stay exact through the f64 keyset cursor
+            (PostOrder::Favorited, Some(user_id)) => {
+                qb.push(
+                    "(SELECT (EXTRACT(EPOCH FROM f.created_at) * 1000000)::bigint \
+                     FROM post_favorites f WHERE f.post_id = e.id AND f.user_id = ",
+                );
+                qb.push_bind(user_id);
+                qb.push(")");
+                return;
+            }
+            _ => {}
+        }
+
+        if self.tags.should.is_empty() {
+            qb.push("0::bigint");
+            return;
+        }
+
+        qb.push("(");
+        for (i, group) in self.tags.should.iter().enumerate() {
+            if i > 0 {
+                qb.push(" + ");
+            }
+            qb.push("(");
+            self.push_has_tag(qb, group);
+            qb.push(")::int");
+        }
+        qb.push(")::bigint");
+    }
+
+    fn push_filters(&self, qb: &mut QueryBuilder<'static, Postgres>) {
+        if self.tags.matches_nothing {
+            qb.push(" AND FALSE");
+            return;
+        }
+
+        // Trashed posts stay hidden until restored or purged
+        if matches!(self.target, SearchTarget::Posts) {
+            qb.push(" AND e.deleted_at IS NULL");
+        }
+
+        if let Some(member_id) = self.member_id {
+            qb.push(" AND (e.owner_id = ");
+            qb.push_bind(member_id);
+            qb.push(
+                " OR EXISTS (SELECT 1 FROM playlist_collaborators c \
+                 WHERE c.playlist_id = e.id AND c.user_id = ",
+            );
+            qb.push_bind(member_id);
+            qb.push("))");
+        }
+
+        // Implied by the source unless a tag drives the scan
+        if let Some(user_id) = self.favorited_by
+            && !self.tags.must.is_empty()
+        {
+            qb.push(
+                " AND EXISTS (SELECT 1 FROM post_favorites f \
+                 WHERE f.post_id = e.id AND f.user_id = ",
+            );
+            qb.push_bind(user_id);
+            qb.push(")");
+        }
+
+        if let Some(ratings) = &self.ratings {
+            qb.push(" AND e.rating = ANY(");
+            qb.push_bind(ratings.iter().copied().map(i16::from).collect::<Vec<i16>>());
+            qb.push(")");
+        }
+
+        if let Some(visibility) = self.visibility {
+            qb.push(" AND e.visibility = ");
+            qb.push_bind(i16::from(visibility));
+        }
+
+        if let Some(pattern) = &self.text_pattern {
+            qb.push(" AND (e.title ILIKE ");
+            qb.push_bind(pattern.clone());
+            qb.push(" OR COALESCE(e.description, '') ILIKE ");
+            qb.push_bind(pattern.clone());
+            qb.push(")");
+        }
+
+        for group in self.tags.must.iter().skip(1) {
+            qb.push(" AND ");
+            self.push_has_tag(qb, group);
+        }
+
+        if !self.tags.must_not.is_empty() {
+            qb.push(" AND NOT ");
+            self.push_has_tag(qb, &self.tags.must_not);
+        }
+    }
+
+    fn push_page(&self, qb: &mut QueryBuilder<'static, Postgres>) {
+        match &self.page {
+            Page::Offset { limit, offset } => {
+                qb.push(" ORDER BY ");
+                qb.push(self.order_by(""));
+                qb.push(" LIMIT ");
+                qb.push_bind(*limit);
+                qb.push(" OFFSET ");
+                qb.push_bind(*offset);
+            }
+            Page::Keyset {
+                direction,
+                after,
+                limit,
+            } => {
+                if let Some((last_score, last_id)) = after {
+                    let cmp = match direction {
+                        KeysetDirection::Next => " < ",
+                        KeysetDirection::Prev => " > ",
+                    };
+                    qb.push(" WHERE (score::double precision");
+                    qb.push(cmp);
+                    qb.push_bind(*last_score);
+                    qb.push(" OR (score::double precision = ");
+                    qb.push_bind(*last_score);
+                    qb.push(" AND id");
+                    qb.push(cmp);
+                    qb.push_bind(*last_id);
+                    qb.push("))");
+                }
+                qb.push(" ORDER BY ");
+                qb.push(self.order_by(""));
+                qb.push(" LIMIT ");
+                qb.push_bind(*limit);
+            }
+        }
+    }
+
+    fn order_by(&self, prefix: &str) -> String {
+        let order = match &self.page {
+            Page::Keyset {
+                direction: KeysetDirection::Prev,
+                ..
+            } => "ASC",
+            _ => "DESC",
+        };
+        format!("{prefix}score {order}, {prefix}id {order}")
+    }
+}
+
+fn dedup(values: &[String]) -> Vec<String> {
+    let mut result: Vec<String> = Vec::with_capacity(values.len());
+    for value in values {
+        if !result.contains(value) {
+            result.push(value.clone());
+        }
+    }
+    result
+}
+
+// Resolved keyset position taken from a client cursor.
+// Unscored listings ignore `last_score` and page on id alone.
+#[derive(Clone, Debug)]
+pub struct KeysetArgs {
+    pub direction: KeysetDirection,
+    pub after: Option<(f64, Uuid)>,
+    pub limit: i64,
+}
+
+impl KeysetArgs {
+    pub fn from_cursor(cursor: &KeysetCursor, limit: i64, scored: bool) -> Self {
+        let after = match (cursor.last_id, cursor.last_score) {
+            (Some(last_id), Some(last_score)) if scored => Some((last_score, last_id)),
+            (Some(last_id), _) if !scored => Some((0.0, last_id)),
+            _ => None,
+        };
+        let direction = if after.is_some() {
+            cursor.direction.clone().unwrap_or_default()
+        } else {
+            KeysetDirection::Next
+        };
+
+        Self {
+            direction,
+            after,
+            limit,
+        }
+    }
+
+    pub fn use_cursor(&self) -> bool {
+        self.after.is_some()
+    }
+}
+
+pub struct KeysetPage<T> {
+    pub entries: Vec<T>,
+    pub has_next: bool,
+    pub has_prev: bool,
+    pub next_cursor: Option<KeysetPageCursor>,
+    pub prev_cursor: Option<KeysetPageCursor>,
+}
+
+pub fn build_keyset_page<T>(
+    mut entries: Vec<(T, f64)>,
+    id_of: fn(&T) -> Uuid,
+    keyset: &KeysetArgs,
+) -> KeysetPage<T> {
+    let limit = keyset.limit;
+    let use_cursor = keyset.use_cursor();
+    let has_more_in_direction = entries.len() as i64 > limit;
+    if has_more_in_direction {
+        entries.truncate(limit as usize);
+    }
+
+    let is_prev = matches!(keyset.direction, KeysetDirection::Prev);
+    if is_prev {
+        entries.reverse();
+    }
+
+    let has_next = if is_prev {
+        use_cursor
+    } else {
+        has_more_in_direction
+    };
+    let has_prev = if is_prev {
+        has_more_in_direction
+    } else {
+        use_cursor
+    };
+
+    let page_cursor = |(entry, score): &(T, f64), direction: KeysetDirection| KeysetPageCursor {
+        mode: PaginationMode::Keyset,
+        direction,
+        last_id: id_of(entry),
+        last_score: *score,
+        limit,
+    };
+
+    let next_cursor = if has_next {
+        entries
+            .last()
+            .map(|entry| page_cursor(entry, KeysetDirection::Next))
+    } else {
+        None
+    };
+    let prev_cursor = if has_prev {
+        entries
+            .first()
+            .map(|entry| page_cursor(entry, KeysetDirection::Prev))
+    } else {
+        None
+    };
+
+    KeysetPage {
+        entries: entries.into_iter().map(|(entry, _)| entry).collect(),
+        has_next,
+        has_prev,
+        next_cursor,
+        prev_cursor,
+    }
+}
+
+pub fn count_total_pages(full_count: i64, limit: i64) -> i64 {
+    if full_count == 0 {
+        0
+    } else {
+        (full_count + limit - 1) / limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::contracts::{
+        Cursor, PlaylistQuery, SearchPlaylistsResponse, SearchPostsKeysetResponse,
+    };
+    use crate::application::ports::{PlaylistRepository, PostRepository};
+    use crate::storage::postgres::playlists::PostgresPlaylistRepository;
+    use crate::storage::postgres::posts::PostgresPostRepository;
+    use crate::storage::postgres::testing::load_schema;
+
+    const OWNER_ID: Uuid = Uuid::from_u128(1);
+    const OTHER_ID: Uuid = Uuid::from_u128(2);
+
+    fn keyset_cursor(
+        last_id: Option<Uuid>,
+        last_score: Option<f64>,
+        direction: Option<KeysetDirection>,
+    ) -> KeysetCursor {
+        KeysetCursor {
+            mode: Some(PaginationMode::Keyset),
+            last_id,
+            last_score,
+            limit: Some(3),
+            direction,
+        }
+    }
+
+    fn ids(values: &[u128]) -> Vec<Uuid> {
+        values.iter().copied().map(Uuid::from_u128).collect()
+    }
+
+    fn cursor_position(cursor: &Option<KeysetPageCursor>) -> Option<(Uuid, f64)> {
+        cursor
+            .as_ref()
+            .map(|cursor| (cursor.last_id, cursor.last_score))
+    }
+
+    #[test]
+    fn total_pages_round_up() {
+        assert_eq!(count_total_pages(0, 20), 0);
+        assert_eq!(count_total_pages(1, 20), 1);
+        assert_eq!(count_total_pages(20, 20), 1);
+        assert_eq!(count_total_pages(21, 20), 2);
+    }
+
+    #[test]
+    fn scored_cursor_needs_id_and_score() {
+        let id = Uuid::from_u128(7);
+
+        let keyset = KeysetArgs::from_cursor(
+            &keyset_cursor(Some(id), Some(2.0), Some(KeysetDirection::Prev)),
+            3,
+            true,
+        );
+        assert_eq!(keyset.after, Some((2.0, id)));
+        assert!(matches!(keyset.direction, KeysetDirection::Prev));
+        assert!(keyset.use_cursor());
+
+        let keyset = KeysetArgs::from_cursor(
+            &keyset_cursor(Some(id), None, Some(KeysetDirection::Prev)),
+            3,
+            true,
+        );
+        assert_eq!(keyset.after, None);
+        assert!(matches!(keyset.direction, KeysetDirection::Next));
+    }
+
+    #[test]
+    fn unscored_cursor_ignores_score() {
+        let id = Uuid::from_u128(7);
+
+        let keyset = KeysetArgs::from_cursor(
+            &keyset_cursor(Some(id), Some(2.0), Some(KeysetDirection::Prev)),
+            3,
+            false,
+        );
+        assert_eq!(keyset.after, Some((0.0, id)));
+        assert!(matches!(keyset.direction, KeysetDirection::Prev));
+
+        let keyset = KeysetArgs::from_cursor(&keyset_cursor(Some(id), None, None), 3, false);
+        assert_eq!(keyset.after, Some((0.0, id)));
+        assert!(matches!(keyset.direction, KeysetDirection::Next));
+
+        let keyset = KeysetArgs::from_cursor(
+            &keyset_cursor(None, None, Some(KeysetDirection::Prev)),
+            3,
+            false,
+        );
+        assert!(!keyset.use_cursor());
+        assert!(matches!(keyset.direction, KeysetDirection::Next));
+    }
+
+    #[test]
+    fn keyset_page_first_page() {
+        let keyset = KeysetArgs::from_cursor(&keyset_cursor(None, None, None), 3, true);
+        let entries = vec![(5, 2.0), (4, 1.0), (3, 1.0), (2, 0.0)]
+            .into_iter()
+            .map(|(id, score)| (Uuid::from_u128(id), score))
+            .collect();
+
+        let page = build_keyset_page(entries, |id: &Uuid| *id, &keyset);
+
+        assert_eq!(page.entries, ids(&[5, 4, 3]));
+        assert!(page.has_next);
+        assert!(!page.has_prev);
+        assert_eq!(
+            cursor_position(&page.next_cursor),
+            Some((Uuid::from_u128(3), 1.0))
+        );
+        assert!(page.prev_cursor.is_none());
+    }
+
+    #[test]
+    fn keyset_page_last_page() {
+        let cursor = keyset_cursor(Some(Uuid::from_u128(3)), Some(1.0), None);
+        let keyset = KeysetArgs::from_cursor(&cursor, 3, true);
+        let entries = vec![(Uuid::from_u128(2), 0.0), (Uuid::from_u128(1), 0.0)];
+
+        let page = build_keyset_page(entries, |id: &Uuid| *id, &keyset);
+
+        assert_eq!(page.entries, ids(&[2, 1]));
+        assert!(!page.has_next);
+        assert!(page.has_prev);
+        assert!(page.next_cursor.is_none());
+        assert_eq!(
+            cursor_position(&page.prev_cursor),
+            Some((Uuid::from_u128(2), 0.0))
+        );
+    }
+
+    #[test]
+    fn keyset_page_prev_restores_order() {
+        let cursor = keyset_cursor(
+            Some(Uuid::from_u128(5)),
+            Some(0.0),
+            Some(KeysetDirection::Prev),
+        );
+        let keyset = KeysetArgs::from_cursor(&cursor, 3, true);
+        // Prev pages come back ascending, one extra row past the limit
+        let entries = vec![(6, 0.0), (7, 0.0), (8, 1.0), (9, 2.0)]
+            .into_iter()
+            .map(|(id, score)| (Uuid::from_u128(id), score))
+            .collect();
+
+        let page = build_keyset_page(entries, |id: &Uuid| *id, &keyset);
+
+        assert_eq!(page.entries, ids(&[8, 7, 6]));
+        assert!(page.has_next);
+        assert!(page.has_prev);
+        assert_eq!(
+            cursor_position(&page.next_cursor),
+            Some((Uuid::from_u128(6), 0.0))
+        );
+        assert_eq!(
+            cursor_position(&page.prev_cursor),
+            Some((Uuid::from_u128(8), 1.0))
+        );
+    }
+
+    // The queries below are the ones search ran before the shared builder,
+    // trimmed to the columns that decide membership and order. They predate
+    // the trash, ratings and collaborators, so the fixture keeps every post
+    // live and shares no playlists.
+
+    const FIXTURE: &str = r#"
+        INSERT INTO users (id, username, password_hash) VALUES
+            ('00000000-0000-0000-0000-000000000001', 'owner', 'x'),
+            ('00000000-0000-0000-0000-000000000002', 'other', 'x');
+        INSERT INTO files (id, path, media_type)
+            VALUES ('00000000-0000-0000-0000-0000000000f1', 'fixture.png', 0);
+        INSERT INTO tags (name, category)
+            VALUES ('a', 3), ('b', 3), ('b', 1), ('c', 3), ('d', 3), ('e', 3);
+
+        INSERT INTO posts (title, file_id, uploader_id)
+        SELECT i::text, '00000000-0000-0000-0000-0000000000f1', '00000000-0000-0000-0000-000000000001'
+        FROM generate_series(1, 45) i;
+
+        INSERT INTO playlists (title, description, owner_id)
+        SELECT
+            CASE WHEN i % 4 = 0 THEN 'Sun ' ELSE 'List ' END || i,
+            CASE WHEN i % 3 = 0 THEN 'Sunny beach' END,
+            CASE WHEN i <= 16
+                THEN '00000000-0000-0000-0000-000000000001'::uuid
+                ELSE '00000000-0000-0000-0000-000000000002'::uuid
+            END
+        FROM generate_series(1, 20) i;
+
+        WITH numbered AS (SELECT id, title::int AS i FROM posts)
+        INSERT INTO post_tags (post_id, tag_id)
+        SELECT n.id, t.id
+        FROM numbered n
+        JOIN tags t ON
+            (t.name = 'a' AND n.i % 2 = 0)
+            OR (t.name = 'b' AND t.category = 3 AND n.i % 3 = 0)
+            OR (t.name = 'b' AND t.category = 1 AND n.i % 4 = 1)
+            OR (t.name = 'c' AND n.i % 5 = 0)
+            OR (t.name = 'd' AND n.i % 7 = 0);
+
+        WITH numbered AS (SELECT id, row_number() OVER (ORDER BY id) AS i FROM playlists)
+        INSERT INTO playlist_tags (playlist_id, tag_id)
+        SELECT n.id, t.id
+        FROM numbered n
+        JOIN tags t ON
+            (t.name = 'a' AND n.i % 2 = 0)
+            OR (t.name = 'b' AND t.category = 3 AND n.i % 3 = 0)
+            OR (t.name = 'b' AND t.category = 1 AND n.i % 4 = 1)
+            OR (t.name = 'c' AND n.i % 5 = 0);
+    "#;
+
+    async fn seed(pool: &PgPool) {
+        load_schema(pool).await;
+        sqlx::raw_sql(FIXTURE).execute(pool).await.unwrap();
+    }
+
+    fn tag_query(must: &[&str], should: &[&str], must_not: &[&str]) -> TagQuery {
+        let names = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        TagQuery {
+            must: names(must),
+            should: names(should),
+            must_not: names(must_not),
+            ..TagQuery::default()
+        }
+    }
+
+    fn tag_cases() -> Vec<TagQuery> {
+        vec![
+            tag_query(&[], &[], &[]),
+            tag_query(&["a"], &[], &[]),
+            // `b` exists in two categories
+            tag_query(&["a", "b"], &[], &[]),
+            tag_query(&[], &["b", "c", "d"], &[]),
+            tag_query(&["a"], &["b", "c"], &["d"]),
+            tag_query(&[], &[], &["c"]),
+            tag_query(&["missing"], &[], &[]),
+            tag_query(&[], &["missing", "c"], &[]),
+            tag_query(&["e"], &[], &[]),
+        ]
+    }
+
+    // What a page looked like to the client: ids in order, the flags and
+    // the positions the cursors point at
+    #[derive(Debug, PartialEq)]
+    struct PageView {
+        ids: Vec<Uuid>,
+        has_next: bool,
+        has_prev: bool,
+        next: Option<(Uuid, f64)>,
+        prev: Option<(Uuid, f64)>,
+    }
+
+    struct OldSearch<'a> {
+        target: SearchTarget,
+        tags: &'a TagQuery,
+        // Playlists only
+        owner_id: Option<Uuid>,
+        text: &'a str,
+    }
+
+    impl OldSearch<'_> {
+        fn push_ranked(&self, qb: &mut QueryBuilder<'static, Postgres>) {
+            let table = self.target.table();
+            let tag_table = self.target.tag_table();
+            let owner_column = self.target.tag_owner_column();
+
+            qb.push("WITH ranked AS (SELECT e.id, COUNT(DISTINCT CASE WHEN t.name = ANY(");
+            qb.push_bind(self.tags.should.clone());
+            qb.push(format!(
+                ") THEN t.name END)::bigint AS should_score \
+                 FROM {table} e \
+                 LEFT JOIN {tag_table} et ON et.{owner_column} = e.id \
+                 LEFT JOIN tags t ON t.id = et.tag_id"
+            ));
+            if let Some(owner_id) = self.owner_id {
+                let text = self.text.trim();
+                let pattern = format!("%{text}%");
+                qb.push(" WHERE e.owner_id = ");
+                qb.push_bind(owner_id);
+                qb.push(" AND (");
+                qb.push_bind(!text.is_empty());
+                qb.push(" = false OR e.title ILIKE ");
+                qb.push_bind(pattern.clone());
+                qb.push(" OR COALESCE(e.description, '') ILIKE ");
+                qb.push_bind(pattern);
+                qb.push(")");
+            }
+            qb.push(" GROUP BY e.id HAVING COUNT(DISTINCT CASE WHEN t.name = ANY(");
+            qb.push_bind(self.tags.must.clone());
+            qb.push(") THEN t.name END) = cardinality(");
+            qb.push_bind(self.tags.must.clone());
+            qb.push(format!(
+                ") AND NOT EXISTS (SELECT 1 FROM {tag_table} x JOIN tags tx ON tx.id = x.tag_id \
+                 WHERE x.{owner_column} = e.id AND tx.name = ANY("
+            ));
+            qb.push_bind(self.tags.must_not.clone());
+            qb.push(")))");
+        }
+
+        async fn offset(&self, pool: &PgPool, limit: i64, page: i64) -> (Vec<Uuid>, i64) {
+            let mut qb = QueryBuilder::new("");
+            self.push_ranked(&mut qb);
+            qb.push(
+                " SELECT id, should_score, COUNT(*) OVER() FROM ranked \
+                 ORDER BY should_score DESC, id DESC LIMIT ",
+            );
+            qb.push_bind(limit);
+            qb.push(" OFFSET ");
+            qb.push_bind(page * limit);
+
+            let rows: Vec<(Uuid, i64, i64)> = qb.build_query_as().fetch_all(pool).await.unwrap();
+            let full_count = rows.first().map(|row| row.2).unwrap_or(0);
+            let total_pages = if full_count == 0 {
+                0
+            } else {
+                (full_count + limit - 1) / limit
+            };
+            (rows.into_iter().map(|row| row.0).collect(), total_pages)
+        }
+
+        async fn keyset(
+            &self,
+            pool: &PgPool,
+            cursor: &KeysetCursor,
+            limit: i64,
+            scored: bool,
+        ) -> PageView {
+            let use_cursor = if scored {
+                cursor.last_id.is_some() && cursor.last_score.is_some()
+            } else {
+                cursor.last_id.is_some()
+            };
+            let direction = if use_cursor {
+                cursor.direction.clone().unwrap_or_default()
+            } else {
+                KeysetDirection::Next
+            };
+            let is_prev = matches!(direction, KeysetDirection::Prev);
+            let (cmp, order) = if is_prev { (">", "ASC") } else { ("<", "DESC") };
+            let last_id = cursor.last_id.unwrap_or_else(Uuid::nil);
+
+            let mut qb = QueryBuilder::new("");
+            self.push_ranked(&mut qb);
+            qb.push(" SELECT id, should_score FROM ranked WHERE ");
+            qb.push_bind(use_cursor);
+            if scored {
+                let last_score = cursor.last_score.unwrap_or(f64::MAX);
+                qb.push(format!(" = false OR should_score::double precision {cmp} "));
+                qb.push_bind(last_score);
+                qb.push(" OR (should_score::double precision = ");
+                qb.push_bind(last_score);
+                qb.push(format!(" AND id {cmp} "));
+                qb.push_bind(last_id);
+                qb.push(format!(") ORDER BY should_score {order}, id {order}"));
+            } else {
+                qb.push(format!(" = false OR id {cmp} "));
+                qb.push_bind(last_id);
+                qb.push(format!(" ORDER BY id {order}"));
+            }
+            qb.push(" LIMIT ");
+            qb.push_bind(limit + 1);
+
+            let mut rows: Vec<(Uuid, i64)> = qb.build_query_as().fetch_all(pool).await.unwrap();
+            let has_more = rows.len() as i64 > limit;
+            rows.truncate(limit as usize);
+            if is_prev {
+                rows.reverse();
+            }
+            let has_next = if is_prev { use_cursor } else { has_more };
+            let has_prev = if is_prev { has_more } else { use_cursor };
+            let position = |row: &(Uuid, i64)| (row.0, row.1 as f64);
+
+            PageView {
+                ids: rows.iter().map(|row| row.0).collect(),
+                has_next,
+                has_prev,
+                next: rows.last().filter(|_| has_next).map(position),
+                prev: rows.first().filter(|_| has_prev).map(position),
+            }
+        }
+    }
+
+    fn follow(cursor: &Option<KeysetPageCursor>) -> Option<KeysetCursor> {
+        cursor.as_ref().map(|cursor| KeysetCursor {
+            mode: Some(PaginationMode::Keyset),
+            last_id: Some(cursor.last_id),
+            last_score: Some(cursor.last_score),
+            limit: Some(cursor.limit),
+            direction: Some(cursor.direction.clone()),
+        })
+    }
+
+    fn posts_view(response: &SearchPostsKeysetResponse) -> PageView {
+        PageView {
+            ids: response.posts.iter().map(|post| post.id).collect(),
+            has_next: response.has_next,
+            has_prev: response.has_prev,
+            next: cursor_position(&response.next_cursor),
+            prev: cursor_position(&response.prev_cursor),
+        }
+    }
+
+    fn playlists_view(response: &SearchPlaylistsResponse) -> PageView {
+        PageView {
+            ids: response
+                .playlists
+                .iter()
+                .map(|playlist| playlist.id)
+                .collect(),
+            has_next: response.has_next,
+            has_prev: response.has_prev,
+            next: cursor_position(&response.next_cursor),
+            prev: cursor_position(&response.prev_cursor),
+        }
+    }
+
+    // Pages forward to the end and back to the start, comparing every page
+    async fn walk<F, Fut>(old: &OldSearch<'_>, pool: &PgPool, scored: bool, mut fetch: F)
+    where
+        F: FnMut(KeysetCursor) -> Fut,
+        Fut: Future<Output = (PageView, Option<KeysetCursor>, Option<KeysetCursor>)>,
+    {
+        let limit = 7;
+        let mut cursor = KeysetCursor {
+            limit: Some(limit),
+            ..KeysetCursor::default()
+        };
+        let mut pages = 0;
+        loop {
+            let (view, next, prev) = fetch(cursor.clone()).await;
+            assert_eq!(view, old.keyset(pool, &cursor, limit, scored).await);
+            pages += 1;
+            match next {
+                Some(next) => cursor = next,
+                None => {
+                    cursor = match prev {
+                        Some(prev) => prev,
+                        None => return,
+                    };
+                    break;
+                }
+            }
+        }
+        for _ in 0..pages {
+            let (view, _, prev) = fetch(cursor.clone()).await;
+            assert_eq!(view, old.keyset(pool, &cursor, limit, scored).await);
+            match prev {
+                Some(prev) => cursor = prev,
+                None => return,
+            }
+        }
+        panic!("prev cursors never reached the first page");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn post_search_offset_matches_old_query(pool: PgPool) {
+        seed(&pool).await;
+        let repo = PostgresPostRepository::new(pool.clone());
+
+        for tags in tag_cases() {
+            let old = OldSearch {
+                target: SearchTarget::Posts,
+                tags: &tags,
+                owner_id: None,
+                text: "",
+            };
+            for page in 0..4 {
+                let response = repo.search(tags.clone(), Cursor { page }).await.unwrap();
+                let ids: Vec<Uuid> = response.posts.iter().map(|post| post.id).collect();
+                assert_eq!(
+                    (ids, response.total_pages),
+                    old.offset(&pool, 20, page).await,
+                    "{tags:?} page {page}"
+                );
+            }
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn post_search_keyset_matches_old_query(pool: PgPool) {
+        seed(&pool).await;
+        let repo = PostgresPostRepository::new(pool.clone());
+
+        for tags in tag_cases() {
+            let old = OldSearch {
+                target: SearchTarget::Posts,
+                tags: &tags,
+                owner_id: None,
+                text: "",
+            };
+            walk(&old, &pool, true, |cursor| {
+                let (repo, tags) = (repo.clone(), tags.clone());
+                async move {
+                    let response = repo.search_keyset(tags, cursor).await.unwrap();
+                    (
+                        posts_view(&response),
+                        follow(&response.next_cursor),
+                        follow(&response.prev_cursor),
+                    )
+                }
+            })
+            .await;
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn post_listing_matches_old_query(pool: PgPool) {
+        seed(&pool).await;
+        let repo = PostgresPostRepository::new(pool.clone());
+        let tags = TagQuery::default();
+        let old = OldSearch {
+            target: SearchTarget::Posts,
+            tags: &tags,
+            owner_id: None,
+            text: "",
+        };
+
+        for page in 0..4 {
+            let response = repo.get_all(Cursor { page }).await.unwrap();
+            let ids: Vec<Uuid> = response.posts.iter().map(|post| post.id).collect();
+            assert_eq!(
+                (ids, response.total_pages),
+                old.offset(&pool, 20, page).await
+            );
+        }
+
+        walk(&old, &pool, false, |cursor| {
+            let repo = repo.clone();
+            async move {
+                let response = repo.get_all_keyset(cursor).await.unwrap();
+                (
+                    posts_view(&response),
+                    follow(&response.next_cursor),
+                    follow(&response.prev_cursor),
+                )
+            }
+        })
+        .await;
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn playlist_search_matches_old_query(pool: PgPool) {
+        seed(&pool).await;
+        let repo = PostgresPlaylistRepository::new(pool.clone());
+
+        let mut cases: Vec<(TagQuery, &str)> =
+            tag_cases().into_iter().map(|tags| (tags, "")).collect();
+        cases.extend([
+            (TagQuery::default(), "sun"),
+            (TagQuery::default(), "  BEACH "),
+            (TagQuery::default(), "   "),
+            (TagQuery::default(), "nothing like it"),
+            (tag_query(&["a"], &["b"], &[]), "sun"),
+        ]);
+
+        for (tags, text) in cases {
+            let old = OldSearch {
+                target: SearchTarget::Playlists,
+                tags: &tags,
+                owner_id: Some(OWNER_ID),
+                text,
+            };
+            walk(&old, &pool, true, |cursor| {
+                let repo = repo.clone();
+                let query = PlaylistQuery {
+                    tags: tags.clone(),
+                    text: text.to_string(),
+                };
+                async move {
+                    let response = repo.search(OWNER_ID, query, cursor).await.unwrap();
+                    (
+                        playlists_view(&response),
+                        follow(&response.next_cursor),
+                        follow(&response.prev_cursor),
+                    )
+                }
+            })
+            .await;
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn playlist_listing_matches_old_query(pool: PgPool) {
+        seed(&pool).await;
+        let repo = PostgresPlaylistRepository::new(pool.clone());
+        let tags = TagQuery::default();
+
+        for owner_id in [OWNER_ID, OTHER_ID] {
+            let old = OldSearch {
+                target: SearchTarget::Playlists,
+                tags: &tags,
+                owner_id: Some(owner_id),
+                text: "",
+            };
+            walk(&old, &pool, false, |cursor| {
+                let repo = repo.clone();
+                async move {
+                    let response = repo.get_all(owner_id, cursor).await.unwrap();
+                    (
+                        playlists_view(&response),
+                        follow(&response.next_cursor),
+                        follow(&response.prev_cursor),
+                    )
+                }
+            })
+            .await;
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use crate::application::contracts::NewSession;
+use crate::application::ports::SessionRepository;
+use crate::domain::model::{RepoError, Session, SessionID, UserID};
+use async_trait::async_trait;
+use sqlx::PgPool;
+use sqlx::types::Json;
+use std::collections::HashMap;
+use time::OffsetDateTime;
+
+#[derive(Clone)]
+pub struct PostgresSessionRepository {
+    pool: PgPool,
+}
+
+impl PostgresSessionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for PostgresSessionRepository {
+    async fn load(&self, key_hash: &str) -> Result<Option<HashMap<String, String>>, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT state AS "state: Json<HashMap<String, String>>"
+            FROM sessions
+            WHERE key_hash = $1 AND expires_at > NOW()
+            "#,
+            key_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("sessions.load db query failed: {err}");
+            RepoError::StorageError
+        })?;
+
+        Ok(row.map(|row| row.state.0))
+    }
+
+    async fn create(&self, session: NewSession) -> Result<(), RepoError> {
+        // Expired rows are only ever read by this cleanup
+        sqlx::query!("DELETE FROM sessions WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await
+            .map_err(|err| {
+                log::error!("sessions.create failed to purge expired sessions: {err}");
+                RepoError::StorageError
+            })?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (id, key_hash, user_id, state, user_agent, ip, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            session.id,
+            session.key_hash,
+            session.user_id,
+            Json(&session.state) as _,
+            session.user_agent,
+            session.ip,
+            session.expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("sessions.create failed to insert {}: {err}", session.id);
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn update(
+        &self,
+        key_hash: &str,
+        user_id: Option<UserID>,
+        state: HashMap<String, String>,
+        expires_at: OffsetDateTime,
+    ) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET user_id = $2, state = $3, expires_at = $4, last_seen_at = NOW()
+            WHERE key_hash = $1 AND expires_at > NOW()
+            "#,
+            key_hash,
+            user_id,
+            Json(&state) as _,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("sessions.update db query failed: {err}");
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn touch(&self, key_hash: &str, expires_at: OffsetDateTime) -> Result<(), RepoError> {
+        // Every request lands here; a minute of precision saves most of the writes
+        sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET expires_at = $2, last_seen_at = NOW()
+            WHERE key_hash = $1
+              AND expires_at > NOW()
+              AND last_seen_at < NOW() - INTERVAL '1 minute'
+            "#,
+            key_hash,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("sessions.touch db query failed: {err}");
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key_hash: &str) -> Result<(), RepoError> {
+        sqlx::query!("DELETE FROM sessions WHERE key_hash = $1", key_hash)
+            .execute(&self.pool)
+            .await
+            .map_err(|err| {
+                log::error!("sessions.delete db query failed: {err}");
+                RepoError::StorageError
+            })?;
+
+        Ok(())
+    }
+
+    async fn list_for_user(&self, user_id: UserID) -> Result<Vec<Session>, RepoError> {
+        sqlx::query_as!(
+            Session,
+            r#"
+            SELECT id, created_at, last_seen_at, expires_at, user_agent, ip
+            FROM sessions
+            WHERE user_id = $1 AND expires_at > NOW()
+            ORDER BY last_seen_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("sessions.list_for_user failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })
+    }
+
+    async fn revoke(&self, user_id: UserID, session_id: SessionID) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            "DELETE FROM sessions WHERE id = $1 AND user_id = $2",
+            session_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("sessions.revoke failed for {}: {err}", session_id);
+            RepoError::StorageError
+        })?;
+
+        // Someone else's session looks the same as a missing one
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_all(
+        &self,
+        user_id: UserID,
+        except: Option<SessionID>,
+    ) -> Result<u64, RepoError> {
+        let result = sqlx::query!(
+            "DELETE FROM sessions WHERE user_id = $1 AND ($2::uuid IS NULL OR id <> $2)",
+            user_id,
+            except
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("sessions.revoke_all failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::postgres::testing::load_schema;
+    use time::Duration;
+    use uuid::Uuid;
+
+    async fn user(pool: &PgPool, username: &str) -> UserID {
+        sqlx::query_scalar(
+            "INSERT INTO users (username, password_hash) VALUES ($1, 'x') RETURNING id",
+        )
+        .bind(username)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    // Signs the user in and returns the session id and key hash
+    async fn session(repo: &PostgresSessionRepository, user_id: UserID) -> (SessionID, String) {
+        let id = Uuid::now_v7();
+        let key_hash = format!("key-{id}");
+        repo.create(NewSession {
+            id,
+            key_hash: key_hash.clone(),
+            user_id: Some(user_id),
+            user_agent: None,
+            ip: None,
+            state: HashMap::new(),
+            expires_at: OffsetDateTime::now_utc() + Duration::days(1),
+        })
+        .await
+        .unwrap();
+        (id, key_hash)
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn sessions_are_revoked_only_by_their_owner(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresSessionRepository::new(pool.clone());
+        let alice = user(&pool, "alice").await;
+        let bob = user(&pool, "bob").await;
+        let (laptop, laptop_key) = session(&repo, alice).await;
+        let (phone, _) = session(&repo, alice).await;
+
+        assert!(matches!(
+            repo.revoke(bob, laptop).await,
+            Err(RepoError::NotFound)
+        ));
+        assert!(repo.load(&laptop_key).await.unwrap().is_some());
+
+        repo.revoke(alice, phone).await.unwrap();
+        let left: Vec<SessionID> = repo
+            .list_for_user(alice)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|session| session.id)
+            .collect();
+        assert_eq!(left, [laptop]);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn revoke_all_keeps_the_current_session(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresSessionRepository::new(pool.clone());
+        let alice = user(&pool, "alice").await;
+        let bob = user(&pool, "bob").await;
+        let (current, current_key) = session(&repo, alice).await;
+        let (_, other_key) = session(&repo, alice).await;
+        let (_, bob_key) = session(&repo, bob).await;
+
+        assert_eq!(repo.revoke_all(alice, Some(current)).await.unwrap(), 1);
+        assert!(repo.load(&current_key).await.unwrap().is_some());
+        assert!(repo.load(&other_key).await.unwrap().is_none());
+        assert!(repo.load(&bob_key).await.unwrap().is_some());
+
+        assert_eq!(repo.revoke_all(alice, None).await.unwrap(), 1);
+        assert!(repo.load(&current_key).await.unwrap().is_none());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn password_change_signs_the_user_out_everywhere(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresSessionRepository::new(pool.clone());
+        let alice = user(&pool, "alice").await;
+        let bob = user(&pool, "bob").await;
+        let (_, alice_key) = session(&repo, alice).await;
+        let (_, bob_key) = session(&repo, bob).await;
+
+        // Other columns and an unchanged hash leave sessions alone
+        sqlx::query("UPDATE users SET role = 0, password_hash = 'x' WHERE id = $1")
+            .bind(alice)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(repo.load(&alice_key).await.unwrap().is_some());
+
+        sqlx::query("UPDATE users SET password_hash = 'y' WHERE id = $1")
+            .bind(alice)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(repo.load(&alice_key).await.unwrap().is_none());
+        assert!(repo.load(&bob_key).await.unwrap().is_some());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn expired_sessions_do_not_load(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresSessionRepository::new(pool.clone());
+        let alice = user(&pool, "alice").await;
+        let (id, key_hash) = session(&repo, alice).await;
+
+        sqlx::query("UPDATE sessions SET expires_at = NOW() - INTERVAL '1 second' WHERE id = $1")
+            .bind(id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(repo.load(&key_hash).await.unwrap().is_none());
+        assert!(repo.list_for_user(alice).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+use sqlx::PgPool;
+
+// Loads postgres/schema.sql into a fresh test database. psql meta-commands
+// and the dump's session settings (an empty search_path among them) are left
+// out so they don't stick to the pooled connection.
+pub async fn load_schema(pool: &PgPool) {
+    let schema = include_str!("../../../../postgres/schema.sql")
+        .lines()
+        .filter(|line| {
+            !line.starts_with('\\')
+                && !line.starts_with("SET ")
+                && !line.starts_with("SELECT pg_catalog.set_config")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    sqlx::raw_sql(&schema).execute(pool).await.unwrap();
+}
+
+// Applies postgres/migrations/*.sql in order, the way an existing database
+// is brought up to date
+pub async fn run_migrations(pool: &PgPool) {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../postgres/migrations");
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let sql = std::fs::read_to_string(&path).unwrap();
+        sqlx::raw_sql(&sql)
+            .execute(pool)
+            .await
+            .unwrap_or_else(|err| panic!("{} failed: {err}", path.display()));
+    }
+}
+
+mod tests {
+    use super::*;
+
+    // A database created from the current schema.sql has nothing left to
+    // migrate, and every migration may run more than once
+    #[sqlx::test(migrations = false)]
+    async fn migrations_rerun_cleanly_on_the_current_schema(pool: PgPool) {
+        load_schema(&pool).await;
+        run_migrations(&pool).await;
+        run_migrations(&pool).await;
+    }
+}
@@ -0,0 +1,273 @@
+use crate::application::ports::TotpRepository;
+use crate::domain::model::{RepoError, UserID, UserTotp};
+use async_trait::async_trait;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct PostgresTotpRepository {
+    pool: PgPool,
+}
+
+impl PostgresTotpRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+async fn insert_recovery_codes(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: UserID,
+    code_hashes: Vec<String>,
+    context: &str,
+) -> Result<(), RepoError> {
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "{context} failed to drop recovery codes of {}: {err}",
+                user_id
+            );
+            RepoError::StorageError
+        })?;
+
+    let ids: Vec<Uuid> = code_hashes.iter().map(|_| Uuid::now_v7()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO recovery_codes (id, user_id, code_hash)
+        SELECT id, $2, code_hash
+        FROM UNNEST($1::uuid[], $3::text[]) AS t(id, code_hash)
+        "#,
+        &ids,
+        user_id,
+        &code_hashes
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|err| {
+        log::error!(
+            "{context} failed to store recovery codes of {}: {err}",
+            user_id
+        );
+        RepoError::StorageError
+    })?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl TotpRepository for PostgresTotpRepository {
+    async fn find(&self, user_id: UserID) -> Result<UserTotp, RepoError> {
+        sqlx::query_as!(
+            UserTotp,
+            r#"
+            SELECT user_id, secret, confirmed_at
+            FROM user_totp
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("totp.find failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)
+    }
+
+    async fn begin_enrollment(&self, user_id: UserID, secret: String) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO user_totp (user_id, secret)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = EXCLUDED.secret, created_at = NOW()
+            WHERE user_totp.confirmed_at IS NULL
+            "#,
+            user_id,
+            secret
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("totp.begin_enrollment failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::Conflict);
+        }
+
+        Ok(())
+    }
+
+    async fn confirm(
+        &self,
+        user_id: UserID,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("totp.confirm failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE user_totp
+            SET confirmed_at = NOW(), last_used_step = $2
+            WHERE user_id = $1 AND confirmed_at IS NULL
+            "#,
+            user_id,
+            step
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!("totp.confirm failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        insert_recovery_codes(&mut tx, user_id, recovery_code_hashes, "totp.confirm").await?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "totp.confirm failed to commit transaction for {}: {err}",
+                user_id
+            );
+            RepoError::StorageError
+        })
+    }
+
+    async fn use_step(&self, user_id: UserID, step: i64) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE user_totp
+            SET last_used_step = $2
+            WHERE user_id = $1
+              AND confirmed_at IS NOT NULL
+              AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("totp.use_step failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::Conflict);
+        }
+
+        Ok(())
+    }
+
+    async fn use_recovery_code(&self, user_id: UserID, code_hash: &str) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE recovery_codes
+            SET used_at = NOW()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+            user_id,
+            code_hash
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("totp.use_recovery_code failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(
+        &self,
+        user_id: UserID,
+        code_hashes: Vec<String>,
+    ) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("totp.replace_recovery_codes failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        insert_recovery_codes(&mut tx, user_id, code_hashes, "totp.replace_recovery_codes").await?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "totp.replace_recovery_codes failed to commit transaction for {}: {err}",
+                user_id
+            );
+            RepoError::StorageError
+        })
+    }
+
+    async fn recovery_codes_left(&self, user_id: UserID) -> Result<i64, RepoError> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM recovery_codes
+            WHERE user_id = $1 AND used_at IS NULL
+            "#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("totp.recovery_codes_left failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })
+    }
+
+    async fn disable(&self, user_id: UserID) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("totp.disable failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        let result = sqlx::query!("DELETE FROM user_totp WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!("totp.disable failed for {}: {err}", user_id);
+                RepoError::StorageError
+            })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!(
+                    "totp.disable failed to drop recovery codes of {}: {err}",
+                    user_id
+                );
+                RepoError::StorageError
+            })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "totp.disable failed to commit transaction for {}: {err}",
+                user_id
+            );
+            RepoError::StorageError
+        })
+    }
+}
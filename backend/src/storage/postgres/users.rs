@@ -1,8 +1,11 @@
-use crate::application::contracts::NewUser;
+use crate::application::contracts::{ExternalIdentity, NewUser, UpdateUser};
 use crate::application::ports::UserRepository;
-use crate::domain::model::{RepoError, User};
+use crate::domain::model::{
+    Invite, InviteID, PostRating, RegistrationMode, RepoError, User, UserID, UserRole,
+};
 use async_trait::async_trait;
 use sqlx::PgPool;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[derive(Clone)]
@@ -19,42 +22,595 @@ impl PostgresUserRepository {
 #[async_trait]
 impl UserRepository for PostgresUserRepository {
     async fn find_by_id(&self, id: Uuid) -> Result<User, RepoError> {
-        let user = sqlx::query_as!(
-            User,
-            "SELECT id, username, password_hash FROM users WHERE id = $1",
+        let row = sqlx::query!(
+            "SELECT id, username, password_hash, role, max_rating FROM users WHERE id = $1",
             id,
         )
         .fetch_one(&self.pool)
         .await
         .map_err(|_| RepoError::NotFound)?;
 
-        Ok(user)
+        Ok(User {
+            id: row.id,
+            username: row.username,
+            password_hash: row.password_hash,
+            role: UserRole::from(row.role),
+            max_rating: PostRating::from(row.max_rating),
+        })
     }
     async fn find_by_username(&self, username: &str) -> Result<User, RepoError> {
-        let user = sqlx::query_as!(
-            User,
-            "SELECT id, username, password_hash FROM users WHERE username = $1",
+        let row = sqlx::query!(
+            "SELECT id, username, password_hash, role, max_rating FROM users WHERE username = $1",
             username,
         )
         .fetch_one(&self.pool)
         .await
         .map_err(|_| RepoError::NotFound)?;
 
-        Ok(user)
+        Ok(User {
+            id: row.id,
+            username: row.username,
+            password_hash: row.password_hash,
+            role: UserRole::from(row.role),
+            max_rating: PostRating::from(row.max_rating),
+        })
+    }
+
+    async fn find_by_identity(&self, identity: &ExternalIdentity) -> Result<User, RepoError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT u.id, u.username, u.password_hash, u.role, u.max_rating
+            FROM user_identities i
+            JOIN users u ON u.id = i.user_id
+            WHERE i.issuer = $1 AND i.subject = $2
+            "#,
+            identity.issuer,
+            identity.subject
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("users.find_by_identity db query failed: {err}");
+            RepoError::StorageError
+        })?
+        .ok_or(RepoError::NotFound)?;
+
+        Ok(User {
+            id: row.id,
+            username: row.username,
+            password_hash: row.password_hash,
+            role: UserRole::from(row.role),
+            max_rating: PostRating::from(row.max_rating),
+        })
+    }
+
+    async fn link_identity(
+        &self,
+        user_id: UserID,
+        identity: &ExternalIdentity,
+    ) -> Result<(), RepoError> {
+        sqlx::query!(
+            "INSERT INTO user_identities (issuer, subject, user_id) VALUES ($1, $2, $3)",
+            identity.issuer,
+            identity.subject,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            if is_unique_violation(&err) {
+                return RepoError::Conflict;
+            }
+            log::error!("users.link_identity failed for {}: {err}", user_id);
+            RepoError::StorageError
+        })?;
+
+        Ok(())
     }
 
     async fn create(&self, user: NewUser) -> Result<Uuid, RepoError> {
         let id = Uuid::now_v7();
+
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("users.create failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        // Registrations queue up here, so exactly one of them sees an empty
+        // table; updates of existing users wait only for this transaction
+        sqlx::query!("LOCK TABLE users IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!("users.create failed to lock users: {err}");
+                RepoError::StorageError
+            })?;
+
+        let is_first = !sqlx::query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM users) AS "exists!""#)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!("users.create failed to count users: {err}");
+                RepoError::StorageError
+            })?;
+
+        // The first account on a fresh instance administers it, whatever the
+        // registration mode is
+        let role = if is_first {
+            UserRole::Admin
+        } else {
+            UserRole::default()
+        };
+        let invite_code_hash = match (is_first, user.registration) {
+            (true, _) | (false, RegistrationMode::Open) => None,
+            (false, RegistrationMode::Closed) => return Err(RepoError::Forbidden),
+            (false, RegistrationMode::Invite) => {
+                Some(user.invite_code_hash.as_ref().ok_or(RepoError::Forbidden)?)
+            }
+        };
+
         sqlx::query!(
-            "INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)",
+            "INSERT INTO users (id, username, password_hash, role) VALUES ($1, $2, $3, $4)",
             id,
             user.username,
             user.password_hash,
+            i16::from(role),
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await
-        .map_err(|_| RepoError::StorageError)?;
+        .map_err(|err| {
+            if is_unique_violation(&err) {
+                return RepoError::Conflict;
+            }
+            log::error!("users.create failed to insert {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        if let Some(code_hash) = invite_code_hash {
+            let used = sqlx::query!(
+                r#"
+                UPDATE invites
+                SET used_by = $2, used_at = NOW()
+                WHERE code_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+                "#,
+                code_hash,
+                id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!("users.create failed to consume invite for {}: {err}", id);
+                RepoError::StorageError
+            })?;
+
+            if used.rows_affected() == 0 {
+                return Err(RepoError::InvalidInput);
+            }
+        }
+
+        if let Some(identity) = &user.identity {
+            sqlx::query!(
+                "INSERT INTO user_identities (issuer, subject, user_id) VALUES ($1, $2, $3)",
+                identity.issuer,
+                identity.subject,
+                id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                if is_unique_violation(&err) {
+                    return RepoError::Conflict;
+                }
+                log::error!("users.create failed to link identity for {}: {err}", id);
+                RepoError::StorageError
+            })?;
+        }
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "users.create failed to commit transaction for {}: {err}",
+                id
+            );
+            RepoError::StorageError
+        })?;
 
         Ok(id)
     }
+
+    async fn set_role(&self, id: UserID, role: UserRole) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            "UPDATE users SET role = $2 WHERE id = $1",
+            id,
+            i16::from(role),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("users.set_role failed for {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn update(&self, id: UserID, update_user: UpdateUser) -> Result<(), RepoError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET
+                username = COALESCE($2, username),
+                password_hash = COALESCE($3, password_hash),
+                max_rating = COALESCE($4, max_rating)
+            WHERE id = $1
+            "#,
+            id,
+            update_user.username,
+            update_user.password_hash,
+            update_user.max_rating.map(i16::from),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            if is_unique_violation(&err) {
+                return RepoError::Conflict;
+            }
+            log::error!("users.update failed for {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn delete(
+        &self,
+        id: UserID,
+        playlists_to: Option<UserID>,
+        posts_to: Option<UserID>,
+    ) -> Result<(), RepoError> {
+        let mut tx = self.pool.begin().await.map_err(|err| {
+            log::error!("users.delete failed to begin transaction: {err}");
+            RepoError::StorageError
+        })?;
+
+        // Admins are locked too, so two of them can't leave at the same time
+        let locked = sqlx::query!(
+            "SELECT id, role FROM users WHERE id = $1 OR role = $2 FOR UPDATE",
+            id,
+            i16::from(UserRole::Admin),
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|err| {
+            log::error!("users.delete failed to lock {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        let role = locked
+            .iter()
+            .find(|user| user.id == id)
+            .map(|user| UserRole::from(user.role))
+            .ok_or(RepoError::NotFound)?;
+
+        // The instance keeps at least one admin
+        if role == UserRole::Admin && locked.iter().all(|user| user.id == id) {
+            return Err(RepoError::Conflict);
+        }
+
+        match posts_to {
+            Some(heir) => {
+                sqlx::query!(
+                    "UPDATE posts SET uploader_id = $2 WHERE uploader_id = $1",
+                    id,
+                    heir
+                )
+                .execute(&mut *tx)
+                .await
+            }
+            None => {
+                // Trashed like any other deletion, so the purge job also
+                // cleans up their files
+                sqlx::query!(
+                    r#"
+                    UPDATE posts
+                    SET deleted_at = NOW(), deleted_by = $1
+                    WHERE uploader_id = $1 AND deleted_at IS NULL
+                    "#,
+                    id
+                )
+                .execute(&mut *tx)
+                .await
+            }
+        }
+        .map_err(|err| {
+            log::error!("users.delete failed to hand over posts of {}: {err}", id);
+            RepoError::StorageError
+        })?;
+
+        // Without an heir the playlists go with the owner (ON DELETE CASCADE)
+        if let Some(heir) = playlists_to {
+            // The heir owns these now and can't stay a collaborator on them
+            sqlx::query!(
+                r#"
+                DELETE FROM playlist_collaborators pc
+                USING playlists p
+                WHERE pc.playlist_id = p.id AND p.owner_id = $1 AND pc.user_id = $2
+                "#,
+                id,
+                heir
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!(
+                    "users.delete failed to drop collaborator rows of {}: {err}",
+                    heir
+                );
+                RepoError::StorageError
+            })?;
+
+            sqlx::query!(
+                "UPDATE playlists SET owner_id = $2 WHERE owner_id = $1",
+                id,
+                heir
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!(
+                    "users.delete failed to hand over playlists of {}: {err}",
+                    id
+                );
+                RepoError::StorageError
+            })?;
+        }
+
+        sqlx::query!("DELETE FROM users WHERE id = $1", id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                log::error!("users.delete failed for {}: {err}", id);
+                RepoError::StorageError
+            })?;
+
+        tx.commit().await.map_err(|err| {
+            log::error!(
+                "users.delete failed to commit transaction for {}: {err}",
+                id
+            );
+            RepoError::StorageError
+        })?;
+
+        Ok(())
+    }
+
+    async fn create_invite(
+        &self,
+        created_by: UserID,
+        code_hash: String,
+        expires_at: OffsetDateTime,
+    ) -> Result<Invite, RepoError> {
+        let id = Uuid::now_v7();
+
+        sqlx::query_as!(
+            Invite,
+            r#"
+            INSERT INTO invites (id, code_hash, created_by, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, created_by, created_at, expires_at, used_by, used_at
+            "#,
+            id,
+            code_hash,
+            created_by,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("users.create_invite failed for {}: {err}", created_by);
+            RepoError::StorageError
+        })
+    }
+
+    async fn list_invites(&self) -> Result<Vec<Invite>, RepoError> {
+        sqlx::query_as!(
+            Invite,
+            r#"
+            SELECT id, created_by, created_at, expires_at, used_by, used_at
+            FROM invites
+            ORDER BY created_at DESC
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("users.list_invites db query failed: {err}");
+            RepoError::StorageError
+        })
+    }
+
+    async fn revoke_invite(&self, id: InviteID) -> Result<(), RepoError> {
+        let result = sqlx::query!("DELETE FROM invites WHERE id = $1 AND used_at IS NULL", id)
+            .execute(&self.pool)
+            .await
+            .map_err(|err| {
+                log::error!("users.revoke_invite failed for {}: {err}", id);
+                RepoError::StorageError
+            })?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        Ok(())
+    }
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .is_some_and(|err| err.is_unique_violation())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::postgres::testing::load_schema;
+    use futures_util::future::join_all;
+    use time::Duration;
+
+    fn new_user(username: &str, registration: RegistrationMode) -> NewUser {
+        NewUser {
+            username: username.to_string(),
+            password_hash: "x".to_string(),
+            registration,
+            invite_code_hash: None,
+            identity: None,
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn closed_mode_lets_only_the_first_account_in(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresUserRepository::new(pool);
+
+        let first = repo
+            .create(new_user("alice", RegistrationMode::Closed))
+            .await
+            .unwrap();
+        assert_eq!(repo.find_by_id(first).await.unwrap().role, UserRole::Admin);
+
+        let second = repo.create(new_user("bob", RegistrationMode::Closed)).await;
+        assert!(matches!(second, Err(RepoError::Forbidden)));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn concurrent_first_registrations_let_one_through(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresUserRepository::new(pool.clone());
+
+        let usernames: Vec<String> = (0..8).map(|i| format!("user{i}")).collect();
+        let results = join_all(
+            usernames
+                .iter()
+                .map(|username| repo.create(new_user(username, RegistrationMode::Invite))),
+        )
+        .await;
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(
+            results
+                .iter()
+                .all(|result| matches!(result, Ok(_) | Err(RepoError::Forbidden)))
+        );
+        let admins: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = 3")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(admins, 1);
+    }
+
+    fn invited(username: &str, code_hash: Option<&str>) -> NewUser {
+        NewUser {
+            invite_code_hash: code_hash.map(str::to_string),
+            ..new_user(username, RegistrationMode::Invite)
+        }
+    }
+
+    async fn roles(pool: &PgPool) -> Vec<(String, i16)> {
+        sqlx::query_as("SELECT username, role FROM users ORDER BY username")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn role_migration_makes_the_oldest_user_admin_only_without_one(pool: PgPool) {
+        load_schema(&pool).await;
+        sqlx::query(
+            "INSERT INTO users (username, password_hash, role, created_at) VALUES \
+             ('alice', 'x', 1, now() - interval '1 day'), ('bob', 'x', 1, now() - interval '2 days')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let migration = include_str!("../../../../postgres/migrations/007_user_roles.sql");
+        sqlx::raw_sql(migration).execute(&pool).await.unwrap();
+        assert_eq!(
+            roles(&pool).await,
+            [("alice".to_string(), 1), ("bob".to_string(), 3)]
+        );
+
+        // Someone else already administers it
+        sqlx::query("UPDATE users SET role = (CASE username WHEN 'alice' THEN 3 ELSE 1 END)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::raw_sql(migration).execute(&pool).await.unwrap();
+        assert_eq!(
+            roles(&pool).await,
+            [("alice".to_string(), 3), ("bob".to_string(), 1)]
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn invites_let_one_registration_through_before_they_expire(pool: PgPool) {
+        load_schema(&pool).await;
+        let repo = PostgresUserRepository::new(pool.clone());
+        let admin = repo
+            .create(new_user("admin", RegistrationMode::Invite))
+            .await
+            .unwrap();
+        let now = OffsetDateTime::now_utc();
+        let invite = repo
+            .create_invite(admin, "fresh".to_string(), now + Duration::days(1))
+            .await
+            .unwrap();
+        repo.create_invite(admin, "stale".to_string(), now - Duration::minutes(1))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repo.create(invited("bob", None)).await,
+            Err(RepoError::Forbidden)
+        ));
+        for code in ["wrong", "stale"] {
+            assert!(matches!(
+                repo.create(invited("bob", Some(code))).await,
+                Err(RepoError::InvalidInput)
+            ));
+        }
+        // A refused registration leaves no account behind
+        assert!(matches!(
+            repo.find_by_username("bob").await,
+            Err(RepoError::NotFound)
+        ));
+
+        let bob = repo.create(invited("bob", Some("fresh"))).await.unwrap();
+        assert_eq!(repo.find_by_id(bob).await.unwrap().role, UserRole::Uploader);
+        let used = repo
+            .list_invites()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|listed| listed.id == invite.id)
+            .unwrap();
+        assert_eq!(used.used_by, Some(bob));
+
+        assert!(matches!(
+            repo.create(invited("carol", Some("fresh"))).await,
+            Err(RepoError::InvalidInput)
+        ));
+        assert!(matches!(
+            repo.revoke_invite(invite.id).await,
+            Err(RepoError::NotFound)
+        ));
+    }
 }
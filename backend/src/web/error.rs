@@ -1,3 +1,4 @@
+use actix_web::http::header;
 use actix_web::{HttpResponse, ResponseError, http::StatusCode};
 use serde::Serialize;
 
@@ -5,8 +6,13 @@ use serde::Serialize;
 pub enum AppError {
     BadRequest(String),
     Unauthorized(String),
+    Forbidden(String),
     NotFound(String),
     Conflict(String),
+    TooManyRequests {
+        message: String,
+        retry_after_secs: u64,
+    },
     Internal {
         public_message: String,
         context: String,
@@ -27,6 +33,10 @@ impl AppError {
         Self::Unauthorized(message.into())
     }
 
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::Forbidden(message.into())
+    }
+
     pub fn not_found(message: impl Into<String>) -> Self {
         Self::NotFound(message.into())
     }
@@ -35,6 +45,13 @@ impl AppError {
         Self::Conflict(message.into())
     }
 
+    pub fn too_many_requests(message: impl Into<String>, retry_after_secs: u64) -> Self {
+        Self::TooManyRequests {
+            message: message.into(),
+            retry_after_secs,
+        }
+    }
+
     pub fn internal(context: impl Into<String>) -> Self {
         Self::Internal {
             public_message: "Internal server error".to_string(),
@@ -46,8 +63,10 @@ impl AppError {
         match self {
             AppError::BadRequest(message) => message.as_str(),
             AppError::Unauthorized(message) => message.as_str(),
+            AppError::Forbidden(message) => message.as_str(),
             AppError::NotFound(message) => message.as_str(),
             AppError::Conflict(message) => message.as_str(),
+            AppError::TooManyRequests { message, .. } => message.as_str(),
             AppError::Internal { public_message, .. } => public_message.as_str(),
         }
     }
@@ -66,8 +85,10 @@ impl ResponseError for AppError {
         match self {
             AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
             AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
             AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             AppError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -77,7 +98,15 @@ impl ResponseError for AppError {
             log::error!("{context}");
         }
 
-        HttpResponse::build(self.status_code()).json(ErrorBody {
+        let mut response = HttpResponse::build(self.status_code());
+        if let AppError::TooManyRequests {
+            retry_after_secs, ..
+        } = self
+        {
+            response.insert_header((header::RETRY_AFTER, retry_after_secs.to_string()));
+        }
+
+        response.json(ErrorBody {
             error: self.public_message(),
         })
     }
@@ -0,0 +1,378 @@
+use crate::application::helpers::tokens::hash_token;
+use crate::application::ports::{ApiTokenRepository, UserRepository};
+use crate::domain::model::{Actor, TokenScope, UserID, UserRole};
+use crate::web::error::AppError;
+use crate::web::handlers::utils::parse_uuid;
+use actix_identity::Identity;
+use actix_web::http::header;
+use actix_web::web::Data;
+use actix_web::{FromRequest, HttpRequest, dev::Payload};
+use futures_util::future::LocalBoxFuture;
+
+// Resource data naming the scope a token needs on that route. Routes without
+// it need Read for safe methods and Upload otherwise, so only the ones whose
+// method says nothing about what they do (a search sent as POST) declare it.
+#[derive(Clone, Copy)]
+pub struct RequiredScope(pub TokenScope);
+
+// Resolves the caller from an `Authorization: Bearer` API token or else the
+// session identity, and loads the role on every request so a role change
+// takes effect without signing in again.
+// Needs `Data<dyn UserRepository>` and `Data<dyn ApiTokenRepository>`
+// registered on the app.
+impl FromRequest for Actor {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+
+        Box::pin(async move {
+            let (user_id, token_scopes) = match bearer_token(&req) {
+                Some(token) => {
+                    let tokens =
+                        req.app_data::<Data<dyn ApiTokenRepository>>()
+                            .ok_or_else(|| {
+                                AppError::internal("actor extractor has no token repository")
+                            })?;
+                    let grant = tokens
+                        .authenticate(&hash_token(token))
+                        .await
+                        .map_err(|_| AppError::unauthorized("Invalid or expired API token"))?;
+
+                    (grant.user_id, Some(grant.scopes))
+                }
+                None => (session_user_id(&req).await?, None),
+            };
+
+            let users = req
+                .app_data::<Data<dyn UserRepository>>()
+                .ok_or_else(|| AppError::internal("actor extractor has no user repository"))?;
+
+            // A session of a deleted account is as good as none
+            let user = users
+                .find_by_id(user_id)
+                .await
+                .map_err(|_| AppError::unauthorized("Unauthorized"))?;
+
+            let mut actor = Actor {
+                id: user.id,
+                role: user.role,
+                via_token: token_scopes.is_some(),
+                max_rating: user.max_rating,
+            };
+
+            if let Some(scopes) = token_scopes {
+                let required = req
+                    .app_data::<RequiredScope>()
+                    .map(|scope| scope.0)
+                    .unwrap_or_else(|| {
+                        if req.method().is_safe() {
+                            TokenScope::Read
+                        } else {
+                            TokenScope::Upload
+                        }
+                    });
+                if !scopes.contains(&required) {
+                    return Err(AppError::forbidden(format!(
+                        "API token lacks the {} scope",
+                        format!("{required:?}").to_lowercase()
+                    )));
+                }
+                if !scopes.contains(&TokenScope::Admin) {
+                    actor.role = actor.role.min(UserRole::Moderator);
+                }
+            }
+
+            Ok(actor)
+        })
+    }
+}
+
+// Caller of a route that anonymous visitors may use too. A bearer token that
+// was sent but rejected fails the request instead of quietly turning the
+// caller into an anonymous one.
+pub struct MaybeActor(pub Option<Actor>);
+
+impl FromRequest for MaybeActor {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let sent_token = bearer_token(req).is_some();
+        let actor = Actor::from_request(req, payload);
+
+        Box::pin(async move {
+            match actor.await {
+                Ok(actor) => Ok(MaybeActor(Some(actor))),
+                Err(err) if sent_token => Err(err),
+                Err(_) => Ok(MaybeActor(None)),
+            }
+        })
+    }
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+async fn session_user_id(req: &HttpRequest) -> Result<UserID, AppError> {
+    let identity = Identity::extract(req)
+        .await
+        .map_err(|_| AppError::unauthorized("Unauthorized"))?;
+    let user_id = identity.id().map_err(|err| {
+        log::warn!("failed to resolve identity id from session: {err}");
+        AppError::unauthorized("Unauthorized")
+    })?;
+
+    parse_uuid(&user_id, "user id")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::postgres::api_tokens::PostgresApiTokenRepository;
+    use crate::storage::postgres::sessions::PostgresSessionRepository;
+    use crate::storage::postgres::testing::load_schema;
+    use crate::storage::postgres::users::PostgresUserRepository;
+    use crate::web::handlers::users::start_session;
+    use crate::web::session_store::RepoSessionStore;
+    use actix_http::Request;
+    use actix_identity::IdentityMiddleware;
+    use actix_session::SessionMiddleware;
+    use actix_web::body::MessageBody;
+    use actix_web::cookie::{Cookie, Key};
+    use actix_web::dev::{Service, ServiceResponse};
+    use actix_web::http::StatusCode;
+    use actix_web::{App, HttpResponse, test, web};
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    // Stands in for a password login
+    async fn sign_in(path: web::Path<UserID>, req: HttpRequest) -> Result<HttpResponse, AppError> {
+        start_session(&req, path.into_inner(), "test.sign_in")?;
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    async fn whoami(actor: Actor) -> HttpResponse {
+        HttpResponse::Ok().json(actor.role)
+    }
+
+    async fn maybe_whoami(actor: MaybeActor) -> HttpResponse {
+        HttpResponse::Ok().json(actor.0.map(|actor| actor.role))
+    }
+
+    async fn app(
+        pool: &PgPool,
+    ) -> impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = actix_web::Error>
+    {
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(PostgresUserRepository::new(
+            pool.clone(),
+        )) as Arc<dyn UserRepository>);
+        let tokens: Data<dyn ApiTokenRepository> =
+            Data::from(Arc::new(PostgresApiTokenRepository::new(pool.clone()))
+                as Arc<dyn ApiTokenRepository>);
+
+        test::init_service(
+            App::new()
+                .wrap(IdentityMiddleware::default())
+                .wrap(SessionMiddleware::new(
+                    RepoSessionStore::new(PostgresSessionRepository::new(pool.clone())),
+                    Key::generate(),
+                ))
+                .app_data(users)
+                .app_data(tokens)
+                .route("/sign-in/{id}", web::post().to(sign_in))
+                .route("/whoami", web::get().to(whoami))
+                .route("/whoami", web::post().to(whoami))
+                .route("/maybe", web::get().to(maybe_whoami))
+                .service(
+                    web::resource("/search")
+                        .app_data(RequiredScope(TokenScope::Read))
+                        .route(web::post().to(whoami)),
+                ),
+        )
+        .await
+    }
+
+    async fn user(pool: &PgPool, username: &str, role: UserRole) -> UserID {
+        sqlx::query_scalar(
+            "INSERT INTO users (username, password_hash, role) VALUES ($1, 'x', $2) RETURNING id",
+        )
+        .bind(username)
+        .bind(role as i16)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    // Issues a token valid for a day and returns its secret
+    async fn token(pool: &PgPool, user_id: UserID, scopes: &[TokenScope]) -> String {
+        let secret = format!("secret-{}", uuid::Uuid::now_v7());
+        PostgresApiTokenRepository::new(pool.clone())
+            .create(
+                user_id,
+                "test".to_string(),
+                scopes.to_vec(),
+                hash_token(&secret),
+                time::OffsetDateTime::now_utc() + time::Duration::days(1),
+            )
+            .await
+            .unwrap();
+        secret
+    }
+
+    fn with_token(request: test::TestRequest, secret: &str) -> test::TestRequest {
+        request.insert_header((header::AUTHORIZATION, format!("Bearer {secret}")))
+    }
+
+    async fn sign_in_cookie<S, B>(app: &S, id: UserID) -> Cookie<'static>
+    where
+        S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    {
+        let request = test::TestRequest::post()
+            .uri(&format!("/sign-in/{id}"))
+            .to_request();
+        let response = test::call_service(app, request).await;
+        response
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == "id")
+            .map(Cookie::into_owned)
+            .expect("sign-in left no session")
+    }
+
+    // Status and, when it succeeded, the role the route saw
+    async fn call<S, B>(app: &S, request: test::TestRequest) -> (StatusCode, Option<UserRole>)
+    where
+        S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+        B: MessageBody,
+    {
+        let response = test::call_service(app, request.to_request()).await;
+        let status = response.status();
+        if !status.is_success() {
+            return (status, None);
+        }
+        let role: Option<UserRole> = test::read_body_json(response).await;
+        (status, role)
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn role_is_reloaded_on_every_request(pool: PgPool) {
+        load_schema(&pool).await;
+        let app = app(&pool).await;
+        let alice = user(&pool, "alice", UserRole::Uploader).await;
+        let cookie = sign_in_cookie(&app, alice).await;
+        let whoami = || {
+            test::TestRequest::get()
+                .uri("/whoami")
+                .cookie(cookie.clone())
+        };
+
+        assert_eq!(
+            call(&app, whoami()).await,
+            (StatusCode::OK, Some(UserRole::Uploader))
+        );
+
+        sqlx::query("UPDATE users SET role = 0 WHERE id = $1")
+            .bind(alice)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            call(&app, whoami()).await,
+            (StatusCode::OK, Some(UserRole::Viewer))
+        );
+
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(alice)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(call(&app, whoami()).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn anonymous_callers_pass_only_as_maybe_actor(pool: PgPool) {
+        load_schema(&pool).await;
+        let app = app(&pool).await;
+
+        assert_eq!(
+            call(&app, test::TestRequest::get().uri("/whoami")).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            call(&app, test::TestRequest::get().uri("/maybe")).await,
+            (StatusCode::OK, None)
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn token_scopes_follow_the_method_unless_the_route_names_one(pool: PgPool) {
+        load_schema(&pool).await;
+        let app = app(&pool).await;
+        let alice = user(&pool, "alice", UserRole::Uploader).await;
+        let read = token(&pool, alice, &[TokenScope::Read]).await;
+        let upload = token(&pool, alice, &[TokenScope::Upload]).await;
+
+        let get = |secret: &str| with_token(test::TestRequest::get().uri("/whoami"), secret);
+        let post = |secret: &str| with_token(test::TestRequest::post().uri("/whoami"), secret);
+        let search = |secret: &str| with_token(test::TestRequest::post().uri("/search"), secret);
+
+        assert_eq!(
+            call(&app, get(&read)).await,
+            (StatusCode::OK, Some(UserRole::Uploader))
+        );
+        assert_eq!(call(&app, post(&read)).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(call(&app, search(&read)).await.0, StatusCode::OK);
+
+        assert_eq!(call(&app, get(&upload)).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(call(&app, post(&upload)).await.0, StatusCode::OK);
+        assert_eq!(call(&app, search(&upload)).await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn tokens_without_admin_scope_act_as_moderators(pool: PgPool) {
+        load_schema(&pool).await;
+        let app = app(&pool).await;
+        let admin = user(&pool, "admin", UserRole::Admin).await;
+        let plain = token(&pool, admin, &[TokenScope::Read]).await;
+        let full = token(&pool, admin, &[TokenScope::Read, TokenScope::Admin]).await;
+
+        let get = |secret: &str| with_token(test::TestRequest::get().uri("/whoami"), secret);
+        assert_eq!(
+            call(&app, get(&plain)).await,
+            (StatusCode::OK, Some(UserRole::Moderator))
+        );
+        assert_eq!(
+            call(&app, get(&full)).await,
+            (StatusCode::OK, Some(UserRole::Admin))
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn expired_and_unknown_tokens_are_refused(pool: PgPool) {
+        load_schema(&pool).await;
+        let app = app(&pool).await;
+        let alice = user(&pool, "alice", UserRole::Uploader).await;
+        let secret = token(&pool, alice, &[TokenScope::Read]).await;
+        sqlx::query("UPDATE api_tokens SET expires_at = NOW() - INTERVAL '1 second'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for secret in [secret.as_str(), "never-issued"] {
+            let whoami = with_token(test::TestRequest::get().uri("/whoami"), secret);
+            assert_eq!(call(&app, whoami).await.0, StatusCode::UNAUTHORIZED);
+
+            // A rejected token is not mistaken for an anonymous visit
+            let maybe = with_token(test::TestRequest::get().uri("/maybe"), secret);
+            assert_eq!(call(&app, maybe).await.0, StatusCode::UNAUTHORIZED);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::application::contracts::PaginationMode;
+use crate::application::ports::{
+    FileRepository, PlaylistRepository, PostRepository, TagRepository,
+};
+use crate::application::use_cases::services::Services;
+use crate::domain::files::FileStorage;
+use crate::domain::model::{Actor, RepoError};
+use crate::web::error::AppError;
+use crate::web::extractors::MaybeActor;
+use crate::web::handlers::dto::{
+    CommentThreadParams, NewCommentParams, SearchCursorParams, UpdateCommentParams,
+};
+use crate::web::handlers::utils::{map_repo_error, parse_uuid};
+use actix_web::{HttpResponse, web};
+
+const INVALID_COMMENT: &str =
+    "Comment must be 1 to 10000 characters and reply to a live comment of the same post";
+
+pub async fn list_comments<PR, PLR, TR, FR, FS>(
+    MaybeActor(actor): MaybeActor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<String>,
+    thread: web::Query<CommentThreadParams>,
+    cursor: web::Query<SearchCursorParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let post_id = parse_uuid(&path.into_inner(), "post id")?;
+    let cursor = cursor.into_inner();
+    if let Some(PaginationMode::Offset) = cursor.mode {
+        return Err(AppError::bad_request(
+            "Offset mode doesn't support in comments",
+        ));
+    }
+
+    let comments = services
+        .list_comments
+        .execute(
+            post_id,
+            thread.parent_id,
+            Actor::viewer_max_rating(actor.as_ref()),
+            cursor.into(),
+        )
+        .await
+        .map_err(|err| match err {
+            RepoError::Forbidden => {
+                AppError::forbidden("This post is rated above your content filter")
+            }
+            err => map_repo_error(err, "Post not found", "comments.list"),
+        })?;
+
+    Ok(HttpResponse::Ok().json(comments))
+}
+
+pub async fn create_comment<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<String>,
+    payload: web::Json<NewCommentParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let post_id = parse_uuid(&path.into_inner(), "post id")?;
+    let payload = payload.into_inner();
+
+    let comment = services
+        .create_comment
+        .execute(actor, post_id, payload.parent_id, payload.body)
+        .await
+        .map_err(|err| match err {
+            RepoError::InvalidInput => AppError::bad_request(INVALID_COMMENT),
+            err => map_repo_error(err, "Post not found", "comments.create"),
+        })?;
+
+    Ok(HttpResponse::Created().json(comment))
+}
+
+pub async fn update_comment<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<String>,
+    payload: web::Json<UpdateCommentParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let id = parse_uuid(&path.into_inner(), "comment id")?;
+
+    let comment = services
+        .update_comment
+        .execute(actor, id, payload.into_inner().body)
+        .await
+        .map_err(|err| match err {
+            RepoError::InvalidInput => AppError::bad_request(INVALID_COMMENT),
+            err => map_repo_error(err, "Comment not found", "comments.update"),
+        })?;
+
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+pub async fn delete_comment<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let id = parse_uuid(&path.into_inner(), "comment id")?;
+
+    services
+        .delete_comment
+        .execute(actor, id)
+        .await
+        .map_err(|err| map_repo_error(err, "Comment not found", "comments.delete"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,4 +1,5 @@
-use crate::application::contracts::{KeysetDirection, PaginationMode};
+use crate::application::contracts::{KeysetDirection, PaginationMode, PlaylistFormat};
+use crate::domain::model::PostRating;
 use serde::Deserialize;
 use uuid::Uuid;
 
@@ -6,6 +7,8 @@ use uuid::Uuid;
 pub struct CreatePostMeta {
     pub title: String,
     pub tags: Vec<String>,
+    // Required: a missing rating must not slip explicit content past the default filter
+    pub rating: PostRating,
 }
 
 #[derive(Deserialize)]
@@ -21,6 +24,36 @@ pub struct SearchQueryParams {
     pub cursor: Option<SearchCursorParams>,
 }
 
+// Export defaults to the JSON bundle
+#[derive(Deserialize)]
+pub struct PlaylistFormatParams {
+    pub format: Option<PlaylistFormat>,
+}
+
+// 1 up, -1 down, 0 withdraws the vote
+#[derive(Deserialize)]
+pub struct VoteParams {
+    pub value: i16,
+}
+
+// Sits in the query string next to the cursor fields
+#[derive(Deserialize, Default)]
+pub struct CommentThreadParams {
+    // Top-level comments when absent, replies to this comment otherwise
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct NewCommentParams {
+    pub body: String,
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCommentParams {
+    pub body: String,
+}
+
 #[derive(Deserialize, Default, Clone, Debug)]
 pub struct TagQueryParams {
     pub must: Vec<String>,
@@ -1,8 +1,10 @@
+use crate::application::contracts::StorageCheckOptions;
 use crate::application::ports::{
     FileRepository, PlaylistRepository, PostRepository, TagRepository,
 };
 use crate::application::use_cases::services::Services;
 use crate::domain::files::FileStorage;
+use crate::domain::model::{Actor, UserRole};
 use crate::web::error::AppError;
 use crate::web::handlers::utils::{map_repo_error, parse_uuid};
 use actix_web::web::Data;
@@ -53,3 +55,33 @@ where
         .insert_header(("X-Accel-Redirect", redirect_url))
         .finish())
 }
+
+pub async fn check_storage<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: Data<Services<PR, PLR, TR, FR, FS>>,
+    payload: web::Json<StorageCheckOptions>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    if actor.role != UserRole::Admin {
+        return Err(AppError::forbidden("Forbidden"));
+    }
+
+    let options = payload.into_inner();
+    if options.fix {
+        log::info!(target: "audit", "file storage repair started by admin {}", actor.id);
+    }
+
+    let report = services
+        .check_storage
+        .execute(options)
+        .await
+        .map_err(|err| map_repo_error(err, "File not found", "files.check_storage"))?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,74 @@
+use crate::application::contracts::{CreatedInvite, NewInvite};
+use crate::application::helpers::tokens::{generate_token, hash_token};
+use crate::application::ports::UserRepository;
+use crate::domain::model::{Actor, UserRole};
+use crate::web::error::AppError;
+use crate::web::handlers::utils::{map_repo_error, parse_uuid};
+use actix_web::{HttpResponse, web};
+use time::{Duration, OffsetDateTime};
+
+const DEFAULT_EXPIRY_DAYS: u32 = 7;
+const MAX_EXPIRY_DAYS: u32 = 90;
+
+fn require_admin(actor: &Actor) -> Result<(), AppError> {
+    if actor.role != UserRole::Admin {
+        return Err(AppError::forbidden("Forbidden"));
+    }
+    Ok(())
+}
+
+pub async fn create_invite<UR: UserRepository + Clone>(
+    actor: Actor,
+    payload: web::Json<NewInvite>,
+    user_repo: web::Data<UR>,
+) -> Result<HttpResponse, AppError> {
+    require_admin(&actor)?;
+
+    let days = payload.expires_in_days.unwrap_or(DEFAULT_EXPIRY_DAYS);
+    if !(1..=MAX_EXPIRY_DAYS).contains(&days) {
+        return Err(AppError::bad_request(format!(
+            "Invite must expire within 1 to {MAX_EXPIRY_DAYS} days"
+        )));
+    }
+    let expires_at = OffsetDateTime::now_utc() + Duration::days(i64::from(days));
+
+    // Only the hash is stored, the code is shown once
+    let code = generate_token();
+    let invite = user_repo
+        .create_invite(actor.id, hash_token(&code), expires_at)
+        .await
+        .map_err(|err| map_repo_error(err, "User not found", "invites.create"))?;
+
+    Ok(HttpResponse::Created().json(CreatedInvite { invite, code }))
+}
+
+pub async fn list_invites<UR: UserRepository + Clone>(
+    actor: Actor,
+    user_repo: web::Data<UR>,
+) -> Result<HttpResponse, AppError> {
+    require_admin(&actor)?;
+
+    let invites = user_repo
+        .list_invites()
+        .await
+        .map_err(|err| map_repo_error(err, "Invites not found", "invites.list"))?;
+
+    Ok(HttpResponse::Ok().json(invites))
+}
+
+pub async fn revoke_invite<UR: UserRepository + Clone>(
+    actor: Actor,
+    path: web::Path<String>,
+    user_repo: web::Data<UR>,
+) -> Result<HttpResponse, AppError> {
+    require_admin(&actor)?;
+
+    let invite_id = parse_uuid(&path.into_inner(), "invite id")?;
+
+    user_repo
+        .revoke_invite(invite_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Invite not found", "invites.revoke"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
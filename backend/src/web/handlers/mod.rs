@@ -1,7 +1,13 @@
+pub mod comments;
 mod dto;
 pub mod files;
+pub mod invites;
+pub mod oidc;
 pub mod playlists;
 pub mod posts;
+pub mod sessions;
 pub mod tags;
+pub mod tokens;
+pub mod two_factor;
 pub mod users;
-mod utils;
+pub(crate) mod utils;
@@ -0,0 +1,467 @@
+use crate::application::contracts::{ExternalIdentity, NewUser};
+use crate::application::helpers::credentials::{USERNAME_MAX_LEN, username_from};
+use crate::application::helpers::tokens::generate_token;
+use crate::application::ports::{TotpRepository, UserRepository};
+use crate::domain::model::{RegistrationMode, RepoError, User, UserID};
+use crate::web::error::AppError;
+use crate::web::extractors::MaybeActor;
+use crate::web::handlers::users::{defer_to_second_factor, hash_password, start_session};
+use crate::web::handlers::utils::map_repo_error;
+use crate::web::oidc::{IdTokenClaims, OidcClient};
+use actix_session::SessionExt;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+// The authorization request waiting for the provider to send the browser back
+const OIDC_LOGIN_KEY: &str = "gl.oidc_login";
+const OIDC_LOGIN_TTL_SECS: i64 = 10 * 60;
+
+// Tries at a free username before provisioning gives up
+const USERNAME_ATTEMPTS: usize = 5;
+
+#[derive(Serialize, Deserialize)]
+struct OidcLogin {
+    state: String,
+    nonce: String,
+    code_verifier: String,
+    // Set when a signed-in user links the identity to their account
+    link_to: Option<UserID>,
+    // Unix time
+    expires_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+fn configured(oidc: Option<web::Data<OidcClient>>) -> Result<web::Data<OidcClient>, AppError> {
+    oidc.ok_or_else(|| AppError::not_found("OpenID Connect login is not configured"))
+}
+
+fn redirect(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+// Sends the browser to the provider; a signed-in caller links the identity
+// to their account instead of signing in
+pub async fn start_oidc_login(
+    MaybeActor(actor): MaybeActor,
+    oidc: Option<web::Data<OidcClient>>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let oidc = configured(oidc)?;
+
+    let request = oidc
+        .authorization_request()
+        .await
+        .map_err(|err| AppError::internal(format!("oidc.login {err:#}")))?;
+
+    let login = OidcLogin {
+        state: request.state,
+        nonce: request.nonce,
+        code_verifier: request.code_verifier,
+        link_to: actor.filter(|actor| !actor.via_token).map(|actor| actor.id),
+        expires_at: OffsetDateTime::now_utc().unix_timestamp() + OIDC_LOGIN_TTL_SECS,
+    };
+    req.get_session()
+        .insert(OIDC_LOGIN_KEY, login)
+        .map_err(|err| {
+            AppError::internal(format!("oidc.login failed to store login state: {err}"))
+        })?;
+
+    Ok(redirect(&request.url))
+}
+
+pub async fn finish_oidc_login<UR: UserRepository + Clone, TFR: TotpRepository + Clone>(
+    query: web::Query<OidcCallbackQuery>,
+    oidc: Option<web::Data<OidcClient>>,
+    user_repo: web::Data<UR>,
+    totp_repo: web::Data<TFR>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let oidc = configured(oidc)?;
+    let session = req.get_session();
+
+    // Spent whatever happens next, so a callback can't be replayed
+    let login = session
+        .remove_as::<OidcLogin>(OIDC_LOGIN_KEY)
+        .and_then(Result::ok)
+        .filter(|login| login.expires_at > OffsetDateTime::now_utc().unix_timestamp())
+        .ok_or_else(|| AppError::bad_request("No login is waiting for the identity provider"))?;
+
+    if let Some(error) = &query.error {
+        return Err(AppError::unauthorized(format!(
+            "Identity provider refused the login: {error}"
+        )));
+    }
+    if query.state.as_deref() != Some(login.state.as_str()) {
+        return Err(AppError::bad_request(
+            "Login state does not match, start over",
+        ));
+    }
+    let code = query
+        .code
+        .as_deref()
+        .ok_or_else(|| AppError::bad_request("Identity provider sent no authorization code"))?;
+
+    let claims = oidc
+        .exchange_code(code, &login.code_verifier, &login.nonce)
+        .await
+        .map_err(|err| {
+            log::warn!("oidc.callback rejected a login: {err:#}");
+            AppError::unauthorized("Identity provider login failed")
+        })?;
+
+    let identity = ExternalIdentity {
+        issuer: claims.iss.clone(),
+        subject: claims.sub.clone(),
+    };
+
+    let user = match user_repo.find_by_identity(&identity).await {
+        Ok(user) => match login.link_to {
+            Some(user_id) if user_id != user.id => {
+                return Err(AppError::conflict(
+                    "This identity is already linked to another account",
+                ));
+            }
+            // Linked before, nothing changes
+            Some(_) => return Ok(redirect(&oidc.config().after_login_url)),
+            None => user,
+        },
+        Err(RepoError::NotFound) => match login.link_to {
+            Some(user_id) => {
+                user_repo
+                    .link_identity(user_id, &identity)
+                    .await
+                    .map_err(|err| match err {
+                        RepoError::Conflict => {
+                            AppError::conflict("This identity is already linked to another account")
+                        }
+                        err => map_repo_error(err, "User not found", "users.link_identity"),
+                    })?;
+                log::info!(
+                    target: "audit",
+                    "identity {:?} at {} linked to user {}",
+                    identity.subject,
+                    identity.issuer,
+                    user_id
+                );
+
+                // Already signed in, nothing else to do
+                return Ok(redirect(&oidc.config().after_login_url));
+            }
+            None if oidc.config().provision_users => {
+                provision_user(user_repo.get_ref(), identity, &claims).await?
+            }
+            None => {
+                return Err(AppError::forbidden("No account is linked to this identity"));
+            }
+        },
+        Err(err) => {
+            return Err(map_repo_error(
+                err,
+                "User not found",
+                "users.find_by_identity",
+            ));
+        }
+    };
+
+    let after_login = &oidc.config().after_login_url;
+    if defer_to_second_factor(&req, totp_repo.get_ref(), &user, "oidc.callback").await? {
+        let separator = if after_login.contains('?') { '&' } else { '?' };
+        return Ok(redirect(&format!(
+            "{after_login}{separator}two_factor=required"
+        )));
+    }
+
+    start_session(&req, user.id, "oidc.callback")?;
+
+    Ok(redirect(after_login))
+}
+
+// Creates an account for a new identity, named after its claims
+async fn provision_user<UR: UserRepository>(
+    user_repo: &UR,
+    identity: ExternalIdentity,
+    claims: &IdTokenClaims,
+) -> Result<User, AppError> {
+    let claimed = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| {
+            claims
+                .email
+                .as_deref()
+                .and_then(|email| email.split('@').next())
+        })
+        .or(claims.name.as_deref())
+        .unwrap_or_default();
+    let base = username_from(claimed);
+
+    // Nobody knows this password, the account signs in through the provider
+    let password_hash = hash_password(&generate_token())?;
+
+    for attempt in 0..USERNAME_ATTEMPTS {
+        let username = if attempt == 0 {
+            base.clone()
+        } else {
+            let prefix: String = base.chars().take(USERNAME_MAX_LEN - 5).collect();
+            format!("{prefix}-{}", &generate_token()[..4])
+        };
+
+        let new_user = NewUser {
+            username,
+            password_hash: password_hash.clone(),
+            // Provisioning is switched on separately from registration
+            registration: RegistrationMode::Open,
+            invite_code_hash: None,
+            identity: Some(identity.clone()),
+        };
+
+        match user_repo.create(new_user).await {
+            Ok(user_id) => {
+                log::info!(
+                    target: "audit",
+                    "provisioned user {} for identity {:?} at {}",
+                    user_id,
+                    identity.subject,
+                    identity.issuer
+                );
+                return user_repo
+                    .find_by_id(user_id)
+                    .await
+                    .map_err(|err| map_repo_error(err, "User not found", "users.find_by_id"));
+            }
+            // The username is taken, or a parallel login provisioned the
+            // identity first
+            Err(RepoError::Conflict) => {
+                if let Ok(user) = user_repo.find_by_identity(&identity).await {
+                    return Ok(user);
+                }
+            }
+            Err(err) => return Err(map_repo_error(err, "User not found", "users.create")),
+        }
+    }
+
+    Err(AppError::conflict(
+        "Could not find a free username for this identity",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::postgres::sessions::PostgresSessionRepository;
+    use crate::storage::postgres::testing::load_schema;
+    use crate::storage::postgres::totp::PostgresTotpRepository;
+    use crate::storage::postgres::users::PostgresUserRepository;
+    use crate::web::handlers::users::start_session;
+    use crate::web::oidc::mock_idp::{AFTER_LOGIN_URL, Approval, ISSUER, MockIdp};
+    use crate::web::session_store::RepoSessionStore;
+    use actix_http::Request;
+    use actix_identity::IdentityMiddleware;
+    use actix_session::SessionMiddleware;
+    use actix_web::body::MessageBody;
+    use actix_web::cookie::{Cookie, Key};
+    use actix_web::dev::{Service, ServiceResponse};
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test};
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    type Users = PostgresUserRepository;
+    type Totp = PostgresTotpRepository;
+
+    // Stands in for a password login
+    async fn sign_in(path: web::Path<UserID>, req: HttpRequest) -> Result<HttpResponse, AppError> {
+        start_session(&req, path.into_inner(), "test.sign_in")?;
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    async fn app(
+        pool: &PgPool,
+        oidc: OidcClient,
+    ) -> impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = actix_web::Error>
+    {
+        let users = Users::new(pool.clone());
+        let actor_users: web::Data<dyn UserRepository> =
+            web::Data::from(Arc::new(users.clone()) as Arc<dyn UserRepository>);
+
+        test::init_service(
+            App::new()
+                .wrap(IdentityMiddleware::default())
+                .wrap(SessionMiddleware::new(
+                    RepoSessionStore::new(PostgresSessionRepository::new(pool.clone())),
+                    Key::generate(),
+                ))
+                .app_data(web::Data::new(users))
+                .app_data(web::Data::new(Totp::new(pool.clone())))
+                .app_data(actor_users)
+                .app_data(web::Data::new(oidc))
+                .route("/sign-in/{id}", web::post().to(sign_in))
+                .route("/oidc/login", web::get().to(start_oidc_login))
+                .route(
+                    "/oidc/callback",
+                    web::get().to(finish_oidc_login::<Users, Totp>),
+                ),
+        )
+        .await
+    }
+
+    fn session_cookie<B>(response: &ServiceResponse<B>) -> Option<Cookie<'static>> {
+        response
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == "id")
+            .map(Cookie::into_owned)
+    }
+
+    fn location<B>(response: &ServiceResponse<B>) -> String {
+        response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    // Runs the whole redirect dance and returns the callback response
+    async fn oidc_login<S, B>(
+        app: &S,
+        idp: &MockIdp,
+        cookie: Option<Cookie<'static>>,
+        approval: Approval,
+    ) -> ServiceResponse<B>
+    where
+        S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    {
+        let mut request = test::TestRequest::get().uri("/oidc/login");
+        if let Some(cookie) = &cookie {
+            request = request.cookie(cookie.clone());
+        }
+        let response = test::call_service(app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let cookie = session_cookie(&response)
+            .or(cookie)
+            .expect("login left no session");
+
+        let (code, state) = idp.approve(&location(&response), approval);
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/oidc/callback?code={code}&state={state}"))
+            .cookie(cookie)
+            .to_request();
+        test::call_service(app, request).await
+    }
+
+    async fn user_count(pool: &PgPool) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM users")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    fn identity(subject: &str) -> ExternalIdentity {
+        ExternalIdentity {
+            issuer: ISSUER.to_string(),
+            subject: subject.to_string(),
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn provisions_an_unknown_identity_once(pool: PgPool) {
+        load_schema(&pool).await;
+        let idp = MockIdp::start();
+        let app = app(&pool, idp.client(true)).await;
+        let mut approval = Approval::new("alice-sub");
+        approval.username = Some("alice".to_string());
+
+        let response = oidc_login(&app, &idp, None, approval.clone()).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(location(&response), AFTER_LOGIN_URL);
+
+        let user = Users::new(pool.clone())
+            .find_by_identity(&identity("alice-sub"))
+            .await
+            .unwrap();
+        assert_eq!(user.username, "alice");
+
+        let response = oidc_login(&app, &idp, None, approval).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(user_count(&pool).await, 1);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn refuses_unknown_identity_without_provisioning(pool: PgPool) {
+        load_schema(&pool).await;
+        let idp = MockIdp::start();
+        let app = app(&pool, idp.client(false)).await;
+
+        let response = oidc_login(&app, &idp, None, Approval::new("alice-sub")).await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(user_count(&pool).await, 0);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn links_identity_to_the_signed_in_user(pool: PgPool) {
+        load_schema(&pool).await;
+        let bob: UserID = sqlx::query_scalar(
+            "INSERT INTO users (username, password_hash) VALUES ('bob', 'x') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let idp = MockIdp::start();
+        // Linking never provisions, even where provisioning is on
+        let app = app(&pool, idp.client(true)).await;
+
+        let request = test::TestRequest::post()
+            .uri(&format!("/sign-in/{bob}"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        let cookie = session_cookie(&response);
+
+        let response = oidc_login(&app, &idp, cookie, Approval::new("bob-sub")).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let user = Users::new(pool.clone())
+            .find_by_identity(&identity("bob-sub"))
+            .await
+            .unwrap();
+        assert_eq!(user.id, bob);
+        assert_eq!(user_count(&pool).await, 1);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn refuses_to_link_an_identity_of_another_user(pool: PgPool) {
+        load_schema(&pool).await;
+        let idp = MockIdp::start();
+        let app = app(&pool, idp.client(true)).await;
+        let response = oidc_login(&app, &idp, None, Approval::new("alice-sub")).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let bob: UserID = sqlx::query_scalar(
+            "INSERT INTO users (username, password_hash) VALUES ('bob', 'x') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let request = test::TestRequest::post()
+            .uri(&format!("/sign-in/{bob}"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        let cookie = session_cookie(&response);
+
+        let response = oidc_login(&app, &idp, cookie, Approval::new("alice-sub")).await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+}
@@ -1,33 +1,52 @@
 use crate::application::contracts::{
-    NewPlaylist, PaginationMode, PlaylistQuery, TagQuery, UpdatePlaylist,
+    AddPlaylistItem, DerivePlaylistTags, MergePlaylist, MovePlaylistItem, NewPlaylist,
+    NewPlaylistCollaborator, PaginationMode, PlaylistFormat, PlaylistQuery, ReorderPlaylistItems,
+    TagQuery, UpdatePlaylist, UpdatePlaylistCollaborator, UpdatePlaylistProgress,
 };
 use crate::application::ports::{
     FileRepository, PlaylistRepository, PostRepository, TagRepository,
 };
 use crate::application::use_cases::services::Services;
 use crate::domain::files::FileStorage;
+use crate::domain::model::{Actor, RepoError, SmartPlaylistQuery};
 use crate::web::error::AppError;
-use crate::web::handlers::dto::SearchQueryParams;
+use crate::web::extractors::MaybeActor;
+use crate::web::handlers::dto::{PlaylistFormatParams, SearchCursorParams, SearchQueryParams};
 use crate::web::handlers::utils::{has_filters, map_repo_error, parse_uuid};
-use actix_identity::Identity;
+use crate::web::web_server::PublicUrl;
 use actix_web::{HttpResponse, web};
 use uuid::Uuid;
 
-fn resolve_user_id(user: Option<Identity>) -> Result<Uuid, AppError> {
-    let user_id_str = match user {
-        Some(u) => u.id().map_err(|err| {
-            log::warn!("Failed to resolve identity id from session: {err}");
-            AppError::unauthorized("Unauthorized")
-        })?,
-        None => return Err(AppError::unauthorized("Unauthorized")),
-    };
+const INVALID_COVER: &str = "Cover must be an uploaded picture";
 
-    parse_uuid(&user_id_str, "user id")
+// Checked up front so the cover stays the only reason for InvalidInput below
+fn check_smart_limit(query: Option<&SmartPlaylistQuery>) -> Result<(), AppError> {
+    match query {
+        Some(query) if !query.has_valid_limit() => Err(AppError::bad_request(format!(
+            "smart_query.limit must be 1 to {}",
+            SmartPlaylistQuery::MAX_LIMIT
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// Cover upkeep never fails the change that triggered it
+async fn refresh_cover<PR, PLR, TR, FR, FS>(
+    services: &Services<PR, PLR, TR, FR, FS>,
+    playlist_id: Uuid,
+) where
+    PLR: PlaylistRepository,
+    FR: FileRepository,
+    FS: FileStorage,
+{
+    if let Err(err) = services.refresh_playlist_cover.execute(playlist_id).await {
+        log::warn!("failed to refresh cover of playlist {playlist_id}: {err:?}");
+    }
 }
 
 pub async fn get_my_playlists<PR, PLR, TR, FR, FS>(
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
-    user: Option<Identity>,
+    actor: Actor,
     query: web::Json<SearchQueryParams>,
 ) -> Result<HttpResponse, AppError>
 where
@@ -37,7 +56,7 @@ where
     FR: FileRepository + Clone,
     FS: FileStorage + Clone,
 {
-    let user_uuid = resolve_user_id(user)?;
+    let user_uuid = actor.id;
 
     let tag_query = query.tag_query.clone().unwrap_or_default();
     let text_query = query.text_query.clone().unwrap_or_default();
@@ -78,7 +97,7 @@ where
 }
 pub async fn create_playlist<PR, PLR, TR, FR, FS>(
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
-    user: Option<Identity>,
+    actor: Actor,
     payload: web::Json<NewPlaylist>,
 ) -> Result<HttpResponse, AppError>
 where
@@ -88,19 +107,25 @@ where
     FR: FileRepository + Clone,
     FS: FileStorage + Clone,
 {
-    let user_id = resolve_user_id(user)?;
+    let user_id = actor.id;
+    check_smart_limit(payload.smart_query.as_ref())?;
 
     let playlist_id = services
         .create_playlist
         .execute(user_id, payload.into_inner())
         .await
-        .map_err(|err| map_repo_error(err, "Failed to create playlist", "playlists.create"))?;
+        .map_err(|err| match err {
+            RepoError::InvalidInput => AppError::bad_request(INVALID_COVER),
+            err => map_repo_error(err, "Failed to create playlist", "playlists.create"),
+        })?;
+
+    refresh_cover(&services, playlist_id).await;
 
     Ok(HttpResponse::Created().json(playlist_id))
 }
 pub async fn get_playlist_details<PR, PLR, TR, FR, FS>(
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
-    user: Option<Identity>,
+    MaybeActor(actor): MaybeActor,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError>
 where
@@ -110,12 +135,13 @@ where
     FR: FileRepository + Clone,
     FS: FileStorage + Clone,
 {
-    let user_id = resolve_user_id(user)?;
+    let max_rating = Actor::viewer_max_rating(actor.as_ref());
+    let viewer_id = actor.map(|actor| actor.id);
     let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
 
     let playlist = services
         .get_playlist
-        .execute(user_id, playlist_id)
+        .execute(viewer_id, max_rating, playlist_id)
         .await
         .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.get"))?;
 
@@ -124,7 +150,7 @@ where
 
 pub async fn delete_playlist<PR, PLR, TR, FR, FS>(
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
-    user: Option<Identity>,
+    actor: Actor,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError>
 where
@@ -134,7 +160,7 @@ where
     FR: FileRepository + Clone,
     FS: FileStorage + Clone,
 {
-    let user_id = resolve_user_id(user)?;
+    let user_id = actor.id;
     let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
 
     services
@@ -148,7 +174,7 @@ where
 
 pub async fn update_playlist<PR, PLR, TR, FR, FS>(
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
-    user: Option<Identity>,
+    actor: Actor,
     path: web::Path<String>,
     payload: web::Json<UpdatePlaylist>,
 ) -> Result<HttpResponse, AppError>
@@ -159,14 +185,710 @@ where
     FR: FileRepository + Clone,
     FS: FileStorage + Clone,
 {
-    let user_id = resolve_user_id(user)?;
+    let user_id = actor.id;
     let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+    check_smart_limit(payload.smart_query.as_ref().and_then(Option::as_ref))?;
 
     services
         .update_playlist
         .execute(user_id, playlist_id, payload.into_inner())
         .await
-        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.update"))?;
+        .map_err(|err| match err {
+            RepoError::InvalidInput => AppError::bad_request(INVALID_COVER),
+            err => map_repo_error(err, "Playlist not found", "playlists.update"),
+        })?;
+
+    refresh_cover(&services, playlist_id).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_shared_playlist<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    MaybeActor(actor): MaybeActor,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let share_token = path.into_inner();
+
+    let playlist = services
+        .get_shared_playlist
+        .execute(&share_token, Actor::viewer_max_rating(actor.as_ref()))
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.get_shared"))?;
+
+    Ok(HttpResponse::Ok().json(playlist))
+}
+
+pub async fn get_playlist_items<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    MaybeActor(actor): MaybeActor,
+    path: web::Path<String>,
+    query: web::Query<SearchCursorParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let max_rating = Actor::viewer_max_rating(actor.as_ref());
+    let viewer_id = actor.map(|actor| actor.id);
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let cursor = query.into_inner();
+    if let Some(PaginationMode::Offset) = cursor.mode {
+        return Err(AppError::bad_request(
+            "Offset mode doesn't support in playlist",
+        ));
+    }
+
+    let items = services
+        .get_playlist_items
+        .execute(viewer_id, max_rating, playlist_id, cursor.into())
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.get_items"))?;
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
+pub async fn get_shared_playlist_items<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    MaybeActor(actor): MaybeActor,
+    path: web::Path<String>,
+    query: web::Query<SearchCursorParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let share_token = path.into_inner();
+
+    let cursor = query.into_inner();
+    if let Some(PaginationMode::Offset) = cursor.mode {
+        return Err(AppError::bad_request(
+            "Offset mode doesn't support in playlist",
+        ));
+    }
+
+    let items = services
+        .get_shared_playlist_items
+        .execute(
+            &share_token,
+            Actor::viewer_max_rating(actor.as_ref()),
+            cursor.into(),
+        )
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.get_shared_items"))?;
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
+pub async fn search_public_playlists<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    query: web::Json<SearchQueryParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let playlist_query = PlaylistQuery {
+        tags: TagQuery::from(query.tag_query.clone().unwrap_or_default()),
+        text: query.text_query.clone().unwrap_or_default(),
+    };
+
+    let cursor = query.cursor.clone().unwrap_or_default();
+    if let Some(PaginationMode::Offset) = cursor.mode {
+        return Err(AppError::bad_request(
+            "Offset mode doesn't support in playlist",
+        ));
+    }
+
+    let playlists = services
+        .search_public_playlists
+        .execute(playlist_query, cursor.into())
+        .await
+        .map_err(|err| map_repo_error(err, "Playlists not found", "playlists.search_public"))?;
+
+    Ok(HttpResponse::Ok().json(playlists))
+}
+
+pub async fn share_playlist<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let share = services
+        .share_playlist
+        .execute(user_id, playlist_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.share"))?;
+
+    Ok(HttpResponse::Ok().json(share))
+}
+
+pub async fn revoke_playlist_share<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let share = services
+        .revoke_playlist_share
+        .execute(user_id, playlist_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.revoke_share"))?;
+
+    Ok(HttpResponse::Ok().json(share))
+}
+
+pub async fn add_playlist_item<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+    payload: web::Json<AddPlaylistItem>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let item_id = services
+        .add_playlist_item
+        .execute(user_id, playlist_id, payload.into_inner())
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.add_item"))?;
+
+    refresh_cover(&services, playlist_id).await;
+
+    Ok(HttpResponse::Created().json(item_id))
+}
+
+pub async fn remove_playlist_item<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let (playlist_id, item_id) = path.into_inner();
+    let playlist_id = parse_uuid(&playlist_id, "playlist id")?;
+    let item_id = parse_uuid(&item_id, "item id")?;
+
+    services
+        .remove_playlist_item
+        .execute(user_id, playlist_id, item_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist item not found", "playlists.remove_item"))?;
+
+    refresh_cover(&services, playlist_id).await;
 
     Ok(HttpResponse::NoContent().finish())
 }
+
+pub async fn move_playlist_item<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<(String, String)>,
+    payload: web::Json<MovePlaylistItem>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let (playlist_id, item_id) = path.into_inner();
+    let playlist_id = parse_uuid(&playlist_id, "playlist id")?;
+    let item_id = parse_uuid(&item_id, "item id")?;
+
+    services
+        .move_playlist_item
+        .execute(user_id, playlist_id, item_id, payload.index)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist item not found", "playlists.move_item"))?;
+
+    refresh_cover(&services, playlist_id).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn reorder_playlist_items<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+    payload: web::Json<ReorderPlaylistItems>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    services
+        .reorder_playlist_items
+        .execute(user_id, playlist_id, payload.into_inner().item_ids)
+        .await
+        .map_err(|err| match err {
+            RepoError::Conflict => {
+                AppError::conflict("Item ids don't match the current playlist items")
+            }
+            err => map_repo_error(err, "Playlist not found", "playlists.reorder_items"),
+        })?;
+
+    refresh_cover(&services, playlist_id).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn export_playlist<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    MaybeActor(actor): MaybeActor,
+    public_url: web::Data<PublicUrl>,
+    path: web::Path<String>,
+    query: web::Query<PlaylistFormatParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let viewer_id = actor.map(|actor| actor.id);
+    let max_rating = Actor::viewer_max_rating(actor.as_ref());
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+    let format = query.format.unwrap_or(PlaylistFormat::Json);
+
+    let files_url = public_url.join("/api/files/");
+
+    let body = services
+        .export_playlist
+        .execute(viewer_id, max_rating, playlist_id, format, &files_url)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.export"))?;
+
+    let (content_type, extension) = match format {
+        PlaylistFormat::M3u8 => ("application/vnd.apple.mpegurl", "m3u8"),
+        PlaylistFormat::Xspf => ("application/xspf+xml", "xspf"),
+        PlaylistFormat::Json => ("application/json", "json"),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{playlist_id}.{extension}\""),
+        ))
+        .body(body))
+}
+
+pub async fn import_playlist<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    query: web::Query<PlaylistFormatParams>,
+    body: String,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let format = query.format.unwrap_or(PlaylistFormat::Json);
+
+    let imported = services
+        .import_playlist
+        .execute(user_id, format, &body)
+        .await
+        .map_err(|err| match err {
+            RepoError::InvalidInput => AppError::bad_request("Playlist file could not be read"),
+            err => map_repo_error(err, "Failed to import playlist", "playlists.import"),
+        })?;
+
+    refresh_cover(&services, imported.id).await;
+
+    Ok(HttpResponse::Created().json(imported))
+}
+
+pub async fn save_playlist_progress<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+    payload: web::Json<UpdatePlaylistProgress>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let progress = services
+        .save_playlist_progress
+        .execute(user_id, playlist_id, payload.into_inner())
+        .await
+        .map_err(|err| {
+            map_repo_error(err, "Playlist or item not found", "playlists.save_progress")
+        })?;
+
+    Ok(HttpResponse::Ok().json(progress))
+}
+
+pub async fn clear_playlist_progress<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    services
+        .clear_playlist_progress
+        .execute(user_id, playlist_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.clear_progress"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn continue_watching<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+
+    let entries = services
+        .continue_watching
+        .execute(user_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Nothing to continue", "playlists.continue_watching"))?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+pub async fn duplicate_playlist<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let id = services
+        .duplicate_playlist
+        .execute(user_id, playlist_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.duplicate"))?;
+
+    refresh_cover(&services, id).await;
+
+    Ok(HttpResponse::Created().json(id))
+}
+
+pub async fn merge_playlists<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+    payload: web::Json<MergePlaylist>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let result = services
+        .merge_playlists
+        .execute(user_id, playlist_id, payload.source_id)
+        .await
+        .map_err(|err| match err {
+            RepoError::Conflict => AppError::conflict("A playlist can't be merged into itself"),
+            err => map_repo_error(err, "Playlist not found", "playlists.merge"),
+        })?;
+
+    refresh_cover(&services, playlist_id).await;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn apply_playlist_tags<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let result = services
+        .apply_playlist_tags
+        .execute(actor, playlist_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.apply_tags"))?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn derive_playlist_tags<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+    payload: web::Json<DerivePlaylistTags>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let result = services
+        .derive_playlist_tags
+        .execute(user_id, playlist_id, payload.limit)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.derive_tags"))?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub async fn list_playlist_collaborators<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let collaborators = services
+        .list_playlist_collaborators
+        .execute(user_id, playlist_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Playlist not found", "playlists.list_collaborators"))?;
+
+    Ok(HttpResponse::Ok().json(collaborators))
+}
+
+pub async fn add_playlist_collaborator<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<String>,
+    payload: web::Json<NewPlaylistCollaborator>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let collaborator = services
+        .add_playlist_collaborator
+        .execute(user_id, playlist_id, payload.into_inner())
+        .await
+        .map_err(|err| match err {
+            RepoError::Conflict => AppError::conflict("The owner can't be a collaborator"),
+            err => map_repo_error(
+                err,
+                "Playlist or user not found",
+                "playlists.add_collaborator",
+            ),
+        })?;
+
+    Ok(HttpResponse::Created().json(collaborator))
+}
+
+pub async fn update_playlist_collaborator<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<(String, String)>,
+    payload: web::Json<UpdatePlaylistCollaborator>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let (playlist_id, collaborator_id) = path.into_inner();
+    let playlist_id = parse_uuid(&playlist_id, "playlist id")?;
+    let collaborator_id = parse_uuid(&collaborator_id, "user id")?;
+
+    let collaborator = services
+        .update_playlist_collaborator
+        .execute(user_id, playlist_id, collaborator_id, payload.role)
+        .await
+        .map_err(|err| {
+            map_repo_error(
+                err,
+                "Collaborator not found",
+                "playlists.update_collaborator",
+            )
+        })?;
+
+    Ok(HttpResponse::Ok().json(collaborator))
+}
+
+pub async fn remove_playlist_collaborator<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    actor: Actor,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let user_id = actor.id;
+    let (playlist_id, collaborator_id) = path.into_inner();
+    let playlist_id = parse_uuid(&playlist_id, "playlist id")?;
+    let collaborator_id = parse_uuid(&collaborator_id, "user id")?;
+
+    services
+        .remove_playlist_collaborator
+        .execute(user_id, playlist_id, collaborator_id)
+        .await
+        .map_err(|err| {
+            map_repo_error(
+                err,
+                "Collaborator not found",
+                "playlists.remove_collaborator",
+            )
+        })?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_smart_playlist_posts<PR, PLR, TR, FR, FS>(
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    MaybeActor(actor): MaybeActor,
+    path: web::Path<String>,
+    query: web::Query<SearchCursorParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let max_rating = Actor::viewer_max_rating(actor.as_ref());
+    let viewer_id = actor.map(|actor| actor.id);
+    let playlist_id = parse_uuid(&path.into_inner(), "playlist id")?;
+
+    let cursor = query.into_inner();
+    if let Some(PaginationMode::Offset) = cursor.mode {
+        return Err(AppError::bad_request(
+            "Offset mode doesn't support in playlist",
+        ));
+    }
+
+    let posts = services
+        .get_smart_playlist_posts
+        .execute(viewer_id, max_rating, playlist_id, cursor.into())
+        .await
+        .map_err(|err| map_repo_error(err, "Smart playlist not found", "playlists.smart_posts"))?;
+
+    Ok(HttpResponse::Ok().json(posts))
+}
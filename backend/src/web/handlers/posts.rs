@@ -1,13 +1,18 @@
-use crate::application::contracts::{Cursor, KeysetCursor, NewTag, PaginationMode, UpdatePost};
+use crate::application::contracts::{
+    Cursor, KeysetCursor, NewPostDetails, NewTag, PaginationMode, UpdatePost,
+};
 use crate::application::ports::{
     FileRepository, PlaylistRepository, PostRepository, TagRepository,
 };
 use crate::application::use_cases::services::Services;
 use crate::domain::files::FileStorage;
-use crate::domain::model::{ByteStream, StorageError, TagCategory};
+use crate::domain::model::{Actor, ByteStream, RepoError, StorageError, TagCategory};
 use crate::web::error::AppError;
-use crate::web::handlers::dto::{CreatePostMeta, SearchQueryParams};
-use crate::web::handlers::utils::{has_filters, map_repo_error, parse_uuid};
+use crate::web::extractors::MaybeActor;
+use crate::web::handlers::dto::{
+    CreatePostMeta, SearchCursorParams, SearchQueryParams, VoteParams,
+};
+use crate::web::handlers::utils::{has_filters, map_repo_error, parse_uuid, post_tag_query};
 use actix_multipart::Multipart;
 use actix_web::{HttpResponse, web};
 use futures_util::{StreamExt, TryStreamExt};
@@ -18,6 +23,7 @@ use tokio_stream::wrappers::ReceiverStream;
 
 pub async fn search_posts<PR, PLR, TR, FR, FS>(
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    MaybeActor(actor): MaybeActor,
     query: web::Json<SearchQueryParams>,
 ) -> Result<HttpResponse, AppError>
 where
@@ -33,11 +39,16 @@ where
 
     log::info!("search posts requested mode={cursor_mode:?}");
 
+    // The viewer's content filter counts as a filter too
+    let unfiltered = !has_filters(&tag_query);
+    let post_query = post_tag_query(tag_query, actor.as_ref())?;
+    let unfiltered = unfiltered && post_query.ratings.is_none();
+
     match cursor_mode {
         PaginationMode::Offset => {
             let offset_cursor: Cursor = cursor.into();
 
-            if !has_filters(&tag_query) {
+            if unfiltered {
                 let posts = services
                     .get_all_posts
                     .execute(offset_cursor)
@@ -48,7 +59,7 @@ where
 
             let posts = services
                 .search_posts
-                .execute(tag_query.clone().into(), offset_cursor)
+                .execute(post_query, offset_cursor)
                 .await
                 .map_err(|err| map_repo_error(err, "Posts not found", "posts.search"))?;
 
@@ -57,7 +68,7 @@ where
         PaginationMode::Keyset => {
             let keyset_cursor: KeysetCursor = cursor.into();
 
-            if !has_filters(&tag_query) {
+            if unfiltered {
                 let posts = services
                     .get_all_posts_keyset
                     .execute(keyset_cursor)
@@ -71,7 +82,7 @@ where
 
             let posts = services
                 .search_posts_keyset
-                .execute(tag_query.into(), keyset_cursor)
+                .execute(post_query, keyset_cursor)
                 .await
                 .map_err(|err| map_repo_error(err, "Posts not found", "posts.search_keyset"))?;
 
@@ -81,6 +92,7 @@ where
 }
 
 pub async fn create_post<PR, PLR, TR, FR, FS>(
+    actor: Actor,
     mut payload: Multipart,
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
 ) -> Result<HttpResponse, AppError>
@@ -156,15 +168,15 @@ where
                     })
                     .collect();
 
+                let details = NewPostDetails {
+                    title: meta_data.title.clone(),
+                    tags: new_tags,
+                    rating: meta_data.rating,
+                };
+
                 let id = services
                     .create_post
-                    .execute(
-                        meta_data.title.clone(),
-                        stream,
-                        file_ext.as_deref(),
-                        content_type,
-                        new_tags,
-                    )
+                    .execute(actor, details, stream, file_ext.as_deref(), content_type)
                     .await
                     .map_err(|err| map_repo_error(err, "Post not found", "posts.create"))?;
 
@@ -181,6 +193,7 @@ where
 
 pub async fn get_post<PR, PLR, TR, FR, FS>(
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    MaybeActor(actor): MaybeActor,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError>
 where
@@ -197,14 +210,20 @@ where
     let id = parse_uuid(&id_str, "post id")?;
     let post = services
         .get_post
-        .execute(id)
+        .execute(id, Actor::viewer_max_rating(actor.as_ref()))
         .await
-        .map_err(|err| map_repo_error(err, "Post not found", "posts.get"))?;
+        .map_err(|err| match err {
+            RepoError::Forbidden => {
+                AppError::forbidden("This post is rated above your content filter")
+            }
+            err => map_repo_error(err, "Post not found", "posts.get"),
+        })?;
 
     Ok(HttpResponse::Ok().json(post))
 }
 
 pub async fn delete_post<PR, PLR, TR, FR, FS>(
+    actor: Actor,
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError>
@@ -219,7 +238,7 @@ where
 
     services
         .delete_post
-        .execute(id)
+        .execute(actor, id)
         .await
         .map_err(|err| map_repo_error(err, "Post not found", "posts.delete"))?;
 
@@ -227,6 +246,7 @@ where
 }
 
 pub async fn update_post<PR, PLR, TR, FR, FS>(
+    actor: Actor,
     services: web::Data<Services<PR, PLR, TR, FR, FS>>,
     path: web::Path<String>,
     payload: web::Json<UpdatePost>,
@@ -242,9 +262,222 @@ where
 
     services
         .update_post
-        .execute(id, payload.into_inner())
+        .execute(actor, id, payload.into_inner())
         .await
         .map_err(|err| map_repo_error(err, "Post not found", "posts.update"))?;
 
     Ok(HttpResponse::NoContent().finish())
 }
+
+pub async fn toggle_post_favorite<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let id = parse_uuid(&path.into_inner(), "post id")?;
+
+    let interaction = services
+        .toggle_post_favorite
+        .execute(actor, id)
+        .await
+        .map_err(|err| map_repo_error(err, "Post not found", "posts.toggle_favorite"))?;
+
+    Ok(HttpResponse::Ok().json(interaction))
+}
+
+pub async fn vote_post<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<String>,
+    payload: web::Json<VoteParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let id = parse_uuid(&path.into_inner(), "post id")?;
+
+    let interaction = services
+        .vote_post
+        .execute(actor, id, payload.value)
+        .await
+        .map_err(|err| match err {
+            RepoError::InvalidInput => AppError::bad_request("Vote must be 1, -1 or 0"),
+            err => map_repo_error(err, "Post not found", "posts.vote"),
+        })?;
+
+    Ok(HttpResponse::Ok().json(interaction))
+}
+
+pub async fn get_post_interaction<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let id = parse_uuid(&path.into_inner(), "post id")?;
+
+    let interaction = services
+        .get_post_interaction
+        .execute(actor, id)
+        .await
+        .map_err(|err| map_repo_error(err, "Post not found", "posts.interaction"))?;
+
+    Ok(HttpResponse::Ok().json(interaction))
+}
+
+// The caller's favorites, most recently added first
+pub async fn get_favorite_posts<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    query: web::Query<SearchCursorParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let cursor = query.into_inner();
+    if let Some(PaginationMode::Offset) = cursor.mode {
+        return Err(AppError::bad_request(
+            "Offset mode doesn't support in favorites",
+        ));
+    }
+
+    let posts = services
+        .get_favorite_posts
+        .execute(actor, cursor.into())
+        .await
+        .map_err(|err| map_repo_error(err, "Posts not found", "posts.favorites"))?;
+
+    Ok(HttpResponse::Ok().json(posts))
+}
+
+pub async fn get_post_revisions<PR, PLR, TR, FR, FS>(
+    MaybeActor(actor): MaybeActor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<String>,
+    query: web::Query<SearchCursorParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let id = parse_uuid(&path.into_inner(), "post id")?;
+    let cursor = query.into_inner();
+    if let Some(PaginationMode::Offset) = cursor.mode {
+        return Err(AppError::bad_request(
+            "Offset mode doesn't support in revisions",
+        ));
+    }
+
+    let revisions = services
+        .get_post_revisions
+        .execute(id, Actor::viewer_max_rating(actor.as_ref()), cursor.into())
+        .await
+        .map_err(|err| match err {
+            RepoError::Forbidden => {
+                AppError::forbidden("This post is rated above your content filter")
+            }
+            err => map_repo_error(err, "Post not found", "posts.revisions"),
+        })?;
+
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+pub async fn rollback_post<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let (post_id, revision_id) = path.into_inner();
+    let post_id = parse_uuid(&post_id, "post id")?;
+    let revision_id = parse_uuid(&revision_id, "revision id")?;
+
+    services
+        .rollback_post
+        .execute(actor, post_id, revision_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Post or revision not found", "posts.rollback"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_trash<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    query: web::Query<SearchCursorParams>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let cursor = query.into_inner();
+    if let Some(PaginationMode::Offset) = cursor.mode {
+        return Err(AppError::bad_request(
+            "Offset mode doesn't support in trash",
+        ));
+    }
+
+    let posts = services
+        .get_trash
+        .execute(actor, cursor.into())
+        .await
+        .map_err(|err| map_repo_error(err, "Posts not found", "posts.trash"))?;
+
+    Ok(HttpResponse::Ok().json(posts))
+}
+
+pub async fn restore_post<PR, PLR, TR, FR, FS>(
+    actor: Actor,
+    services: web::Data<Services<PR, PLR, TR, FR, FS>>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError>
+where
+    PR: PostRepository + Clone,
+    PLR: PlaylistRepository + Clone,
+    TR: TagRepository + Clone,
+    FR: FileRepository + Clone,
+    FS: FileStorage + Clone,
+{
+    let id = parse_uuid(&path.into_inner(), "post id")?;
+
+    services
+        .restore_post
+        .execute(actor, id)
+        .await
+        .map_err(|err| map_repo_error(err, "Post not found in trash", "posts.restore"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
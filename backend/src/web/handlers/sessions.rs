@@ -0,0 +1,69 @@
+use crate::application::contracts::{ActiveSession, RevokedSessions};
+use crate::application::ports::SessionRepository;
+use crate::domain::model::{Actor, SessionID};
+use crate::web::error::AppError;
+use crate::web::handlers::utils::{map_repo_error, parse_uuid, require_session};
+use crate::web::session_store::{SESSION_META_KEY, SessionMeta};
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+
+fn current_session_id(session: &Session) -> Option<SessionID> {
+    session
+        .get::<SessionMeta>(SESSION_META_KEY)
+        .ok()
+        .flatten()
+        .map(|meta| meta.id)
+}
+
+pub async fn list_sessions<SR: SessionRepository + Clone>(
+    actor: Actor,
+    session: Session,
+    session_repo: web::Data<SR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+    let current = current_session_id(&session);
+
+    let sessions: Vec<ActiveSession> = session_repo
+        .list_for_user(actor.id)
+        .await
+        .map_err(|err| map_repo_error(err, "Sessions not found", "sessions.list"))?
+        .into_iter()
+        .map(|session| ActiveSession {
+            current: Some(session.id) == current,
+            session,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+pub async fn revoke_session<SR: SessionRepository + Clone>(
+    actor: Actor,
+    path: web::Path<String>,
+    session_repo: web::Data<SR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+    let session_id = parse_uuid(&path.into_inner(), "session id")?;
+
+    session_repo
+        .revoke(actor.id, session_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Session not found", "sessions.revoke"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Signs out every other device; the current session ends with logout
+pub async fn revoke_other_sessions<SR: SessionRepository + Clone>(
+    actor: Actor,
+    session: Session,
+    session_repo: web::Data<SR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+    let revoked = session_repo
+        .revoke_all(actor.id, current_session_id(&session))
+        .await
+        .map_err(|err| map_repo_error(err, "Sessions not found", "sessions.revoke_all"))?;
+
+    Ok(HttpResponse::Ok().json(RevokedSessions { revoked }))
+}
@@ -0,0 +1,87 @@
+use crate::application::contracts::{CreatedApiToken, NewApiToken};
+use crate::application::helpers::tokens::{generate_token, hash_token};
+use crate::application::ports::ApiTokenRepository;
+use crate::domain::model::{Actor, TokenScope, UserRole};
+use crate::web::error::AppError;
+use crate::web::handlers::utils::{map_repo_error, parse_uuid, require_session};
+use actix_web::{HttpResponse, web};
+use time::{Duration, OffsetDateTime};
+
+const DEFAULT_EXPIRY_DAYS: u32 = 90;
+const MAX_EXPIRY_DAYS: u32 = 365;
+const TOKEN_PREFIX: &str = "gl_";
+
+pub async fn create_api_token<AR: ApiTokenRepository + Clone>(
+    actor: Actor,
+    payload: web::Json<NewApiToken>,
+    token_repo: web::Data<AR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let NewApiToken {
+        name,
+        mut scopes,
+        expires_in_days,
+    } = payload.into_inner();
+
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::bad_request("Token name must not be empty"));
+    }
+
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(AppError::bad_request("Token needs at least one scope"));
+    }
+    if scopes.contains(&TokenScope::Admin) && actor.role != UserRole::Admin {
+        return Err(AppError::forbidden("Only admins can issue admin tokens"));
+    }
+
+    let days = expires_in_days.unwrap_or(DEFAULT_EXPIRY_DAYS);
+    if !(1..=MAX_EXPIRY_DAYS).contains(&days) {
+        return Err(AppError::bad_request(format!(
+            "Token must expire within 1 to {MAX_EXPIRY_DAYS} days"
+        )));
+    }
+    let expires_at = OffsetDateTime::now_utc() + Duration::days(i64::from(days));
+
+    let secret = format!("{TOKEN_PREFIX}{}", generate_token());
+    let token = token_repo
+        .create(actor.id, name, scopes, hash_token(&secret), expires_at)
+        .await
+        .map_err(|err| map_repo_error(err, "User not found", "api_tokens.create"))?;
+
+    Ok(HttpResponse::Created().json(CreatedApiToken { token, secret }))
+}
+
+pub async fn list_api_tokens<AR: ApiTokenRepository + Clone>(
+    actor: Actor,
+    token_repo: web::Data<AR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let tokens = token_repo
+        .list_for_user(actor.id)
+        .await
+        .map_err(|err| map_repo_error(err, "Tokens not found", "api_tokens.list"))?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+pub async fn revoke_api_token<AR: ApiTokenRepository + Clone>(
+    actor: Actor,
+    path: web::Path<String>,
+    token_repo: web::Data<AR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let token_id = parse_uuid(&path.into_inner(), "token id")?;
+
+    token_repo
+        .revoke(actor.id, token_id)
+        .await
+        .map_err(|err| map_repo_error(err, "Token not found", "api_tokens.revoke"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
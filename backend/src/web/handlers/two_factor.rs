@@ -0,0 +1,317 @@
+use crate::application::contracts::{RecoveryCodes, TotpEnrollment, TotpStatus};
+use crate::application::helpers::tokens::hash_token;
+use crate::application::helpers::totp::{
+    generate_recovery_codes, generate_secret, normalize_recovery_code, otpauth_uri, verify_code,
+};
+use crate::application::ports::{TotpRepository, UserRepository};
+use crate::domain::model::{Actor, RepoError, UserTotp};
+use crate::web::error::AppError;
+use crate::web::handlers::utils::{map_repo_error, require_session};
+use actix_web::{HttpResponse, web};
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+// Shown as the account's label in authenticator apps
+const TOTP_ISSUER: &str = "GL";
+
+#[derive(Deserialize)]
+pub struct ConfirmTotpRequest {
+    code: String,
+}
+
+// Either a code from the authenticator or one of the recovery codes
+#[derive(Deserialize)]
+pub struct SecondFactorRequest {
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+fn hash_recovery_codes(codes: &[String]) -> Vec<String> {
+    codes
+        .iter()
+        .map(|code| hash_token(&normalize_recovery_code(code)))
+        .collect()
+}
+
+// Spends the code, so each one gets accepted once
+pub(crate) async fn verify_second_factor<TFR: TotpRepository>(
+    totp_repo: &TFR,
+    totp: &UserTotp,
+    request: &SecondFactorRequest,
+) -> Result<bool, AppError> {
+    if let Some(code) = &request.code {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let Some(step) = verify_code(&totp.secret, code, now) else {
+            return Ok(false);
+        };
+
+        return match totp_repo.use_step(totp.user_id, step).await {
+            Ok(()) => Ok(true),
+            Err(RepoError::Conflict) => Ok(false),
+            Err(err) => Err(map_repo_error(err, "Two-factor not found", "totp.use_step")),
+        };
+    }
+
+    if let Some(code) = &request.recovery_code {
+        let code_hash = hash_token(&normalize_recovery_code(code));
+        return match totp_repo.use_recovery_code(totp.user_id, &code_hash).await {
+            Ok(()) => Ok(true),
+            Err(RepoError::NotFound) => Ok(false),
+            Err(err) => Err(map_repo_error(
+                err,
+                "Recovery code not found",
+                "totp.use_recovery_code",
+            )),
+        };
+    }
+
+    Err(AppError::bad_request(
+        "Either code or recovery_code is required",
+    ))
+}
+
+async fn enabled_totp<TFR: TotpRepository>(
+    totp_repo: &TFR,
+    actor: &Actor,
+) -> Result<UserTotp, AppError> {
+    match totp_repo.find(actor.id).await {
+        Ok(totp) if totp.is_enabled() => Ok(totp),
+        Ok(_) | Err(RepoError::NotFound) => Err(AppError::conflict(
+            "Two-factor authentication is not enabled",
+        )),
+        Err(err) => Err(map_repo_error(err, "Two-factor not found", "totp.find")),
+    }
+}
+
+pub async fn get_two_factor_status<TFR: TotpRepository + Clone>(
+    actor: Actor,
+    totp_repo: web::Data<TFR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let enabled = match totp_repo.find(actor.id).await {
+        Ok(totp) => totp.is_enabled(),
+        Err(RepoError::NotFound) => false,
+        Err(err) => return Err(map_repo_error(err, "Two-factor not found", "totp.find")),
+    };
+
+    let recovery_codes_left = totp_repo
+        .recovery_codes_left(actor.id)
+        .await
+        .map_err(|err| {
+            map_repo_error(err, "Recovery codes not found", "totp.recovery_codes_left")
+        })?;
+
+    Ok(HttpResponse::Ok().json(TotpStatus {
+        enabled,
+        recovery_codes_left,
+    }))
+}
+
+// Hands out a fresh secret; two-factor stays off until a code from it is
+// confirmed
+pub async fn begin_two_factor<UR: UserRepository + Clone, TFR: TotpRepository + Clone>(
+    actor: Actor,
+    user_repo: web::Data<UR>,
+    totp_repo: web::Data<TFR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let user = user_repo
+        .find_by_id(actor.id)
+        .await
+        .map_err(|err| map_repo_error(err, "User not found", "users.find_by_id"))?;
+
+    let secret = generate_secret();
+    totp_repo
+        .begin_enrollment(actor.id, secret.clone())
+        .await
+        .map_err(|err| match err {
+            RepoError::Conflict => {
+                AppError::conflict("Two-factor authentication is already enabled")
+            }
+            err => map_repo_error(err, "User not found", "totp.begin_enrollment"),
+        })?;
+
+    Ok(HttpResponse::Created().json(TotpEnrollment {
+        otpauth_uri: otpauth_uri(TOTP_ISSUER, &user.username, &secret),
+        secret,
+    }))
+}
+
+pub async fn confirm_two_factor<TFR: TotpRepository + Clone>(
+    actor: Actor,
+    form: web::Json<ConfirmTotpRequest>,
+    totp_repo: web::Data<TFR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let totp = match totp_repo.find(actor.id).await {
+        Ok(totp) if !totp.is_enabled() => totp,
+        Ok(_) => {
+            return Err(AppError::conflict(
+                "Two-factor authentication is already enabled",
+            ));
+        }
+        Err(RepoError::NotFound) => {
+            return Err(AppError::not_found("No two-factor enrollment in progress"));
+        }
+        Err(err) => return Err(map_repo_error(err, "Two-factor not found", "totp.find")),
+    };
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let step = verify_code(&totp.secret, &form.code, now)
+        .ok_or_else(|| AppError::bad_request("Invalid two-factor code"))?;
+
+    let recovery_codes = generate_recovery_codes();
+    totp_repo
+        .confirm(actor.id, step, hash_recovery_codes(&recovery_codes))
+        .await
+        .map_err(|err| {
+            map_repo_error(err, "No two-factor enrollment in progress", "totp.confirm")
+        })?;
+
+    log::info!(target: "audit", "two-factor authentication enabled for user {}", actor.id);
+
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
+
+pub async fn disable_two_factor<TFR: TotpRepository + Clone>(
+    actor: Actor,
+    form: web::Json<SecondFactorRequest>,
+    totp_repo: web::Data<TFR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let totp = enabled_totp(totp_repo.get_ref(), &actor).await?;
+    if !verify_second_factor(totp_repo.get_ref(), &totp, &form).await? {
+        return Err(AppError::forbidden("Invalid two-factor code"));
+    }
+
+    totp_repo
+        .disable(actor.id)
+        .await
+        .map_err(|err| map_repo_error(err, "Two-factor not found", "totp.disable"))?;
+
+    log::info!(target: "audit", "two-factor authentication disabled for user {}", actor.id);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Replaces every recovery code, spent or not
+pub async fn regenerate_recovery_codes<TFR: TotpRepository + Clone>(
+    actor: Actor,
+    form: web::Json<ConfirmTotpRequest>,
+    totp_repo: web::Data<TFR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let totp = enabled_totp(totp_repo.get_ref(), &actor).await?;
+    let request = SecondFactorRequest {
+        code: Some(form.into_inner().code),
+        recovery_code: None,
+    };
+    if !verify_second_factor(totp_repo.get_ref(), &totp, &request).await? {
+        return Err(AppError::forbidden("Invalid two-factor code"));
+    }
+
+    let recovery_codes = generate_recovery_codes();
+    totp_repo
+        .replace_recovery_codes(actor.id, hash_recovery_codes(&recovery_codes))
+        .await
+        .map_err(|err| map_repo_error(err, "User not found", "totp.replace_recovery_codes"))?;
+
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::helpers::totp::code_for;
+    use crate::domain::model::UserID;
+    use crate::storage::postgres::testing::load_schema;
+    use crate::storage::postgres::totp::PostgresTotpRepository;
+    use sqlx::PgPool;
+
+    async fn enabled_user(
+        pool: &PgPool,
+        totp_repo: &PostgresTotpRepository,
+        recovery_codes: &[String],
+    ) -> UserTotp {
+        load_schema(pool).await;
+        let user_id: UserID = sqlx::query_scalar(
+            "INSERT INTO users (username, password_hash) VALUES ('alice', 'x') RETURNING id",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        totp_repo
+            .begin_enrollment(user_id, generate_secret())
+            .await
+            .unwrap();
+        totp_repo
+            .confirm(user_id, 0, hash_recovery_codes(recovery_codes))
+            .await
+            .unwrap();
+        totp_repo.find(user_id).await.unwrap()
+    }
+
+    fn recovery(code: &str) -> SecondFactorRequest {
+        SecondFactorRequest {
+            code: None,
+            recovery_code: Some(code.to_string()),
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn recovery_codes_work_once(pool: PgPool) {
+        let totp_repo = PostgresTotpRepository::new(pool.clone());
+        let codes = generate_recovery_codes();
+        let totp = enabled_user(&pool, &totp_repo, &codes).await;
+
+        let spelled_differently = codes[0].to_uppercase().replace('-', " ");
+        assert!(
+            verify_second_factor(&totp_repo, &totp, &recovery(&spelled_differently))
+                .await
+                .unwrap()
+        );
+        assert!(
+            !verify_second_factor(&totp_repo, &totp, &recovery(&codes[0]))
+                .await
+                .unwrap()
+        );
+        assert!(
+            verify_second_factor(&totp_repo, &totp, &recovery(&codes[1]))
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            totp_repo.recovery_codes_left(totp.user_id).await.unwrap(),
+            codes.len() as i64 - 2
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn authenticator_codes_work_once(pool: PgPool) {
+        let totp_repo = PostgresTotpRepository::new(pool.clone());
+        let totp = enabled_user(&pool, &totp_repo, &generate_recovery_codes()).await;
+
+        let code = code_for(&totp.secret, OffsetDateTime::now_utc().unix_timestamp());
+        let request = SecondFactorRequest {
+            code: Some(code),
+            recovery_code: None,
+        };
+
+        assert!(
+            verify_second_factor(&totp_repo, &totp, &request)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !verify_second_factor(&totp_repo, &totp, &request)
+                .await
+                .unwrap()
+        );
+    }
+}
@@ -1,20 +1,32 @@
-use crate::application::contracts::NewUser;
-use crate::application::ports::UserRepository;
-use crate::domain::model::RepoError;
+use crate::application::contracts::{NewUser, UpdateUser, UpdateUserRole};
+use crate::application::helpers::credentials::{validate_password, validate_username};
+use crate::application::helpers::tokens::hash_token;
+use crate::application::ports::{TotpRepository, UserRepository};
+use crate::domain::model::{
+    Actor, PostRating, RegistrationMode, RepoError, User, UserID, UserRole,
+};
 use crate::web::error::AppError;
-use crate::web::handlers::utils::{map_repo_error, parse_uuid};
+use crate::web::handlers::two_factor::{SecondFactorRequest, verify_second_factor};
+use crate::web::handlers::utils::{map_repo_error, parse_uuid, require_session};
+use crate::web::rate_limit::{LoginThrottle, client_addr, client_ip};
+use crate::web::session_store::{SESSION_META_KEY, SessionMeta};
 use actix_identity::Identity;
+use actix_session::SessionExt;
+use actix_web::http::header;
 use actix_web::{HttpMessage, HttpRequest, HttpResponse, web};
 use argon2::password_hash::SaltString;
 use argon2::password_hash::rand_core::OsRng;
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[derive(Serialize)]
 pub struct UserProfile {
     id: Uuid,
     username: String,
+    role: UserRole,
+    max_rating: PostRating,
     // avatar: Options<String>
 }
 
@@ -24,57 +36,235 @@ pub struct AuthRequest {
     password: String,
 }
 
-pub async fn get_current_user<UR: UserRepository + Clone>(
-    user: Option<Identity>,
-    user_repo: web::Data<UR>,
-) -> Result<HttpResponse, AppError> {
-    let user_id = match user {
-        Some(u) => u.id().map_err(|err| {
-            log::warn!("failed to resolve identity id from session: {err}");
-            AppError::unauthorized("Unauthorized")
-        })?,
-        None => return Err(AppError::unauthorized("Unauthorized")),
+#[derive(Serialize)]
+pub struct LoginChallenge {
+    two_factor_required: bool,
+}
+
+// Password checked, second factor still due; kept in the otherwise
+// anonymous session
+const PENDING_LOGIN_KEY: &str = "gl.pending_login";
+const PENDING_LOGIN_TTL_SECS: i64 = 5 * 60;
+
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    user_id: UserID,
+    username: String,
+    // Unix time
+    expires_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterRequest {
+    username: String,
+    password: String,
+    invite_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateProfileRequest {
+    username: Option<String>,
+    max_rating: Option<PostRating>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnedContent {
+    Delete,
+    Transfer,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest {
+    password: String,
+    playlists: OwnedContent,
+    posts: OwnedContent,
+    // Username of the account taking over whatever is transferred
+    transfer_to: Option<String>,
+}
+
+// Hash of a throwaway password with the default Argon2 parameters, checked
+// against when the username is unknown
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$eGw2dU13FKUw7lZAAY7pjw$uTVqjBdQcZHpeTddzcD3Mi4kLpYP+84jyk1gtXo4q9k";
+
+pub(crate) fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| AppError::bad_request(format!("Invalid password: {err}")))
+}
+
+fn verify_password(password_hash: &str, password: &str, context: &str) -> Result<bool, AppError> {
+    let parsed_hash = PasswordHash::new(password_hash)
+        .map_err(|err| AppError::internal(format!("{context} invalid password hash: {err}")))?;
+
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .is_ok())
+}
+
+// Signs the request's session in and records where it comes from for the
+// session list
+pub(crate) fn start_session(
+    req: &HttpRequest,
+    user_id: UserID,
+    context: &str,
+) -> Result<(), AppError> {
+    Identity::login(&req.extensions(), user_id.to_string()).map_err(|err| {
+        AppError::internal(format!(
+            "{context} failed to set identity in session: {err}"
+        ))
+    })?;
+
+    let meta = SessionMeta {
+        id: Uuid::now_v7(),
+        user_id,
+        user_agent: req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        ip: client_addr(req).map(|ip| ip.to_string()),
     };
 
-    let uuid = parse_uuid(&user_id, "user id")?;
+    req.get_session()
+        .insert(SESSION_META_KEY, meta)
+        .map_err(|err| {
+            AppError::internal(format!("{context} failed to store session metadata: {err}"))
+        })
+}
 
+pub async fn get_current_user<UR: UserRepository + Clone>(
+    actor: Actor,
+    user_repo: web::Data<UR>,
+) -> Result<HttpResponse, AppError> {
     let user_model = user_repo
-        .find_by_id(uuid)
+        .find_by_id(actor.id)
         .await
         .map_err(|err| map_repo_error(err, "User not found", "users.find_by_id"))?;
 
     Ok(HttpResponse::Ok().json(UserProfile {
         id: user_model.id,
         username: user_model.username,
+        role: user_model.role,
+        max_rating: user_model.max_rating,
     }))
 }
 
-pub async fn login_user<UR: UserRepository + Clone>(
+pub async fn login_user<UR: UserRepository + Clone, TFR: TotpRepository + Clone>(
     form: web::Json<AuthRequest>,
     user_repo: web::Data<UR>,
+    totp_repo: web::Data<TFR>,
+    throttle: web::Data<LoginThrottle>,
     req: HttpRequest,
 ) -> Result<HttpResponse, AppError> {
-    let user = user_repo
-        .find_by_username(&form.username)
-        .await
-        .map_err(|_| AppError::unauthorized("Invalid username or password"))?;
+    // Checked before hashing so a locked username costs no Argon2 work
+    throttle.check(&form.username)?;
 
-    let parsed_hash = PasswordHash::new(&user.password_hash)
-        .map_err(|err| AppError::internal(format!("users.login invalid password hash: {err}")))?;
+    let client = client_ip(&req);
 
-    let is_valid = Argon2::default()
-        .verify_password(form.password.as_bytes(), &parsed_hash)
-        .is_ok();
+    let user = match user_repo.find_by_username(&form.username).await {
+        Ok(user) => user,
+        Err(_) => {
+            // Same Argon2 work as a wrong password, so timing doesn't tell
+            // which usernames exist
+            verify_password(DUMMY_PASSWORD_HASH, &form.password, "users.login")?;
+            throttle.record_failure(&form.username, &client);
+            return Err(AppError::unauthorized("Invalid username or password"));
+        }
+    };
 
-    if !is_valid {
+    if !verify_password(&user.password_hash, &form.password, "users.login")? {
+        throttle.record_failure(&form.username, &client);
         return Err(AppError::unauthorized("Invalid username or password"));
     }
 
-    Identity::login(&req.extensions(), user.id.to_string()).map_err(|err| {
-        AppError::internal(format!(
-            "users.login failed to set identity in session: {err}"
-        ))
-    })?;
+    if defer_to_second_factor(&req, totp_repo.get_ref(), &user, "users.login").await? {
+        return Ok(HttpResponse::Accepted().json(LoginChallenge {
+            two_factor_required: true,
+        }));
+    }
+
+    throttle.record_success(&form.username);
+
+    start_session(&req, user.id, "users.login")?;
+
+    Ok(HttpResponse::Ok().json("Successfully logged in"))
+}
+
+// With two-factor on, a first factor only earns a pending login that
+// `complete_login` turns into a signed-in session; tells whether it did
+pub(crate) async fn defer_to_second_factor<TFR: TotpRepository>(
+    req: &HttpRequest,
+    totp_repo: &TFR,
+    user: &User,
+    context: &str,
+) -> Result<bool, AppError> {
+    match totp_repo.find(user.id).await {
+        Ok(totp) if totp.is_enabled() => {}
+        Ok(_) | Err(RepoError::NotFound) => return Ok(false),
+        Err(err) => return Err(map_repo_error(err, "Two-factor not found", "totp.find")),
+    }
+
+    let pending = PendingLogin {
+        user_id: user.id,
+        username: user.username.clone(),
+        expires_at: OffsetDateTime::now_utc().unix_timestamp() + PENDING_LOGIN_TTL_SECS,
+    };
+    req.get_session()
+        .insert(PENDING_LOGIN_KEY, pending)
+        .map_err(|err| {
+            AppError::internal(format!("{context} failed to store pending login: {err}"))
+        })?;
+
+    Ok(true)
+}
+
+// Second step of a login with two-factor on
+pub async fn complete_login<TFR: TotpRepository + Clone>(
+    form: web::Json<SecondFactorRequest>,
+    totp_repo: web::Data<TFR>,
+    throttle: web::Data<LoginThrottle>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let session = req.get_session();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    let pending = session
+        .get::<PendingLogin>(PENDING_LOGIN_KEY)
+        .ok()
+        .flatten()
+        .filter(|pending| pending.expires_at > now)
+        .ok_or_else(|| AppError::unauthorized("No login is waiting for a second factor"))?;
+
+    // Code guesses count against the username like wrong passwords do
+    throttle.check(&pending.username)?;
+
+    let totp = totp_repo
+        .find(pending.user_id)
+        .await
+        .map_err(|err| match err {
+            RepoError::NotFound => {
+                AppError::unauthorized("No login is waiting for a second factor")
+            }
+            err => map_repo_error(err, "Two-factor not found", "totp.find"),
+        })?;
+
+    if !verify_second_factor(totp_repo.get_ref(), &totp, &form).await? {
+        throttle.record_failure(&pending.username, &client_ip(&req));
+        return Err(AppError::unauthorized("Invalid two-factor code"));
+    }
+    throttle.record_success(&pending.username);
+
+    session.remove(PENDING_LOGIN_KEY);
+    start_session(&req, pending.user_id, "users.complete_login")?;
 
     Ok(HttpResponse::Ok().json("Successfully logged in"))
 }
@@ -85,32 +275,208 @@ pub async fn logout_user(user: Identity) -> Result<HttpResponse, AppError> {
 }
 
 pub async fn register_user<UR: UserRepository + Clone>(
-    form: web::Json<AuthRequest>,
+    form: web::Json<RegisterRequest>,
     user_repo: web::Data<UR>,
-    req: actix_web::HttpRequest,
+    mode: web::Data<RegistrationMode>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, AppError> {
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let password_hash = argon2
-        .hash_password(form.password.as_bytes(), &salt)
-        .map_err(|err| AppError::bad_request(format!("Invalid password: {err}")))?
-        .to_string();
+    let RegisterRequest {
+        username,
+        password,
+        invite_code,
+    } = form.into_inner();
+
+    validate_username(&username).map_err(|err| AppError::bad_request(err.to_string()))?;
+    validate_password(&password, &username)
+        .map_err(|err| AppError::bad_request(err.to_string()))?;
+
+    let invite_code = invite_code
+        .map(|code| code.trim().to_string())
+        .filter(|code| !code.is_empty());
+
+    // Closed mode turns everyone away in the repository, except the first account
+    let invite_code_hash = match **mode {
+        RegistrationMode::Invite => invite_code.map(|code| hash_token(&code)),
+        RegistrationMode::Open | RegistrationMode::Closed => None,
+    };
 
     let new_user = NewUser {
-        username: form.username.clone(),
-        password_hash,
+        username,
+        password_hash: hash_password(&password)?,
+        registration: **mode,
+        invite_code_hash,
+        identity: None,
     };
 
     let user_id = user_repo.create(new_user).await.map_err(|err| match err {
-        RepoError::StorageError => AppError::conflict("User already exists"),
-        RepoError::NotFound => AppError::internal("users.register impossible not found state"),
+        RepoError::Conflict => AppError::conflict("Username is already taken"),
+        RepoError::Forbidden if **mode == RegistrationMode::Closed => {
+            AppError::forbidden("Registration is closed")
+        }
+        RepoError::Forbidden => AppError::forbidden("Registration requires an invite code"),
+        RepoError::InvalidInput => {
+            AppError::forbidden("Invite code is invalid, expired or already used")
+        }
+        err => map_repo_error(err, "User not found", "users.register"),
     })?;
 
-    Identity::login(&req.extensions(), user_id.to_string()).map_err(|err| {
-        AppError::internal(format!(
-            "users.register failed to set identity in session: {err}"
-        ))
-    })?;
+    start_session(&req, user_id, "users.register")?;
 
     Ok(HttpResponse::Created().body("User registered"))
 }
+
+pub async fn set_user_role<UR: UserRepository + Clone>(
+    actor: Actor,
+    path: web::Path<String>,
+    payload: web::Json<UpdateUserRole>,
+    user_repo: web::Data<UR>,
+) -> Result<HttpResponse, AppError> {
+    if actor.role != UserRole::Admin {
+        return Err(AppError::forbidden("Forbidden"));
+    }
+
+    let user_id = parse_uuid(&path.into_inner(), "user id")?;
+
+    // Keeps the instance from losing its last admin by accident
+    if user_id == actor.id {
+        return Err(AppError::conflict("Admins can't change their own role"));
+    }
+
+    user_repo
+        .set_role(user_id, payload.role)
+        .await
+        .map_err(|err| map_repo_error(err, "User not found", "users.set_role"))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn change_password<UR: UserRepository + Clone>(
+    actor: Actor,
+    form: web::Json<ChangePasswordRequest>,
+    user_repo: web::Data<UR>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let user = user_repo
+        .find_by_id(actor.id)
+        .await
+        .map_err(|err| map_repo_error(err, "User not found", "users.change_password"))?;
+
+    if !verify_password(
+        &user.password_hash,
+        &form.current_password,
+        "users.change_password",
+    )? {
+        return Err(AppError::forbidden("Current password is wrong"));
+    }
+    validate_password(&form.new_password, &user.username)
+        .map_err(|err| AppError::bad_request(err.to_string()))?;
+
+    let update = UpdateUser {
+        username: None,
+        password_hash: Some(hash_password(&form.new_password)?),
+        max_rating: None,
+    };
+    user_repo
+        .update(actor.id, update)
+        .await
+        .map_err(|err| map_repo_error(err, "User not found", "users.change_password"))?;
+
+    // The new password ended every session of the account, this one included;
+    // the caller stays signed in under a fresh one
+    start_session(&req, actor.id, "users.change_password")?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn update_profile<UR: UserRepository + Clone>(
+    actor: Actor,
+    form: web::Json<UpdateProfileRequest>,
+    user_repo: web::Data<UR>,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let form = form.into_inner();
+    let username = form.username.map(|username| username.trim().to_string());
+    if let Some(username) = &username {
+        validate_username(username).map_err(|err| AppError::bad_request(err.to_string()))?;
+    }
+
+    let update = UpdateUser {
+        username,
+        password_hash: None,
+        max_rating: form.max_rating,
+    };
+    user_repo
+        .update(actor.id, update)
+        .await
+        .map_err(|err| match err {
+            RepoError::Conflict => AppError::conflict("Username is already taken"),
+            err => map_repo_error(err, "User not found", "users.update_profile"),
+        })?;
+
+    let user = user_repo
+        .find_by_id(actor.id)
+        .await
+        .map_err(|err| map_repo_error(err, "User not found", "users.find_by_id"))?;
+
+    Ok(HttpResponse::Ok().json(UserProfile {
+        id: user.id,
+        username: user.username,
+        role: user.role,
+        max_rating: user.max_rating,
+    }))
+}
+
+pub async fn delete_account<UR: UserRepository + Clone>(
+    actor: Actor,
+    form: web::Json<DeleteAccountRequest>,
+    user_repo: web::Data<UR>,
+    user: Identity,
+) -> Result<HttpResponse, AppError> {
+    require_session(&actor)?;
+
+    let account = user_repo
+        .find_by_id(actor.id)
+        .await
+        .map_err(|err| map_repo_error(err, "User not found", "users.delete_account"))?;
+
+    if !verify_password(
+        &account.password_hash,
+        &form.password,
+        "users.delete_account",
+    )? {
+        return Err(AppError::forbidden("Password is wrong"));
+    }
+
+    let wants_heir =
+        form.playlists == OwnedContent::Transfer || form.posts == OwnedContent::Transfer;
+    let heir = match (&form.transfer_to, wants_heir) {
+        (Some(username), true) => {
+            let heir = user_repo
+                .find_by_username(username)
+                .await
+                .map_err(|_| AppError::bad_request("Transfer target not found"))?;
+            if heir.id == actor.id {
+                return Err(AppError::bad_request("Can't transfer content to yourself"));
+            }
+            Some(heir.id)
+        }
+        (None, true) => return Err(AppError::bad_request("transfer_to is required to transfer")),
+        (_, false) => None,
+    };
+    let heir_for = |content: OwnedContent| heir.filter(|_| content == OwnedContent::Transfer);
+
+    user_repo
+        .delete(actor.id, heir_for(form.playlists), heir_for(form.posts))
+        .await
+        .map_err(|err| match err {
+            RepoError::Conflict => AppError::conflict("The last admin can't delete their account"),
+            err => map_repo_error(err, "User not found", "users.delete_account"),
+        })?;
+
+    user.logout();
+
+    Ok(HttpResponse::NoContent().finish())
+}
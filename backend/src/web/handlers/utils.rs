@@ -1,5 +1,5 @@
-use crate::application::contracts::{Cursor, KeysetCursor, PaginationMode, TagQuery};
-use crate::domain::model::RepoError;
+use crate::application::contracts::{Cursor, KeysetCursor, PaginationMode, PostOrder, TagQuery};
+use crate::domain::model::{Actor, PostRating, RepoError};
 use crate::web::error::AppError;
 use crate::web::handlers::dto::{SearchCursorParams, TagQueryParams};
 use uuid::Uuid;
@@ -8,6 +8,89 @@ pub fn has_filters(tag_query: &TagQueryParams) -> bool {
     !(tag_query.must.is_empty() && tag_query.should.is_empty() && tag_query.must_not.is_empty())
 }
 
+fn parse_rating(term: &str) -> Result<PostRating, AppError> {
+    term.trim_start_matches("rating:")
+        .parse()
+        .map_err(AppError::bad_request)
+}
+
+// Post searches take `fav:me`, `order:score` and `rating:<rating>` among the
+// required tags, and `rating:<rating>` among the excluded ones. Without any
+// rating term the caller's content filter applies.
+pub fn post_tag_query(params: TagQueryParams, actor: Option<&Actor>) -> Result<TagQuery, AppError> {
+    let is_meta = |term: &String| {
+        term.starts_with("fav:") || term.starts_with("order:") || term.starts_with("rating:")
+    };
+    if params.should.iter().any(is_meta)
+        || params
+            .must_not
+            .iter()
+            .any(|term| is_meta(term) && !term.starts_with("rating:"))
+    {
+        return Err(AppError::bad_request(
+            "fav: and order: terms only work as required terms",
+        ));
+    }
+
+    let (meta, must): (Vec<String>, Vec<String>) = params.must.into_iter().partition(is_meta);
+    let (excluded, must_not): (Vec<String>, Vec<String>) =
+        params.must_not.into_iter().partition(is_meta);
+    let mut query = TagQuery {
+        must,
+        should: params.should,
+        must_not,
+        ..TagQuery::default()
+    };
+
+    let mut ratings: Option<Vec<PostRating>> = None;
+    for term in meta {
+        if term.starts_with("rating:") {
+            ratings.get_or_insert_default().push(parse_rating(&term)?);
+            continue;
+        }
+
+        match term.as_str() {
+            "fav:me" => {
+                let actor =
+                    actor.ok_or_else(|| AppError::unauthorized("fav:me needs a signed-in user"))?;
+                query.favorited_by = Some(actor.id);
+            }
+            "order:score" => query.order = PostOrder::Score,
+            _ => {
+                return Err(AppError::bad_request(format!(
+                    "Unknown search term `{term}`"
+                )));
+            }
+        }
+    }
+
+    let mut ratings = match ratings {
+        Some(ratings) => ratings,
+        None if excluded.is_empty() => PostRating::up_to(Actor::viewer_max_rating(actor)),
+        None => PostRating::ALL.to_vec(),
+    };
+    for term in &excluded {
+        let rating = parse_rating(term)?;
+        ratings.retain(|allowed| *allowed != rating);
+    }
+    if ratings.len() < PostRating::ALL.len() {
+        query.ratings = Some(ratings);
+    }
+
+    Ok(query)
+}
+
+// Credentials and the account itself are managed from a signed-in session
+// only, so a leaked API token can't mint successors or lock the owner out
+pub fn require_session(actor: &Actor) -> Result<(), AppError> {
+    if actor.via_token {
+        return Err(AppError::forbidden(
+            "This needs a signed-in session, not an API token",
+        ));
+    }
+    Ok(())
+}
+
 pub fn parse_uuid(value: &str, field_name: &str) -> Result<Uuid, AppError> {
     Uuid::parse_str(value).map_err(|err| {
         log::warn!("invalid uuid in {field_name}: {err}; value={value}");
@@ -18,6 +101,9 @@ pub fn parse_uuid(value: &str, field_name: &str) -> Result<Uuid, AppError> {
 pub fn map_repo_error(error: RepoError, not_found_message: &str, context: &str) -> AppError {
     match error {
         RepoError::NotFound => AppError::not_found(not_found_message),
+        RepoError::Forbidden => AppError::forbidden("Forbidden"),
+        RepoError::Conflict => AppError::conflict(format!("{context}: conflicting state")),
+        RepoError::InvalidInput => AppError::bad_request(format!("{context}: invalid input")),
         RepoError::StorageError => AppError::internal(format!("{context}: storage failure")),
     }
 }
@@ -49,6 +135,7 @@ impl From<TagQueryParams> for TagQuery {
             must: query.must,
             should: query.should,
             must_not: query.must_not,
+            ..Self::default()
         }
     }
 }
@@ -1,3 +1,7 @@
 pub mod error;
+mod extractors;
 mod handlers;
+pub mod oidc;
+pub mod rate_limit;
+mod session_store;
 pub mod web_server;
@@ -0,0 +1,562 @@
+use crate::web::error::AppError;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready};
+use actix_web::http::header;
+use actix_web::web::Data;
+use actix_web::{Error, HttpRequest};
+use futures_util::future::{LocalBoxFuture, Ready, ready};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Buckets of idle clients are dropped once a limiter tracks this many keys
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// Failed logins allowed before the username gets locked
+const FREE_LOGIN_FAILURES: u32 = 5;
+const BASE_LOCKOUT: Duration = Duration::from_secs(30);
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+// A username's failures are forgotten after this long without a new one
+const FAILURE_MEMORY: Duration = Duration::from_secs(60 * 60);
+
+// `burst` requests at once, refilled evenly over `period`
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitConfig {
+    pub burst: u32,
+    pub period: Duration,
+}
+
+impl RateLimitConfig {
+    pub const fn new(burst: u32, period_secs: u64) -> Self {
+        Self {
+            burst,
+            period: Duration::from_secs(period_secs),
+        }
+    }
+}
+
+// Parses `<burst>/<seconds>`, e.g. `10/60`
+impl FromStr for RateLimitConfig {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid rate limit `{value}`, expected <burst>/<seconds>");
+
+        let (burst, secs) = value.trim().split_once('/').ok_or_else(invalid)?;
+        let burst = burst.trim().parse::<u32>().map_err(|_| invalid())?;
+        let secs = secs.trim().parse::<u64>().map_err(|_| invalid())?;
+        if burst == 0 || secs == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self::new(burst, secs))
+    }
+}
+
+// Per route group limits, each bucket keyed by client IP
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimits {
+    pub api: RateLimitConfig,
+    pub auth: RateLimitConfig,
+    pub upload: RateLimitConfig,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            api: RateLimitConfig::new(300, 60),
+            auth: RateLimitConfig::new(10, 60),
+            upload: RateLimitConfig::new(30, 60),
+        }
+    }
+}
+
+// Reverse proxies allowed to name the client in `Forwarded` or
+// `X-Forwarded-For`. Anyone can send those headers, so without a trusted
+// peer in front the connection's own address is the client.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+// Parses a comma separated list of IP addresses
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse::<IpAddr>()
+                    .map_err(|_| format!("invalid proxy address `{item}`"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
+    }
+}
+
+impl TrustedProxies {
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.0.contains(&ip)
+    }
+
+    // Walks the forwarded chain from the nearest hop back and stops at the
+    // first address that isn't one of our proxies
+    fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+        if !self.trusts(peer) {
+            return Some(peer);
+        }
+
+        let mut hops = forwarded_for(req);
+        hops.reverse();
+        Some(
+            hops.into_iter()
+                .find(|ip| !self.trusts(*ip))
+                .unwrap_or(peer),
+        )
+    }
+}
+
+// Hops named by `Forwarded`, or by `X-Forwarded-For` when that is absent,
+// in the order the headers list them
+fn forwarded_for(req: &HttpRequest) -> Vec<IpAddr> {
+    let values = |name| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+    };
+
+    let forwarded = values(header::FORWARDED);
+    if !forwarded.is_empty() {
+        return forwarded
+            .into_iter()
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.trim().split_once('=')?;
+                    key.eq_ignore_ascii_case("for")
+                        .then(|| parse_node(value.trim_matches('"')))
+                        .flatten()
+                })
+            })
+            .collect();
+    }
+
+    values(header::X_FORWARDED_FOR)
+        .into_iter()
+        .filter_map(parse_node)
+        .collect()
+}
+
+// `192.0.2.1`, `192.0.2.1:8080`, `2001:db8::1` or `[2001:db8::1]:8080`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.rsplit_once(':')?.0.parse().ok()
+}
+
+// Client address used for rate limits, lockout logs and the session list.
+// Reads `Data<TrustedProxies>` when it is registered on the app.
+pub fn client_addr(req: &HttpRequest) -> Option<IpAddr> {
+    match req.app_data::<Data<TrustedProxies>>() {
+        Some(proxies) => proxies.client_ip(req),
+        None => req.peer_addr().map(|addr| addr.ip()),
+    }
+}
+
+pub fn client_ip(req: &HttpRequest) -> String {
+    client_addr(req).map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        f64::from(self.config.burst) / self.config.period.as_secs_f64()
+    }
+
+    // Takes a token for `key`, or tells how many seconds until one is back
+    pub fn check(&self, key: &str) -> Result<(), u64> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<(), u64> {
+        let capacity = f64::from(self.config.burst);
+        let rate = self.refill_per_sec();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * rate
+                    < capacity
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err((((1.0 - bucket.tokens) / rate).ceil() as u64).max(1))
+    }
+}
+
+// Wraps a scope or resource and answers `429` once the client IP runs out
+// of tokens; a limiter shared between several wraps makes them one group
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service,
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let client = client_ip(req.request());
+
+        if let Err(retry_after) = self.limiter.check(&client) {
+            return Box::pin(ready(Err(AppError::too_many_requests(
+                "Too many requests",
+                retry_after,
+            )
+            .into())));
+        }
+
+        Box::pin(self.service.call(req))
+    }
+}
+
+struct FailedLogins {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+// Throttles sign-in attempts per username, on top of the per IP limit, and
+// locks a username for exponentially longer after repeated failures
+pub struct LoginThrottle {
+    attempts: RateLimiter,
+    failures: Mutex<HashMap<String, FailedLogins>>,
+}
+
+impl LoginThrottle {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            attempts: RateLimiter::new(config),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Usernames differing only in case share their attempts
+    fn key(username: &str) -> String {
+        username.trim().to_lowercase()
+    }
+
+    pub fn check(&self, username: &str) -> Result<(), AppError> {
+        self.check_at(username, Instant::now())
+    }
+
+    fn check_at(&self, username: &str, now: Instant) -> Result<(), AppError> {
+        let key = Self::key(username);
+
+        {
+            let failures = self.failures.lock().unwrap_or_else(|err| err.into_inner());
+            if let Some(locked_until) = failures.get(&key).and_then(|entry| entry.locked_until)
+                && locked_until > now
+            {
+                let retry_after = locked_until.duration_since(now).as_secs().max(1);
+                return Err(AppError::too_many_requests(
+                    "Too many failed login attempts, try again later",
+                    retry_after,
+                ));
+            }
+        }
+
+        self.attempts.check_at(&key, now).map_err(|retry_after| {
+            AppError::too_many_requests("Too many login attempts, try again later", retry_after)
+        })
+    }
+
+    pub fn record_failure(&self, username: &str, client: &str) {
+        self.record_failure_at(username, client, Instant::now());
+    }
+
+    fn record_failure_at(&self, username: &str, client: &str, now: Instant) {
+        let key = Self::key(username);
+
+        let mut failures = self.failures.lock().unwrap_or_else(|err| err.into_inner());
+
+        if failures.len() >= PRUNE_THRESHOLD {
+            failures.retain(|_, entry| now.duration_since(entry.last_failure) < FAILURE_MEMORY);
+        }
+
+        let entry = failures.entry(key).or_insert(FailedLogins {
+            count: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        if now.duration_since(entry.last_failure) >= FAILURE_MEMORY {
+            entry.count = 0;
+        }
+        entry.count += 1;
+        entry.last_failure = now;
+
+        if entry.count >= FREE_LOGIN_FAILURES {
+            let doublings = (entry.count - FREE_LOGIN_FAILURES).min(16);
+            let lockout = (BASE_LOCKOUT * 2u32.pow(doublings)).min(MAX_LOCKOUT);
+            entry.locked_until = Some(now + lockout);
+
+            log::warn!(
+                target: "audit",
+                "login for {:?} locked for {}s after {} failed attempts, last from {}",
+                username,
+                lockout.as_secs(),
+                entry.count,
+                client
+            );
+        }
+    }
+
+    pub fn record_success(&self, username: &str) {
+        self.failures
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&Self::key(username));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn retry_after(result: Result<(), AppError>) -> Option<u64> {
+        match result {
+            Ok(()) => None,
+            Err(AppError::TooManyRequests {
+                retry_after_secs, ..
+            }) => Some(retry_after_secs),
+            Err(err) => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    #[test]
+    fn limiter_allows_a_burst_then_refills_evenly() {
+        let limiter = RateLimiter::new(RateLimitConfig::new(4, 32));
+        let start = Instant::now();
+
+        for _ in 0..4 {
+            assert_eq!(limiter.check_at("a", start), Ok(()));
+        }
+        assert_eq!(limiter.check_at("a", start), Err(8));
+        // Other clients have buckets of their own
+        assert_eq!(limiter.check_at("b", start), Ok(()));
+
+        assert_eq!(limiter.check_at("a", start + secs(6)), Err(2));
+        assert_eq!(limiter.check_at("a", start + secs(8)), Ok(()));
+        assert_eq!(limiter.check_at("a", start + secs(8)), Err(8));
+
+        // A long pause refills no more than the burst
+        let later = start + secs(600);
+        for _ in 0..4 {
+            assert_eq!(limiter.check_at("a", later), Ok(()));
+        }
+        assert!(limiter.check_at("a", later).is_err());
+    }
+
+    #[test]
+    fn repeated_login_failures_lock_the_username_for_longer_each_time() {
+        let throttle = LoginThrottle::new(RateLimitConfig::new(100, 60));
+        let start = Instant::now();
+
+        for _ in 0..FREE_LOGIN_FAILURES - 1 {
+            throttle.record_failure_at("Alice", "192.0.2.1", start);
+        }
+        assert_eq!(retry_after(throttle.check_at("alice", start)), None);
+
+        throttle.record_failure_at("Alice", "192.0.2.1", start);
+        assert_eq!(retry_after(throttle.check_at(" ALICE ", start)), Some(30));
+        assert_eq!(retry_after(throttle.check_at("bob", start)), None);
+        assert_eq!(
+            retry_after(throttle.check_at("alice", start + secs(29))),
+            Some(1)
+        );
+        assert_eq!(
+            retry_after(throttle.check_at("alice", start + secs(30))),
+            None
+        );
+
+        let next = start + secs(30);
+        throttle.record_failure_at("alice", "192.0.2.1", next);
+        assert_eq!(retry_after(throttle.check_at("alice", next)), Some(60));
+
+        // Lockouts double up to an hour
+        for _ in 0..20 {
+            throttle.record_failure_at("alice", "192.0.2.1", next);
+        }
+        assert_eq!(
+            retry_after(throttle.check_at("alice", next)),
+            Some(MAX_LOCKOUT.as_secs())
+        );
+
+        throttle.record_success("alice");
+        assert_eq!(retry_after(throttle.check_at("alice", next)), None);
+    }
+
+    #[test]
+    fn login_failures_are_forgotten_after_a_quiet_hour() {
+        let throttle = LoginThrottle::new(RateLimitConfig::new(100, 60));
+        let start = Instant::now();
+
+        for _ in 0..FREE_LOGIN_FAILURES - 1 {
+            throttle.record_failure_at("alice", "192.0.2.1", start);
+        }
+        let later = start + FAILURE_MEMORY;
+        throttle.record_failure_at("alice", "192.0.2.1", later);
+
+        assert_eq!(retry_after(throttle.check_at("alice", later)), None);
+    }
+
+    #[test]
+    fn login_attempts_are_limited_per_username() {
+        let throttle = LoginThrottle::new(RateLimitConfig::new(2, 16));
+        let start = Instant::now();
+
+        assert_eq!(retry_after(throttle.check_at("alice", start)), None);
+        assert_eq!(retry_after(throttle.check_at("Alice", start)), None);
+        assert_eq!(retry_after(throttle.check_at("alice", start)), Some(8));
+    }
+
+    fn client(proxies: &str, peer: &str, headers: &[(&str, &str)]) -> String {
+        let mut request = TestRequest::default().peer_addr(peer.parse().unwrap());
+        for &(name, value) in headers {
+            request = request.append_header((name, value));
+        }
+        let req = request
+            .app_data(Data::new(proxies.parse::<TrustedProxies>().unwrap()))
+            .to_http_request();
+        client_ip(&req)
+    }
+
+    #[test]
+    fn forwarded_headers_count_only_behind_trusted_proxies() {
+        let spoofed = [("X-Forwarded-For", "203.0.113.7")];
+        assert_eq!(client("", "198.51.100.1:4000", &spoofed), "198.51.100.1");
+        assert_eq!(
+            client("10.0.0.1", "198.51.100.1:4000", &spoofed),
+            "198.51.100.1"
+        );
+        assert_eq!(client("10.0.0.1", "10.0.0.1:4000", &spoofed), "203.0.113.7");
+
+        // The client may prepend anything; the chain is read from our end
+        let chain = [("X-Forwarded-For", "1.1.1.1, 203.0.113.7, 10.0.0.2")];
+        assert_eq!(
+            client("10.0.0.1, 10.0.0.2", "10.0.0.1:4000", &chain),
+            "203.0.113.7"
+        );
+        // Only proxies in the chain leaves the nearest one
+        let proxies_only = [("X-Forwarded-For", "10.0.0.2")];
+        assert_eq!(
+            client("10.0.0.1, 10.0.0.2", "10.0.0.1:4000", &proxies_only),
+            "10.0.0.1"
+        );
+    }
+
+    #[test]
+    fn forwarded_header_wins_over_x_forwarded_for() {
+        let headers = [
+            (
+                "Forwarded",
+                r#"for=1.1.1.1, for="[2001:db8::7]:4711";proto=https"#,
+            ),
+            ("X-Forwarded-For", "203.0.113.7"),
+        ];
+        assert_eq!(client("10.0.0.1", "10.0.0.1:4000", &headers), "2001:db8::7");
+
+        let with_port = [("X-Forwarded-For", "203.0.113.7:5000")];
+        assert_eq!(
+            client("10.0.0.1", "10.0.0.1:4000", &with_port),
+            "203.0.113.7"
+        );
+    }
+
+    #[test]
+    fn trusted_proxies_parse_a_comma_separated_list() {
+        assert!("10.0.0.1, ::1,".parse::<TrustedProxies>().is_ok());
+        assert!("10.0.0.0/8".parse::<TrustedProxies>().is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::application::contracts::NewSession;
+use crate::application::helpers::tokens::{generate_token, hash_token};
+use crate::application::ports::SessionRepository;
+use crate::domain::model::{RepoError, SessionID, UserID};
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+// The store never sees the request, so login puts what the session list
+// shows into the session state under this key
+pub const SESSION_META_KEY: &str = "gl.session";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionMeta {
+    pub id: SessionID,
+    pub user_id: UserID,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl SessionMeta {
+    fn from_state(state: &HashMap<String, String>) -> Option<Self> {
+        serde_json::from_str(state.get(SESSION_META_KEY)?).ok()
+    }
+}
+
+// `actix-session` backend over `SessionRepository`
+#[derive(Clone)]
+pub struct RepoSessionStore<SR> {
+    sessions: SR,
+}
+
+impl<SR: SessionRepository> RepoSessionStore<SR> {
+    pub fn new(sessions: SR) -> Self {
+        Self { sessions }
+    }
+}
+
+impl<SR: SessionRepository> SessionStore for RepoSessionStore<SR> {
+    async fn load(
+        &self,
+        session_key: &SessionKey,
+    ) -> Result<Option<HashMap<String, String>>, LoadError> {
+        self.sessions
+            .load(&hash_token(session_key.as_ref()))
+            .await
+            .map_err(|_| LoadError::Other(anyhow!("session repository failed to load")))
+    }
+
+    async fn save(
+        &self,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let key = generate_token();
+        let meta = SessionMeta::from_state(&session_state);
+
+        self.sessions
+            .create(NewSession {
+                id: meta.as_ref().map_or_else(Uuid::now_v7, |meta| meta.id),
+                key_hash: hash_token(&key),
+                user_id: meta.as_ref().map(|meta| meta.user_id),
+                user_agent: meta.as_ref().and_then(|meta| meta.user_agent.clone()),
+                ip: meta.and_then(|meta| meta.ip),
+                state: session_state,
+                expires_at: OffsetDateTime::now_utc() + *ttl,
+            })
+            .await
+            .map_err(|_| SaveError::Other(anyhow!("session repository failed to save")))?;
+
+        SessionKey::try_from(key).map_err(|err| SaveError::Other(err.into()))
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let user_id = SessionMeta::from_state(&session_state).map(|meta| meta.user_id);
+        let result = self
+            .sessions
+            .update(
+                &hash_token(session_key.as_ref()),
+                user_id,
+                session_state,
+                OffsetDateTime::now_utc() + *ttl,
+            )
+            .await;
+
+        match result {
+            Ok(()) => Ok(session_key),
+            // Revoked while the request ran: the state is dropped and the
+            // cookie keeps pointing at nothing
+            Err(RepoError::NotFound) => Ok(session_key),
+            Err(_) => Err(UpdateError::Other(anyhow!(
+                "session repository failed to update"
+            ))),
+        }
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        self.sessions
+            .touch(
+                &hash_token(session_key.as_ref()),
+                OffsetDateTime::now_utc() + *ttl,
+            )
+            .await
+            .map_err(|_| anyhow!("session repository failed to extend a session"))
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        self.sessions
+            .delete(&hash_token(session_key.as_ref()))
+            .await
+            .map_err(|_| anyhow!("session repository failed to delete a session"))
+    }
+}
@@ -1,30 +1,98 @@
 use crate::application::ports::{
-    FileRepository, PlaylistRepository, PostRepository, TagRepository, UserRepository,
+    ApiTokenRepository, FileRepository, PlaylistRepository, PostRepository, SessionRepository,
+    TagRepository, TotpRepository, UserRepository,
 };
 use crate::application::use_cases::services::Services;
 use crate::domain::files::FileStorage;
-use crate::web::handlers::files::download_file;
+use crate::domain::model::{RegistrationMode, TokenScope};
+use crate::web::extractors::RequiredScope;
+use crate::web::handlers::comments::{
+    create_comment, delete_comment, list_comments, update_comment,
+};
+use crate::web::handlers::files::{check_storage, download_file};
+use crate::web::handlers::invites::{create_invite, list_invites, revoke_invite};
+use crate::web::handlers::oidc::{finish_oidc_login, start_oidc_login};
 use crate::web::handlers::playlists::{
-    create_playlist, delete_playlist, get_my_playlists, get_playlist_details, update_playlist,
+    add_playlist_collaborator, add_playlist_item, apply_playlist_tags, clear_playlist_progress,
+    continue_watching, create_playlist, delete_playlist, derive_playlist_tags, duplicate_playlist,
+    export_playlist, get_my_playlists, get_playlist_details, get_playlist_items,
+    get_shared_playlist, get_shared_playlist_items, get_smart_playlist_posts, import_playlist,
+    list_playlist_collaborators, merge_playlists, move_playlist_item, remove_playlist_collaborator,
+    remove_playlist_item, reorder_playlist_items, revoke_playlist_share, save_playlist_progress,
+    search_public_playlists, share_playlist, update_playlist, update_playlist_collaborator,
+};
+use crate::web::handlers::posts::{
+    create_post, delete_post, get_favorite_posts, get_post, get_post_interaction,
+    get_post_revisions, get_trash, restore_post, rollback_post, search_posts, toggle_post_favorite,
+    update_post, vote_post,
 };
-use crate::web::handlers::posts::{create_post, delete_post, get_post, search_posts, update_post};
+use crate::web::handlers::sessions::{list_sessions, revoke_other_sessions, revoke_session};
 use crate::web::handlers::tags::search_tags;
-use crate::web::handlers::users::{get_current_user, login_user, logout_user, register_user};
+use crate::web::handlers::tokens::{create_api_token, list_api_tokens, revoke_api_token};
+use crate::web::handlers::two_factor::{
+    begin_two_factor, confirm_two_factor, disable_two_factor, get_two_factor_status,
+    regenerate_recovery_codes,
+};
+use crate::web::handlers::users::{
+    change_password, complete_login, delete_account, get_current_user, login_user, logout_user,
+    register_user, set_user_role, update_profile,
+};
+use crate::web::oidc::OidcClient;
+use crate::web::rate_limit::{LoginThrottle, RateLimit, RateLimiter, RateLimits, TrustedProxies};
+use crate::web::session_store::RepoSessionStore;
 use actix_identity::IdentityMiddleware;
 use actix_session::SessionMiddleware;
-use actix_session::storage::CookieSessionStore;
+use actix_session::config::{PersistentSession, TtlExtensionPolicy};
 use actix_web::cookie::Key;
+use actix_web::cookie::time::Duration;
 use actix_web::middleware::Logger;
 use actix_web::web::Data;
 use actix_web::{App, HttpServer, web};
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Sessions live this long past the last request
+const SESSION_TTL_DAYS: i64 = 30;
+
+// Origin clients reach the site at, e.g. `https://gl.example.com`. Exported
+// playlists link to files under it; the Host header can't be trusted for that.
+// Empty when not configured, links are then relative to the site.
+#[derive(Clone, Debug, Default)]
+pub struct PublicUrl(String);
+
+impl FromStr for PublicUrl {
+    type Err = String;
 
-pub async fn run_web_server<PR, PLR, TR, FR, UR, FS>(
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim().trim_end_matches('/');
+        if !value.starts_with("http://") && !value.starts_with("https://") {
+            return Err(format!("`{value}` must start with http:// or https://"));
+        }
+        Ok(Self(value.to_string()))
+    }
+}
+
+impl PublicUrl {
+    pub fn join(&self, path: &str) -> String {
+        format!("{}{path}", self.0)
+    }
+}
+
+pub async fn run_web_server<PR, PLR, TR, FR, UR, SR, AR, TFR, FS>(
     post_repo: PR,
     playlist_repo: PLR,
     tag_repo: TR,
     file_repo: FR,
     user_repo: UR,
+    session_repo: SR,
+    token_repo: AR,
+    totp_repo: TFR,
     file_storage: FS,
+    registration_mode: RegistrationMode,
+    rate_limits: RateLimits,
+    trusted_proxies: TrustedProxies,
+    public_url: PublicUrl,
+    oidc: Option<OidcClient>,
     ip_address: String,
     port: u16,
     secret_key: String,
@@ -35,14 +103,43 @@ where
     TR: TagRepository + Clone + Send + Sync + 'static,
     FR: FileRepository + Clone + Send + Sync + 'static,
     UR: UserRepository + Clone + Send + Sync + 'static,
+    SR: SessionRepository + Clone + Send + Sync + 'static,
+    AR: ApiTokenRepository + Clone + Send + Sync + 'static,
+    TFR: TotpRepository + Clone + Send + Sync + 'static,
     FS: FileStorage + Clone + Send + Sync + 'static,
 {
     let services = Services::new(post_repo, playlist_repo, tag_repo, file_repo, file_storage);
 
     let services_data = Data::new(services);
 
+    // The `Actor` extractor can't name `UR` and `AR`, so it reads the
+    // repositories as trait objects
+    let actor_users: Data<dyn UserRepository> =
+        Data::from(Arc::new(user_repo.clone()) as Arc<dyn UserRepository>);
+    let actor_tokens: Data<dyn ApiTokenRepository> =
+        Data::from(Arc::new(token_repo.clone()) as Arc<dyn ApiTokenRepository>);
+
     let user_data = Data::new(user_repo);
 
+    let session_data = Data::new(session_repo.clone());
+
+    let token_data = Data::new(token_repo);
+
+    let totp_data = Data::new(totp_repo);
+
+    let registration_data = Data::new(registration_mode);
+
+    // Built once so every worker draws from the same buckets
+    let api_limiter = Arc::new(RateLimiter::new(rate_limits.api));
+    let auth_limiter = Arc::new(RateLimiter::new(rate_limits.auth));
+    let upload_limiter = Arc::new(RateLimiter::new(rate_limits.upload));
+    let login_throttle = Data::new(LoginThrottle::new(rate_limits.auth));
+    let proxies_data = Data::new(trusted_proxies);
+    let public_url_data = Data::new(public_url);
+
+    // Only registered when configured; the routes answer 404 otherwise
+    let oidc_data = oidc.map(Data::new);
+
     let apply_key = Key::derive_from(secret_key.as_bytes());
 
     log::info!("binding web server to {ip_address}:{port}");
@@ -51,20 +148,94 @@ where
         App::new()
             .wrap(Logger::new(r#"%a "%r" %s %b "%{User-Agent}i" %T"#))
             .wrap(IdentityMiddleware::default())
-            .wrap(SessionMiddleware::new(
-                CookieSessionStore::default(),
-                apply_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(
+                    RepoSessionStore::new(session_repo.clone()),
+                    apply_key.clone(),
+                )
+                .session_lifecycle(
+                    PersistentSession::default()
+                        .session_ttl(Duration::days(SESSION_TTL_DAYS))
+                        .session_ttl_extension_policy(TtlExtensionPolicy::OnEveryRequest),
+                )
+                .build(),
+            )
             .app_data(services_data.clone())
             .app_data(user_data.clone())
+            .app_data(session_data.clone())
+            .app_data(actor_users.clone())
+            .app_data(actor_tokens.clone())
+            .app_data(token_data.clone())
+            .app_data(totp_data.clone())
+            .app_data(registration_data.clone())
+            .app_data(login_throttle.clone())
+            .app_data(proxies_data.clone())
+            .app_data(public_url_data.clone())
+            .configure(|cfg| {
+                if let Some(oidc) = &oidc_data {
+                    cfg.app_data(oidc.clone());
+                }
+            })
             .service(
                 web::scope("/api")
+                    .wrap(RateLimit::new(api_limiter.clone()))
                     .service(
                         web::scope("/auth")
                             .route("me", web::get().to(get_current_user::<UR>))
-                            .route("login", web::post().to(login_user::<UR>))
-                            .route("register", web::post().to(register_user::<UR>))
-                            .route("logout", web::post().to(logout_user)),
+                            .route("me", web::patch().to(update_profile::<UR>))
+                            .route("me", web::delete().to(delete_account::<UR>))
+                            .service(
+                                web::resource("password")
+                                    .wrap(RateLimit::new(auth_limiter.clone()))
+                                    .route(web::put().to(change_password::<UR>)),
+                            )
+                            .service(
+                                web::resource("login")
+                                    .wrap(RateLimit::new(auth_limiter.clone()))
+                                    .route(web::post().to(login_user::<UR, TFR>)),
+                            )
+                            .service(
+                                web::resource("login/2fa")
+                                    .wrap(RateLimit::new(auth_limiter.clone()))
+                                    .route(web::post().to(complete_login::<TFR>)),
+                            )
+                            .service(
+                                web::scope("/oidc")
+                                    .wrap(RateLimit::new(auth_limiter.clone()))
+                                    .route("/login", web::get().to(start_oidc_login))
+                                    .route(
+                                        "/callback",
+                                        web::get().to(finish_oidc_login::<UR, TFR>),
+                                    ),
+                            )
+                            .service(
+                                web::resource("register")
+                                    .wrap(RateLimit::new(auth_limiter.clone()))
+                                    .route(web::post().to(register_user::<UR>)),
+                            )
+                            .route("logout", web::post().to(logout_user))
+                            .route("sessions", web::get().to(list_sessions::<SR>))
+                            .route("sessions", web::delete().to(revoke_other_sessions::<SR>))
+                            .route("sessions/{id}", web::delete().to(revoke_session::<SR>))
+                            .route("tokens", web::get().to(list_api_tokens::<AR>))
+                            .route("tokens", web::post().to(create_api_token::<AR>))
+                            .route("tokens/{id}", web::delete().to(revoke_api_token::<AR>))
+                            .service(
+                                web::scope("/2fa")
+                                    .wrap(RateLimit::new(auth_limiter.clone()))
+                                    .route("", web::get().to(get_two_factor_status::<TFR>))
+                                    .route("", web::post().to(begin_two_factor::<UR, TFR>))
+                                    .route("", web::delete().to(disable_two_factor::<TFR>))
+                                    .route("/confirm", web::post().to(confirm_two_factor::<TFR>))
+                                    .route(
+                                        "/recovery-codes",
+                                        web::post().to(regenerate_recovery_codes::<TFR>),
+                                    ),
+                            ),
+                    )
+                    .service(
+                        web::scope("/users")
+                            .route("/{id}/role", web::patch().to(set_user_role::<UR>)),
                     )
                     .service(
                         web::scope("/playlists")
@@ -73,6 +244,27 @@ where
                                 "/search",
                                 web::get().to(get_my_playlists::<PR, PLR, TR, FR, FS>),
                             )
+                            .service(
+                                web::resource("/import")
+                                    .wrap(RateLimit::new(upload_limiter.clone()))
+                                    .route(web::post().to(import_playlist::<PR, PLR, TR, FR, FS>)),
+                            )
+                            .route(
+                                "/continue",
+                                web::get().to(continue_watching::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/public/search",
+                                web::get().to(search_public_playlists::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/shared/{token}",
+                                web::get().to(get_shared_playlist::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/shared/{token}/items",
+                                web::get().to(get_shared_playlist_items::<PR, PLR, TR, FR, FS>),
+                            )
                             .route(
                                 "/{id}",
                                 web::get().to(get_playlist_details::<PR, PLR, TR, FR, FS>),
@@ -84,21 +276,158 @@ where
                             .route(
                                 "/{id}",
                                 web::patch().to(update_playlist::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/share",
+                                web::post().to(share_playlist::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/share",
+                                web::delete().to(revoke_playlist_share::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/items",
+                                web::get().to(get_playlist_items::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/items",
+                                web::post().to(add_playlist_item::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/items/order",
+                                web::put().to(reorder_playlist_items::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/items/{item_id}",
+                                web::patch().to(move_playlist_item::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/items/{item_id}",
+                                web::delete().to(remove_playlist_item::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/posts",
+                                web::get().to(get_smart_playlist_posts::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/export",
+                                web::get().to(export_playlist::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/progress",
+                                web::put().to(save_playlist_progress::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/progress",
+                                web::delete().to(clear_playlist_progress::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/duplicate",
+                                web::post().to(duplicate_playlist::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/merge",
+                                web::post().to(merge_playlists::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/tags/apply",
+                                web::post().to(apply_playlist_tags::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/tags/derive",
+                                web::post().to(derive_playlist_tags::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/collaborators",
+                                web::get().to(list_playlist_collaborators::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/collaborators",
+                                web::post().to(add_playlist_collaborator::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/collaborators/{user_id}",
+                                web::patch()
+                                    .to(update_playlist_collaborator::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/collaborators/{user_id}",
+                                web::delete()
+                                    .to(remove_playlist_collaborator::<PR, PLR, TR, FR, FS>),
                             ),
                     )
+                    .service(
+                        web::scope("/invites")
+                            .route("", web::get().to(list_invites::<UR>))
+                            .route("", web::post().to(create_invite::<UR>))
+                            .route("/{id}", web::delete().to(revoke_invite::<UR>)),
+                    )
                     .service(
                         web::scope("/posts")
-                            .route("", web::post().to(create_post::<PR, PLR, TR, FR, FS>))
+                            .service(
+                                web::resource("")
+                                    .wrap(RateLimit::new(upload_limiter.clone()))
+                                    .route(web::post().to(create_post::<PR, PLR, TR, FR, FS>)),
+                            )
+                            .service(
+                                web::resource("/search")
+                                    .app_data(RequiredScope(TokenScope::Read))
+                                    .route(web::post().to(search_posts::<PR, PLR, TR, FR, FS>)),
+                            )
                             .route(
-                                "/search",
-                                web::post().to(search_posts::<PR, PLR, TR, FR, FS>),
+                                "/favorites",
+                                web::get().to(get_favorite_posts::<PR, PLR, TR, FR, FS>),
                             )
+                            .route("/trash", web::get().to(get_trash::<PR, PLR, TR, FR, FS>))
                             .route("/{id}", web::get().to(get_post::<PR, PLR, TR, FR, FS>))
                             .route(
                                 "/{id}",
                                 web::delete().to(delete_post::<PR, PLR, TR, FR, FS>),
                             )
-                            .route("/{id}", web::patch().to(update_post::<PR, PLR, TR, FR, FS>)),
+                            .route("/{id}", web::patch().to(update_post::<PR, PLR, TR, FR, FS>))
+                            .route(
+                                "/{id}/interaction",
+                                web::get().to(get_post_interaction::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/favorite",
+                                web::post().to(toggle_post_favorite::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/vote",
+                                web::put().to(vote_post::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/comments",
+                                web::get().to(list_comments::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/comments",
+                                web::post().to(create_comment::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/revisions",
+                                web::get().to(get_post_revisions::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/restore",
+                                web::post().to(restore_post::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}/revisions/{revision_id}/rollback",
+                                web::post().to(rollback_post::<PR, PLR, TR, FR, FS>),
+                            ),
+                    )
+                    .service(
+                        web::scope("/comments")
+                            .route(
+                                "/{id}",
+                                web::patch().to(update_comment::<PR, PLR, TR, FR, FS>),
+                            )
+                            .route(
+                                "/{id}",
+                                web::delete().to(delete_comment::<PR, PLR, TR, FR, FS>),
+                            ),
                     )
                     .service(
                         web::scope("/tags")
@@ -107,7 +436,11 @@ where
                     .service(
                         web::scope("/files")
                             .route("/{id}", web::get().to(download_file::<PR, PLR, TR, FR, FS>)),
-                    ),
+                    )
+                    .service(web::scope("/admin").route(
+                        "/storage/check",
+                        web::post().to(check_storage::<PR, PLR, TR, FR, FS>),
+                    )),
             )
     })
     .bind((ip_address, port))?
@@ -0,0 +1,61 @@
+--
+-- Search benchmark fixture: 1M posts, 5k tags, ~7 tags per post.
+--
+-- Load into an empty database created from schema.sql:
+--   psql -d glab_bench -f postgres/bench/search_fixture.sql
+--
+-- Tag popularity is skewed (tag1 is on roughly a third of posts, most tags on
+-- a few hundred) so both selective and broad `must` filters can be measured.
+--
+
+\set posts 1000000
+\set tags 5000
+
+BEGIN;
+
+ALTER TABLE public.post_tags DISABLE TRIGGER tag_count_trigger;
+
+INSERT INTO public.tags (id, name, category)
+SELECT uuidv7(), 'bench_' || i, i % 4
+FROM generate_series(1, :tags) AS i;
+
+INSERT INTO public.files (id, path, media_type)
+SELECT uuidv7(), 'bench/' || i || '.png', i % 3
+FROM generate_series(1, :posts) AS i;
+
+INSERT INTO public.posts (id, title, file_id, description)
+SELECT uuidv7(), 'bench post ' || f.n, f.id, 'fixture'
+FROM (
+    SELECT id, row_number() OVER (ORDER BY id) AS n
+    FROM public.files
+    WHERE path LIKE 'bench/%'
+) f;
+
+CREATE TEMP TABLE bench_tags AS
+SELECT id, row_number() OVER (ORDER BY (substring(name FROM 7))::int) AS rank
+FROM public.tags
+WHERE name LIKE 'bench\_%';
+
+CREATE INDEX ON bench_tags (rank);
+
+INSERT INTO public.post_tags (post_id, tag_id)
+SELECT DISTINCT p.id, t.id
+FROM public.posts p
+CROSS JOIN LATERAL (
+    SELECT 1 + floor(:tags * power(random(), 3))::int AS rank
+    FROM generate_series(1, 8)
+    WHERE p.id IS NOT NULL
+) r
+JOIN bench_tags t ON t.rank = r.rank
+WHERE p.description = 'fixture';
+
+UPDATE public.tags t
+SET post_count = c.count
+FROM (SELECT tag_id, COUNT(*) AS count FROM public.post_tags GROUP BY tag_id) c
+WHERE c.tag_id = t.id;
+
+ALTER TABLE public.post_tags ENABLE TRIGGER tag_count_trigger;
+
+COMMIT;
+
+ANALYZE;
@@ -0,0 +1,128 @@
+--
+-- Tag search latency: previous GROUP BY/HAVING shape vs the id-driven shape
+-- built by storage/postgres/query.rs. Run against the search_fixture.sql data:
+--   psql -d glab_bench -f postgres/bench/search_queries.sql
+--
+-- Each case runs a first keyset page (limit 30 + 1) and reports EXPLAIN ANALYZE.
+--
+
+\timing on
+
+SELECT array_agg(id) AS selective FROM public.tags WHERE name = 'bench_1200' \gset
+SELECT array_agg(id) AS broad FROM public.tags WHERE name = 'bench_1' \gset
+SELECT array_agg(id) AS second FROM public.tags WHERE name = 'bench_2' \gset
+SELECT array_agg(id) AS medium FROM public.tags WHERE name = 'bench_40' \gset
+
+\echo '== case 1: must=[bench_1200] (old)'
+EXPLAIN (ANALYZE, COSTS OFF, SUMMARY ON)
+WITH ranked_posts AS (
+    SELECT p.id, COUNT(DISTINCT CASE WHEN t.name = ANY('{}'::text[]) THEN t.name END) AS should_score
+    FROM public.posts p
+    LEFT JOIN public.post_tags pt ON pt.post_id = p.id
+    LEFT JOIN public.tags t ON t.id = pt.tag_id
+    GROUP BY p.id
+    HAVING COUNT(DISTINCT CASE WHEN t.name = ANY('{bench_1200}') THEN t.name END) = cardinality('{bench_1200}'::text[])
+       AND NOT EXISTS (
+           SELECT 1 FROM public.post_tags x JOIN public.tags tx ON tx.id = x.tag_id
+           WHERE x.post_id = p.id AND tx.name = ANY('{}'::text[])
+       )
+)
+SELECT id, should_score FROM ranked_posts ORDER BY should_score DESC, id DESC LIMIT 31;
+
+\echo '== case 1: must=[bench_1200] (new)'
+EXPLAIN (ANALYZE, COSTS OFF, SUMMARY ON)
+WITH matched AS (
+    SELECT e.id, 0::bigint AS score
+    FROM (SELECT d.post_id AS id FROM public.post_tags d WHERE d.tag_id = ANY(:'selective')) d
+    JOIN public.posts e ON e.id = d.id
+), page AS (
+    SELECT id, score, 0::bigint AS full_count FROM matched ORDER BY score DESC, id DESC LIMIT 31
+)
+SELECT page.id, page.score, page.full_count FROM page JOIN public.posts e ON e.id = page.id ORDER BY page.score DESC, page.id DESC;
+
+\echo '== case 2: must=[bench_1, bench_40] (old)'
+EXPLAIN (ANALYZE, COSTS OFF, SUMMARY ON)
+WITH ranked_posts AS (
+    SELECT p.id, COUNT(DISTINCT CASE WHEN t.name = ANY('{}'::text[]) THEN t.name END) AS should_score
+    FROM public.posts p
+    LEFT JOIN public.post_tags pt ON pt.post_id = p.id
+    LEFT JOIN public.tags t ON t.id = pt.tag_id
+    GROUP BY p.id
+    HAVING COUNT(DISTINCT CASE WHEN t.name = ANY('{bench_1,bench_40}') THEN t.name END) = cardinality('{bench_1,bench_40}'::text[])
+       AND NOT EXISTS (
+           SELECT 1 FROM public.post_tags x JOIN public.tags tx ON tx.id = x.tag_id
+           WHERE x.post_id = p.id AND tx.name = ANY('{}'::text[])
+       )
+)
+SELECT id, should_score FROM ranked_posts ORDER BY should_score DESC, id DESC LIMIT 31;
+
+\echo '== case 2: must=[bench_1, bench_40] (new)'
+EXPLAIN (ANALYZE, COSTS OFF, SUMMARY ON)
+WITH matched AS (
+    SELECT e.id, 0::bigint AS score
+    FROM (SELECT d.post_id AS id FROM public.post_tags d WHERE d.tag_id = ANY(:'medium')) d
+    JOIN public.posts e ON e.id = d.id
+    WHERE TRUE
+      AND EXISTS (SELECT 1 FROM public.post_tags x WHERE x.post_id = e.id AND x.tag_id = ANY(:'broad'))
+), page AS (
+    SELECT id, score, 0::bigint AS full_count FROM matched ORDER BY score DESC, id DESC LIMIT 31
+)
+SELECT page.id, page.score, page.full_count FROM page JOIN public.posts e ON e.id = page.id ORDER BY page.score DESC, page.id DESC;
+
+\echo '== case 3: must=[bench_40], must_not=[bench_1], should=[bench_2] (old)'
+EXPLAIN (ANALYZE, COSTS OFF, SUMMARY ON)
+WITH ranked_posts AS (
+    SELECT p.id, COUNT(DISTINCT CASE WHEN t.name = ANY('{bench_2}') THEN t.name END) AS should_score
+    FROM public.posts p
+    LEFT JOIN public.post_tags pt ON pt.post_id = p.id
+    LEFT JOIN public.tags t ON t.id = pt.tag_id
+    GROUP BY p.id
+    HAVING COUNT(DISTINCT CASE WHEN t.name = ANY('{bench_40}') THEN t.name END) = cardinality('{bench_40}'::text[])
+       AND NOT EXISTS (
+           SELECT 1 FROM public.post_tags x JOIN public.tags tx ON tx.id = x.tag_id
+           WHERE x.post_id = p.id AND tx.name = ANY('{bench_1}')
+       )
+)
+SELECT id, should_score FROM ranked_posts ORDER BY should_score DESC, id DESC LIMIT 31;
+
+\echo '== case 3: must=[bench_40], must_not=[bench_1], should=[bench_2] (new)'
+EXPLAIN (ANALYZE, COSTS OFF, SUMMARY ON)
+WITH matched AS (
+    SELECT e.id,
+           ((EXISTS (SELECT 1 FROM public.post_tags x WHERE x.post_id = e.id AND x.tag_id = ANY(:'second')))::int)::bigint AS score
+    FROM (SELECT d.post_id AS id FROM public.post_tags d WHERE d.tag_id = ANY(:'medium')) d
+    JOIN public.posts e ON e.id = d.id
+    WHERE TRUE
+      AND NOT EXISTS (SELECT 1 FROM public.post_tags x WHERE x.post_id = e.id AND x.tag_id = ANY(:'broad'))
+), page AS (
+    SELECT id, score, 0::bigint AS full_count FROM matched ORDER BY score DESC, id DESC LIMIT 31
+)
+SELECT page.id, page.score, page.full_count FROM page JOIN public.posts e ON e.id = page.id ORDER BY page.score DESC, page.id DESC;
+
+\echo '== case 4: must_not=[bench_1] only (old)'
+EXPLAIN (ANALYZE, COSTS OFF, SUMMARY ON)
+WITH ranked_posts AS (
+    SELECT p.id, COUNT(DISTINCT CASE WHEN t.name = ANY('{}'::text[]) THEN t.name END) AS should_score
+    FROM public.posts p
+    LEFT JOIN public.post_tags pt ON pt.post_id = p.id
+    LEFT JOIN public.tags t ON t.id = pt.tag_id
+    GROUP BY p.id
+    HAVING COUNT(DISTINCT CASE WHEN t.name = ANY('{}'::text[]) THEN t.name END) = cardinality('{}'::text[])
+       AND NOT EXISTS (
+           SELECT 1 FROM public.post_tags x JOIN public.tags tx ON tx.id = x.tag_id
+           WHERE x.post_id = p.id AND tx.name = ANY('{bench_1}')
+       )
+)
+SELECT id, should_score FROM ranked_posts ORDER BY should_score DESC, id DESC LIMIT 31;
+
+\echo '== case 4: must_not=[bench_1] only (new)'
+EXPLAIN (ANALYZE, COSTS OFF, SUMMARY ON)
+WITH matched AS (
+    SELECT e.id, 0::bigint AS score
+    FROM public.posts e
+    WHERE TRUE
+      AND NOT EXISTS (SELECT 1 FROM public.post_tags x WHERE x.post_id = e.id AND x.tag_id = ANY(:'broad'))
+), page AS (
+    SELECT id, score, 0::bigint AS full_count FROM matched ORDER BY score DESC, id DESC LIMIT 31
+)
+SELECT page.id, page.score, page.full_count FROM page JOIN public.posts e ON e.id = page.id ORDER BY page.score DESC, page.id DESC;
@@ -0,0 +1,22 @@
+-- Covering indexes for tag search, which starts from the rarest tag and
+-- probes the rest by (tag_id, post_id).
+
+BEGIN;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1
+        FROM pg_indexes
+        WHERE schemaname = 'public'
+          AND indexname = 'idx_post_tags_tag_id'
+          AND indexdef LIKE '%(tag_id, post_id)'
+    ) THEN
+        DROP INDEX IF EXISTS public.idx_post_tags_tag_id;
+        CREATE INDEX idx_post_tags_tag_id ON public.post_tags(tag_id, post_id);
+    END IF;
+END $$;
+
+CREATE INDEX IF NOT EXISTS idx_playlist_tags_tag_id ON public.playlist_tags(tag_id, playlist_id);
+
+COMMIT;
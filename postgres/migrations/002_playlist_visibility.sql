@@ -0,0 +1,29 @@
+-- Replaces playlists.is_public with visibility (0: private, 1: unlisted,
+-- 2: public) on databases created from an older schema.sql. Public playlists
+-- stay public, the rest become private.
+
+BEGIN;
+
+ALTER TABLE public.playlists
+    ADD COLUMN IF NOT EXISTS visibility smallint DEFAULT 0 NOT NULL,
+    ADD COLUMN IF NOT EXISTS share_token text;
+
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1
+        FROM information_schema.columns
+        WHERE table_schema = 'public'
+          AND table_name = 'playlists'
+          AND column_name = 'is_public'
+    ) THEN
+        UPDATE public.playlists SET visibility = 2 WHERE is_public;
+        ALTER TABLE public.playlists DROP COLUMN is_public;
+    END IF;
+END $$;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_playlists_share_token
+    ON public.playlists(share_token) WHERE share_token IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_playlists_public ON public.playlists(id) WHERE visibility = 2;
+
+COMMIT;
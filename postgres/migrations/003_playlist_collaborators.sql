@@ -0,0 +1,26 @@
+-- Playlist collaborators and who added each item.
+
+BEGIN;
+
+ALTER TABLE public.playlist_items
+    ADD COLUMN IF NOT EXISTS added_by uuid;
+
+ALTER TABLE ONLY public.playlist_items
+    DROP CONSTRAINT IF EXISTS playlist_items_added_by_fkey,
+    ADD CONSTRAINT playlist_items_added_by_fkey FOREIGN KEY (added_by) REFERENCES public.users(id) ON DELETE SET NULL;
+
+CREATE TABLE IF NOT EXISTS public.playlist_collaborators (
+    playlist_id uuid NOT NULL REFERENCES public.playlists(id) ON DELETE CASCADE,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    role smallint NOT NULL, -- 0: viewer, 1: editor, 2: admin
+    invited_by uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    created_at timestamp with time zone DEFAULT now(),
+    PRIMARY KEY(playlist_id, user_id)
+);
+
+ALTER TABLE public.playlist_collaborators OWNER TO glab;
+
+CREATE INDEX IF NOT EXISTS idx_playlist_collaborators_user_id
+    ON public.playlist_collaborators(user_id, playlist_id);
+
+COMMIT;
@@ -0,0 +1,8 @@
+-- Stored tag query of smart playlists.
+
+BEGIN;
+
+ALTER TABLE public.playlists
+    ADD COLUMN IF NOT EXISTS smart_query jsonb; -- NULL for manually curated playlists
+
+COMMIT;
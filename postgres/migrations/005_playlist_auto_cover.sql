@@ -0,0 +1,13 @@
+-- Automatic playlist covers built from the item pictures.
+
+BEGIN;
+
+ALTER TABLE public.playlists
+    ADD COLUMN IF NOT EXISTS auto_cover_file_id uuid,
+    ADD COLUMN IF NOT EXISTS auto_cover_sources uuid[] DEFAULT '{}'::uuid[] NOT NULL;
+
+ALTER TABLE ONLY public.playlists
+    DROP CONSTRAINT IF EXISTS playlists_auto_cover_file_id_fkey,
+    ADD CONSTRAINT playlists_auto_cover_file_id_fkey FOREIGN KEY (auto_cover_file_id) REFERENCES public.files(id) ON DELETE SET NULL;
+
+COMMIT;
@@ -0,0 +1,19 @@
+-- Per-user playback progress through playlists.
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS public.playlist_progress (
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    playlist_id uuid NOT NULL REFERENCES public.playlists(id) ON DELETE CASCADE,
+    item_id uuid REFERENCES public.playlist_items(id) ON DELETE SET NULL, -- NULL once the item is removed
+    position_ms bigint DEFAULT 0 NOT NULL,
+    updated_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY(user_id, playlist_id)
+);
+
+ALTER TABLE public.playlist_progress OWNER TO glab;
+
+CREATE INDEX IF NOT EXISTS idx_playlist_progress_recent
+    ON public.playlist_progress(user_id, updated_at DESC);
+
+COMMIT;
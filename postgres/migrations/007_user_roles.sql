@@ -0,0 +1,25 @@
+-- User roles (0: viewer, 1: uploader, 2: moderator, 3: admin) and post
+-- uploaders. Existing accounts become uploaders; the oldest one is made admin
+-- unless an admin already exists, so the instance can still be administered.
+-- Posts uploaded before this have no known uploader.
+
+BEGIN;
+
+ALTER TABLE public.users
+    ADD COLUMN IF NOT EXISTS role smallint DEFAULT 1 NOT NULL;
+
+UPDATE public.users
+SET role = 3
+WHERE id = (SELECT id FROM public.users ORDER BY created_at, id LIMIT 1)
+  AND NOT EXISTS (SELECT 1 FROM public.users WHERE role = 3);
+
+ALTER TABLE public.posts
+    ADD COLUMN IF NOT EXISTS uploader_id uuid;
+
+ALTER TABLE ONLY public.posts
+    DROP CONSTRAINT IF EXISTS posts_uploader_id_fkey,
+    ADD CONSTRAINT posts_uploader_id_fkey FOREIGN KEY (uploader_id) REFERENCES public.users(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_posts_uploader_id ON public.posts(uploader_id);
+
+COMMIT;
@@ -0,0 +1,36 @@
+-- Login sessions kept in Postgres. Sessions of the old cookie store can't be
+-- carried over, everyone signs in again once.
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS public.sessions (
+    id uuid PRIMARY KEY,
+    key_hash text NOT NULL UNIQUE,
+    user_id uuid REFERENCES public.users(id) ON DELETE CASCADE,
+    state jsonb DEFAULT '{}' NOT NULL,
+    user_agent text,
+    ip text,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    last_seen_at timestamp with time zone DEFAULT now() NOT NULL,
+    expires_at timestamp with time zone NOT NULL
+);
+
+ALTER TABLE public.sessions OWNER TO glab;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON public.sessions(user_id, last_seen_at DESC);
+CREATE INDEX IF NOT EXISTS idx_sessions_expires_at ON public.sessions(expires_at);
+
+CREATE OR REPLACE FUNCTION public.revoke_sessions_on_password_change()
+RETURNS trigger AS $$
+BEGIN
+    DELETE FROM public.sessions WHERE user_id = NEW.id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER password_change_trigger
+AFTER UPDATE OF password_hash ON public.users
+FOR EACH ROW WHEN (OLD.password_hash IS DISTINCT FROM NEW.password_hash)
+EXECUTE FUNCTION public.revoke_sessions_on_password_change();
+
+COMMIT;
@@ -0,0 +1,20 @@
+-- Personal API tokens.
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS public.api_tokens (
+    id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    name text NOT NULL,
+    token_hash text NOT NULL UNIQUE,
+    scopes smallint[] NOT NULL, -- 0: read, 1: upload, 2: admin
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    expires_at timestamp with time zone NOT NULL,
+    last_used_at timestamp with time zone
+);
+
+ALTER TABLE public.api_tokens OWNER TO glab;
+
+CREATE INDEX IF NOT EXISTS idx_api_tokens_user_id ON public.api_tokens(user_id, created_at DESC);
+
+COMMIT;
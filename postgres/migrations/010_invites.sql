@@ -0,0 +1,17 @@
+-- Admin-issued registration invites.
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS public.invites (
+    id uuid PRIMARY KEY,
+    code_hash text NOT NULL UNIQUE,
+    created_by uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    expires_at timestamp with time zone NOT NULL,
+    used_by uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    used_at timestamp with time zone
+);
+
+ALTER TABLE public.invites OWNER TO glab;
+
+COMMIT;
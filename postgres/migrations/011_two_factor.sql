@@ -0,0 +1,29 @@
+-- TOTP two-factor authentication and recovery codes.
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS public.user_totp (
+    user_id uuid PRIMARY KEY REFERENCES public.users(id) ON DELETE CASCADE,
+    secret text NOT NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    confirmed_at timestamp with time zone,
+    last_used_step bigint
+);
+
+ALTER TABLE public.user_totp OWNER TO glab;
+
+ALTER TABLE public.user_totp
+    ADD COLUMN IF NOT EXISTS last_used_step bigint;
+
+CREATE TABLE IF NOT EXISTS public.recovery_codes (
+    id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    code_hash text NOT NULL,
+    used_at timestamp with time zone
+);
+
+ALTER TABLE public.recovery_codes OWNER TO glab;
+
+CREATE INDEX IF NOT EXISTS idx_recovery_codes_user_id ON public.recovery_codes(user_id);
+
+COMMIT;
@@ -0,0 +1,17 @@
+-- OpenID Connect identities linked to users.
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS public.user_identities (
+    issuer text NOT NULL,
+    subject text NOT NULL,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY (issuer, subject)
+);
+
+ALTER TABLE public.user_identities OWNER TO glab;
+
+CREATE INDEX IF NOT EXISTS idx_user_identities_user_id ON public.user_identities(user_id);
+
+COMMIT;
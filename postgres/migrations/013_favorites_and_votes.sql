@@ -0,0 +1,59 @@
+-- Post favorites, votes and the score the votes add up to.
+
+BEGIN;
+
+ALTER TABLE public.posts
+    ADD COLUMN IF NOT EXISTS score integer DEFAULT 0 NOT NULL;
+
+CREATE TABLE IF NOT EXISTS public.post_favorites (
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    post_id uuid NOT NULL REFERENCES public.posts(id) ON DELETE CASCADE,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY (user_id, post_id)
+);
+
+ALTER TABLE public.post_favorites OWNER TO glab;
+
+CREATE TABLE IF NOT EXISTS public.post_votes (
+    post_id uuid NOT NULL REFERENCES public.posts(id) ON DELETE CASCADE,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    value smallint NOT NULL CHECK (value IN (-1, 1)),
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY (post_id, user_id)
+);
+
+ALTER TABLE public.post_votes OWNER TO glab;
+
+CREATE OR REPLACE FUNCTION public.update_post_score()
+RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE public.posts
+        SET score = score + NEW.value
+        WHERE id = NEW.post_id;
+
+    ELSIF TG_OP = 'UPDATE' THEN
+        UPDATE public.posts
+        SET score = score - OLD.value + NEW.value
+        WHERE id = NEW.post_id;
+
+    ELSIF TG_OP = 'DELETE' THEN
+        UPDATE public.posts
+        SET score = score - OLD.value
+        WHERE id = OLD.post_id;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER post_score_trigger
+AFTER INSERT OR UPDATE OF value OR DELETE ON public.post_votes
+FOR EACH ROW EXECUTE FUNCTION public.update_post_score();
+
+CREATE INDEX IF NOT EXISTS idx_post_favorites_recent ON public.post_favorites(user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_post_favorites_post_id ON public.post_favorites(post_id);
+CREATE INDEX IF NOT EXISTS idx_post_votes_user_id ON public.post_votes(user_id);
+CREATE INDEX IF NOT EXISTS idx_posts_score ON public.posts(score DESC, id DESC);
+
+COMMIT;
@@ -0,0 +1,15 @@
+-- Post content ratings (0: safe, 1: questionable, 2: explicit) and the
+-- per-user filter. Nobody has reviewed the existing posts, so they start out
+-- explicit and stay hidden from the default filter until someone rates them.
+
+BEGIN;
+
+ALTER TABLE public.posts
+    ADD COLUMN IF NOT EXISTS rating smallint DEFAULT 2 NOT NULL;
+ALTER TABLE public.posts
+    ALTER COLUMN rating SET DEFAULT 2;
+
+ALTER TABLE public.users
+    ADD COLUMN IF NOT EXISTS max_rating smallint DEFAULT 0 NOT NULL;
+
+COMMIT;
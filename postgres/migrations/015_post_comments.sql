@@ -0,0 +1,23 @@
+-- Threaded post comments.
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS public.post_comments (
+    id uuid PRIMARY KEY,
+    post_id uuid NOT NULL REFERENCES public.posts(id) ON DELETE CASCADE,
+    parent_id uuid REFERENCES public.post_comments(id) ON DELETE CASCADE,
+    author_id uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    body text NOT NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    edited_at timestamp with time zone,
+    deleted_at timestamp with time zone,
+    deleted_by uuid REFERENCES public.users(id) ON DELETE SET NULL
+);
+
+ALTER TABLE public.post_comments OWNER TO glab;
+
+CREATE INDEX IF NOT EXISTS idx_post_comments_thread ON public.post_comments(post_id, parent_id, id);
+CREATE INDEX IF NOT EXISTS idx_post_comments_parent_id ON public.post_comments(parent_id, id);
+CREATE INDEX IF NOT EXISTS idx_post_comments_author_id ON public.post_comments(author_id);
+
+COMMIT;
@@ -0,0 +1,20 @@
+-- Post edit history. Edits made before this have no revisions.
+
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS public.post_revisions (
+    id uuid DEFAULT uuidv7() PRIMARY KEY,
+    post_id uuid NOT NULL REFERENCES public.posts(id) ON DELETE CASCADE,
+    editor_id uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    changes jsonb DEFAULT '{}'::jsonb NOT NULL,
+    tags_added uuid[] DEFAULT '{}'::uuid[] NOT NULL,
+    tags_removed uuid[] DEFAULT '{}'::uuid[] NOT NULL
+);
+
+ALTER TABLE public.post_revisions OWNER TO glab;
+
+CREATE INDEX IF NOT EXISTS idx_post_revisions_post_id ON public.post_revisions(post_id, id);
+CREATE INDEX IF NOT EXISTS idx_post_revisions_editor_id ON public.post_revisions(editor_id);
+
+COMMIT;
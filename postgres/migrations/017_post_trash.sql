@@ -0,0 +1,15 @@
+-- Trash for deleted posts.
+
+BEGIN;
+
+ALTER TABLE public.posts
+    ADD COLUMN IF NOT EXISTS deleted_at timestamp with time zone,
+    ADD COLUMN IF NOT EXISTS deleted_by uuid;
+
+ALTER TABLE ONLY public.posts
+    DROP CONSTRAINT IF EXISTS posts_deleted_by_fkey,
+    ADD CONSTRAINT posts_deleted_by_fkey FOREIGN KEY (deleted_by) REFERENCES public.users(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_posts_trash ON public.posts(deleted_at) WHERE deleted_at IS NOT NULL;
+
+COMMIT;
@@ -58,7 +58,8 @@ CREATE TABLE public.playlist_items (
     "position" integer NOT NULL,
     post_id uuid,
     note_text text,
-    created_at timestamp with time zone DEFAULT now()
+    created_at timestamp with time zone DEFAULT now(),
+    added_by uuid
 );
 
 
@@ -87,8 +88,12 @@ CREATE TABLE public.playlists (
     cover_file_id uuid,
     created_at timestamp with time zone DEFAULT now(),
     updated_at timestamp with time zone DEFAULT now(),
-    is_public boolean DEFAULT false NOT NULL,
-    owner_id uuid
+    visibility smallint DEFAULT 0 NOT NULL, -- 0: private, 1: unlisted, 2: public
+    share_token text,
+    owner_id uuid,
+    smart_query jsonb, -- NULL for manually curated playlists
+    auto_cover_file_id uuid, -- shown while cover_file_id is NULL
+    auto_cover_sources uuid[] DEFAULT '{}'::uuid[] NOT NULL -- picture files the auto cover was built from
 );
 
 
@@ -131,7 +136,14 @@ CREATE TABLE public.posts (
     file_id uuid NOT NULL,
     description text,
     created_at timestamp with time zone DEFAULT now(),
-    updated_at timestamp with time zone DEFAULT now()
+    updated_at timestamp with time zone DEFAULT now(),
+    uploader_id uuid,
+    -- Sum of post_votes values, kept up to date by a trigger
+    score integer DEFAULT 0 NOT NULL,
+    rating smallint DEFAULT 2 NOT NULL, -- 0: safe, 1: questionable, 2: explicit
+    -- Set while the post sits in the trash, the purge job removes it later
+    deleted_at timestamp with time zone,
+    deleted_by uuid
 );
 
 
@@ -156,7 +168,9 @@ CREATE TABLE public.users (
     id uuid DEFAULT uuidv7() NOT NULL,
     username text NOT NULL,
     password_hash text NOT NULL,
-    created_at timestamp with time zone DEFAULT now()
+    created_at timestamp with time zone DEFAULT now(),
+    role smallint DEFAULT 1 NOT NULL,
+    max_rating smallint DEFAULT 0 NOT NULL -- highest post rating shown by default
 );
 
 
@@ -266,6 +280,14 @@ ALTER TABLE ONLY public.playlist_items
     ADD CONSTRAINT playlist_items_post_id_fkey FOREIGN KEY (post_id) REFERENCES public.posts(id) ON DELETE SET NULL;
 
 
+--
+-- Name: playlist_items playlist_items_added_by_fkey; Type: FK CONSTRAINT; Schema: public; Owner: glab
+--
+
+ALTER TABLE ONLY public.playlist_items
+    ADD CONSTRAINT playlist_items_added_by_fkey FOREIGN KEY (added_by) REFERENCES public.users(id) ON DELETE SET NULL;
+
+
 --
 -- Name: playlist_tags playlist_tags_playlist_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: glab
 --
@@ -282,6 +304,14 @@ ALTER TABLE ONLY public.playlist_tags
     ADD CONSTRAINT playlist_tags_tag_id_fkey FOREIGN KEY (tag_id) REFERENCES public.tags(id) ON DELETE CASCADE;
 
 
+--
+-- Name: playlists playlists_auto_cover_file_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: glab
+--
+
+ALTER TABLE ONLY public.playlists
+    ADD CONSTRAINT playlists_auto_cover_file_id_fkey FOREIGN KEY (auto_cover_file_id) REFERENCES public.files(id) ON DELETE SET NULL;
+
+
 --
 -- Name: playlists playlists_cover_file_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: glab
 --
@@ -330,6 +360,21 @@ ALTER TABLE ONLY public.posts
     ADD CONSTRAINT posts_file_id_fkey FOREIGN KEY (file_id) REFERENCES public.files(id);
 
 
+--
+-- Name: posts posts_uploader_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: glab
+--
+
+ALTER TABLE ONLY public.posts
+    ADD CONSTRAINT posts_uploader_id_fkey FOREIGN KEY (uploader_id) REFERENCES public.users(id) ON DELETE SET NULL;
+
+--
+-- Name: posts posts_deleted_by_fkey; Type: FK CONSTRAINT; Schema: public; Owner: glab
+--
+
+ALTER TABLE ONLY public.posts
+    ADD CONSTRAINT posts_deleted_by_fkey FOREIGN KEY (deleted_by) REFERENCES public.users(id) ON DELETE SET NULL;
+
+
 CREATE TABLE public.thumbnails (
     id uuid DEFAULT uuidv7() PRIMARY KEY,
     file_id uuid NOT NULL REFERENCES public.files(id) ON DELETE CASCADE,
@@ -381,15 +426,228 @@ CREATE TABLE public.tag_relations (
 ALTER TABLE public.tag_relations OWNER TO glab;
 
 
+CREATE TABLE public.playlist_collaborators (
+    playlist_id uuid NOT NULL REFERENCES public.playlists(id) ON DELETE CASCADE,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    role smallint NOT NULL, -- 0: viewer, 1: editor, 2: admin
+    invited_by uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    created_at timestamp with time zone DEFAULT now(),
+    PRIMARY KEY(playlist_id, user_id)
+);
+
+ALTER TABLE public.playlist_collaborators OWNER TO glab;
+
+
+CREATE TABLE public.playlist_progress (
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    playlist_id uuid NOT NULL REFERENCES public.playlists(id) ON DELETE CASCADE,
+    item_id uuid REFERENCES public.playlist_items(id) ON DELETE SET NULL, -- NULL once the item is removed
+    position_ms bigint DEFAULT 0 NOT NULL,
+    updated_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY(user_id, playlist_id)
+);
+
+ALTER TABLE public.playlist_progress OWNER TO glab;
+
+-- Login sessions; the cookie carries the key, only its sha256 is stored
+CREATE TABLE public.sessions (
+    id uuid PRIMARY KEY,
+    key_hash text NOT NULL UNIQUE,
+    user_id uuid REFERENCES public.users(id) ON DELETE CASCADE,
+    state jsonb DEFAULT '{}' NOT NULL,
+    user_agent text,
+    ip text,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    last_seen_at timestamp with time zone DEFAULT now() NOT NULL,
+    expires_at timestamp with time zone NOT NULL
+);
+
+ALTER TABLE public.sessions OWNER TO glab;
+
+-- Personal API tokens; like sessions only the sha256 of the secret is kept
+CREATE TABLE public.api_tokens (
+    id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    name text NOT NULL,
+    token_hash text NOT NULL UNIQUE,
+    scopes smallint[] NOT NULL, -- 0: read, 1: upload, 2: admin
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    expires_at timestamp with time zone NOT NULL,
+    last_used_at timestamp with time zone
+);
+
+ALTER TABLE public.api_tokens OWNER TO glab;
+
+-- Single-use registration codes issued by admins; stored by sha256
+CREATE TABLE public.invites (
+    id uuid PRIMARY KEY,
+    code_hash text NOT NULL UNIQUE,
+    created_by uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    expires_at timestamp with time zone NOT NULL,
+    used_by uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    used_at timestamp with time zone
+);
+
+ALTER TABLE public.invites OWNER TO glab;
+
+-- An unconfirmed row is an enrollment in progress; two-factor is on once
+-- confirmed_at is set
+CREATE TABLE public.user_totp (
+    user_id uuid PRIMARY KEY REFERENCES public.users(id) ON DELETE CASCADE,
+    secret text NOT NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    confirmed_at timestamp with time zone,
+    -- Time step of the last accepted code, so a code works only once
+    last_used_step bigint
+);
+
+ALTER TABLE public.user_totp OWNER TO glab;
+
+CREATE TABLE public.recovery_codes (
+    id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    code_hash text NOT NULL,
+    used_at timestamp with time zone
+);
+
+ALTER TABLE public.recovery_codes OWNER TO glab;
+
+-- Accounts at an external OpenID Connect provider signing in as a user
+CREATE TABLE public.user_identities (
+    issuer text NOT NULL,
+    subject text NOT NULL,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY (issuer, subject)
+);
+
+ALTER TABLE public.user_identities OWNER TO glab;
+
+CREATE TABLE public.post_favorites (
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    post_id uuid NOT NULL REFERENCES public.posts(id) ON DELETE CASCADE,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY (user_id, post_id)
+);
+
+ALTER TABLE public.post_favorites OWNER TO glab;
+
+CREATE TABLE public.post_votes (
+    post_id uuid NOT NULL REFERENCES public.posts(id) ON DELETE CASCADE,
+    user_id uuid NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    value smallint NOT NULL CHECK (value IN (-1, 1)),
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    PRIMARY KEY (post_id, user_id)
+);
+
+ALTER TABLE public.post_votes OWNER TO glab;
+
+-- Deleted comments stay behind as tombstones so their replies keep a parent
+CREATE TABLE public.post_comments (
+    id uuid PRIMARY KEY,
+    post_id uuid NOT NULL REFERENCES public.posts(id) ON DELETE CASCADE,
+    parent_id uuid REFERENCES public.post_comments(id) ON DELETE CASCADE,
+    author_id uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    body text NOT NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    edited_at timestamp with time zone,
+    deleted_at timestamp with time zone,
+    deleted_by uuid REFERENCES public.users(id) ON DELETE SET NULL
+);
+
+ALTER TABLE public.post_comments OWNER TO glab;
+
+-- One row per post edit; `changes` holds {field: {old, new}} for title,
+-- description, rating and notes
+CREATE TABLE public.post_revisions (
+    id uuid DEFAULT uuidv7() PRIMARY KEY,
+    post_id uuid NOT NULL REFERENCES public.posts(id) ON DELETE CASCADE,
+    editor_id uuid REFERENCES public.users(id) ON DELETE SET NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    changes jsonb DEFAULT '{}'::jsonb NOT NULL,
+    tags_added uuid[] DEFAULT '{}'::uuid[] NOT NULL,
+    tags_removed uuid[] DEFAULT '{}'::uuid[] NOT NULL
+);
+
+ALTER TABLE public.post_revisions OWNER TO glab;
+
+CREATE OR REPLACE FUNCTION public.update_post_score()
+RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE public.posts
+        SET score = score + NEW.value
+        WHERE id = NEW.post_id;
+
+    ELSIF TG_OP = 'UPDATE' THEN
+        UPDATE public.posts
+        SET score = score - OLD.value + NEW.value
+        WHERE id = NEW.post_id;
+
+    ELSIF TG_OP = 'DELETE' THEN
+        UPDATE public.posts
+        SET score = score - OLD.value
+        WHERE id = OLD.post_id;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER post_score_trigger
+AFTER INSERT OR UPDATE OF value OR DELETE ON public.post_votes
+FOR EACH ROW EXECUTE FUNCTION public.update_post_score();
+
+-- A new password signs the account out everywhere
+CREATE OR REPLACE FUNCTION public.revoke_sessions_on_password_change()
+RETURNS trigger AS $$
+BEGIN
+    DELETE FROM public.sessions WHERE user_id = NEW.id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER password_change_trigger
+AFTER UPDATE OF password_hash ON public.users
+FOR EACH ROW WHEN (OLD.password_hash IS DISTINCT FROM NEW.password_hash)
+EXECUTE FUNCTION public.revoke_sessions_on_password_change();
+
+
 CREATE INDEX thumbnails_file_id_idx
     ON public.thumbnails(file_id);
 
 CREATE INDEX idx_tags_category ON public.tags(category);
 
 CREATE INDEX idx_posts_file_id ON public.posts(file_id);
-
-CREATE INDEX idx_post_tags_tag_id ON public.post_tags(tag_id);
+CREATE INDEX idx_posts_uploader_id ON public.posts(uploader_id);
+
+CREATE UNIQUE INDEX idx_playlists_share_token
+    ON public.playlists(share_token) WHERE share_token IS NOT NULL;
+CREATE INDEX idx_playlists_public ON public.playlists(id) WHERE visibility = 2;
+
+CREATE INDEX idx_post_tags_tag_id ON public.post_tags(tag_id, post_id);
+CREATE INDEX idx_playlist_tags_tag_id ON public.playlist_tags(tag_id, playlist_id);
+CREATE INDEX idx_playlist_collaborators_user_id
+    ON public.playlist_collaborators(user_id, playlist_id);
+CREATE INDEX idx_playlist_progress_recent
+    ON public.playlist_progress(user_id, updated_at DESC);
 CREATE INDEX idx_post_tags_post_id ON public.post_tags(post_id);
+CREATE INDEX idx_sessions_user_id ON public.sessions(user_id, last_seen_at DESC);
+CREATE INDEX idx_sessions_expires_at ON public.sessions(expires_at);
+CREATE INDEX idx_api_tokens_user_id ON public.api_tokens(user_id, created_at DESC);
+CREATE INDEX idx_recovery_codes_user_id ON public.recovery_codes(user_id);
+CREATE INDEX idx_user_identities_user_id ON public.user_identities(user_id);
+CREATE INDEX idx_post_favorites_recent ON public.post_favorites(user_id, created_at DESC);
+CREATE INDEX idx_post_favorites_post_id ON public.post_favorites(post_id);
+CREATE INDEX idx_post_votes_user_id ON public.post_votes(user_id);
+CREATE INDEX idx_posts_score ON public.posts(score DESC, id DESC);
+CREATE INDEX idx_posts_trash ON public.posts(deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX idx_post_comments_thread ON public.post_comments(post_id, parent_id, id);
+CREATE INDEX idx_post_comments_parent_id ON public.post_comments(parent_id, id);
+CREATE INDEX idx_post_comments_author_id ON public.post_comments(author_id);
+CREATE INDEX idx_post_revisions_post_id ON public.post_revisions(post_id, id);
+CREATE INDEX idx_post_revisions_editor_id ON public.post_revisions(editor_id);
 
 --
 -- PostgreSQL database dump complete